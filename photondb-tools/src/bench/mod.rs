@@ -61,6 +61,10 @@ pub(crate) struct Args {
     #[arg(long, default_value_t = -1)]
     read_writes: i64,
 
+    /// Number of keys to get in each batch for the MultiReadRandom workload.
+    #[arg(long, default_value_t = 16)]
+    multi_get_batch_size: u64,
+
     /// Ratio of reads to reads/writes for the ReadRandomWriteRandom workload.
     /// Default value 90 means "9 gets for every 1 put".
     #[arg(long, default_value_t = 90)]
@@ -163,13 +167,17 @@ pub(crate) struct BenchOperation {
 #[derive(Debug, Copy, Clone)]
 enum BenchmarkType {
     Fillseq,
+    FillseqIngest,
     FillRandom,
     ReadSeq,
     ReadRandom,
+    MultiReadRandom,
     UpdateRandom,
     ReadRandomWriteRandom,
     Flush,
     WaitForReclaiming,
+    ChecksumSinglePass,
+    ChecksumTwoPass,
 }
 
 impl BenchmarkType {
@@ -185,13 +193,17 @@ impl From<&str> for BenchmarkType {
     fn from(str: &str) -> Self {
         match str {
             "fillseq" => BenchmarkType::Fillseq,
+            "fillseqingest" => BenchmarkType::FillseqIngest,
             "fillrandom" => BenchmarkType::FillRandom,
             "readseq" => BenchmarkType::ReadSeq,
             "readrandom" => BenchmarkType::ReadRandom,
+            "multireadrandom" => BenchmarkType::MultiReadRandom,
             "updaterandom" => BenchmarkType::UpdateRandom,
             "readrandomwriterandom" => BenchmarkType::ReadRandomWriteRandom,
             "flush" => BenchmarkType::Flush,
             "waitforreclaiming" => BenchmarkType::WaitForReclaiming,
+            "checksumsinglepass" => BenchmarkType::ChecksumSinglePass,
+            "checksumtwopass" => BenchmarkType::ChecksumTwoPass,
             _ => panic!("invalid benchmark type"),
         }
     }
@@ -16,6 +16,10 @@ pub(crate) trait Store<E>: std::fmt::Debug + Clone + Sync + Send + 'static {
 
     async fn get(&self, key: &[u8], lsn: u64) -> Result<Option<Vec<u8>>>;
 
+    async fn multi_get(&self, keys: &[&[u8]], lsn: u64) -> Result<Vec<Option<Vec<u8>>>>;
+
+    async fn ingest(&self, entries: &[(&[u8], &[u8])], lsn: u64) -> Result<()>;
+
     async fn flush(&self);
 
     async fn wait_for_reclaiming(&self);
@@ -61,6 +61,23 @@ where
         Ok(r)
     }
 
+    async fn multi_get(&self, keys: &[&[u8]], lsn: u64) -> Result<Vec<Option<Vec<u8>>>> {
+        let r = self
+            .table
+            .multi_get(keys, lsn)
+            .await
+            .expect("multi get fail");
+        Ok(r)
+    }
+
+    async fn ingest(&self, entries: &[(&[u8], &[u8])], lsn: u64) -> Result<()> {
+        self.table
+            .ingest(entries.iter().copied(), lsn)
+            .await
+            .expect("ingest fail");
+        Ok(())
+    }
+
     async fn flush(&self) {
         self.table.flush(&FlushOptions::default()).await;
     }
@@ -86,3 +103,39 @@ impl<E: Env + Sync + Send + 'static> std::fmt::Debug for PhotondbStore<E> {
         f.debug_struct("PhotonDbStore").finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use photondb::env::Std;
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[photonio::test]
+    async fn store_round_trip() {
+        let path = TempDir::new("store_round_trip").unwrap();
+        let args = Args::parse_from([
+            "bench",
+            "--db",
+            path.path().to_str().unwrap(),
+            "--benchmarks",
+            "fillseq",
+        ]);
+        let store: PhotondbStore<Std> = PhotondbStore::open_table(Arc::new(args), &Std).await;
+        store.put(b"key", 1, b"value").await.unwrap();
+        let value = store.get(b"key", 1).await.unwrap();
+        assert_eq!(value.as_deref(), Some(b"value".as_slice()));
+        assert_eq!(store.get(b"missing", 1).await.unwrap(), None);
+        let values = store
+            .multi_get(&[b"missing".as_slice(), b"key"], 1)
+            .await
+            .unwrap();
+        assert_eq!(values, [None, Some(b"value".to_vec())]);
+
+        let stats = store.stats().unwrap();
+        assert!(stats.tree.success.write >= 1);
+        assert!(stats.tree.success.read >= 2);
+        store.close().await.unwrap();
+    }
+}
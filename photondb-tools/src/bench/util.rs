@@ -361,6 +361,10 @@ impl<S: Store<E>, E: Env> Stats<S, E> {
         }
     }
 
+    pub(super) fn reset_start(&mut self) {
+        self.start = Instant::now();
+    }
+
     pub(super) fn add_msg(&mut self, msg: &str) {
         if msg.is_empty() {
             return;
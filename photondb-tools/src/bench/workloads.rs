@@ -172,11 +172,19 @@ impl<S: Store<E>, E: Env> Workloads<S, E> {
                     BenchmarkType::Fillseq => {
                         Self::do_write(&mut task_ctx, GenMode::Sequence).await
                     }
+                    BenchmarkType::FillseqIngest => Self::do_ingest_seq(&mut task_ctx).await,
                     BenchmarkType::ReadRandom => Self::do_read_random(&mut task_ctx).await,
+                    BenchmarkType::MultiReadRandom => {
+                        Self::do_multi_read_random(&mut task_ctx).await
+                    }
                     BenchmarkType::UpdateRandom => Self::do_update_random(&mut task_ctx).await,
                     BenchmarkType::ReadRandomWriteRandom => {
                         Self::do_read_random_write_random(&mut task_ctx).await
                     }
+                    BenchmarkType::ChecksumSinglePass => {
+                        Self::do_checksum(&mut task_ctx, true).await
+                    }
+                    BenchmarkType::ChecksumTwoPass => Self::do_checksum(&mut task_ctx, false).await,
                     _ => unimplemented!(),
                 }
                 task_ctx.stats.as_ref().borrow_mut().stop();
@@ -264,6 +272,92 @@ impl<S: Store<E>, E: Env> Workloads<S, E> {
         }
     }
 
+    // Writes the same keys as `fillseq`, but with a single ingest, which is
+    // only faster than puts if the table is empty. More keys are written if
+    // they don't fill a write buffer, so that the ingest is measured across
+    // write buffers like a real bulk load.
+    async fn do_ingest_seq(ctx: &mut TaskCtx<S, E>) {
+        let table = ctx.table.clone();
+        let cfg = ctx.config.to_owned();
+        let op_cnt = if cfg.writes >= 0 {
+            cfg.writes as u64
+        } else {
+            cfg.num
+        };
+        let mut key_gen = KeyGenerator::new(
+            GenMode::Sequence,
+            ctx.config.key_size,
+            ctx.config.num,
+            ctx.seed,
+            ctx.config.key_rand_dist,
+        );
+        let mut value_gen = ValueGenerator::new(
+            ctx.config.value_size_distribution_type,
+            ctx.config.value_size,
+        );
+        let mut entries = Vec::with_capacity(op_cnt as usize);
+        let mut bytes = 0;
+        while (entries.len() as u64) < op_cnt || bytes as u64 <= cfg.write_buffer_size {
+            let mut key = vec![0u8; ctx.config.key_size as usize];
+            key_gen.generate_key(&mut key);
+            let value = value_gen.generate_value().to_vec();
+            bytes += key.len() + value.len() + std::mem::size_of::<u64>();
+            entries.push((key, value));
+        }
+        // Excludes the time to generate the entries.
+        ctx.stats.borrow_mut().reset_start();
+        let entry_refs = entries
+            .iter()
+            .map(|(k, v)| (k.as_slice(), v.as_slice()))
+            .collect::<Vec<_>>();
+        table.ingest(&entry_refs, 0).await.expect("ingest fail");
+        ctx.stats.borrow_mut().finish_operation(
+            OpType::Write,
+            entries.len() as u64,
+            0,
+            bytes as u64,
+        );
+    }
+
+    // Copies pages of `page_size` bytes into a buffer in chunks, like a flush
+    // fills its write buffer, and computes the CRC32 of each page either while
+    // copying it or over the whole copied page afterwards. The table is not
+    // used. Compare `checksumsinglepass` with `checksumtwopass` on large pages
+    // to see what computing page checksums in a single pass saves.
+    async fn do_checksum(ctx: &mut TaskCtx<S, E>, single_pass: bool) {
+        const CHUNK_SIZE: usize = 4 << 10;
+        let cfg = ctx.config.to_owned();
+        let op_cnt = if cfg.writes >= 0 {
+            cfg.writes as u64
+        } else {
+            cfg.num
+        };
+        let page_size = cfg.page_size as usize;
+        let page = (0..page_size).map(|i| i as u8).collect::<Vec<_>>();
+        let mut buf = Vec::with_capacity(page_size);
+        let mut checksum = 0;
+        for _ in Until::new(op_cnt, cfg.duration) {
+            buf.clear();
+            let mut hasher = crc32fast::Hasher::new();
+            for chunk in page.chunks(CHUNK_SIZE) {
+                buf.extend_from_slice(chunk);
+                if single_pass {
+                    hasher.update(chunk);
+                }
+            }
+            if !single_pass {
+                hasher.update(&buf);
+            }
+            // Keeps the checksums from being optimized out.
+            checksum ^= hasher.finalize();
+            ctx.stats
+                .borrow_mut()
+                .finish_operation(OpType::Write, 1, 0, page_size as u64);
+        }
+        let msg = format!("(checksum:{checksum:08x})");
+        ctx.stats.borrow_mut().add_msg(&msg);
+    }
+
     async fn do_read_random(ctx: &mut TaskCtx<S, E>) {
         let table = ctx.table.clone();
         let cfg = ctx.config.to_owned();
@@ -304,6 +398,60 @@ impl<S: Store<E>, E: Env> Workloads<S, E> {
         ctx.stats.borrow_mut().add_msg(&msg);
     }
 
+    // Compare the tree reads in the db stats with those of `do_read_random` to
+    // see how many page lookups the batches save.
+    async fn do_multi_read_random(ctx: &mut TaskCtx<S, E>) {
+        let table = ctx.table.clone();
+        let cfg = ctx.config.to_owned();
+        let op_cnt = if cfg.reads >= 0 {
+            cfg.reads as u64
+        } else {
+            cfg.num
+        };
+        let batch_size = cfg.multi_get_batch_size.max(1);
+
+        let mut key_gen = KeyGenerator::new(
+            GenMode::Random,
+            ctx.config.key_size,
+            ctx.config.num,
+            ctx.seed,
+            ctx.config.key_rand_dist,
+        );
+
+        let mut reads = 0;
+        let mut founds = 0;
+
+        for _ in Until::new(op_cnt / batch_size, cfg.duration) {
+            let mut keys = vec![vec![0u8; ctx.config.key_size as usize]; batch_size as usize];
+            for key in &mut keys {
+                key_gen.generate_key(key);
+            }
+            let key_refs = keys.iter().map(|k| k.as_slice()).collect::<Vec<_>>();
+            let values = table
+                .multi_get(&key_refs, 0)
+                .await
+                .expect("multi get keys fail");
+            reads += keys.len();
+            let mut found = 0;
+            let mut bytes = 0;
+            for (key, value) in keys.iter().zip(values) {
+                if let Some(v) = value {
+                    found += 1;
+                    bytes += key.len() + v.len() + std::mem::size_of::<u64>();
+                }
+            }
+            founds += found;
+            ctx.stats.borrow_mut().finish_operation(
+                OpType::Read,
+                found,
+                keys.len() as u64 - found,
+                bytes as u64,
+            );
+        }
+        let msg = format!("(reads:{reads} founds:{founds})");
+        ctx.stats.borrow_mut().add_msg(&msg);
+    }
+
     async fn do_update_random(ctx: &mut TaskCtx<S, E>) {
         let table = ctx.table.clone();
         let cfg = ctx.config.to_owned();
@@ -0,0 +1,411 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    future::Future,
+    io::{Error, ErrorKind, Result},
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+use parking_lot::Mutex;
+
+use super::*;
+
+/// An implementation of [`Env`] that injects faults into the file operations
+/// of another environment.
+///
+/// Faults are injected according to the rules added with [`Self::inject`].
+/// This is used to test how the engine handles IO errors, short IO, and slow
+/// devices, without breaking a real device.
+#[derive(Clone)]
+pub struct FaultEnv<E> {
+    inner: E,
+    rules: Arc<FaultRules>,
+}
+
+/// File operations that faults can be injected into.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum FaultOp {
+    /// Opens a file for reads or writes.
+    Open,
+    /// Reads from a file.
+    Read,
+    /// Writes to a file, including truncations.
+    Write,
+    /// Syncs a file to the device.
+    Sync,
+    /// Renames a file.
+    Rename,
+    /// Removes a file or a directory.
+    Remove,
+    /// Enables direct IO on an open file.
+    DirectIo,
+}
+
+/// Faults to inject into file operations.
+#[derive(Clone, Debug)]
+pub enum Fault {
+    /// Fails the operation with an IO error of the kind.
+    Error(ErrorKind),
+    /// Reads or writes only half of the requested bytes.
+    ///
+    /// It has no effect on the other operations.
+    Short,
+    /// Delays the operation.
+    ///
+    /// The delay blocks the calling thread, so it also delays the other tasks
+    /// running on the thread.
+    Delay(Duration),
+}
+
+/// A rule to inject a fault into file operations.
+#[derive(Clone, Debug)]
+pub struct FaultRule {
+    op: FaultOp,
+    fault: Fault,
+    path: Option<String>,
+    times: Option<usize>,
+}
+
+impl FaultRule {
+    /// Creates a rule that injects the fault into all the operations of the
+    /// kind.
+    pub fn new(op: FaultOp, fault: Fault) -> Self {
+        Self {
+            op,
+            fault,
+            path: None,
+            times: None,
+        }
+    }
+
+    /// Only injects the fault into operations on paths that contain the
+    /// pattern.
+    pub fn on_path(mut self, pattern: impl Into<String>) -> Self {
+        self.path = Some(pattern.into());
+        self
+    }
+
+    /// Only injects the fault the given number of times.
+    ///
+    /// A rule with zero times never injects the fault.
+    pub fn times(mut self, times: usize) -> Self {
+        self.times = Some(times);
+        self
+    }
+
+    fn matches(&self, op: FaultOp, path: &Path) -> bool {
+        self.op == op
+            && self.path.as_ref().map_or(true, |pattern| {
+                path.to_string_lossy().contains(pattern.as_str())
+            })
+    }
+}
+
+#[derive(Default)]
+struct FaultRules {
+    rules: Mutex<Vec<FaultRule>>,
+    issued: Mutex<HashMap<FaultOp, usize>>,
+}
+
+impl FaultRules {
+    /// Returns the fault to inject into the operation on the path.
+    fn take(&self, op: FaultOp, path: &Path) -> Option<Fault> {
+        let mut rules = self.rules.lock();
+        let index = rules.iter().position(|rule| rule.matches(op, path))?;
+        let rule = &mut rules[index];
+        let fault = rule.fault.clone();
+        if let Some(times) = rule.times.as_mut() {
+            *times -= 1;
+            if *times == 0 {
+                rules.remove(index);
+            }
+        }
+        Some(fault)
+    }
+
+    /// Injects a fault into the operation on the path, if any.
+    ///
+    /// Returns true if the operation should be short.
+    fn apply(&self, op: FaultOp, path: &Path) -> Result<bool> {
+        *self.issued.lock().entry(op).or_default() += 1;
+        match self.take(op, path) {
+            None => Ok(false),
+            Some(Fault::Error(kind)) => Err(Error::new(
+                kind,
+                format!("injected {op:?} fault on {}", path.display()),
+            )),
+            Some(Fault::Short) => Ok(true),
+            Some(Fault::Delay(delay)) => {
+                thread::sleep(delay);
+                Ok(false)
+            }
+        }
+    }
+}
+
+impl<E: Env> FaultEnv<E> {
+    /// Creates an environment that injects faults into `inner`.
+    pub fn new(inner: E) -> Self {
+        Self {
+            inner,
+            rules: Arc::default(),
+        }
+    }
+
+    /// Adds a rule to inject faults.
+    ///
+    /// If an operation matches more than one rule, the rule added first is
+    /// applied.
+    pub fn inject(&self, rule: FaultRule) {
+        if rule.times == Some(0) {
+            return;
+        }
+        self.rules.rules.lock().push(rule);
+    }
+
+    /// Removes all the rules.
+    pub fn clear(&self) {
+        self.rules.rules.lock().clear();
+    }
+
+    /// Returns the number of operations of the kind issued so far, including
+    /// the ones that faults are injected into.
+    pub fn issued(&self, op: FaultOp) -> usize {
+        self.rules.issued.lock().get(&op).copied().unwrap_or(0)
+    }
+}
+
+impl<E> fmt::Debug for FaultEnv<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FaultEnv")
+            .field("rules", &*self.rules.rules.lock())
+            .finish()
+    }
+}
+
+#[async_trait]
+impl<E: Env> Env for FaultEnv<E> {
+    type PositionalReader = FaultReader<E::PositionalReader>;
+    type SequentialWriter = FaultWriter<E::SequentialWriter>;
+    type JoinHandle<T: Send> = E::JoinHandle<T>;
+    type Directory = E::Directory;
+
+    async fn open_positional_reader<P>(&self, path: P) -> Result<Self::PositionalReader>
+    where
+        P: AsRef<Path> + Send,
+    {
+        let path = path.as_ref().to_owned();
+        self.rules.apply(FaultOp::Open, &path)?;
+        let inner = self.inner.open_positional_reader(&path).await?;
+        Ok(FaultReader {
+            inner,
+            path,
+            rules: self.rules.clone(),
+        })
+    }
+
+    async fn open_sequential_writer<P>(&self, path: P) -> Result<Self::SequentialWriter>
+    where
+        P: AsRef<Path> + Send,
+    {
+        let path = path.as_ref().to_owned();
+        self.rules.apply(FaultOp::Open, &path)?;
+        let inner = self.inner.open_sequential_writer(&path).await?;
+        Ok(FaultWriter {
+            inner,
+            path,
+            rules: self.rules.clone(),
+        })
+    }
+
+    fn spawn_background<F>(&self, f: F) -> Self::JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send,
+    {
+        self.inner.spawn_background(f)
+    }
+
+    fn spawn_background_on<F>(&self, cpus: &[usize], f: F) -> Self::JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send,
+    {
+        self.inner.spawn_background_on(cpus, f)
+    }
+
+    async fn rename<P: AsRef<Path> + Send, Q: AsRef<Path> + Send>(
+        &self,
+        from: P,
+        to: Q,
+    ) -> Result<()> {
+        self.rules.apply(FaultOp::Rename, from.as_ref())?;
+        self.inner.rename(from, to).await
+    }
+
+    async fn remove_file<P: AsRef<Path> + Send>(&self, path: P) -> Result<()> {
+        self.rules.apply(FaultOp::Remove, path.as_ref())?;
+        self.inner.remove_file(path).await
+    }
+
+    async fn create_dir_all<P: AsRef<Path> + Send>(&self, path: P) -> Result<()> {
+        self.inner.create_dir_all(path).await
+    }
+
+    async fn remove_dir_all<P: AsRef<Path> + Send>(&self, path: P) -> Result<()> {
+        self.rules.apply(FaultOp::Remove, path.as_ref())?;
+        self.inner.remove_dir_all(path).await
+    }
+
+    fn read_dir<P: AsRef<Path>>(&self, path: P) -> Result<Vec<PathBuf>> {
+        self.inner.read_dir(path)
+    }
+
+    async fn metadata<P: AsRef<Path> + Send>(&self, path: P) -> Result<Metadata> {
+        self.inner.metadata(path).await
+    }
+
+    async fn canonicalize<P: AsRef<Path> + Send>(&self, path: P) -> Result<PathBuf> {
+        self.inner.canonicalize(path).await
+    }
+
+    async fn open_dir<P: AsRef<Path> + Send>(&self, path: P) -> Result<Self::Directory> {
+        self.inner.open_dir(path).await
+    }
+}
+
+/// A [`PositionalReader`] returned by [`FaultEnv`].
+pub struct FaultReader<R> {
+    inner: R,
+    path: PathBuf,
+    rules: Arc<FaultRules>,
+}
+
+#[async_trait]
+impl<R: PositionalReader> PositionalReader for FaultReader<R> {
+    type ReadAt<'a> = impl Future<Output = Result<usize>> + 'a + Send;
+
+    fn read_at<'a>(&'a self, buf: &'a mut [u8], pos: u64) -> Self::ReadAt<'a> {
+        async move {
+            let len = if self.rules.apply(FaultOp::Read, &self.path)? {
+                (buf.len() / 2).max(1).min(buf.len())
+            } else {
+                buf.len()
+            };
+            self.inner.read_at(&mut buf[..len], pos).await
+        }
+    }
+
+    fn direct_io_ify(&self) -> Result<()> {
+        self.rules.apply(FaultOp::DirectIo, &self.path)?;
+        self.inner.direct_io_ify()
+    }
+}
+
+/// A [`SequentialWriter`] returned by [`FaultEnv`].
+pub struct FaultWriter<W> {
+    inner: W,
+    path: PathBuf,
+    rules: Arc<FaultRules>,
+}
+
+#[async_trait]
+impl<W: SequentialWriter> SequentialWriter for FaultWriter<W> {
+    type Write<'a> = impl Future<Output = Result<usize>> + 'a + Send;
+
+    fn write<'a>(&'a mut self, buf: &'a [u8]) -> Self::Write<'a> {
+        async move {
+            let len = if self.rules.apply(FaultOp::Write, &self.path)? {
+                (buf.len() / 2).max(1).min(buf.len())
+            } else {
+                buf.len()
+            };
+            self.inner.write(&buf[..len]).await
+        }
+    }
+
+    async fn sync_data(&mut self) -> Result<()> {
+        self.rules.apply(FaultOp::Sync, &self.path)?;
+        self.inner.sync_data().await
+    }
+
+    async fn sync_all(&mut self) -> Result<()> {
+        self.rules.apply(FaultOp::Sync, &self.path)?;
+        self.inner.sync_all().await
+    }
+
+    async fn truncate(&self, len: u64) -> Result<()> {
+        self.rules.apply(FaultOp::Write, &self.path)?;
+        self.inner.truncate(len).await
+    }
+
+    fn direct_io_ify(&self) -> Result<()> {
+        self.rules.apply(FaultOp::DirectIo, &self.path)?;
+        self.inner.direct_io_ify()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use futures::executor::block_on;
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn inject_faults() {
+        let base = TempDir::new("inject_faults").unwrap();
+        let good = base.path().join("good");
+        let bad = base.path().join("bad");
+        let env = FaultEnv::new(Std);
+        block_on(async {
+            env.inject(
+                FaultRule::new(FaultOp::Write, Fault::Error(ErrorKind::StorageFull)).on_path("bad"),
+            );
+            env.inject(FaultRule::new(FaultOp::Sync, Fault::Error(ErrorKind::Other)).times(0));
+            env.inject(FaultRule::new(FaultOp::Sync, Fault::Error(ErrorKind::Other)).times(1));
+
+            // Errors are only injected into the matched paths.
+            let mut writer = env.open_sequential_writer(&bad).await.unwrap();
+            let err = writer.write_all(b"hello").await.unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::StorageFull);
+            let mut writer = env.open_sequential_writer(&good).await.unwrap();
+            writer.write_all(b"hello").await.unwrap();
+
+            // Rules with limited times are removed once they are used up.
+            assert!(writer.sync_all().await.is_err());
+            writer.sync_all().await.unwrap();
+            assert_eq!(env.issued(FaultOp::Sync), 2);
+
+            // Short reads and writes make progress.
+            env.clear();
+            env.inject(FaultRule::new(FaultOp::Write, Fault::Short));
+            env.inject(FaultRule::new(FaultOp::Read, Fault::Short));
+            assert_eq!(writer.write(b"world").await.unwrap(), 2);
+            writer.write_all(b"rld").await.unwrap();
+            writer.sync_all().await.unwrap();
+            let reader = env.open_positional_reader(&good).await.unwrap();
+            let mut buf = vec![0; 10];
+            assert_eq!(reader.read_at(&mut buf, 0).await.unwrap(), 5);
+            reader.read_exact_at(&mut buf, 0).await.unwrap();
+            assert_eq!(buf, b"helloworld");
+
+            let delay = Duration::from_millis(10);
+            env.clear();
+            env.inject(FaultRule::new(FaultOp::Read, Fault::Delay(delay)));
+            let start = Instant::now();
+            reader.read_exact_at(&mut buf, 0).await.unwrap();
+            assert!(start.elapsed() >= delay);
+
+            env.inject(
+                FaultRule::new(FaultOp::Open, Fault::Error(ErrorKind::NotFound)).on_path("good"),
+            );
+            assert!(env.open_positional_reader(&good).await.is_err());
+        });
+    }
+}
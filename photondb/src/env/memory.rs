@@ -0,0 +1,326 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    future::Future,
+    io::{Error, ErrorKind, Result},
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+
+use parking_lot::{Mutex, RwLock};
+
+use super::*;
+
+/// An implementation of [`Env`] that keeps files in memory.
+///
+/// Files are shared by the clones of an environment, so a table can be
+/// reopened with a clone to read the files written before. Nothing is
+/// persisted, and direct IO is not supported. Background tasks run on
+/// dedicated threads like [`Std`].
+///
+/// This is used to run tests without touching the filesystem.
+#[derive(Clone, Debug, Default)]
+pub struct Memory {
+    fs: Arc<Mutex<FileSystem>>,
+}
+
+#[derive(Debug, Default)]
+struct FileSystem {
+    files: BTreeMap<PathBuf, Arc<RwLock<Vec<u8>>>>,
+    dirs: BTreeSet<PathBuf>,
+}
+
+impl FileSystem {
+    fn has_parent(&self, path: &Path) -> bool {
+        match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => self.dirs.contains(parent),
+            _ => true,
+        }
+    }
+}
+
+fn not_found(path: &Path) -> Error {
+    Error::new(ErrorKind::NotFound, format!("{} not found", path.display()))
+}
+
+fn direct_io_unsupported() -> Error {
+    Error::new(
+        ErrorKind::Unsupported,
+        "direct io is not supported in memory",
+    )
+}
+
+#[async_trait]
+impl Env for Memory {
+    type PositionalReader = PositionalReader;
+    type SequentialWriter = SequentialWriter;
+    type JoinHandle<T: Send> = <Std as Env>::JoinHandle<T>;
+    type Directory = Directory;
+
+    async fn open_positional_reader<P>(&self, path: P) -> Result<Self::PositionalReader>
+    where
+        P: AsRef<Path> + Send,
+    {
+        let path = path.as_ref();
+        let fs = self.fs.lock();
+        let file = fs.files.get(path).ok_or_else(|| not_found(path))?;
+        Ok(PositionalReader(file.clone()))
+    }
+
+    async fn open_sequential_writer<P>(&self, path: P) -> Result<Self::SequentialWriter>
+    where
+        P: AsRef<Path> + Send,
+    {
+        let path = path.as_ref();
+        let mut fs = self.fs.lock();
+        if !fs.has_parent(path) || fs.dirs.contains(path) {
+            return Err(not_found(path));
+        }
+        // Like `File::create`, an existing file is truncated.
+        let file = fs.files.entry(path.to_owned()).or_default().clone();
+        file.write().clear();
+        Ok(SequentialWriter(file))
+    }
+
+    fn spawn_background<F>(&self, f: F) -> Self::JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send,
+    {
+        Std.spawn_background(f)
+    }
+
+    fn spawn_background_on<F>(&self, cpus: &[usize], f: F) -> Self::JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send,
+    {
+        Std.spawn_background_on(cpus, f)
+    }
+
+    async fn rename<P: AsRef<Path> + Send, Q: AsRef<Path> + Send>(
+        &self,
+        from: P,
+        to: Q,
+    ) -> Result<()> {
+        let (from, to) = (from.as_ref(), to.as_ref());
+        let mut fs = self.fs.lock();
+        if !fs.has_parent(to) {
+            return Err(not_found(to));
+        }
+        let file = fs.files.remove(from).ok_or_else(|| not_found(from))?;
+        fs.files.insert(to.to_owned(), file);
+        Ok(())
+    }
+
+    async fn remove_file<P: AsRef<Path> + Send>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let mut fs = self.fs.lock();
+        fs.files
+            .remove(path)
+            .map(|_| ())
+            .ok_or_else(|| not_found(path))
+    }
+
+    async fn create_dir_all<P: AsRef<Path> + Send>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let mut fs = self.fs.lock();
+        for dir in path.ancestors() {
+            if dir.as_os_str().is_empty() {
+                continue;
+            }
+            if fs.files.contains_key(dir) {
+                return Err(Error::new(
+                    ErrorKind::AlreadyExists,
+                    format!("{} is a file", dir.display()),
+                ));
+            }
+            fs.dirs.insert(dir.to_owned());
+        }
+        Ok(())
+    }
+
+    async fn remove_dir_all<P: AsRef<Path> + Send>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let mut fs = self.fs.lock();
+        if !fs.dirs.contains(path) {
+            return Err(not_found(path));
+        }
+        fs.files.retain(|file, _| !file.starts_with(path));
+        fs.dirs.retain(|dir| !dir.starts_with(path));
+        Ok(())
+    }
+
+    fn read_dir<P: AsRef<Path>>(&self, path: P) -> Result<Vec<PathBuf>> {
+        let path = path.as_ref();
+        let fs = self.fs.lock();
+        if !fs.dirs.contains(path) {
+            return Err(not_found(path));
+        }
+        let entries = fs
+            .files
+            .keys()
+            .chain(fs.dirs.iter())
+            .filter(|entry| entry.parent() == Some(path))
+            .cloned()
+            .collect();
+        Ok(entries)
+    }
+
+    async fn metadata<P: AsRef<Path> + Send>(&self, path: P) -> Result<Metadata> {
+        let path = path.as_ref();
+        let fs = self.fs.lock();
+        if let Some(file) = fs.files.get(path) {
+            return Ok(Metadata {
+                len: file.read().len() as u64,
+                is_dir: false,
+            });
+        }
+        if fs.dirs.contains(path) {
+            return Ok(Metadata {
+                len: 0,
+                is_dir: true,
+            });
+        }
+        Err(not_found(path))
+    }
+
+    async fn canonicalize<P: AsRef<Path> + Send>(&self, path: P) -> Result<PathBuf> {
+        // There are no links in memory, so the path is only normalized.
+        let path = path.as_ref();
+        let mut canonical = PathBuf::new();
+        for component in path.components() {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    canonical.pop();
+                }
+                component => canonical.push(component),
+            }
+        }
+        let fs = self.fs.lock();
+        if !fs.files.contains_key(&canonical) && !fs.dirs.contains(&canonical) {
+            return Err(not_found(path));
+        }
+        Ok(canonical)
+    }
+
+    async fn open_dir<P: AsRef<Path> + Send>(&self, path: P) -> Result<Self::Directory> {
+        let path = path.as_ref();
+        let fs = self.fs.lock();
+        if fs.files.contains_key(path) {
+            return Err(Error::new(ErrorKind::NotADirectory, "not a dir"));
+        }
+        if !fs.dirs.contains(path) {
+            return Err(not_found(path));
+        }
+        Ok(Directory)
+    }
+}
+
+pub struct PositionalReader(Arc<RwLock<Vec<u8>>>);
+
+#[async_trait]
+impl super::PositionalReader for PositionalReader {
+    type ReadAt<'a> = impl Future<Output = Result<usize>> + 'a + Send;
+
+    fn read_at<'a>(&'a self, buf: &'a mut [u8], pos: u64) -> Self::ReadAt<'a> {
+        async move {
+            let data = self.0.read();
+            let start = (pos as usize).min(data.len());
+            let len = buf.len().min(data.len() - start);
+            buf[..len].copy_from_slice(&data[start..start + len]);
+            Ok(len)
+        }
+    }
+
+    fn direct_io_ify(&self) -> Result<()> {
+        Err(direct_io_unsupported())
+    }
+}
+
+pub struct SequentialWriter(Arc<RwLock<Vec<u8>>>);
+
+#[async_trait]
+impl super::SequentialWriter for SequentialWriter {
+    type Write<'a> = impl Future<Output = Result<usize>> + 'a + Send;
+
+    fn write<'a>(&'a mut self, buf: &'a [u8]) -> Self::Write<'a> {
+        async move {
+            self.0.write().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+    }
+
+    async fn sync_data(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn sync_all(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn truncate(&self, len: u64) -> Result<()> {
+        self.0.write().resize(len as usize, 0);
+        Ok(())
+    }
+
+    fn direct_io_ify(&self) -> Result<()> {
+        Err(direct_io_unsupported())
+    }
+}
+
+pub struct Directory;
+
+#[async_trait]
+impl super::Directory for Directory {
+    async fn sync_all(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::*;
+
+    #[test]
+    fn memory_files() {
+        let env = Memory::default();
+        block_on(async {
+            let dir = Path::new("/db");
+            assert!(env.open_sequential_writer(dir.join("a")).await.is_err());
+            env.create_dir_all(dir).await.unwrap();
+            assert!(env.metadata("/").await.unwrap().is_dir);
+
+            let mut writer = env.open_sequential_writer(dir.join("a")).await.unwrap();
+            writer.write_all(b"hello world").await.unwrap();
+            writer.truncate(5).await.unwrap();
+            assert_eq!(env.metadata(dir.join("a")).await.unwrap().len, 5);
+
+            // Files are shared by clones, and readers follow renames.
+            let clone = env.clone();
+            let reader = clone.open_positional_reader(dir.join("a")).await.unwrap();
+            env.rename(dir.join("a"), dir.join("b")).await.unwrap();
+            let mut buf = vec![0; 5];
+            reader.read_exact_at(&mut buf, 0).await.unwrap();
+            assert_eq!(buf, b"hello");
+            assert!(reader.read_exact_at(&mut buf, 1).await.is_err());
+            assert!(env.open_positional_reader(dir.join("a")).await.is_err());
+
+            env.create_dir_all(dir.join("sub")).await.unwrap();
+            let path = env.canonicalize("/db/./sub/../b").await.unwrap();
+            assert_eq!(path, dir.join("b"));
+            assert!(env.canonicalize("/db/c").await.is_err());
+            let mut entries = env.read_dir(dir).unwrap();
+            entries.sort();
+            assert_eq!(entries, [dir.join("b"), dir.join("sub")]);
+
+            env.remove_file(dir.join("b")).await.unwrap();
+            assert!(env.remove_file(dir.join("b")).await.is_err());
+            env.remove_dir_all(dir).await.unwrap();
+            assert!(env.read_dir(dir).is_err());
+            assert!(env.open_dir(dir).await.is_err());
+        });
+    }
+}
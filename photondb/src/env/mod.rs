@@ -1,6 +1,10 @@
 //! Environments for PhotonDB to interact with different runtimes and platforms.
 
-use std::{future::Future, io::Result, path::Path};
+use std::{
+    future::Future,
+    io::Result,
+    path::{Path, PathBuf},
+};
 
 pub use async_trait::async_trait;
 pub use photonio::io::{Read, ReadAt, Write, WriteAt};
@@ -11,6 +15,14 @@ pub use stdenv::Std;
 mod photon;
 pub use photon::Photon;
 
+mod memory;
+pub use memory::Memory;
+
+#[cfg(any(test, feature = "fault-injection"))]
+mod fault;
+#[cfg(any(test, feature = "fault-injection"))]
+pub use fault::{Fault, FaultEnv, FaultOp, FaultReader, FaultRule, FaultWriter};
+
 /// Provides an environment to interact with a specific platform.
 #[async_trait]
 pub trait Env: Clone + Send + Sync + 'static {
@@ -39,6 +51,19 @@ pub trait Env: Clone + Send + Sync + 'static {
         F: Future + Send + 'static,
         F::Output: Send;
 
+    /// Spawns a task to run in the background on the given CPUs.
+    ///
+    /// This is only supported by environments that run background tasks on
+    /// dedicated threads. The default implementation ignores the CPUs.
+    fn spawn_background_on<F>(&self, cpus: &[usize], f: F) -> Self::JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send,
+    {
+        let _ = cpus;
+        self.spawn_background(f)
+    }
+
     /// An async version of [`std::fs::rename`].
     async fn rename<P: AsRef<Path> + Send, Q: AsRef<Path> + Send>(
         &self,
@@ -59,16 +84,23 @@ pub trait Env: Clone + Send + Sync + 'static {
     /// See also [`std::fs::remove_dir_all`].
     async fn remove_dir_all<P: AsRef<Path> + Send>(&self, path: P) -> Result<()>;
 
-    /// Returns an iterator over the entries within a directory.
-    /// See also [`std::fs::read_dir`].
+    /// Returns the paths of the entries within a directory.
+    ///
+    /// The paths are joined with the given path, and are not in any
+    /// particular order. See also [`std::fs::read_dir`].
     /// TODO: async iterator impl?
-    fn read_dir<P: AsRef<Path>>(&self, path: P) -> Result<std::fs::ReadDir>;
+    fn read_dir<P: AsRef<Path>>(&self, path: P) -> Result<Vec<PathBuf>>;
 
     /// Given a path, query the file system to get information about a file,
     /// directory, etc.
     /// See also [`std::fs::metadata`].
     async fn metadata<P: AsRef<Path> + Send>(&self, path: P) -> Result<Metadata>;
 
+    /// Returns the canonical, absolute form of a path with all intermediate
+    /// components normalized and symbolic links resolved.
+    /// See also [`std::fs::canonicalize`].
+    async fn canonicalize<P: AsRef<Path> + Send>(&self, path: P) -> Result<PathBuf>;
+
     /// Open the directory.
     async fn open_dir<P: AsRef<Path> + Send>(&self, path: P) -> Result<Self::Directory>;
 }
@@ -227,6 +259,49 @@ pub(in crate::env) fn direct_io_ify(_: i32) -> Result<()> {
     ))
 }
 
+pub(in crate::env) fn read_dir_paths<P: AsRef<Path>>(path: P) -> Result<Vec<PathBuf>> {
+    std::fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect()
+}
+
+/// Returns true if the CPU can be set in the affinity of a thread.
+#[cfg(target_os = "linux")]
+pub(crate) fn is_valid_cpu(cpu: usize) -> bool {
+    cpu < libc::CPU_SETSIZE as usize
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn is_valid_cpu(_: usize) -> bool {
+    true
+}
+
+#[cfg(target_os = "linux")]
+pub(in crate::env) fn set_thread_affinity(cpus: &[usize]) -> Result<()> {
+    if cpus.is_empty() || !cpus.iter().all(|&cpu| is_valid_cpu(cpu)) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("invalid cpu set {cpus:?}"),
+        ));
+    }
+    // Safety: `cpu_set_t` is a plain bitmask, and the CPUs are within it.
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    for &cpu in cpus {
+        unsafe { libc::CPU_SET(cpu, &mut set) };
+    }
+    let res = unsafe { libc::sched_setaffinity(0, std::mem::size_of_val(&set), &set) };
+    if res == -1 {
+        Err(std::io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(in crate::env) fn set_thread_affinity(_: &[usize]) -> Result<()> {
+    Ok(())
+}
+
 /// A handle to an opened directory.
 #[async_trait]
 pub trait Directory {
@@ -48,6 +48,17 @@ impl Env for Photon {
         JoinHandle { handle }
     }
 
+    /// Background tasks run on the threads of the PhotonIO runtime, which
+    /// can't be pinned to other CPUs, so the CPUs are ignored with a warning.
+    fn spawn_background_on<F>(&self, cpus: &[usize], f: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send,
+    {
+        log::warn!("ignore cpu set {cpus:?}, which is not supported by the photon env");
+        self.spawn_background(f)
+    }
+
     /// An async version of [`std::fs::rename`].
     async fn rename<P: AsRef<Path> + Send, Q: AsRef<Path> + Send>(
         &self,
@@ -72,10 +83,10 @@ impl Env for Photon {
         std::fs::remove_dir_all(path) // TODO: async impl
     }
 
-    /// Returns an iterator over the entries within a directory.
+    /// Returns the paths of the entries within a directory.
     /// See also [`std::fs::read_dir`].
-    fn read_dir<P: AsRef<Path>>(&self, path: P) -> Result<std::fs::ReadDir> {
-        std::fs::read_dir(path)
+    fn read_dir<P: AsRef<Path>>(&self, path: P) -> Result<Vec<PathBuf>> {
+        read_dir_paths(path)
     }
 
     async fn metadata<P: AsRef<Path> + Send>(&self, path: P) -> Result<Metadata> {
@@ -89,6 +100,11 @@ impl Env for Photon {
         Ok(metadata)
     }
 
+    /// An async version of [`std::fs::canonicalize`].
+    async fn canonicalize<P: AsRef<Path> + Send>(&self, path: P) -> Result<PathBuf> {
+        std::fs::canonicalize(path) // TODO: async impl
+    }
+
     async fn open_dir<P: AsRef<Path> + Send>(&self, path: P) -> Result<Self::Directory> {
         let file = File::open(path).await?;
         if !file.metadata().await?.is_dir() {
@@ -9,6 +9,7 @@ use std::{
 };
 
 use futures::executor::block_on;
+use log::warn;
 
 use super::*;
 
@@ -48,6 +49,23 @@ impl Env for Std {
         }
     }
 
+    fn spawn_background_on<F>(&self, cpus: &[usize], f: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send,
+    {
+        let cpus = cpus.to_owned();
+        let handle = thread::spawn(move || {
+            if let Err(err) = super::set_thread_affinity(&cpus) {
+                warn!("set background thread affinity to {cpus:?}: {err}");
+            }
+            block_on(f)
+        });
+        JoinHandle {
+            handle: Some(handle),
+        }
+    }
+
     /// An async version of [`std::fs::rename`].
     async fn rename<P: AsRef<Path> + Send, Q: AsRef<Path> + Send>(
         &self,
@@ -72,10 +90,10 @@ impl Env for Std {
         std::fs::remove_dir_all(path)
     }
 
-    /// Returns an iterator over the entries within a directory.
+    /// Returns the paths of the entries within a directory.
     /// See alos [`std::fs::read_dir`].
-    fn read_dir<P: AsRef<Path>>(&self, path: P) -> Result<std::fs::ReadDir> {
-        std::fs::read_dir(path)
+    fn read_dir<P: AsRef<Path>>(&self, path: P) -> Result<Vec<PathBuf>> {
+        read_dir_paths(path)
     }
 
     async fn metadata<P: AsRef<Path> + Send>(&self, path: P) -> Result<Metadata> {
@@ -86,6 +104,11 @@ impl Env for Std {
         })
     }
 
+    /// An async version of [`std::fs::canonicalize`].
+    async fn canonicalize<P: AsRef<Path> + Send>(&self, path: P) -> Result<PathBuf> {
+        std::fs::canonicalize(path)
+    }
+
     async fn open_dir<P: AsRef<Path> + Send>(&self, path: P) -> Result<Self::Directory> {
         let file = File::open(path)?;
         if !file.metadata()?.is_dir() {
@@ -167,3 +190,30 @@ impl super::Directory for Directory {
         self.0.sync_all()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn spawn_background_on_cpus() {
+        fn current_cpus() -> Vec<usize> {
+            let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+            let res = unsafe { libc::sched_getaffinity(0, std::mem::size_of_val(&set), &mut set) };
+            assert_eq!(res, 0);
+            (0..libc::CPU_SETSIZE as usize)
+                .filter(|&cpu| unsafe { libc::CPU_ISSET(cpu, &set) })
+                .collect()
+        }
+
+        let cpus = current_cpus();
+        let target = vec![cpus[0]];
+        let handle = Std.spawn_background_on(&target, async { current_cpus() });
+        assert_eq!(block_on(handle), target);
+
+        // Threads spawned without CPUs are not affected.
+        let handle = Std.spawn_background(async { current_cpus() });
+        assert_eq!(block_on(handle), cpus);
+    }
+}
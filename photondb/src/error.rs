@@ -5,6 +5,9 @@ use crate::page_store::Error as PageError;
 /// A list of possible errors returned by PhotonDB.
 #[derive(Error, Debug)]
 pub enum Error {
+    /// Some arguments are invalid.
+    #[error("InvalidArgument")]
+    InvalidArgument,
     /// Some data is corrupted.
     #[error("Corrupted")]
     Corrupted,
@@ -14,14 +17,34 @@ pub enum Error {
     /// Put data is too large.
     #[error("TooLargeSize")]
     TooLargeSize,
+    /// The read LSN is below the safe LSN, so the versions visible to it may
+    /// have been dropped.
+    #[error("SnapshotTooOld")]
+    SnapshotTooOld,
+    /// The keys read by an optimistic transaction have changed, so the
+    /// transaction can't be committed.
+    #[error("Conflict")]
+    Conflict,
+    /// The table is already open in this process.
+    #[error("AlreadyOpen")]
+    AlreadyOpen,
+    /// The table has open snapshots, so it can't be swapped.
+    #[error("SnapshotOpen")]
+    SnapshotOpen,
+    /// An IO operation failed.
+    #[error("IO {0}")]
+    Io(#[from] std::io::Error),
 }
 
 impl From<PageError> for Error {
     fn from(err: PageError) -> Self {
         match err {
             PageError::Corrupted => Self::Corrupted,
+            PageError::InvalidArgument => Self::InvalidArgument,
             PageError::MemoryLimit => Self::MemoryLimit,
             PageError::TooLargeSize => Self::TooLargeSize,
+            PageError::SnapshotTooOld => Self::SnapshotTooOld,
+            PageError::Io(err) => Self::Io(err),
             e => unreachable!("unexpected error: {:?}", e),
         }
     }
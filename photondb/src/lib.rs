@@ -35,6 +35,7 @@
 
 pub mod env;
 pub mod raw;
+pub use raw::WriteBatch;
 pub mod std;
 
 pub mod photon;
@@ -44,11 +45,15 @@ mod error;
 pub use error::{Error, Result};
 
 mod tree;
-pub use tree::{Options as TableOptions, PageIter, ReadOptions, TreeStats, WriteOptions};
+pub use tree::{
+    ChangeKind, Durability, Isolation, MergeOperator, Options as TableOptions, PageIter,
+    ReadOptions, ScanOptions, SplitPolicy, TreeStats, WriteOptions,
+};
 
 mod page_store;
 pub use page_store::{
-    ChecksumType, Compression, FlushOptions, Options as PageStoreOptions, StoreStats,
+    CachePolicy, CacheStats, ChecksumFailure, ChecksumReport, ChecksumType, Compression,
+    FlushOptions, HealthCheckOptions, Options as PageStoreOptions, StoreStats,
 };
 
 mod page;
@@ -56,15 +61,34 @@ mod util;
 
 #[cfg(test)]
 mod tests {
+    use ::std::{ops::Bound, sync::Arc, time::Duration};
     use rand::random;
     use tempfile::tempdir;
 
     use super::*;
-    use crate::page_store::{ChecksumType, Compression};
+    use crate::{
+        page_store::{ChecksumType, Compression},
+        util::timer::unix_timestamp,
+    };
 
     const OPTIONS: TableOptions = TableOptions {
         page_size: 128,
-        page_chain_length: 4,
+        data_chain_length: 4,
+        index_chain_length: 2,
+        bloom_fp_rate: 0.01,
+        partial_consolidation_percent: 50,
+        split_policy: SplitPolicy::Count,
+        disable_background: false,
+        initial_leaf_count: 1,
+        initial_split_keys: Vec::new(),
+        read_io_depth: 1,
+        retain_versions: 0,
+        skip_tombstone_for_absent: false,
+        dedup_identical_puts: false,
+        dedup_keeps_versions: false,
+        reject_reads_below_safe_lsn: false,
+        share_open_tables: false,
+        merge_operator: None,
         page_store: PageStoreOptions {
             write_buffer_capacity: 1 << 20,
             max_write_buffers: 8,
@@ -73,15 +97,32 @@ mod tests {
             max_space_amplification_percent: 10,
             space_used_high: u64::MAX,
             file_base_size: 1 << 20,
+            max_concurrent_compactions: 1,
+            compact_in_key_order: false,
+            compaction_throttle_ops_per_sec: u64::MAX,
+            compaction_idle_only: false,
             cache_capacity: 2 << 10,
+            cache_policy: CachePolicy::Lru,
             cache_estimated_entry_charge: 1,
             cache_file_reader_capacity: 1000,
+            cache_async_insert_queue_size: 0,
             cache_strict_capacity_limit: false,
             prepopulate_cache_on_flush: true,
             compression_on_flush: Compression::SNAPPY,
             compression_on_cold_compact: Compression::ZSTD,
             page_checksum_type: ChecksumType::CRC32,
+            disable_checksum_verification: false,
+            persist_cache_warmset: false,
             avoid_flush_during_shutdown: false,
+            background_cpu_set: None,
+            page_file_dir_shards: 0,
+            health_check: HealthCheckOptions {
+                interval: None,
+                warn_interval: Duration::from_secs(600),
+                min_cache_hit_ratio: 0.5,
+                max_space_amplification_percent: 300,
+                max_detached_cache_percent: 10,
+            },
         },
     };
 
@@ -124,6 +165,639 @@ mod tests {
         table.close().await.unwrap();
     }
 
+    #[photonio::test]
+    async fn direct_io() {
+        // Falls back to buffered IO if the files reject direct IO with EINVAL.
+        let path = tempdir().unwrap();
+        let env = env::FaultEnv::new(env::Std);
+        env.inject(env::FaultRule::new(
+            env::FaultOp::DirectIo,
+            env::Fault::Error(::std::io::ErrorKind::InvalidInput),
+        ));
+        let options = TableOptions {
+            page_store: PageStoreOptions {
+                use_direct_io: true,
+                ..OPTIONS.page_store
+            },
+            ..OPTIONS
+        };
+        let table = raw::Table::open(env.clone(), &path, options.clone())
+            .await
+            .unwrap();
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            let buf = i.to_be_bytes();
+            table.put(&buf, i, &buf).await.unwrap();
+        }
+        table.flush(&FlushOptions::default()).await;
+        table.close().await.unwrap();
+        let issued = env.issued(env::FaultOp::DirectIo);
+        assert!(issued > 0);
+
+        let table = raw::Table::open(env.clone(), &path, options).await.unwrap();
+        for i in 0..N {
+            let buf = i.to_be_bytes();
+            assert_eq!(table.get(&buf, i).await.unwrap(), Some(buf.to_vec()));
+        }
+        table.close().await.unwrap();
+        assert!(env.issued(env::FaultOp::DirectIo) > issued);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[photonio::test]
+    async fn background_cpu_set() {
+        // Returns the CPUs of each thread of the process.
+        fn thread_cpus() -> Vec<Vec<usize>> {
+            let mut cpus = Vec::new();
+            let tids = ::std::fs::read_dir("/proc/self/task")
+                .unwrap()
+                .filter_map(|e| e.ok()?.file_name().to_str()?.parse::<libc::pid_t>().ok());
+            for tid in tids {
+                let mut set: libc::cpu_set_t = unsafe { ::std::mem::zeroed() };
+                let size = ::std::mem::size_of_val(&set);
+                // The thread may have exited.
+                if unsafe { libc::sched_getaffinity(tid, size, &mut set) } != 0 {
+                    continue;
+                }
+                cpus.push(
+                    (0..libc::CPU_SETSIZE as usize)
+                        .filter(|&cpu| unsafe { libc::CPU_ISSET(cpu, &set) })
+                        .collect(),
+                );
+            }
+            cpus
+        }
+
+        let path = tempdir().unwrap();
+        let with_cpus = |cpus: Vec<usize>| TableOptions {
+            page_store: PageStoreOptions {
+                background_cpu_set: Some(cpus),
+                ..OPTIONS.page_store
+            },
+            ..OPTIONS
+        };
+        for cpus in [vec![], vec![libc::CPU_SETSIZE as usize]] {
+            let res = raw::Table::open(env::Std, &path, with_cpus(cpus)).await;
+            assert!(matches!(res, Err(Error::InvalidArgument)));
+        }
+
+        let all = thread_cpus()
+            .into_iter()
+            .max_by_key(|cpus| cpus.len())
+            .unwrap();
+        let target = vec![all[0]];
+        let table = raw::Table::open(env::Std, &path, with_cpus(target.clone()))
+            .await
+            .unwrap();
+        for i in 0..1024u64 {
+            let buf = i.to_be_bytes();
+            table.put(&buf, i, &buf).await.unwrap();
+        }
+        // The background threads of the table are pinned to the target CPU.
+        if all.len() > 1 {
+            assert!(thread_cpus().contains(&target));
+        }
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn page_size_smaller_than_entry() {
+        let path = tempdir().unwrap();
+        let options = TableOptions {
+            page_size: 8,
+            ..OPTIONS
+        };
+        let table = Table::open(&path, options).await.unwrap();
+        // Every page is over-large, and pages with a single key can't be split.
+        const N: u64 = 16;
+        let value = |i: u64| vec![i as u8; 100];
+        for i in 0..N {
+            table.put(&i.to_be_bytes(), i, &value(i)).await.unwrap();
+        }
+        for i in 0..N {
+            let got = table.get(&i.to_be_bytes(), i).await.unwrap();
+            assert_eq!(got, Some(value(i)));
+        }
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn scan_stream() {
+        use futures::StreamExt;
+
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, OPTIONS).await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in (0..N).rev() {
+            must_put(&table, i, 0).await;
+        }
+
+        let guard = table.pin();
+        let entries: Vec<_> = guard.pages().into_stream().take(10).collect().await;
+        let keys: Vec<_> = entries.into_iter().map(|e| e.unwrap().0).collect();
+        let expect: Vec<_> = (0..10u64).map(|i| i.to_be_bytes().to_vec()).collect();
+        assert_eq!(keys, expect);
+
+        let count = guard.pages().into_stream().count().await;
+        assert_eq!(count as u64, N);
+        drop(guard);
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn get_with_mem_page() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, OPTIONS).await.unwrap();
+        let key = 1u64.to_be_bytes();
+        let value = vec![7u8; 64 << 10];
+        table.put(&key, 1, &value).await.unwrap();
+
+        // Values in memory pages are handed out without any copy, so they
+        // should point to the same memory as the pinned value.
+        let guard = table.pin();
+        let pinned = guard.get(&key, 1).await.unwrap().unwrap();
+        assert_eq!(pinned, &value[..]);
+        let ptr = table
+            .get_with(&key, 1, |v| v.map(|v| v.as_ptr()))
+            .await
+            .unwrap();
+        assert_eq!(ptr, Some(pinned.as_ptr()));
+        let ptr = table
+            .get_with(&2u64.to_be_bytes(), 1, |v| v.map(|v| v.as_ptr()))
+            .await
+            .unwrap();
+        assert_eq!(ptr, None);
+        drop(guard);
+
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn initial_leaves() {
+        async fn load(options: TableOptions, keys: &[u64]) -> u64 {
+            let path = tempdir().unwrap();
+            let table = Table::open(&path, options).await.unwrap();
+            for &i in keys {
+                must_put(&table, i, 0).await;
+            }
+            for &i in keys {
+                must_get(&table, i, 0, Some(i)).await;
+            }
+            let splits = table.stats().tree.success.split_page;
+            table.close().await.unwrap();
+            splits
+        }
+
+        const N: u64 = 1 << 12;
+        // Keys distributed uniformly in the key space.
+        let keys = (0..N).map(|i| i << 52).collect::<Vec<_>>();
+        let default_splits = load(OPTIONS, &keys).await;
+        let options = TableOptions {
+            initial_leaf_count: 16,
+            ..OPTIONS
+        };
+        assert!(load(options, &keys).await < default_splits);
+
+        let keys = (0..N).collect::<Vec<_>>();
+        let default_splits = load(OPTIONS, &keys).await;
+        let options = TableOptions {
+            initial_split_keys: (1..16)
+                .map(|i| (i * N / 16).to_be_bytes().to_vec())
+                .collect(),
+            ..OPTIONS
+        };
+        assert!(load(options, &keys).await < default_splits);
+
+        let path = tempdir().unwrap();
+        for options in [
+            TableOptions {
+                initial_leaf_count: 0,
+                ..OPTIONS
+            },
+            TableOptions {
+                initial_split_keys: vec![vec![2], vec![1]],
+                ..OPTIONS
+            },
+            TableOptions {
+                initial_split_keys: vec![vec![]],
+                ..OPTIONS
+            },
+        ] {
+            assert!(matches!(
+                Table::open(&path, options).await,
+                Err(Error::InvalidArgument)
+            ));
+        }
+    }
+
+    #[photonio::test]
+    async fn page_filter() {
+        let path = tempdir().unwrap();
+        let options = TableOptions {
+            page_size: 1 << 20,
+            disable_background: true,
+            ..OPTIONS
+        };
+        let table = Table::open(&path, options).await.unwrap();
+        // Builds a base page with even keys, and a few delta pages on it.
+        const N: u64 = 1 << 10;
+        for i in (0..N).step_by(2) {
+            must_put(&table, i, 0).await;
+        }
+        table.compact_range(&[], None).await.unwrap();
+        for i in (N..N + 8).step_by(2) {
+            must_put(&table, i, 0).await;
+        }
+
+        let stats = table.stats().tree;
+        for i in (1..N).step_by(2) {
+            must_get(&table, i, 0, None).await;
+        }
+        let skipped = table.stats().tree.sub(&stats).success.skip_page;
+        // Most lookups of absent keys should skip the base page.
+        assert!(skipped >= N / 4, "skipped {skipped} pages");
+
+        for i in (0..N + 8).step_by(2) {
+            must_get(&table, i, 0, Some(i)).await;
+        }
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn write_durability() {
+        let path = tempdir().unwrap();
+        let env = env::FaultEnv::new(env::Std);
+        let table = raw::Table::open(env.clone(), &path, OPTIONS).await.unwrap();
+        let flush_bytes = |table: &raw::Table<_>| table.stats().store.jobs.flush_write_bytes;
+        let syncs = || env.issued(env::FaultOp::Sync);
+
+        // Writes in memory return without any IO.
+        let synced = syncs();
+        let options = WriteOptions {
+            durability: Durability::Memory,
+            ..Default::default()
+        };
+        let buf = 1u64.to_be_bytes();
+        table
+            .put_with_options(&buf, 0, &buf, &options)
+            .await
+            .unwrap();
+        assert_eq!(flush_bytes(&table), 0);
+        assert_eq!(syncs(), synced);
+
+        for (i, durability) in [(2u64, Durability::Flushed), (3, Durability::Synced)] {
+            let flushed = flush_bytes(&table);
+            let synced = syncs();
+            let options = WriteOptions {
+                durability,
+                ..Default::default()
+            };
+            let buf = i.to_be_bytes();
+            table
+                .put_with_options(&buf, 0, &buf, &options)
+                .await
+                .unwrap();
+            assert!(flush_bytes(&table) > flushed, "{durability:?}");
+            if durability == Durability::Synced {
+                assert!(syncs() > synced);
+            }
+        }
+        for i in 1..=3 {
+            let buf = i.to_be_bytes();
+            let value = table.get(&buf, 0).await.unwrap();
+            assert_eq!(value, Some(buf.to_vec()));
+        }
+
+        let synced = syncs();
+        let options = WriteOptions {
+            durability: Durability::Synced,
+            ..Default::default()
+        };
+        let buf = 1u64.to_be_bytes();
+        table.delete_with_options(&buf, 1, &options).await.unwrap();
+        assert!(syncs() > synced);
+        assert_eq!(table.get(&buf, 1).await.unwrap(), None);
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn skip_tombstone_for_absent() {
+        for skip in [true, false] {
+            let path = tempdir().unwrap();
+            let options = TableOptions {
+                skip_tombstone_for_absent: skip,
+                ..OPTIONS
+            };
+            let table = Table::open(&path, options).await.unwrap();
+            must_put(&table, 1, 1).await;
+
+            let stats = table.stats().tree;
+            table.delete(&2u64.to_be_bytes(), 2).await.unwrap();
+            let stats = table.stats().tree.sub(&stats).success;
+            if skip {
+                assert_eq!(stats.write, 0);
+                assert_eq!(stats.write_bytes, 0);
+                assert_eq!(stats.skip_write, 1);
+            } else {
+                assert_eq!(stats.write, 1);
+                assert_eq!(stats.skip_write, 0);
+            }
+
+            // Deletes of present keys are always written.
+            let key = 1u64.to_be_bytes();
+            let stats = table.stats().tree;
+            table.delete(&key, 2).await.unwrap();
+            // The key is absent after it is deleted.
+            table.delete(&key, 3).await.unwrap();
+            let stats = table.stats().tree.sub(&stats).success;
+            assert_eq!(stats.write, if skip { 1 } else { 2 });
+            must_get(&table, 1, 1, Some(1)).await;
+            must_get(&table, 1, 3, None).await;
+            table.close().await.unwrap();
+        }
+    }
+
+    #[photonio::test]
+    async fn index_chain_length() {
+        let mut consolidations = vec![];
+        for index_chain_length in [1, 64] {
+            let path = tempdir().unwrap();
+            let options = TableOptions {
+                page_size: 256,
+                data_chain_length: 4,
+                index_chain_length,
+                ..OPTIONS
+            };
+            let table = Table::open(&path, options).await.unwrap();
+            const N: u64 = 1 << 10;
+            for i in 0..N {
+                must_put(&table, i, 0).await;
+            }
+            for i in 0..N {
+                must_get(&table, i, 0, Some(i)).await;
+            }
+            consolidations.push(table.stats().tree.success.consolidate_page);
+            table.close().await.unwrap();
+        }
+        // Short index chains consolidate inner pages more often.
+        assert!(consolidations[0] > consolidations[1], "{consolidations:?}");
+    }
+
+    #[photonio::test]
+    async fn partial_consolidation() {
+        for percent in [50, 0] {
+            let path = tempdir().unwrap();
+            let options = TableOptions {
+                page_size: 1 << 20,
+                disable_background: true,
+                partial_consolidation_percent: percent,
+                ..OPTIONS
+            };
+            let table = Table::open(&path, options).await.unwrap();
+            // Builds a large base page with two tiny delta pages on it.
+            const N: u64 = 1 << 10;
+            for i in 0..N {
+                must_put(&table, i, 0).await;
+            }
+            table.compact_range(&[], None).await.unwrap();
+            let base_bytes = table.stats().tree.success.consolidate_bytes;
+            must_put(&table, N, 0).await;
+            must_put(&table, N + 1, 0).await;
+
+            let stats = table.stats().tree;
+            table.compact_range(&[], None).await.unwrap();
+            let stats = table.stats().tree.sub(&stats).success;
+            assert_eq!(stats.consolidate_page, 1);
+            if percent > 0 {
+                // Only the deltas are merged, the base page is kept.
+                assert!(stats.consolidate_bytes * 100 < base_bytes);
+            } else {
+                assert!(stats.consolidate_bytes > base_bytes);
+            }
+            for i in 0..N + 2 {
+                must_get(&table, i, 0, Some(i)).await;
+            }
+            table.close().await.unwrap();
+        }
+    }
+
+    #[photonio::test]
+    async fn compact_range_consolidates_deltas() {
+        let path = tempdir().unwrap();
+        let options = TableOptions {
+            disable_background: true,
+            partial_consolidation_percent: 0,
+            ..OPTIONS
+        };
+        let table = Table::open(&path, options).await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            must_put(&table, i, 0).await;
+        }
+        // Splits leave delta pages behind, so repeat until all the chains are
+        // flattened.
+        let mut rounds = 0;
+        while table.compact_range(&[], None).await.unwrap() > 0 {
+            rounds += 1;
+            assert!(rounds < 16);
+        }
+
+        // Adds a delta page to the first and the last leaf pages. The old
+        // versions are dropped by consolidation, so the pages are not split.
+        must_put(&table, 0, 1).await;
+        must_put(&table, N - 1, 1).await;
+        table.set_safe_lsn(1);
+        let first = table
+            .compact_range(&[], Some(&1u64.to_be_bytes()))
+            .await
+            .unwrap();
+        assert_eq!(first, 1);
+        assert_eq!(table.compact_range(&[], None).await.unwrap(), 1);
+        assert_eq!(table.compact_range(&[], None).await.unwrap(), 0);
+        for i in 0..N {
+            must_get(&table, i, 1, Some(i)).await;
+        }
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn ingest_sorted_entries() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, OPTIONS).await.unwrap();
+        const N: u64 = 1 << 12;
+        let keys: Vec<_> = (0..N).map(|i| i.to_be_bytes()).collect();
+        let entries = keys.iter().map(|k| (&k[..], &k[..]));
+
+        // Unsorted and duplicated keys are rejected without any writes.
+        let unsorted = entries.clone().rev();
+        assert!(matches!(
+            table.ingest(unsorted, 1).await,
+            Err(Error::InvalidArgument)
+        ));
+        let duplicated = entries.clone().chain(entries.clone().take(1));
+        assert!(matches!(
+            table.ingest(duplicated, 1).await,
+            Err(Error::InvalidArgument)
+        ));
+        must_get(&table, 0, 1, None).await;
+
+        // Entries are installed as leaf pages without delta pages.
+        table.ingest(entries, 1).await.unwrap();
+        assert_eq!(table.stats().tree.success.write, 0);
+        for i in 0..N {
+            must_get(&table, i, 1, Some(i)).await;
+        }
+
+        // Entries are written as delta pages of many entries if the table is
+        // not empty.
+        let keys: Vec<_> = (N..N * 2).map(|i| i.to_be_bytes()).collect();
+        let entries = keys.iter().map(|k| (&k[..], &k[..]));
+        table.ingest(entries, 2).await.unwrap();
+        assert_eq!(table.stats().tree.success.write, N);
+        let mut cursor = table.scan(&[], None, 2);
+        for i in 0..N * 2 {
+            let (k, v) = cursor.next().await.unwrap().unwrap();
+            assert_eq!(k, i.to_be_bytes());
+            assert_eq!(v, i.to_be_bytes());
+        }
+        assert!(cursor.next().await.unwrap().is_none());
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn ingest_beyond_write_buffer() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, OPTIONS).await.unwrap();
+        const N: u64 = 1 << 17;
+        // The keys and values alone take more than one write buffer.
+        assert!(N * 16 > OPTIONS.page_store.write_buffer_capacity as u64);
+        let keys: Vec<_> = (0..N).map(|i| i.to_be_bytes()).collect();
+        let entries = keys.iter().map(|k| (&k[..], &k[..]));
+        table.ingest(entries, 1).await.unwrap();
+
+        // All pages are built directly, without any delta pages.
+        let stats = table.stats().tree.success;
+        assert_eq!(stats.write, 0);
+        assert_eq!(stats.consolidate_page, 0);
+        assert_eq!(stats.split_page, 0);
+        for i in 0..N {
+            must_get(&table, i, 1, Some(i)).await;
+        }
+        table.close().await.unwrap();
+
+        let table = Table::open(&path, OPTIONS).await.unwrap();
+        for i in 0..N {
+            must_get(&table, i, 1, Some(i)).await;
+        }
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn get_pinned_during_consolidation() {
+        let path = tempdir().unwrap();
+        let options = TableOptions {
+            disable_background: true,
+            ..OPTIONS
+        };
+        let table = Table::open(&path, options).await.unwrap();
+        let key = 0u64.to_be_bytes();
+        let value = vec![7u8; 4 << 10];
+        table.put(&key, 0, &value).await.unwrap();
+        let pinned = table.get_pinned(&key, 0).await.unwrap().unwrap();
+        assert!(table
+            .get_pinned(&1u64.to_be_bytes(), 0)
+            .await
+            .unwrap()
+            .is_none());
+
+        // Overwrites and consolidates the page that contains the pinned value.
+        let writer = {
+            let table = table.clone();
+            photonio::task::spawn(async move {
+                for lsn in 1..64 {
+                    table.put(&key, lsn, &[lsn as u8; 16]).await.unwrap();
+                    table.set_safe_lsn(lsn);
+                    table.compact_range(&[], None).await.unwrap();
+                }
+            })
+        };
+        writer.await.unwrap();
+        assert_eq!(&*pinned, value.as_slice());
+        drop(pinned);
+        let latest = table.get(&key, u64::MAX).await.unwrap();
+        assert_eq!(latest, Some(vec![63; 16]));
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn page_key_span() {
+        let path = tempdir().unwrap();
+        let options = TableOptions {
+            page_size: 1 << 20,
+            disable_background: true,
+            ..OPTIONS
+        };
+        let table = Table::open(&path, options).await.unwrap();
+        // Builds a chain of delta pages with disjoint key spans.
+        const N: u64 = 8;
+        for i in 0..N {
+            must_put(&table, i * 2, 0).await;
+        }
+
+        // Every page, including the empty base page, is skipped for keys outside all
+        // of the spans.
+        let stats = table.stats().tree;
+        must_get(&table, N * 2, 0, None).await;
+        let skipped = table.stats().tree.sub(&stats).success.skip_page;
+        assert_eq!(skipped, N + 1);
+
+        // Every delta page but the matched one is skipped for present keys.
+        let stats = table.stats().tree;
+        must_get(&table, 0, 0, Some(0)).await;
+        let skipped = table.stats().tree.sub(&stats).success.skip_page;
+        assert_eq!(skipped, N - 1);
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn put_with() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, OPTIONS).await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            let value = i.to_be_bytes();
+            table
+                .put_with(&value, i, value.len(), |buf| buf.copy_from_slice(&value))
+                .await
+                .unwrap();
+        }
+        for i in 0..N {
+            must_get(&table, i, i, Some(i)).await;
+        }
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn crud_with_size_split_policy() {
+        let path = tempdir().unwrap();
+        let opts = TableOptions {
+            split_policy: SplitPolicy::Size,
+            ..OPTIONS
+        };
+        let table = Table::open(&path, opts).await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            // Mix small and large values.
+            let value = vec![0; if i % 8 == 0 { 64 } else { 1 }];
+            table.put(&i.to_be_bytes(), i, &value).await.unwrap();
+        }
+        for i in 0..N {
+            let value = table.get(&i.to_be_bytes(), i).await.unwrap().unwrap();
+            assert_eq!(value.len(), if i % 8 == 0 { 64 } else { 1 });
+        }
+        table.close().await.unwrap();
+    }
+
     #[photonio::test]
     async fn random_crud() {
         let path = tempdir().unwrap();
@@ -137,6 +811,32 @@ mod tests {
         table.close().await.unwrap();
     }
 
+    #[photonio::test]
+    async fn get_after_largest_key() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, OPTIONS).await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            must_put(&table, i, 0).await;
+        }
+        assert!(table.stats().tree.success.split_page > 0);
+
+        // Keys after the largest key are routed to the rightmost leaf, which
+        // has no range end, and must not match any key in it.
+        let last = (N - 1).to_be_bytes();
+        let keys = [
+            [last.as_slice(), &[0]].concat(),
+            N.to_be_bytes().to_vec(),
+            u64::MAX.to_be_bytes().to_vec(),
+            vec![u8::MAX; 64],
+        ];
+        for key in &keys {
+            assert_eq!(table.get(key, 0).await.unwrap(), None);
+        }
+        must_get(&table, N - 1, 0, Some(N - 1)).await;
+        table.close().await.unwrap();
+    }
+
     #[photonio::test]
     async fn concurrent_crud() {
         let path = tempdir().unwrap();
@@ -160,6 +860,41 @@ mod tests {
         table.close().await.unwrap();
     }
 
+    #[photonio::test]
+    async fn concurrent_writes_at_split_boundary() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, OPTIONS).await.unwrap();
+        const M: u64 = 4;
+        const N: u64 = 1 << 10;
+        let mut tasks = Vec::new();
+        for t in 0..M {
+            let table = table.clone();
+            let handle = photonio::task::spawn(async move {
+                // Interleave the keys of all tasks, so that every split happens while
+                // other tasks are writing keys around the split key.
+                for i in 0..N {
+                    let k = i * M + t;
+                    must_put(&table, k, 1).await;
+                    must_put(&table, k, 2).await;
+                    must_get(&table, k, 1, Some(k)).await;
+                    must_get(&table, k, 2, Some(k)).await;
+                    // Keys written before must stay readable.
+                    let j = random::<u64>() % (i + 1) * M + t;
+                    must_get(&table, j, 2, Some(j)).await;
+                }
+            });
+            tasks.push(handle);
+        }
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert!(table.stats().tree.success.split_page > 0);
+        for k in 0..N * M {
+            must_get(&table, k, 2, Some(k)).await;
+        }
+        table.close().await.unwrap();
+    }
+
     #[photonio::test]
     async fn graceful_shutdown() {
         let path = tempdir().unwrap();
@@ -179,4 +914,1726 @@ mod tests {
         let table = Table::open(&path, opts).await.unwrap();
         must_get(&table, i, i, Some(i)).await;
     }
+
+    #[photonio::test]
+    async fn invalid_bloom_fp_rate() {
+        let path = tempdir().unwrap();
+        for bloom_fp_rate in [0.0, 1.0, -0.5, f64::NAN] {
+            let opts = TableOptions {
+                bloom_fp_rate,
+                ..OPTIONS
+            };
+            let result = Table::open(&path, opts).await;
+            assert!(matches!(result, Err(Error::InvalidArgument)));
+        }
+    }
+
+    #[photonio::test]
+    async fn file_key_ranges() {
+        fn check_ranges(ranges: &[(u32, Vec<u8>, Vec<u8>)], n: u64) {
+            assert!(!ranges.is_empty());
+            for (_, min_key, max_key) in ranges {
+                assert!(min_key <= max_key);
+                assert!(max_key.as_slice() <= (n - 1).to_be_bytes().as_slice());
+            }
+            let min_key = ranges.iter().map(|(_, k, _)| k).min().unwrap();
+            let max_key = ranges.iter().map(|(_, _, k)| k).max().unwrap();
+            assert_eq!(min_key, &0u64.to_be_bytes());
+            assert_eq!(max_key, &(n - 1).to_be_bytes());
+        }
+
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, OPTIONS).await.unwrap();
+        assert!(table.file_key_ranges().is_empty());
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            must_put(&table, i, i).await;
+        }
+        table.flush(&FlushOptions::default()).await;
+        check_ranges(&table.file_key_ranges(), N);
+        table.close().await.unwrap();
+
+        // The key ranges are persisted with files.
+        let table = Table::open(&path, OPTIONS).await.unwrap();
+        check_ranges(&table.file_key_ranges(), N);
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn disable_background() {
+        let path = tempdir().unwrap();
+        let options = TableOptions {
+            page_size: 1 << 20,
+            disable_background: true,
+            ..OPTIONS
+        };
+        let table = Table::open(&path, options).await.unwrap();
+        // All writes go to the root page, and no pages are consolidated.
+        const N: u64 = 32;
+        for i in 0..N {
+            must_put(&table, i, i).await;
+        }
+        let stats = table.stats().tree;
+        assert_eq!(stats.success.write, N);
+        assert_eq!(stats.success.consolidate_page, 0);
+        assert_eq!(stats.success.split_page, 0);
+
+        table.compact_range(&[], None).await.unwrap();
+        let stats = table.stats().tree;
+        assert_eq!(stats.success.consolidate_page, 1);
+        assert_eq!(stats.success.split_page, 0);
+        for i in 0..N {
+            must_get(&table, i, i, Some(i)).await;
+        }
+
+        // The page has been consolidated, nothing to do now.
+        table.compact_range(&[], None).await.unwrap();
+        assert_eq!(table.stats().tree.success.consolidate_page, 1);
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn persist_cache_warmset() {
+        let path = tempdir().unwrap();
+        let options = TableOptions {
+            page_size: 1 << 10,
+            page_store: PageStoreOptions {
+                cache_capacity: 1 << 20,
+                persist_cache_warmset: true,
+                ..OPTIONS.page_store
+            },
+            ..OPTIONS
+        };
+        let table = Table::open(&path, options.clone()).await.unwrap();
+        const N: u64 = 8;
+        for i in 0..N {
+            must_put(&table, i, i).await;
+        }
+        table.flush(&FlushOptions::default()).await;
+        table.close().await.unwrap();
+        assert!(path.path().join("WARMSET").exists());
+
+        // The pages are cached on open, so reads don't miss the cache.
+        let table = Table::open(&path, options.clone()).await.unwrap();
+        let stats = table.stats().store.page_cache;
+        for i in 0..N {
+            must_get(&table, i, i, Some(i)).await;
+        }
+        let new_stats = table.stats().store.page_cache;
+        assert!(new_stats.lookup_hit > stats.lookup_hit);
+        assert_eq!(new_stats.lookup_miss, stats.lookup_miss);
+        table.close().await.unwrap();
+
+        // A corrupted warm-set is ignored.
+        ::std::fs::write(path.path().join("WARMSET"), b"corrupted").unwrap();
+        let table = Table::open(&path, options).await.unwrap();
+        must_get(&table, 0, 0, Some(0)).await;
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn cache_capacity() {
+        let path = tempdir().unwrap();
+        let options = TableOptions {
+            page_store: PageStoreOptions {
+                cache_capacity: 4 << 10,
+                prepopulate_cache_on_flush: false,
+                ..OPTIONS.page_store
+            },
+            ..OPTIONS
+        };
+        let table = Table::open(&path, options.clone()).await.unwrap();
+        const N: u64 = 256;
+        for i in 0..N {
+            must_put(&table, i, i).await;
+        }
+        table.flush(&FlushOptions::default()).await;
+        table.close().await.unwrap();
+
+        // Reads from files cache the pages, but never over the capacity.
+        let table = Table::open(&path, options).await.unwrap();
+        assert_eq!(table.cache_capacity(), 4 << 10);
+        let usage = table.cache_usage();
+        let mut max_usage = usage;
+        for i in 0..N {
+            must_get(&table, i, i, Some(i)).await;
+            max_usage = max_usage.max(table.cache_usage());
+            assert!(table.cache_usage() <= table.cache_capacity());
+        }
+        assert!(max_usage > usage);
+
+        // Shrinking the capacity evicts pages immediately.
+        table.set_cache_capacity(1 << 10);
+        assert_eq!(table.cache_capacity(), 1 << 10);
+        assert!(table.cache_usage() <= 1 << 10);
+        for i in 0..N {
+            must_get(&table, i, i, Some(i)).await;
+            assert!(table.cache_usage() <= 1 << 10);
+        }
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn cache_policies() {
+        for policy in [CachePolicy::Clock, CachePolicy::Lru] {
+            let path = tempdir().unwrap();
+            let options = TableOptions {
+                page_store: PageStoreOptions {
+                    cache_policy: policy,
+                    prepopulate_cache_on_flush: false,
+                    ..OPTIONS.page_store
+                },
+                ..OPTIONS
+            };
+            let table = Table::open(&path, options.clone()).await.unwrap();
+            const N: u64 = 256;
+            for i in 0..N {
+                must_put(&table, i, i).await;
+            }
+            for i in (0..N).step_by(2) {
+                table.delete(&i.to_be_bytes(), N + i).await.unwrap();
+            }
+            table.flush(&FlushOptions::default()).await;
+            table.close().await.unwrap();
+
+            // Reads from files go through the page cache of the policy.
+            let table = Table::open(&path, options).await.unwrap();
+            for _ in 0..2 {
+                for i in 0..N {
+                    let expect = if i % 2 == 0 { None } else { Some(i) };
+                    must_get(&table, i, N * 2, expect).await;
+                }
+            }
+            let stats = table.stats().store.page_cache;
+            assert!(stats.insert > 0, "{policy:?}");
+            assert!(stats.lookup_hit > 0, "{policy:?}");
+            table.close().await.unwrap();
+        }
+    }
+
+    #[photonio::test]
+    async fn retain_versions() {
+        for retain_versions in [0, 2] {
+            let path = tempdir().unwrap();
+            let options = TableOptions {
+                disable_background: true,
+                retain_versions,
+                ..OPTIONS
+            };
+            let table = Table::open(&path, options).await.unwrap();
+            let key = [0];
+            for lsn in 1..=4u64 {
+                table.put(&key, lsn, &lsn.to_be_bytes()).await.unwrap();
+            }
+            table.set_safe_lsn(4);
+            table.compact_range(&[], None).await.unwrap();
+
+            // The latest version is always visible to the safe LSN.
+            let expect = |lsn: u64| {
+                let retained = lsn as usize + retain_versions.max(1) > 4;
+                retained.then(|| lsn.to_be_bytes().to_vec())
+            };
+            for lsn in 1..=4 {
+                assert_eq!(table.get(&key, lsn).await.unwrap(), expect(lsn));
+            }
+            table.close().await.unwrap();
+        }
+    }
+
+    #[photonio::test]
+    async fn scan_from_leftmost() {
+        let path = tempdir().unwrap();
+        let options = TableOptions {
+            initial_leaf_count: 4,
+            ..OPTIONS
+        };
+        let table = Table::open(&path, options).await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in (0..N).rev() {
+            must_put(&table, i, 0).await;
+        }
+        // The empty key is the smallest one.
+        table.put(&[], 0, &[]).await.unwrap();
+        assert_eq!(table.get(&[], 0).await.unwrap(), Some(vec![]));
+
+        let guard = table.pin();
+        let mut pages = guard.pages();
+        let first = pages.next().await.unwrap().unwrap().next();
+        assert_eq!(first, Some(([].as_slice(), [].as_slice())));
+        let mut keys = vec![];
+        let mut pages = guard.pages();
+        while let Some(page) = pages.next().await.unwrap() {
+            keys.extend(page.map(|(k, _)| k.to_vec()));
+        }
+        assert_eq!(keys.len() as u64, N + 1);
+        assert_eq!(keys[1], 0u64.to_be_bytes());
+        drop(guard);
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn scan_with_read_io_depth() {
+        let path = tempdir().unwrap();
+        let options = TableOptions {
+            initial_leaf_count: 16,
+            read_io_depth: 4,
+            ..OPTIONS
+        };
+        const N: u64 = 1 << 10;
+        let table = Table::open(&path, options.clone()).await.unwrap();
+        for i in 0..N {
+            must_put(&table, i, 0).await;
+        }
+        table.close().await.unwrap();
+
+        // Reopen the table so that the pages are read from files.
+        let table = Table::open(&path, options).await.unwrap();
+        let guard = table.pin();
+        let mut keys = vec![];
+        let mut pages = guard.pages();
+        while let Some(page) = pages.next().await.unwrap() {
+            keys.extend(page.map(|(k, _)| k.to_vec()));
+        }
+        let expect: Vec<_> = (0..N).map(|i| i.to_be_bytes().to_vec()).collect();
+        assert_eq!(keys, expect);
+        drop(guard);
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn verify_checksums() {
+        let path = tempdir().unwrap();
+        let options = TableOptions {
+            page_store: PageStoreOptions {
+                disable_space_reclaiming: true,
+                ..OPTIONS.page_store
+            },
+            ..OPTIONS
+        };
+        let table = Table::open(&path, options).await.unwrap();
+        for i in 0..2 {
+            must_put(&table, i, i).await;
+            table.flush(&FlushOptions::default()).await;
+        }
+        let report = table.verify_checksums().await.unwrap();
+        assert_eq!(report.num_files, 2);
+        assert!(report.num_pages >= 2);
+        assert_eq!(report.num_failures, 0);
+
+        // Corrupts the first page of each file.
+        let mut file_ids = Vec::new();
+        for entry in ::std::fs::read_dir(path.path()).unwrap() {
+            let entry = entry.unwrap();
+            let name = entry.file_name().into_string().unwrap();
+            if let Some(file_id) = name.strip_prefix("map_") {
+                file_ids.push(file_id.parse::<u32>().unwrap());
+                let mut content = ::std::fs::read(entry.path()).unwrap();
+                content[0] ^= 0xff;
+                ::std::fs::write(entry.path(), content).unwrap();
+            }
+        }
+        file_ids.sort_unstable();
+        let expect = file_ids
+            .into_iter()
+            .map(|file_id| ChecksumFailure { file_id, offset: 0 })
+            .collect::<Vec<_>>();
+        let report = table.verify_checksums().await.unwrap();
+        assert_eq!(report.num_failures, 2);
+        assert_eq!(report.failures, expect);
+
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn unsupported_checksum_type() {
+        let path = tempdir().unwrap();
+        let options = TableOptions {
+            page_store: PageStoreOptions {
+                page_checksum_type: ChecksumType::XXHASH,
+                ..OPTIONS.page_store
+            },
+            ..OPTIONS
+        };
+        let result = Table::open(&path, options).await;
+        assert!(matches!(result, Err(Error::InvalidArgument)));
+    }
+
+    #[photonio::test]
+    async fn poison_on_corrupted_inner_page() {
+        // With one initial leaf, the root is a leaf page.
+        for (leaves, poisoned) in [(1, false), (2, true)] {
+            let path = tempdir().unwrap();
+            let options = TableOptions {
+                initial_leaf_count: leaves,
+                disable_background: true,
+                page_store: PageStoreOptions {
+                    prepopulate_cache_on_flush: false,
+                    ..OPTIONS.page_store
+                },
+                ..OPTIONS
+            };
+            let table = Table::open(&path, options.clone()).await.unwrap();
+            for i in 0..leaves as u64 {
+                must_put(&table, i, 0).await;
+            }
+            table.close().await.unwrap();
+
+            // Corrupts all the pages after the file metadata is loaded, so that the root
+            // page is read from the corrupted files.
+            let table = Table::open(&path, options).await.unwrap();
+            for entry in ::std::fs::read_dir(path.path()).unwrap() {
+                let entry = entry.unwrap();
+                let name = entry.file_name().into_string().unwrap();
+                if name.starts_with("map_") {
+                    let mut content = ::std::fs::read(entry.path()).unwrap();
+                    content.iter_mut().for_each(|b| *b ^= 0xff);
+                    ::std::fs::write(entry.path(), content).unwrap();
+                }
+            }
+            assert!(!table.is_poisoned());
+            let buf = 0u64.to_be_bytes();
+            assert!(matches!(table.get(&buf, 0).await, Err(Error::Corrupted)));
+            // A corrupted leaf page doesn't poison the table.
+            assert_eq!(table.is_poisoned(), poisoned, "leaves {leaves}");
+
+            if poisoned {
+                // All operations fail fast now.
+                assert!(matches!(
+                    table.put(&buf, 1, &buf).await,
+                    Err(Error::Corrupted)
+                ));
+                assert!(matches!(table.get(&buf, 1).await, Err(Error::Corrupted)));
+            }
+            table.close().await.unwrap();
+        }
+    }
+
+    #[photonio::test]
+    async fn inject_read_errors() {
+        let path = tempdir().unwrap();
+        let env = env::FaultEnv::new(env::Std);
+        let options = TableOptions {
+            disable_background: true,
+            page_store: PageStoreOptions {
+                prepopulate_cache_on_flush: false,
+                ..OPTIONS.page_store
+            },
+            ..OPTIONS
+        };
+        let table = raw::Table::open(env.clone(), &path, options.clone())
+            .await
+            .unwrap();
+        let buf = 1u64.to_be_bytes();
+        table.put(&buf, 1, &buf).await.unwrap();
+        table.close().await.unwrap();
+
+        let table = raw::Table::open(env.clone(), &path, options).await.unwrap();
+        env.inject(
+            env::FaultRule::new(
+                env::FaultOp::Read,
+                env::Fault::Error(::std::io::ErrorKind::Other),
+            )
+            .on_path("map_")
+            .times(1),
+        );
+        assert!(matches!(table.get(&buf, 1).await, Err(Error::Io(_))));
+        // The error isn't kept, so the read succeeds once the fault is gone.
+        assert!(!table.is_poisoned());
+        assert_eq!(table.get(&buf, 1).await.unwrap(), Some(buf.to_vec()));
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn scan_changes() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, OPTIONS).await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            must_put(&table, i, 1).await;
+        }
+        // Overwrite even keys and delete keys that are multiples of three.
+        for i in (0..N).step_by(2) {
+            must_put(&table, i, 2).await;
+        }
+        for i in (0..N).step_by(3) {
+            table.delete(&i.to_be_bytes(), 3).await.unwrap();
+        }
+
+        async fn changes(table: &Table, since_lsn: u64, lsn: u64) -> Vec<(u64, u64, ChangeKind)> {
+            let mut cursor = table.scan_changes(since_lsn, lsn);
+            let mut changes = Vec::new();
+            while let Some((k, lsn, kind)) = cursor.next().await.unwrap() {
+                changes.push((u64::from_be_bytes(k.try_into().unwrap()), lsn, kind));
+            }
+            changes
+        }
+        let put = |i: u64| ChangeKind::Put(i.to_be_bytes().to_vec());
+
+        let expect = (0..N).map(|i| (i, 1, put(i))).collect::<Vec<_>>();
+        assert_eq!(changes(&table, 0, 1).await, expect);
+
+        let expect = (0..N)
+            .step_by(2)
+            .map(|i| (i, 2, put(i)))
+            .collect::<Vec<_>>();
+        assert_eq!(changes(&table, 1, 2).await, expect);
+
+        let expect = (0..N)
+            .step_by(3)
+            .map(|i| (i, 3, ChangeKind::Delete))
+            .collect::<Vec<_>>();
+        assert_eq!(changes(&table, 2, 3).await, expect);
+
+        // Every version in the window is reported, from the latest to the
+        // oldest version of each key.
+        let mut expect = Vec::new();
+        for i in 0..N {
+            if i % 3 == 0 {
+                expect.push((i, 3, ChangeKind::Delete));
+            }
+            if i % 2 == 0 {
+                expect.push((i, 2, put(i)));
+            }
+            expect.push((i, 1, put(i)));
+        }
+        assert_eq!(changes(&table, 0, 3).await, expect);
+
+        assert!(changes(&table, 3, 4).await.is_empty());
+
+        // The changes can be streamed.
+        use futures::TryStreamExt;
+        let stream = table.scan_changes(0, u64::MAX).into_stream();
+        let count = stream
+            .try_fold(0, |n, _| async move { Ok(n + 1) })
+            .await
+            .unwrap();
+        assert_eq!(count, expect.len());
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn put_auto() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, OPTIONS).await.unwrap();
+        let key = b"key";
+        table.put(key, 100, b"0").await.unwrap();
+        table.flush(&FlushOptions::default()).await;
+        table.close().await.unwrap();
+
+        // Assigned LSNs resume after the largest LSN in the table.
+        let table = Table::open(&path, OPTIONS).await.unwrap();
+        let mut last_lsn = 100;
+        for i in 1..=10u64 {
+            let other = table.put_auto(b"other", &i.to_be_bytes()).await.unwrap();
+            let lsn = table.put_auto(key, &i.to_be_bytes()).await.unwrap();
+            assert!(other > last_lsn);
+            assert!(lsn > other);
+            last_lsn = lsn;
+        }
+        let value = table.get(key, u64::MAX).await.unwrap();
+        assert_eq!(value, Some(10u64.to_be_bytes().to_vec()));
+
+        let lsn = table.delete_auto(key).await.unwrap();
+        assert!(lsn > last_lsn);
+        assert_eq!(table.get(key, u64::MAX).await.unwrap(), None);
+        assert_eq!(table.get(key, lsn - 1).await.unwrap(), value);
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn iter_isolation() {
+        const N: u64 = 1 << 8;
+
+        // Scans the table and writes new versions of all keys at `write_lsn`
+        // after each page. Returns the LSNs in the values scanned.
+        async fn scan_with_writes(table: &Table, isolation: Isolation, write_lsn: u64) -> Vec<u64> {
+            let guard = table.pin();
+            let mut pages = guard.iter(isolation).await.unwrap();
+            let mut lsns = Vec::new();
+            while let Some(page) = pages.next().await.unwrap() {
+                lsns.extend(page.map(|(_, v)| u64::from_be_bytes(v.try_into().unwrap())));
+                for i in 0..N {
+                    let value = write_lsn.to_be_bytes();
+                    table
+                        .put(&i.to_be_bytes(), write_lsn, &value)
+                        .await
+                        .unwrap();
+                }
+            }
+            lsns
+        }
+
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, OPTIONS).await.unwrap();
+        for i in 0..N {
+            table
+                .put(&i.to_be_bytes(), 1, &1u64.to_be_bytes())
+                .await
+                .unwrap();
+        }
+
+        // The isolated scan only sees the versions at the snapshot.
+        let lsns = scan_with_writes(&table, Isolation::Snapshot, 2).await;
+        assert_eq!(lsns, vec![1; N as usize]);
+
+        // The latest scan sees the new versions in the pages visited later.
+        let lsns = scan_with_writes(&table, Isolation::Latest, 3).await;
+        assert_eq!(lsns.len() as u64, N);
+        assert_eq!(lsns[0], 2);
+        assert_eq!(*lsns.last().unwrap(), 3);
+
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn read_txn_conflict() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, OPTIONS).await.unwrap();
+        let key = b"counter";
+        table.put_auto(key, &0u64.to_be_bytes()).await.unwrap();
+
+        // Both transactions increment the counter read from the same version.
+        let mut txns = [table.begin_txn(), table.begin_txn()];
+        let mut values = Vec::new();
+        for txn in &mut txns {
+            let value = txn.get(key).await.unwrap().unwrap();
+            let count = u64::from_be_bytes(value.try_into().unwrap());
+            values.push((count + 1).to_be_bytes());
+        }
+        let mut results = Vec::new();
+        for (txn, value) in txns.into_iter().zip(&values) {
+            let writes = [(key.as_slice(), Some(value.as_slice()))];
+            results.push(txn.commit_if_unchanged(&writes).await);
+        }
+        assert!(results[0].is_ok());
+        assert!(matches!(results[1], Err(Error::Conflict)));
+        let value = table.get(key, u64::MAX).await.unwrap();
+        assert_eq!(value, Some(1u64.to_be_bytes().to_vec()));
+
+        // Absent keys are conflicts once written.
+        let mut txn = table.begin_txn();
+        assert_eq!(txn.get(b"absent").await.unwrap(), None);
+        table.delete_auto(b"absent").await.unwrap();
+        let result = txn.commit_if_unchanged(&[]).await;
+        assert!(matches!(result, Err(Error::Conflict)));
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn swap_tables() {
+        const N: u64 = 100;
+        let path_a = tempdir().unwrap();
+        let path_b = tempdir().unwrap();
+        let a = Table::open(&path_a, OPTIONS).await.unwrap();
+        let b = Table::open(&path_b, OPTIONS).await.unwrap();
+        for i in 0..N {
+            must_put(&a, i, 1).await;
+            must_put(&b, i + N, 2).await;
+        }
+
+        // Reads started before the swap keep reading the old contents.
+        let guard = a.pin();
+        a.swap(&b).unwrap();
+        let mut pages = guard.pages();
+        let mut keys = Vec::new();
+        while let Some(page) = pages.next().await.unwrap() {
+            keys.extend(page.map(|(k, _)| u64::from_be_bytes(k.try_into().unwrap())));
+        }
+        assert_eq!(keys, (0..N).collect::<Vec<_>>());
+        drop(pages);
+        drop(guard);
+
+        for i in 0..N {
+            must_get(&a, i, u64::MAX, None).await;
+            must_get(&a, i + N, u64::MAX, Some(i + N)).await;
+            must_get(&b, i, u64::MAX, Some(i)).await;
+            must_get(&b, i + N, u64::MAX, None).await;
+        }
+        // LSNs assigned after the swap cover the entries of both tables.
+        assert!(a.put_auto(b"key", b"value").await.unwrap() > 2);
+        assert!(b.put_auto(b"key", b"value").await.unwrap() > 2);
+
+        // Swapping back restores the original contents.
+        b.swap(&a).unwrap();
+        must_get(&a, 0, u64::MAX, Some(0)).await;
+        must_get(&b, N, u64::MAX, Some(N)).await;
+        a.close().await.unwrap();
+        b.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn swap_during_scans() {
+        const N: u64 = 100;
+        let path_a = tempdir().unwrap();
+        let path_b = tempdir().unwrap();
+        let a = Table::open(&path_a, OPTIONS).await.unwrap();
+        let b = Table::open(&path_b, OPTIONS).await.unwrap();
+        for i in 0..N {
+            must_put(&a, i * 2, 1).await;
+            must_put(&b, i * 2 + 1, 1).await;
+        }
+
+        // Cursors started before the swap only read the old contents.
+        let mut cursor = a.scan(&[], None, u64::MAX);
+        let mut changes = a.scan_changes(0, u64::MAX);
+        let (first, _) = cursor.next().await.unwrap().unwrap();
+        let (first_change, ..) = changes.next().await.unwrap().unwrap();
+        a.swap(&b).unwrap();
+        let mut keys = vec![first];
+        while let Some((k, _)) = cursor.next().await.unwrap() {
+            keys.push(k);
+        }
+        let mut change_keys = vec![first_change];
+        while let Some((k, ..)) = changes.next().await.unwrap() {
+            change_keys.push(k);
+        }
+        let expect: Vec<_> = (0..N).map(|i| (i * 2).to_be_bytes().to_vec()).collect();
+        assert_eq!(keys, expect);
+        assert_eq!(change_keys, expect);
+        drop(cursor);
+        drop(changes);
+
+        // Optimistic transactions started before the swap conflict.
+        b.swap(&a).unwrap();
+        let mut txn = a.begin_txn();
+        assert!(txn.get(&0u64.to_be_bytes()).await.unwrap().is_some());
+        a.swap(&b).unwrap();
+        let result = txn.commit_if_unchanged(&[]).await;
+        assert!(matches!(result, Err(Error::Conflict)));
+        a.close().await.unwrap();
+        b.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn swap_with_snapshot() {
+        const N: u64 = 100;
+        let path_a = tempdir().unwrap();
+        let path_b = tempdir().unwrap();
+        let a = Table::open(&path_a, OPTIONS).await.unwrap();
+        let b = Table::open(&path_b, OPTIONS).await.unwrap();
+        for i in 0..N {
+            must_put(&a, i, 1).await;
+        }
+
+        // Tables with open snapshots can't be swapped, so the versions that
+        // the snapshot reads are retained by its own table.
+        let snapshot = a.snapshot().await.unwrap();
+        for i in 0..N {
+            a.put(&i.to_be_bytes(), 2, b"new").await.unwrap();
+        }
+        assert!(matches!(a.swap(&b), Err(Error::SnapshotOpen)));
+        assert!(matches!(b.swap(&a), Err(Error::SnapshotOpen)));
+        a.set_safe_lsn(2);
+        b.set_safe_lsn(2);
+        a.compact_range(&[], None).await.unwrap();
+        b.compact_range(&[], None).await.unwrap();
+        for i in 0..N {
+            let value = snapshot.get(&i.to_be_bytes()).await.unwrap();
+            assert_eq!(value, Some(&i.to_be_bytes()[..]));
+        }
+        drop(snapshot);
+
+        a.swap(&b).unwrap();
+        must_get(&a, 0, u64::MAX, None).await;
+        assert_eq!(
+            b.get(&0u64.to_be_bytes(), u64::MAX).await.unwrap(),
+            Some(b"new".to_vec())
+        );
+        a.close().await.unwrap();
+        b.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn open_same_path() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, OPTIONS).await.unwrap();
+        let result = Table::open(path.path().join("."), OPTIONS).await;
+        assert!(matches!(result, Err(Error::AlreadyOpen)));
+
+        // Tables opened with sharing enabled are the same table.
+        let options = TableOptions {
+            share_open_tables: true,
+            ..OPTIONS
+        };
+        let shared = Table::open(&path, options.clone()).await.unwrap();
+        must_put(&table, 1, 1).await;
+        must_get(&shared, 1, 1, Some(1)).await;
+        must_put(&shared, 2, 2).await;
+        must_get(&table, 2, 2, Some(2)).await;
+
+        // The table can be opened again once it is closed.
+        let table = table.close().await.unwrap_err();
+        drop(table);
+        shared.close().await.unwrap();
+        let table = Table::open(&path, options.clone()).await.unwrap();
+        must_get(&table, 2, 2, Some(2)).await;
+
+        // Sharing follows the contents of swapped tables.
+        let other_path = tempdir().unwrap();
+        let other = Table::open(&other_path, options.clone()).await.unwrap();
+        table.swap(&other).unwrap();
+        let shared = Table::open(&path, options.clone()).await.unwrap();
+        must_put(&shared, 3, 3).await;
+        must_get(&other, 3, 3, Some(3)).await;
+        must_get(&table, 3, 3, None).await;
+        drop(shared);
+        let shared = Table::open(&other_path, options).await.unwrap();
+        must_get(&shared, 2, 2, None).await;
+        drop(shared);
+        table.close().await.unwrap();
+        other.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn scan_range() {
+        async fn scan(table: &Table, start: u64, end: Option<u64>, lsn: u64) -> Vec<u64> {
+            let end = end.map(|end| end.to_be_bytes());
+            let mut cursor = table.scan(&start.to_be_bytes(), end.as_ref().map(|e| &e[..]), lsn);
+            let mut keys = Vec::new();
+            while let Some((k, v)) = cursor.next().await.unwrap() {
+                assert_eq!(k, v);
+                keys.push(u64::from_be_bytes(k.try_into().unwrap()));
+            }
+            keys
+        }
+
+        const N: u64 = 1000;
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, OPTIONS).await.unwrap();
+        for i in 0..N {
+            must_put(&table, i, i).await;
+        }
+        table.delete(&10u64.to_be_bytes(), N).await.unwrap();
+
+        assert_eq!(
+            scan(&table, 0, None, N).await,
+            (0..N).filter(|&i| i != 10).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            scan(&table, 5, Some(15), N - 1).await,
+            (5..15).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            scan(&table, 500, None, N).await,
+            (500..N).collect::<Vec<_>>()
+        );
+        // Entries newer than the LSN are skipped.
+        assert_eq!(
+            scan(&table, 0, Some(N), 99).await,
+            (0..100).collect::<Vec<_>>()
+        );
+        // Empty ranges.
+        assert!(scan(&table, 15, Some(15), N).await.is_empty());
+        assert!(scan(&table, 20, Some(10), N).await.is_empty());
+        assert!(scan(&table, N, None, N).await.is_empty());
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn scan_bounded_range() {
+        fn bound_bytes(bound: Bound<u64>) -> Bound<[u8; 8]> {
+            match bound {
+                Bound::Included(k) => Bound::Included(k.to_be_bytes()),
+                Bound::Excluded(k) => Bound::Excluded(k.to_be_bytes()),
+                Bound::Unbounded => Bound::Unbounded,
+            }
+        }
+
+        fn as_slice(bound: &Bound<[u8; 8]>) -> Bound<&[u8]> {
+            match bound {
+                Bound::Included(k) => Bound::Included(&k[..]),
+                Bound::Excluded(k) => Bound::Excluded(&k[..]),
+                Bound::Unbounded => Bound::Unbounded,
+            }
+        }
+
+        async fn range(table: &Table, start: Bound<u64>, end: Bound<u64>) -> Vec<u64> {
+            let (start, end) = (bound_bytes(start), bound_bytes(end));
+            let mut cursor = table.range(as_slice(&start), as_slice(&end), u64::MAX);
+            let mut keys = Vec::new();
+            while let Some((k, _)) = cursor.next().await.unwrap() {
+                keys.push(u64::from_be_bytes(k.try_into().unwrap()));
+            }
+            keys
+        }
+
+        const N: u64 = 1000;
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, OPTIONS).await.unwrap();
+        for i in 0..N {
+            must_put(&table, i, i).await;
+        }
+
+        use Bound::*;
+        assert_eq!(
+            range(&table, Unbounded, Unbounded).await,
+            (0..N).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            range(&table, Included(10), Included(20)).await,
+            (10..=20).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            range(&table, Excluded(10), Excluded(20)).await,
+            (11..20).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            range(&table, Excluded(10), Included(20)).await,
+            (11..=20).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            range(&table, Unbounded, Excluded(5)).await,
+            (0..5).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            range(&table, Excluded(N - 5), Unbounded).await,
+            (N - 4..N).collect::<Vec<_>>()
+        );
+        // Empty ranges.
+        assert!(range(&table, Excluded(10), Excluded(11)).await.is_empty());
+        assert!(range(&table, Excluded(10), Included(10)).await.is_empty());
+        assert!(range(&table, Included(20), Excluded(10)).await.is_empty());
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn scan_stream() {
+        use futures::{StreamExt, TryStreamExt};
+
+        const N: u64 = 1000;
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, OPTIONS).await.unwrap();
+        for i in 0..N {
+            must_put(&table, i, i).await;
+        }
+
+        let start = 100u64.to_be_bytes();
+        let end = 900u64.to_be_bytes();
+        let entries: Vec<_> = table
+            .scan(&start, Some(&end), u64::MAX)
+            .into_stream()
+            .try_collect()
+            .await
+            .unwrap();
+        let expect: Vec<_> = (100..900u64)
+            .map(|i| (i.to_be_bytes().to_vec(), i.to_be_bytes().to_vec()))
+            .collect();
+        assert_eq!(entries, expect);
+
+        // The stream composes with the stream adapters.
+        let keys: Vec<_> = table
+            .scan(&[], None, u64::MAX)
+            .into_stream()
+            .try_filter(|(k, _)| futures::future::ready(k[7] % 2 == 0))
+            .take(5)
+            .map_ok(|(k, _)| u64::from_be_bytes(k.try_into().unwrap()))
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(keys, [0, 2, 4, 6, 8]);
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn scan_seek() {
+        async fn next_keys(cursor: &mut photon::Cursor<'_>, n: usize) -> Vec<u64> {
+            let mut keys = Vec::new();
+            while keys.len() < n {
+                match cursor.next().await.unwrap() {
+                    Some((k, _)) => keys.push(u64::from_be_bytes(k.try_into().unwrap())),
+                    None => break,
+                }
+            }
+            keys
+        }
+
+        const N: u64 = 1000;
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, OPTIONS).await.unwrap();
+        for i in 0..N {
+            must_put(&table, i, i).await;
+        }
+
+        let mut cursor = table.scan(&100u64.to_be_bytes(), Some(&900u64.to_be_bytes()), N);
+        assert_eq!(next_keys(&mut cursor, 3).await, [100, 101, 102]);
+        // Seek forward across many pages.
+        cursor.seek(&500u64.to_be_bytes());
+        assert_eq!(next_keys(&mut cursor, 3).await, [500, 501, 502]);
+        // Seek backward, all the entries after the target are returned again.
+        cursor.seek(&200u64.to_be_bytes());
+        assert_eq!(
+            next_keys(&mut cursor, N as usize).await,
+            (200..900).collect::<Vec<_>>()
+        );
+        // Seeks are bounded by the range.
+        cursor.seek(&0u64.to_be_bytes());
+        assert_eq!(next_keys(&mut cursor, 2).await, [100, 101]);
+        cursor.seek(&900u64.to_be_bytes());
+        assert!(next_keys(&mut cursor, 1).await.is_empty());
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn scan_prefix() {
+        async fn scan(table: &Table, prefix: &[u8]) -> Vec<Vec<u8>> {
+            let mut cursor = table.scan_prefix(prefix, 0);
+            let mut keys = Vec::new();
+            while let Some((k, _)) = cursor.next().await.unwrap() {
+                keys.push(k);
+            }
+            keys
+        }
+
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, OPTIONS).await.unwrap();
+        let keys: [&[u8]; 7] = [
+            b"ap",
+            b"app",
+            b"apple",
+            b"applet",
+            b"apq",
+            b"banana",
+            &[0xff, 0xff, 0x01],
+        ];
+        for key in keys {
+            table.put(key, 0, key).await.unwrap();
+        }
+
+        assert_eq!(
+            scan(&table, b"app").await,
+            [&b"app"[..], b"apple", b"applet"]
+        );
+        assert_eq!(scan(&table, b"apple").await, [&b"apple"[..], b"applet"]);
+        assert_eq!(scan(&table, b"b").await, [b"banana"]);
+        assert!(scan(&table, b"c").await.is_empty());
+        // The range of a prefix of 0xff bytes extends to the end of the table.
+        assert_eq!(scan(&table, &[0xff, 0xff]).await, [[0xff, 0xff, 0x01]]);
+        assert_eq!(scan(&table, b"").await.len(), keys.len());
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn scan_with_options() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, OPTIONS).await.unwrap();
+        for i in 0..100 {
+            must_put(&table, i, i).await;
+        }
+        for i in (0..100).step_by(2) {
+            table.delete(&i.to_be_bytes(), 100 + i).await.unwrap();
+        }
+
+        let mut options = ScanOptions {
+            end: Some(50u64.to_be_bytes().to_vec()),
+            ..Default::default()
+        };
+        let mut cursor = table.scan_with_options(options.clone());
+        let mut keys = Vec::new();
+        while let Some((k, _)) = cursor.next().await.unwrap() {
+            keys.push(u64::from_be_bytes(k.try_into().unwrap()));
+        }
+        // Deleted keys are skipped.
+        assert_eq!(keys, (1..50).step_by(2).collect::<Vec<_>>());
+
+        // Deletes are not visible to smaller LSNs.
+        options.lsn = 99;
+        let mut cursor = table.scan_with_options(options);
+        let mut count = 0;
+        while cursor.next().await.unwrap().is_some() {
+            count += 1;
+        }
+        assert_eq!(count, 50);
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn expire_entries() {
+        const N: u64 = 100;
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, OPTIONS).await.unwrap();
+        let now = ::std::time::SystemTime::now()
+            .duration_since(::std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        for i in 0..N {
+            let mut options = WriteOptions::default();
+            // Odd keys have expired already.
+            options.expire_at = Some(if i % 2 == 0 { now + 3600 } else { now - 1 });
+            let key = i.to_be_bytes();
+            table
+                .put_with_options(&key, 1, &key, &options)
+                .await
+                .unwrap();
+        }
+        for i in 0..N {
+            must_get(&table, i, 1, (i % 2 == 0).then_some(i)).await;
+        }
+        let mut cursor = table.scan(&[], None, 1);
+        let mut count = 0;
+        while let Some((k, _)) = cursor.next().await.unwrap() {
+            assert_eq!(u64::from_be_bytes(k.try_into().unwrap()) % 2, 0);
+            count += 1;
+        }
+        assert_eq!(count, N / 2);
+
+        // A put without an expiration clears the expiration.
+        must_put(&table, 1, 2).await;
+        must_get(&table, 1, 1, None).await;
+        must_get(&table, 1, 2, Some(1)).await;
+
+        // Expired entries are dropped when their pages are consolidated.
+        table.set_safe_lsn(2);
+        table.compact_range(&[], None).await.unwrap();
+        let mut versions = table.scan_changes(0, u64::MAX);
+        let mut count = 0;
+        while versions.next().await.unwrap().is_some() {
+            count += 1;
+        }
+        assert_eq!(count, N / 2 + 1);
+        for i in 0..N {
+            let expect = (i % 2 == 0 || i == 1).then_some(i);
+            must_get(&table, i, 2, expect).await;
+        }
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn contains_key() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, OPTIONS).await.unwrap();
+        let key = 1u64.to_be_bytes();
+        must_put(&table, 1, 1).await;
+        table.delete(&key, 2).await.unwrap();
+        assert!(!table.contains_key(&key, 0).await.unwrap());
+        assert!(table.contains_key(&key, 1).await.unwrap());
+        // A tombstone is not present.
+        assert!(!table.contains_key(&key, 2).await.unwrap());
+        assert!(!table.contains_key(&2u64.to_be_bytes(), 2).await.unwrap());
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn multi_get() {
+        const N: u64 = 1000;
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, OPTIONS).await.unwrap();
+        for i in 0..N {
+            must_put(&table, i, i).await;
+        }
+
+        let keys = (0..N + 10)
+            .rev()
+            .map(|i| i.to_be_bytes())
+            .collect::<Vec<_>>();
+        let key_refs = keys.iter().map(|k| k.as_slice()).collect::<Vec<_>>();
+        let stats = table.stats().tree;
+        let values = table.multi_get(&key_refs, u64::MAX).await.unwrap();
+        let reads = table.stats().tree.sub(&stats).success.read;
+        for (key, value) in keys.iter().zip(values) {
+            let i = u64::from_be_bytes(*key);
+            let expect = (i < N).then(|| key.to_vec());
+            assert_eq!(value, expect);
+        }
+        // Each leaf page is found once, instead of once for each key.
+        assert!(reads < N, "{reads}");
+
+        // Only versions visible to the LSN are returned.
+        let values = table.multi_get(&key_refs, 10).await.unwrap();
+        assert_eq!(values.iter().filter(|v| v.is_some()).count(), 11);
+
+        // Repeated keys get the same value at each position.
+        let (a, b) = (1u64.to_be_bytes(), N.to_be_bytes());
+        let values = table.multi_get(&[&a, &b, &a], u64::MAX).await.unwrap();
+        assert_eq!(values, [Some(a.to_vec()), None, Some(a.to_vec())]);
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn delete_range() {
+        const N: u64 = 100;
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, OPTIONS).await.unwrap();
+        for i in 0..N {
+            must_put(&table, i, 1).await;
+        }
+
+        let (start, end) = (10u64.to_be_bytes(), 20u64.to_be_bytes());
+        table.delete_range(&start, &end, 2).await.unwrap();
+        for i in 0..N {
+            let deleted = (10..20).contains(&i);
+            must_get(&table, i, 1, Some(i)).await;
+            must_get(&table, i, 2, (!deleted).then_some(i)).await;
+        }
+
+        // Keys written after the delete at LSNs it covers are deleted too.
+        must_put(&table, 15, 1).await;
+        must_put(&table, 16, 2).await;
+        must_put(&table, 17, 3).await;
+        for lsn in [2, 3] {
+            must_get(&table, 15, lsn, None).await;
+            must_get(&table, 16, lsn, None).await;
+        }
+        must_get(&table, 17, 2, None).await;
+        must_get(&table, 17, 3, Some(17)).await;
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn delete_overlapping_ranges() {
+        const N: u64 = 1000;
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, OPTIONS).await.unwrap();
+        for i in 0..N {
+            must_put(&table, i, 1).await;
+        }
+
+        // The ranges span many leaf pages with the small page size.
+        let ranges = [(100u64, 500u64, 2), (300, 800, 3)];
+        for (start, end, lsn) in ranges {
+            let (start, end) = (start.to_be_bytes(), end.to_be_bytes());
+            table.delete_range(&start, &end, lsn).await.unwrap();
+        }
+        for i in 0..N {
+            must_get(&table, i, 1, Some(i)).await;
+            must_get(&table, i, 2, (!(100..500).contains(&i)).then_some(i)).await;
+            must_get(&table, i, 3, (!(100..800).contains(&i)).then_some(i)).await;
+        }
+
+        let mut cursor = table.scan(&[], None, 3);
+        let mut count = 0;
+        while let Some((k, _)) = cursor.next().await.unwrap() {
+            let i = u64::from_be_bytes(k.try_into().unwrap());
+            assert!(!(100..800).contains(&i), "{i}");
+            count += 1;
+        }
+        assert_eq!(count, N - 700);
+
+        // The ranges still cover the keys after the pages are consolidated and
+        // split, until the safe LSN passes them.
+        for i in 100..200 {
+            must_put(&table, i, 4).await;
+        }
+        table.compact_range(&[], None).await.unwrap();
+        for i in 0..N {
+            must_get(&table, i, 3, (!(100..800).contains(&i)).then_some(i)).await;
+            must_get(&table, i, 4, (!(200..800).contains(&i)).then_some(i)).await;
+        }
+        table.set_safe_lsn(4);
+        table.compact_range(&[], None).await.unwrap();
+        for i in 0..N {
+            must_get(&table, i, 4, (!(200..800).contains(&i)).then_some(i)).await;
+        }
+        table.close().await.unwrap();
+    }
+
+    #[derive(Debug)]
+    struct AddOperator;
+
+    impl MergeOperator for AddOperator {
+        fn merge(&self, _: &[u8], existing: Option<&[u8]>, operands: &[&[u8]]) -> Vec<u8> {
+            let decode = |v: &[u8]| u64::from_be_bytes(v.try_into().unwrap());
+            let sum = operands.iter().fold(existing.map_or(0, decode), |sum, v| {
+                sum.wrapping_add(decode(v))
+            });
+            sum.to_be_bytes().to_vec()
+        }
+    }
+
+    #[photonio::test]
+    async fn merge_operator() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, OPTIONS).await.unwrap();
+        let key = 1u64.to_be_bytes();
+        assert!(matches!(
+            table.merge(&key, 1, &1u64.to_be_bytes()).await,
+            Err(Error::InvalidArgument)
+        ));
+        table.close().await.unwrap();
+
+        let path = tempdir().unwrap();
+        let options = TableOptions {
+            merge_operator: Some(Arc::new(AddOperator)),
+            ..OPTIONS
+        };
+        let table = Table::open(&path, options).await.unwrap();
+        // A merge with no prior value.
+        table.merge(&key, 1, &1u64.to_be_bytes()).await.unwrap();
+        must_get(&table, 1, 1, Some(1)).await;
+        // Stacked merges over a put.
+        must_put(&table, 1, 2).await;
+        for lsn in 3..10 {
+            table.merge(&key, lsn, &1u64.to_be_bytes()).await.unwrap();
+        }
+        must_get(&table, 1, 2, Some(1)).await;
+        must_get(&table, 1, 9, Some(8)).await;
+        // A merge after a delete.
+        table.delete(&key, 10).await.unwrap();
+        must_get(&table, 1, 10, None).await;
+        table.merge(&key, 12, &2u64.to_be_bytes()).await.unwrap();
+        must_get(&table, 1, 12, Some(2)).await;
+        // A delete after a merge.
+        table.delete(&key, 13).await.unwrap();
+        must_get(&table, 1, 12, Some(2)).await;
+        must_get(&table, 1, 13, None).await;
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn merge_counters_across_deltas() {
+        const N: u64 = 10;
+        let path = tempdir().unwrap();
+        let options = TableOptions {
+            merge_operator: Some(Arc::new(AddOperator)),
+            ..OPTIONS
+        };
+        let table = Table::open(&path, options.clone()).await.unwrap();
+        // Each round stacks a delta of each counter over the previous ones, and
+        // some rounds consolidate them.
+        for lsn in 1..=N {
+            for i in 0..N {
+                table
+                    .merge(&i.to_be_bytes(), lsn, &i.to_be_bytes())
+                    .await
+                    .unwrap();
+            }
+            if lsn % 3 == 0 {
+                table.compact_range(&[], None).await.unwrap();
+            }
+        }
+        for i in 1..N {
+            must_get(&table, i, N / 2, Some(i * N / 2)).await;
+            must_get(&table, i, N, Some(i * N)).await;
+        }
+        must_get(&table, 0, N, Some(0)).await;
+        table.close().await.unwrap();
+
+        // The merged values are persisted like other values.
+        let table = Table::open(&path, options).await.unwrap();
+        for i in 1..N {
+            must_get(&table, i, N, Some(i * N)).await;
+        }
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn merge_operands_in_one_chain() {
+        const N: u64 = 5;
+        let path = tempdir().unwrap();
+        // Consolidate whole chains, so that all the operands can be merged.
+        let options = TableOptions {
+            merge_operator: Some(Arc::new(AddOperator)),
+            partial_consolidation_percent: 0,
+            ..OPTIONS
+        };
+        let table = Table::open(&path, options).await.unwrap();
+        // A base value and several operands stacked over it in the delta chain.
+        let key = 1u64.to_be_bytes();
+        must_put(&table, 1, 1).await;
+        for lsn in 2..=N {
+            table.merge(&key, lsn, &1u64.to_be_bytes()).await.unwrap();
+        }
+        // A counter without a base value.
+        let key = 2u64.to_be_bytes();
+        for lsn in 1..=N {
+            table.merge(&key, lsn, &2u64.to_be_bytes()).await.unwrap();
+        }
+        // The operands are merged on reads, in LSN order.
+        for lsn in 1..=N {
+            must_get(&table, 1, lsn, Some(lsn)).await;
+            must_get(&table, 2, lsn, Some(lsn * 2)).await;
+        }
+        // Consolidations merge the operands invisible to the safe LSN.
+        table.set_safe_lsn(N);
+        table.compact_range(&[], None).await.unwrap();
+        must_get(&table, 1, N, Some(N)).await;
+        must_get(&table, 2, N, Some(N * 2)).await;
+        table.close().await.unwrap();
+
+        // The merged values are read without the operator, which ignores
+        // operands, so they must have been merged by the consolidation.
+        let table = Table::open(&path, OPTIONS).await.unwrap();
+        must_get(&table, 1, N, Some(N)).await;
+        must_get(&table, 2, N, Some(N * 2)).await;
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn write_batch() {
+        const N: u64 = 100;
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, OPTIONS).await.unwrap();
+        for i in 0..N {
+            must_put(&table, i, 1).await;
+        }
+
+        let mut batch = WriteBatch::new();
+        for i in N / 2..N + N / 2 {
+            batch.put(&i.to_be_bytes(), &i.to_be_bytes());
+        }
+        for i in 0..N / 2 {
+            batch.delete(&i.to_be_bytes());
+        }
+        // A later write of a key overrides earlier ones in the batch.
+        batch.delete(&N.to_be_bytes());
+        assert_eq!(batch.len(), N as usize + 1);
+        table.write_batch(batch, 2).await.unwrap();
+
+        for i in 0..N + N / 2 {
+            must_get(&table, i, 1, (i < N).then_some(i)).await;
+            must_get(&table, i, 2, (i >= N / 2 && i != N).then_some(i)).await;
+        }
+        // LSNs assigned later are larger than the LSN of the batch.
+        assert!(table.put_auto(b"key", b"value").await.unwrap() > 2);
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn write_batch_rollback() {
+        // Merges into a value larger than a write buffer, which can be read but
+        // can't be written back.
+        #[derive(Debug)]
+        struct LargeOperator;
+
+        impl MergeOperator for LargeOperator {
+            fn merge(&self, _: &[u8], _: Option<&[u8]>, _: &[&[u8]]) -> Vec<u8> {
+                vec![0; 2 << 20]
+            }
+        }
+
+        const N: u64 = 10;
+        let path = tempdir().unwrap();
+        let options = TableOptions {
+            reject_reads_below_safe_lsn: true,
+            merge_operator: Some(Arc::new(LargeOperator)),
+            ..OPTIONS
+        };
+        let table = Table::open(&path, options).await.unwrap();
+        for i in 0..N {
+            must_put(&table, i, 1).await;
+        }
+        table.merge(&N.to_be_bytes(), 1, b"large").await.unwrap();
+        let expiring = (N + 2).to_be_bytes();
+        let mut write_options = WriteOptions::default();
+        write_options.expire_at = Some(unix_timestamp() + 2);
+        table
+            .put_with_options(&expiring, 1, &expiring, &write_options)
+            .await
+            .unwrap();
+        // The versions to roll back to are below the safe LSN.
+        table.set_safe_lsn(2);
+
+        let mut batch = WriteBatch::new();
+        batch.put(&N.to_be_bytes(), b"batch");
+        batch.put(&expiring, &expiring);
+        for i in 0..N {
+            batch.delete(&i.to_be_bytes());
+        }
+        batch.put(&(N + 1).to_be_bytes(), &vec![0; 2 << 20]);
+        let result = table.write_batch(batch, 2).await;
+        assert!(matches!(result, Err(Error::TooLargeSize)));
+
+        // The merged value fails to roll back, but the other keys are still
+        // rolled back.
+        let value = table.get(&N.to_be_bytes(), 2).await.unwrap();
+        assert_eq!(value, Some(b"batch".to_vec()));
+        for i in 0..N {
+            must_get(&table, i, 2, Some(i)).await;
+        }
+        must_get(&table, N + 1, 2, None).await;
+
+        // The rolled back value still expires.
+        must_get(&table, N + 2, 2, Some(N + 2)).await;
+        ::std::thread::sleep(Duration::from_secs(3));
+        must_get(&table, N + 2, 2, None).await;
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn snapshot_retains_versions() {
+        const N: u64 = 100;
+        let path = tempdir().unwrap();
+        let options = TableOptions {
+            reject_reads_below_safe_lsn: true,
+            ..OPTIONS
+        };
+        let table = Table::open(&path, options).await.unwrap();
+        for i in 0..N {
+            must_put(&table, i, 1).await;
+        }
+
+        let snapshot = table.snapshot().await.unwrap();
+        assert_eq!(snapshot.lsn(), 1);
+        for i in 0..N {
+            table.delete(&i.to_be_bytes(), 2).await.unwrap();
+        }
+        // Advancing the safe LSN doesn't drop the versions of the snapshot.
+        table.set_safe_lsn(2);
+        table.compact_range(&[], None).await.unwrap();
+        for i in 0..N {
+            let key = i.to_be_bytes();
+            assert_eq!(snapshot.get(&key).await.unwrap(), Some(key.as_slice()));
+            must_get(&table, i, 2, None).await;
+        }
+        drop(snapshot);
+
+        // Reads below the safe LSN are rejected once the snapshot is dropped.
+        let result = table.get(&0u64.to_be_bytes(), 1).await;
+        assert!(matches!(result, Err(Error::SnapshotTooOld)));
+        // New snapshots read at the safe LSN at least.
+        table.set_safe_lsn(3);
+        assert_eq!(table.snapshot().await.unwrap().lsn(), 3);
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn snapshot_scan() {
+        const N: u64 = 100;
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, OPTIONS).await.unwrap();
+        for i in 0..N {
+            must_put(&table, i, 1).await;
+        }
+
+        let snapshot = table.snapshot().await.unwrap();
+        for i in 0..N {
+            if i % 2 == 0 {
+                table.delete(&i.to_be_bytes(), 2).await.unwrap();
+            } else {
+                must_put(&table, i + N, 2).await;
+            }
+        }
+        table.set_safe_lsn(2);
+        table.compact_range(&[], None).await.unwrap();
+
+        // Scans through the snapshot are repeatable.
+        for _ in 0..2 {
+            let mut cursor = snapshot.scan(&[], None);
+            let mut keys = Vec::new();
+            while let Some((k, _)) = cursor.next().await.unwrap() {
+                keys.push(u64::from_be_bytes(k.try_into().unwrap()));
+            }
+            assert_eq!(keys, (0..N).collect::<Vec<_>>());
+        }
+        drop(snapshot);
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn reject_reads_below_safe_lsn() {
+        for reject in [true, false] {
+            let path = tempdir().unwrap();
+            let options = TableOptions {
+                reject_reads_below_safe_lsn: reject,
+                ..OPTIONS
+            };
+            let table = Table::open(&path, options).await.unwrap();
+            must_put(&table, 1, 1).await;
+            must_put(&table, 1, 2).await;
+            table.set_safe_lsn(2);
+
+            must_get(&table, 1, 2, Some(2)).await;
+            let key = 1u64.to_be_bytes();
+            let result = table.get(&key, 1).await;
+            if reject {
+                assert!(matches!(result, Err(Error::SnapshotTooOld)));
+                let guard = table.pin();
+                assert!(matches!(
+                    guard.get(&key, 1).await,
+                    Err(Error::SnapshotTooOld)
+                ));
+            } else {
+                assert!(result.is_ok());
+            }
+            table.close().await.unwrap();
+        }
+    }
+
+    #[photonio::test]
+    async fn flush_before_close() {
+        let path = tempdir().unwrap();
+        // Unflushed writes are dropped on close, so only flushed writes are
+        // recovered.
+        let options = TableOptions {
+            page_store: PageStoreOptions {
+                avoid_flush_during_shutdown: true,
+                ..OPTIONS.page_store
+            },
+            ..OPTIONS
+        };
+        let table = Table::open(&path, options.clone()).await.unwrap();
+        const N: u64 = 1 << 8;
+        for i in 0..N {
+            must_put(&table, i, i).await;
+        }
+        table.flush(&FlushOptions::default()).await;
+        table.close().await.unwrap();
+
+        let table = Table::open(&path, options).await.unwrap();
+        for i in 0..N {
+            must_get(&table, i, i, Some(i)).await;
+        }
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn memory_env() {
+        let env = env::Memory::default();
+        let table = raw::Table::open(env.clone(), "/db", OPTIONS).await.unwrap();
+        const N: u64 = 1 << 8;
+        for i in 0..N {
+            let buf = i.to_be_bytes();
+            table.put(&buf, i, &buf).await.unwrap();
+        }
+        table.flush(&FlushOptions::default()).await;
+        table.close().await.unwrap();
+        assert!(!env.read_dir("/db").unwrap().is_empty());
+
+        // Files are shared by clones of the environment.
+        let table = raw::Table::open(env.clone(), "/db", OPTIONS).await.unwrap();
+        for i in 0..N {
+            let buf = i.to_be_bytes();
+            let value = table.get(&buf, i).await.unwrap();
+            assert_eq!(value, Some(buf.to_vec()));
+        }
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn flush_delta_pages() {
+        let path = tempdir().unwrap();
+        let options = TableOptions {
+            page_size: 1 << 20,
+            disable_background: true,
+            ..OPTIONS
+        };
+        let table = Table::open(&path, options.clone()).await.unwrap();
+        const N: u64 = 1 << 8;
+        for i in 0..N {
+            must_put(&table, i, 0).await;
+        }
+        table.compact_range(&[], None).await.unwrap();
+        // Each delete adds a delta page to the only leaf page.
+        for i in 0..4u64 {
+            table.delete(&i.to_be_bytes(), 1).await.unwrap();
+        }
+        let stats = table.stats().tree;
+        table.flush(&FlushOptions::default()).await;
+        let stats = table.stats().tree.sub(&stats).success;
+        assert_eq!(stats.consolidate_page, 0);
+        table.close().await.unwrap();
+
+        // The delta chain is read from the page files after reopen.
+        let table = Table::open(&path, options).await.unwrap();
+        for i in 0..N {
+            must_get(&table, i, 0, Some(i)).await;
+            must_get(&table, i, 1, (i >= 4).then_some(i)).await;
+        }
+        assert_eq!(table.stats().tree.success.consolidate_page, 0);
+
+        // The chain is flattened when it is consolidated later.
+        table.compact_range(&[], None).await.unwrap();
+        assert_eq!(table.stats().tree.success.consolidate_page, 1);
+        table.compact_range(&[], None).await.unwrap();
+        assert_eq!(table.stats().tree.success.consolidate_page, 1);
+        for i in 0..N {
+            must_get(&table, i, 1, (i >= 4).then_some(i)).await;
+        }
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn orphan_files() {
+        let path = tempdir().unwrap();
+        let table = Table::open(&path, OPTIONS).await.unwrap();
+        const N: u64 = 1 << 10;
+        for i in 0..N {
+            must_put(&table, i, i).await;
+        }
+        table.flush(&FlushOptions::default()).await;
+        assert!(table.find_orphan_files().await.unwrap().is_empty());
+        let live_files: Vec<_> = ::std::fs::read_dir(&path)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| {
+                path.file_name()
+                    .unwrap()
+                    .to_str()
+                    .unwrap()
+                    .starts_with("map_")
+            })
+            .collect();
+        assert!(!live_files.is_empty());
+
+        let orphan = path.path().join("map_1000000");
+        ::std::fs::write(&orphan, b"orphan").unwrap();
+        assert_eq!(table.find_orphan_files().await.unwrap(), vec![1000000]);
+        assert_eq!(table.gc_orphans().await.unwrap(), vec![1000000]);
+        assert!(!orphan.exists());
+        for file in live_files {
+            assert!(file.exists(), "{file:?} is removed");
+        }
+        assert!(table.find_orphan_files().await.unwrap().is_empty());
+        for i in 0..N {
+            must_get(&table, i, i, Some(i)).await;
+        }
+        table.close().await.unwrap();
+    }
+
+    #[photonio::test]
+    async fn dedup_identical_puts() {
+        for (dedup, keep_versions) in [(true, false), (true, true), (false, false)] {
+            let path = tempdir().unwrap();
+            let options = TableOptions {
+                dedup_identical_puts: dedup,
+                dedup_keeps_versions: keep_versions,
+                disable_background: true,
+                ..OPTIONS
+            };
+            let table = Table::open(&path, options).await.unwrap();
+            const N: u64 = 16;
+            let stats = table.stats().tree;
+            for lsn in 1..=N {
+                must_put(&table, 1, lsn).await;
+            }
+            let stats = table.stats().tree.sub(&stats).success;
+            if dedup {
+                // Only the first put adds a delta page.
+                assert_eq!(stats.write, 1);
+                assert_eq!(stats.skip_write, N - 1);
+            } else {
+                assert_eq!(stats.write, N);
+                assert_eq!(stats.skip_write, 0);
+            }
+            for lsn in 1..=N {
+                must_get(&table, 1, lsn, Some(1)).await;
+            }
+            if keep_versions {
+                // Every put is reported as a change, although the value is stored once.
+                let mut cursor = table.scan_changes(0, N);
+                let mut lsns = Vec::new();
+                while let Some((_, lsn, kind)) = cursor.next().await.unwrap() {
+                    assert_eq!(kind, ChangeKind::Put(1u64.to_be_bytes().to_vec()));
+                    lsns.push(lsn);
+                }
+                assert_eq!(lsns, (1..=N).rev().collect::<Vec<_>>());
+
+                // Consolidation keeps the value of the latest version.
+                table.set_safe_lsn(N);
+                table.compact_range(&[], None).await.unwrap();
+                must_get(&table, 1, N, Some(1)).await;
+            }
+
+            // Different values are always written.
+            let key = 1u64.to_be_bytes();
+            let stats = table.stats().tree;
+            table.put(&key, N + 1, b"other").await.unwrap();
+            table.delete(&key, N + 2).await.unwrap();
+            must_put(&table, 1, N + 3).await;
+            let stats = table.stats().tree.sub(&stats).success;
+            assert_eq!(stats.write, 3);
+            must_get(&table, 1, N + 2, None).await;
+            must_get(&table, 1, N + 3, Some(1)).await;
+            table.close().await.unwrap();
+        }
+    }
 }
@@ -6,16 +6,19 @@ use std::{
     slice,
 };
 
-/// Page format {
+/// Page format (integers are little-endian) {
 ///     epoch      : 6 bytes
 ///     flags      : 1 bytes
 ///     chain_len  : 1 bytes
 ///     chain_next : 8 bytes
 ///     content    : multiple bytes
+///     filter     : multiple bytes (optional)
+///     filter_len : 4 bytes (optional)
 /// }
 const PAGE_EPOCH_MAX: u64 = (1 << 48) - 1;
 const PAGE_EPOCH_LEN: usize = 6;
 const PAGE_HEADER_LEN: usize = 16;
+const PAGE_FILTER_LEN_LEN: usize = 4;
 
 /// A raw pointer to a page.
 ///
@@ -43,6 +46,25 @@ impl PagePtr {
         self.flags().kind()
     }
 
+    /// Returns true if the page contains range deletes.
+    pub(crate) fn has_range_deletes(&self) -> bool {
+        self.flags().has_range_deletes()
+    }
+
+    /// Returns true if the page or any page after it in the chain contains
+    /// range deletes.
+    pub(crate) fn chain_has_range_deletes(&self) -> bool {
+        self.flags().chain_has_range_deletes()
+    }
+
+    /// Updates whether any page after this one in the chain contains range
+    /// deletes.
+    pub(crate) fn set_chain_range_deletes(&mut self, has_range_deletes: bool) {
+        let mut flags = self.flags();
+        flags.set_chain_range_deletes(has_range_deletes);
+        self.set_flags(flags);
+    }
+
     /// Returns the page epoch.
     pub(crate) fn epoch(&self) -> u64 {
         unsafe {
@@ -78,12 +100,12 @@ impl PagePtr {
 
     /// Returns the address of the next page.
     pub(crate) fn chain_next(&self) -> u64 {
-        unsafe { self.chain_next_ptr().read() }
+        unsafe { u64::from_le(self.chain_next_ptr().read()) }
     }
 
     /// Updates the address of the next page.
     pub(crate) fn set_chain_next(&mut self, addr: u64) {
-        unsafe { self.chain_next_ptr().write(addr) }
+        unsafe { self.chain_next_ptr().write(addr.to_le()) }
     }
 
     /// Returns the page size.
@@ -96,6 +118,24 @@ impl PagePtr {
         unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
     }
 
+    /// Returns a mutable byte slice of the page data.
+    fn data_mut<'a>(&mut self) -> &'a mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+
+    /// Returns the key filter of the page, if any.
+    pub(crate) fn filter<'a>(&self) -> Option<&'a [u8]> {
+        if !self.flags().has_filter() {
+            return None;
+        }
+        let filter_len = self.filter_len();
+        let end = self.len - PAGE_FILTER_LEN_LEN;
+        unsafe {
+            let ptr = self.as_ptr().add(end - filter_len);
+            Some(slice::from_raw_parts(ptr, filter_len))
+        }
+    }
+
     /// Returns a byte slice of the page content.
     pub(super) fn content<'a>(&self) -> &'a [u8] {
         unsafe { slice::from_raw_parts(self.content_ptr(), self.content_size()) }
@@ -108,7 +148,8 @@ impl PagePtr {
 
     /// Returns the page info.
     pub(crate) fn info(&self) -> PageInfo {
-        let meta = unsafe { self.as_ptr().cast::<u64>().read() };
+        // The epoch, flags and chain length are read as a little-endian word.
+        let meta = unsafe { u64::from_le(self.as_ptr().cast::<u64>().read()) };
         let next = self.chain_next();
         let size = self.len;
         PageInfo { meta, next, size }
@@ -149,7 +190,19 @@ impl PagePtr {
     }
 
     fn content_size(&self) -> usize {
-        self.len - PAGE_HEADER_LEN
+        if self.flags().has_filter() {
+            self.len - PAGE_HEADER_LEN - self.filter_len() - PAGE_FILTER_LEN_LEN
+        } else {
+            self.len - PAGE_HEADER_LEN
+        }
+    }
+
+    /// Returns the length of the filter, the page must have a filter.
+    fn filter_len(&self) -> usize {
+        debug_assert!(self.flags().has_filter());
+        let data = self.data();
+        let bytes = &data[data.len() - PAGE_FILTER_LEN_LEN..];
+        u32::from_le_bytes(bytes.try_into().unwrap()) as usize
     }
 }
 
@@ -347,11 +400,44 @@ impl From<u8> for PageKind {
     }
 }
 
+/// Set if the page has a key filter at the end.
+const PAGE_FILTER_MASK: u8 = 0b0001_0000;
+/// Set if the page contains range deletes.
+const PAGE_RANGE_DELETES_MASK: u8 = 0b0010_0000;
+/// Set if any page after this one in the chain contains range deletes.
+const PAGE_CHAIN_RANGE_DELETES_MASK: u8 = 0b0100_0000;
+
 struct PageFlags(u8);
 
 impl PageFlags {
-    fn new(tier: PageTier, kind: PageKind) -> Self {
-        Self(tier as u8 | kind as u8)
+    fn new(tier: PageTier, kind: PageKind, has_filter: bool, has_range_deletes: bool) -> Self {
+        let filter = if has_filter { PAGE_FILTER_MASK } else { 0 };
+        let range_deletes = if has_range_deletes {
+            PAGE_RANGE_DELETES_MASK
+        } else {
+            0
+        };
+        Self(tier as u8 | kind as u8 | filter | range_deletes)
+    }
+
+    fn has_filter(&self) -> bool {
+        self.0 & PAGE_FILTER_MASK != 0
+    }
+
+    fn has_range_deletes(&self) -> bool {
+        self.0 & PAGE_RANGE_DELETES_MASK != 0
+    }
+
+    fn chain_has_range_deletes(&self) -> bool {
+        self.0 & (PAGE_RANGE_DELETES_MASK | PAGE_CHAIN_RANGE_DELETES_MASK) != 0
+    }
+
+    fn set_chain_range_deletes(&mut self, has_range_deletes: bool) {
+        if has_range_deletes {
+            self.0 |= PAGE_CHAIN_RANGE_DELETES_MASK;
+        } else {
+            self.0 &= !PAGE_CHAIN_RANGE_DELETES_MASK;
+        }
     }
 
     fn tier(&self) -> PageTier {
@@ -367,23 +453,60 @@ impl PageFlags {
 pub(super) struct PageBuilder {
     tier: PageTier,
     kind: PageKind,
+    filter: Option<Vec<u8>>,
+    has_range_deletes: bool,
 }
 
 impl PageBuilder {
     pub(super) fn new(tier: PageTier, kind: PageKind) -> Self {
-        Self { tier, kind }
+        Self {
+            tier,
+            kind,
+            filter: None,
+            has_range_deletes: false,
+        }
+    }
+
+    /// Attaches a key filter to the page.
+    pub(super) fn set_filter(&mut self, filter: Vec<u8>) {
+        assert!(filter.len() <= u32::MAX as usize);
+        self.filter = Some(filter);
+    }
+
+    /// Marks the page as containing range deletes.
+    pub(super) fn set_range_deletes(&mut self) {
+        self.has_range_deletes = true;
     }
 
     pub(super) fn size(&self, content_size: usize) -> usize {
-        PAGE_HEADER_LEN + content_size
+        let filter_size = self
+            .filter
+            .as_ref()
+            .map_or(0, |filter| filter.len() + PAGE_FILTER_LEN_LEN);
+        PAGE_HEADER_LEN + content_size + filter_size
     }
 
+    /// Builds the page header and the filter.
+    ///
+    /// The content should be built after this, since the content size depends
+    /// on the filter.
     pub(super) fn build(&self, page: &mut PageBuf<'_>) {
-        let flags = PageFlags::new(self.tier, self.kind);
+        let flags = PageFlags::new(
+            self.tier,
+            self.kind,
+            self.filter.is_some(),
+            self.has_range_deletes,
+        );
         page.set_flags(flags);
         page.set_epoch(0);
         page.set_chain_len(1);
         page.set_chain_next(0);
+        if let Some(filter) = self.filter.as_ref() {
+            let data = page.data_mut();
+            let (rest, filter_len) = data.split_at_mut(data.len() - PAGE_FILTER_LEN_LEN);
+            filter_len.copy_from_slice(&(filter.len() as u32).to_le_bytes());
+            rest[rest.len() - filter.len()..].copy_from_slice(filter);
+        }
     }
 }
 
@@ -414,6 +537,13 @@ impl PageInfo {
         self.flags().kind()
     }
 
+    /// Returns true if the page or any page after it in the chain contains
+    /// range deletes.
+    #[inline]
+    pub(crate) fn chain_has_range_deletes(&self) -> bool {
+        self.flags().chain_has_range_deletes()
+    }
+
     /// Returns the page epoch.
     #[inline]
     pub(crate) fn epoch(&self) -> u64 {
@@ -484,5 +614,73 @@ mod tests {
         assert_eq!(page.data().len(), PAGE_HEADER_LEN + 1);
         assert_eq!(page.content().len(), 1);
         assert_eq!(page.content_mut().len(), 1);
+        assert_eq!(page.filter(), None);
+    }
+
+    #[test]
+    fn page_with_range_deletes() {
+        let mut buf = alloc_page(PAGE_HEADER_LEN);
+        let mut page = PageBuf::new(buf.as_mut());
+        PageBuilder::new(PageTier::Leaf, PageKind::Data).build(&mut page);
+        assert!(!page.has_range_deletes());
+        assert!(!page.chain_has_range_deletes());
+        page.set_chain_range_deletes(true);
+        assert!(!page.has_range_deletes());
+        assert!(page.chain_has_range_deletes());
+        assert!(page.info().chain_has_range_deletes());
+        page.set_chain_range_deletes(false);
+        assert!(!page.chain_has_range_deletes());
+
+        let mut builder = PageBuilder::new(PageTier::Leaf, PageKind::Data);
+        builder.set_range_deletes();
+        builder.build(&mut page);
+        assert!(page.has_range_deletes());
+        assert!(page.chain_has_range_deletes());
+        // The flags don't change the other parts of the header.
+        assert!(page.tier().is_leaf());
+        assert!(page.kind().is_data());
+        assert_eq!(page.filter(), None);
+    }
+
+    #[test]
+    fn page_header_layout() {
+        let mut buf = alloc_page(PAGE_HEADER_LEN);
+        let mut page = PageBuf::new(buf.as_mut());
+        PageBuilder::new(PageTier::Inner, PageKind::Data).build(&mut page);
+        page.set_epoch(0x0605_0403_0201);
+        page.set_chain_len(7);
+        page.set_chain_next(0x1817_1615_1413_1211);
+
+        // The header is the same on all hosts.
+        let flags = page.flags().0;
+        let expect = [
+            [1, 2, 3, 4, 5, 6, flags, 7],
+            [0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18],
+        ]
+        .concat();
+        assert_eq!(page.data(), expect.as_slice());
+
+        let info = page.info();
+        assert_eq!(info.epoch(), 0x0605_0403_0201);
+        assert_eq!(info.chain_len(), 7);
+        assert_eq!(info.chain_next(), 0x1817_1615_1413_1211);
+        assert!(info.tier().is_inner());
+    }
+
+    #[test]
+    fn page_with_filter() {
+        let mut builder = PageBuilder::new(PageTier::Leaf, PageKind::Data);
+        builder.set_filter(vec![1, 2, 3]);
+        let size = builder.size(5);
+        assert_eq!(size, PAGE_HEADER_LEN + 5 + 3 + PAGE_FILTER_LEN_LEN);
+        let mut buf = alloc_page(size);
+        let mut page = PageBuf::new(buf.as_mut());
+        builder.build(&mut page);
+        assert_eq!(page.content_mut().len(), 5);
+        assert_eq!(page.filter(), Some([1, 2, 3].as_slice()));
+
+        let page = PageRef::new(&buf);
+        assert_eq!(page.content().len(), 5);
+        assert_eq!(page.filter(), Some([1, 2, 3].as_slice()));
     }
 }
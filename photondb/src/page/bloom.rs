@@ -0,0 +1,176 @@
+use std::f64::consts::LN_2;
+
+/// The maximum number of probes of a bloom filter.
+const MAX_PROBES: u32 = 30;
+
+/// Builds a bloom filter over a set of keys.
+///
+/// The filter layout is the same as LevelDB: a bit array followed by one byte
+/// recording the number of probes.
+pub(crate) struct BloomFilterBuilder {
+    bits_per_key: usize,
+    num_probes: u32,
+    hashes: Vec<u32>,
+}
+
+impl BloomFilterBuilder {
+    /// Creates a builder with the given number of bits per key.
+    pub(crate) fn new(bits_per_key: usize) -> Self {
+        let bits_per_key = bits_per_key.max(1);
+        // The optimal number of probes is `bits_per_key * ln(2)`.
+        let num_probes = ((bits_per_key as f64) * LN_2) as u32;
+        Self {
+            bits_per_key,
+            num_probes: num_probes.clamp(1, MAX_PROBES),
+            hashes: Vec::new(),
+        }
+    }
+
+    /// Creates a builder targeting the given false-positive rate.
+    ///
+    /// The rate must be in (0, 1).
+    pub(crate) fn with_fp_rate(fp_rate: f64) -> Self {
+        assert!(
+            is_valid_fp_rate(fp_rate),
+            "invalid bloom false-positive rate {fp_rate}"
+        );
+        // The optimal number of bits per key is `-ln(p) / ln(2)^2`.
+        let bits_per_key = (-fp_rate.ln() / (LN_2 * LN_2)).ceil() as usize;
+        Self::new(bits_per_key)
+    }
+
+    /// Adds a key to the filter.
+    pub(crate) fn add(&mut self, key: &[u8]) {
+        self.hashes.push(bloom_hash(key));
+    }
+
+    /// Finishes the filter and returns its content.
+    pub(crate) fn finish(&mut self) -> Vec<u8> {
+        // Use a minimum size to avoid a high false-positive rate for small sets.
+        let bits = (self.hashes.len() * self.bits_per_key).max(64);
+        let bytes = (bits + 7) / 8;
+        let bits = bytes * 8;
+        let mut data = vec![0u8; bytes + 1];
+        for &hash in &self.hashes {
+            for_each_probe(hash, self.num_probes, bits, |pos| {
+                data[pos / 8] |= 1 << (pos % 8);
+            });
+        }
+        data[bytes] = self.num_probes as u8;
+        self.hashes.clear();
+        data
+    }
+}
+
+/// A reference to a bloom filter built by [`BloomFilterBuilder`].
+#[derive(Clone, Copy)]
+pub(crate) struct BloomFilter<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> BloomFilter<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    /// Returns false if the key is definitely not in the filter.
+    pub(crate) fn may_contain(&self, key: &[u8]) -> bool {
+        let Some((&num_probes, data)) = self.data.split_last() else {
+            return true;
+        };
+        if num_probes as u32 > MAX_PROBES || data.is_empty() {
+            // Reserved for other encodings, consider it a match.
+            return true;
+        }
+        let bits = data.len() * 8;
+        let mut found = true;
+        for_each_probe(bloom_hash(key), num_probes as u32, bits, |pos| {
+            found &= data[pos / 8] & (1 << (pos % 8)) != 0;
+        });
+        found
+    }
+}
+
+/// Returns true if the false-positive rate is in (0, 1).
+pub(crate) fn is_valid_fp_rate(fp_rate: f64) -> bool {
+    fp_rate > 0.0 && fp_rate < 1.0
+}
+
+/// Uses double hashing to generate a sequence of probes.
+fn for_each_probe(mut hash: u32, num_probes: u32, bits: usize, mut f: impl FnMut(usize)) {
+    let delta = hash.rotate_right(17);
+    for _ in 0..num_probes {
+        f(hash as usize % bits);
+        hash = hash.wrapping_add(delta);
+    }
+}
+
+/// The hash function used by LevelDB bloom filters.
+fn bloom_hash(key: &[u8]) -> u32 {
+    const SEED: u32 = 0xbc9f1d34;
+    const M: u32 = 0xc6a4a793;
+    let mut h = SEED ^ (key.len() as u32).wrapping_mul(M);
+    let mut chunks = key.chunks_exact(4);
+    for chunk in &mut chunks {
+        let w = u32::from_le_bytes(chunk.try_into().unwrap());
+        h = h.wrapping_add(w).wrapping_mul(M);
+        h ^= h >> 16;
+    }
+    let rest = chunks.remainder();
+    if !rest.is_empty() {
+        for (i, &b) in rest.iter().enumerate() {
+            h = h.wrapping_add((b as u32) << (8 * i));
+        }
+        h = h.wrapping_mul(M);
+        h ^= h >> 24;
+    }
+    h
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bloom_fp_rate() {
+        let mut builder = BloomFilterBuilder::with_fp_rate(0.01);
+        assert_eq!(builder.bits_per_key, 10);
+        assert_eq!(builder.num_probes, 6);
+
+        const N: u32 = 10000;
+        for i in 0..N {
+            builder.add(&i.to_be_bytes());
+        }
+        let data = builder.finish();
+        let filter = BloomFilter::new(&data);
+        for i in 0..N {
+            assert!(filter.may_contain(&i.to_be_bytes()));
+        }
+
+        let mut false_positives = 0;
+        for i in N..(N * 11) {
+            if filter.may_contain(&i.to_be_bytes()) {
+                false_positives += 1;
+            }
+        }
+        let fp_rate = false_positives as f64 / (N * 10) as f64;
+        assert!(fp_rate < 0.02, "false-positive rate {fp_rate}");
+    }
+
+    #[test]
+    fn bloom_empty() {
+        let mut builder = BloomFilterBuilder::new(10);
+        let data = builder.finish();
+        let filter = BloomFilter::new(&data);
+        assert!(!filter.may_contain(b"hello"));
+        assert!(!filter.may_contain(b""));
+    }
+
+    #[test]
+    fn bloom_invalid_fp_rate() {
+        assert!(!is_valid_fp_rate(0.0));
+        assert!(!is_valid_fp_rate(1.0));
+        assert!(!is_valid_fp_rate(f64::NAN));
+        assert!(is_valid_fp_rate(0.5));
+    }
+}
@@ -74,6 +74,11 @@ impl Encoder {
         let cursor = self.take(v.len());
         cursor.copy_from_nonoverlapping(v.as_ptr(), v.len());
     }
+
+    /// Skips `n` bytes, leaving them to be written later.
+    pub(super) unsafe fn skip(&mut self, n: usize) {
+        self.take(n);
+    }
 }
 
 // An unsafe, little-endian decoder.
@@ -47,20 +47,54 @@ impl<'a> Range<'a> {
             end: None,
         }
     }
+
+    /// Returns true if the range contains the raw key.
+    pub(crate) fn contains(&self, raw: &[u8]) -> bool {
+        self.start <= raw && self.end.map_or(true, |end| raw < end)
+    }
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub(crate) enum Value<'a> {
     Put(&'a [u8]),
     Delete,
+    /// A put that expires at the unix time in seconds.
+    Expiring(&'a [u8], u64),
+    /// A version that keeps the value of the previous version of the key.
+    ///
+    /// It records the LSN of a put that is deduplicated against the previous
+    /// value, and must be resolved against the previous version when read.
+    Unchanged,
+    /// A delete of the keys from the raw key of the version up to the given
+    /// end, exclusive.
+    ///
+    /// It deletes all versions of the keys in the range whose LSNs are not
+    /// larger than its LSN, including the ones written after it.
+    RangeDelete(&'a [u8]),
+    /// An operand to merge into the value of the previous version of the key
+    /// with the merge operator.
+    Merge(&'a [u8]),
 }
 
 impl<'a> Value<'a> {
     /// Returns the length of value.
     pub(crate) fn len(&self) -> usize {
         match self {
-            Value::Put(v) => v.len(),
-            Value::Delete => 0,
+            Value::Put(v) | Value::Expiring(v, _) | Value::Merge(v) => v.len(),
+            Value::Delete | Value::Unchanged | Value::RangeDelete(_) => 0,
+        }
+    }
+
+    /// Returns the content of the value if it is live at the unix time `now`
+    /// in seconds.
+    ///
+    /// Unchanged versions and merge operands must be resolved first, they have
+    /// no content.
+    pub(crate) fn live_at(&self, now: u64) -> Option<&'a [u8]> {
+        match *self {
+            Value::Put(v) => Some(v),
+            Value::Expiring(v, expire_at) if now < expire_at => Some(v),
+            _ => None,
         }
     }
 }
@@ -97,4 +131,16 @@ mod tests {
         assert!(b > d);
         assert!(c > d);
     }
+
+    #[test]
+    fn value_live_at() {
+        assert_eq!(Value::Put(b"v").live_at(u64::MAX), Some(b"v".as_slice()));
+        assert_eq!(Value::Delete.live_at(0), None);
+        let value = Value::Expiring(b"v", 10);
+        assert_eq!(value.live_at(9), Some(b"v".as_slice()));
+        assert_eq!(value.live_at(10), None);
+        assert_eq!(Value::Unchanged.live_at(0), None);
+        assert_eq!(Value::RangeDelete(b"v").live_at(0), None);
+        assert_eq!(Value::Merge(b"v").live_at(0), None);
+    }
 }
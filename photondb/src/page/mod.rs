@@ -7,6 +7,10 @@ mod data;
 pub(crate) use data::{Index, Key, Range, Value};
 
 mod codec;
+pub(crate) use codec::Codec;
+
+mod bloom;
+pub(crate) use bloom::is_valid_fp_rate;
 
 mod base_page;
 use base_page::PageBuilder;
@@ -14,7 +18,8 @@ pub(crate) use base_page::{PageBuf, PageInfo, PageKind, PageRef, PageTier};
 
 mod sorted_page;
 pub(crate) use sorted_page::{
-    SortedPageBuilder, SortedPageIter, SortedPageKey, SortedPageRef, SortedPageValue,
+    leaf_page_key_range, reserved_value_mut, ReservedValue, SortedPageBuilder, SortedPageIter,
+    SortedPageKey, SortedPageRangeIter, SortedPageRef, SortedPageValue,
 };
 
 pub(crate) type ValuePageRef<'a> = SortedPageRef<'a, Key<'a>, Value<'a>>;
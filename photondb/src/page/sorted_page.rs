@@ -8,8 +8,11 @@ use std::{
 };
 
 use super::{
-    codec::*, data::*, ItemIter, PageBuf, PageBuilder, PageKind, PageRef, PageTier,
-    RewindableIterator, SeekableIterator, SliceIter,
+    bloom::{BloomFilter, BloomFilterBuilder},
+    codec::*,
+    data::*,
+    ItemIter, PageBuf, PageBuilder, PageKind, PageRef, PageTier, RewindableIterator,
+    SeekableIterator, SliceIter,
 };
 
 /// Builds a sorted page from an iterator.
@@ -18,6 +21,7 @@ pub(crate) struct SortedPageBuilder<I> {
     iter: Option<I>,
     num_items: usize,
     content_size: usize,
+    filter: Option<BloomFilterBuilder>,
 }
 
 impl<I, K, V> SortedPageBuilder<I>
@@ -34,17 +38,43 @@ where
             iter: None,
             num_items: 0,
             content_size: 0,
+            filter: None,
         }
     }
 
+    /// Builds a bloom filter over the raw keys with the given false-positive
+    /// rate.
+    ///
+    /// This must be called before the items are provided.
+    pub(crate) fn with_filter(mut self, fp_rate: f64) -> Self {
+        assert!(self.iter.is_none());
+        self.filter = Some(BloomFilterBuilder::with_fp_rate(fp_rate));
+        self
+    }
+
     /// Creates a [`SortedPageBuilder`] that will build a page from the given
     /// iterator.
     pub(crate) fn with_iter(mut self, mut iter: I) -> Self {
+        let mut has_range_deletes = false;
         for (k, v) in &mut iter {
             self.num_items += 1;
             self.content_size += k.encode_size() + v.encode_size();
+            if let Some(filter) = self.filter.as_mut() {
+                filter.add(k.as_raw());
+            }
+            has_range_deletes |= v.is_range_delete();
+        }
+        // Range deletes are marked in the page header, so that reads only look
+        // for them in the pages that have them.
+        if has_range_deletes {
+            self.base.set_range_deletes();
         }
         self.content_size += self.num_items * mem::size_of::<u32>();
+        if let Some(mut filter) = self.filter.take() {
+            if self.num_items > 0 {
+                self.base.set_filter(filter.finish());
+            }
+        }
         // We use `u32` to store item offsets, so the content size must not exceed
         // `u32::MAX`.
         assert!(self.content_size <= u32::MAX as usize);
@@ -169,6 +199,22 @@ where
         self.offsets.len()
     }
 
+    /// Returns false if the page definitely doesn't contain the raw key.
+    ///
+    /// The key is checked against the span of keys in the page first, and then
+    /// against the filter if the page has one.
+    pub(crate) fn may_contain(&self, raw: &[u8]) -> bool {
+        let (Some(first), Some(last)) = (self.key(0), self.key(self.len().wrapping_sub(1))) else {
+            return false;
+        };
+        if raw < first.as_raw() || raw > last.as_raw() {
+            return false;
+        }
+        self.page
+            .filter()
+            .map_or(true, |filter| BloomFilter::new(filter).may_contain(raw))
+    }
+
     /// Returns the item at the given index.
     pub(crate) fn get(&self, index: usize) -> Option<(K, V)> {
         if let Some(item) = self.item(index) {
@@ -199,11 +245,7 @@ where
         let mut right = self.len();
         while left < right {
             let mid = (left + right) / 2;
-            let key = unsafe {
-                let item = self.item(mid).unwrap();
-                let mut dec = Decoder::new(item);
-                K::decode_from(&mut dec)
-            };
+            let key = self.key(mid).unwrap();
             match key.borrow().cmp(target) {
                 Ordering::Less => left = mid + 1,
                 Ordering::Greater => right = mid,
@@ -213,7 +255,8 @@ where
         Err(left)
     }
 
-    /// Finds a separator to split the page into two halves.
+    /// Finds a separator to split the page into two halves with the same number
+    /// of items.
     ///
     /// If a split separator is found, returns [`Option::Some`] with the split
     /// separator, an iterator over items before the separator, and another
@@ -225,9 +268,54 @@ where
         K,
         SortedPageRangeIter<'a, K, V>,
         SortedPageRangeIter<'a, K, V>,
+    )> {
+        let mid = self.len() / 2;
+        self.into_split_iter_at(mid)
+    }
+
+    /// Finds a separator to split the page into two halves with approximately
+    /// the same size of items.
+    ///
+    /// This is similar to [`Self::into_split_iter`], but it works better if
+    /// the sizes of items vary a lot.
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn into_split_iter_by_size(
+        self,
+    ) -> Option<(
+        K,
+        SortedPageRangeIter<'a, K, V>,
+        SortedPageRangeIter<'a, K, V>,
+    )> {
+        let Some(start) = self.item_offset(0) else {
+            return None;
+        };
+        let half = (start + self.content.len()) / 2;
+        // Finds the first item that starts at or after the half of the content.
+        let mut mid = self
+            .offsets
+            .partition_point(|v| (u32::from_le(*v) as usize) < half);
+        // The item before it crosses the half, choose the boundary closer to the half.
+        if mid > 1 {
+            let prev = self.item_offset(mid - 1).unwrap();
+            let next = self.item_offset(mid).unwrap_or(self.content.len());
+            if mid == self.len() || half - prev < next - half {
+                mid -= 1;
+            }
+        }
+        self.into_split_iter_at(mid)
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn into_split_iter_at(
+        self,
+        mid: usize,
+    ) -> Option<(
+        K,
+        SortedPageRangeIter<'a, K, V>,
+        SortedPageRangeIter<'a, K, V>,
     )> {
         let len = self.len();
-        if let Some((mid, _)) = self.get(len / 2) {
+        if let Some((mid, _)) = self.get(mid) {
             let sep = mid.as_split_separator();
             let index = match self.rank(&sep) {
                 Ok(i) => i,
@@ -242,6 +330,13 @@ where
         None
     }
 
+    fn key(&self, index: usize) -> Option<K> {
+        self.item(index).map(|item| {
+            let mut dec = Decoder::new(item);
+            unsafe { K::decode_from(&mut dec) }
+        })
+    }
+
     fn item(&self, index: usize) -> Option<&[u8]> {
         if let Some(offset) = self.item_offset(index) {
             let next_offset = self.item_offset(index + 1).unwrap_or(self.content.len());
@@ -275,6 +370,18 @@ where
     }
 }
 
+/// Returns the first and the last raw keys of a leaf data page.
+///
+/// Returns `None` if the page is not a leaf data page or is empty.
+pub(crate) fn leaf_page_key_range(page: PageRef<'_>) -> Option<(&[u8], &[u8])> {
+    if !page.tier().is_leaf() || !page.kind().is_data() {
+        return None;
+    }
+    let page = SortedPageRef::<Key<'_>, Value<'_>>::new(page);
+    let last = page.len().checked_sub(1)?;
+    Some((page.key(0)?.raw, page.key(last)?.raw))
+}
+
 /// An iterator over the items in a sorted page.
 #[derive(Clone)]
 pub(crate) struct SortedPageIter<'a, K, V> {
@@ -418,9 +525,12 @@ pub(crate) trait SortedPageKey: Codec + Clone + Ord {
 }
 
 /// Required methods for values in a sorted page.
-pub(crate) trait SortedPageValue: Codec + Clone {}
-
-impl<T> SortedPageValue for T where T: Codec + Clone {}
+pub(crate) trait SortedPageValue: Codec + Clone {
+    /// Returns true if the value deletes a range of keys.
+    fn is_range_delete(&self) -> bool {
+        false
+    }
+}
 
 impl Codec for &[u8] {
     fn encode_size(&self) -> usize {
@@ -438,6 +548,8 @@ impl Codec for &[u8] {
     }
 }
 
+impl SortedPageValue for &[u8] {}
+
 impl SortedPageKey for &[u8] {
     fn as_raw(&self) -> &[u8] {
         self
@@ -479,12 +591,18 @@ impl SortedPageKey for Key<'_> {
 /// These values are persisted to disk, don't change them.
 const VALUE_KIND_PUT: u8 = 0;
 const VALUE_KIND_DELETE: u8 = 1;
+const VALUE_KIND_EXPIRING: u8 = 2;
+const VALUE_KIND_UNCHANGED: u8 = 3;
+const VALUE_KIND_RANGE_DELETE: u8 = 4;
+const VALUE_KIND_MERGE: u8 = 5;
 
 impl Codec for Value<'_> {
     fn encode_size(&self) -> usize {
         1 + match self {
-            Self::Put(v) => v.len(),
-            Self::Delete => 0,
+            Self::Put(v) | Self::Merge(v) => v.len(),
+            Self::Delete | Self::Unchanged => 0,
+            Self::Expiring(v, _) => mem::size_of::<u64>() + v.len(),
+            Self::RangeDelete(end) => end.len(),
         }
     }
 
@@ -495,6 +613,20 @@ impl Codec for Value<'_> {
                 enc.put_slice(v);
             }
             Value::Delete => enc.put_u8(VALUE_KIND_DELETE),
+            Value::Expiring(v, expire_at) => {
+                enc.put_u8(VALUE_KIND_EXPIRING);
+                enc.put_u64(*expire_at);
+                enc.put_slice(v);
+            }
+            Value::Unchanged => enc.put_u8(VALUE_KIND_UNCHANGED),
+            Value::RangeDelete(end) => {
+                enc.put_u8(VALUE_KIND_RANGE_DELETE);
+                enc.put_slice(end);
+            }
+            Value::Merge(v) => {
+                enc.put_u8(VALUE_KIND_MERGE);
+                enc.put_slice(v);
+            }
         }
     }
 
@@ -503,11 +635,61 @@ impl Codec for Value<'_> {
         match kind {
             VALUE_KIND_PUT => Self::Put(dec.get_slice(dec.remaining())),
             VALUE_KIND_DELETE => Self::Delete,
+            VALUE_KIND_EXPIRING => {
+                let expire_at = dec.get_u64();
+                Self::Expiring(dec.get_slice(dec.remaining()), expire_at)
+            }
+            VALUE_KIND_UNCHANGED => Self::Unchanged,
+            VALUE_KIND_RANGE_DELETE => Self::RangeDelete(dec.get_slice(dec.remaining())),
+            VALUE_KIND_MERGE => Self::Merge(dec.get_slice(dec.remaining())),
             _ => unreachable!(),
         }
     }
 }
 
+impl SortedPageValue for Value<'_> {
+    fn is_range_delete(&self) -> bool {
+        matches!(self, Self::RangeDelete(_))
+    }
+}
+
+/// A put value whose content is written after the page is built.
+///
+/// A [`ReservedValue`] is decoded as a [`Value::Put`].
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct ReservedValue(pub(crate) usize);
+
+impl Codec for ReservedValue {
+    fn encode_size(&self) -> usize {
+        1 + self.0
+    }
+
+    unsafe fn encode_to(&self, enc: &mut Encoder) {
+        enc.put_u8(VALUE_KIND_PUT);
+        enc.skip(self.0);
+    }
+
+    unsafe fn decode_from(dec: &mut Decoder) -> Self {
+        let kind = dec.get_u8();
+        assert_eq!(kind, VALUE_KIND_PUT);
+        Self(dec.remaining())
+    }
+}
+
+impl SortedPageValue for ReservedValue {}
+
+/// Returns the content of the reserved value in a page built from a single
+/// `(key, value)` item.
+pub(crate) fn reserved_value_mut<'a, K: SortedPageKey>(
+    page: &mut PageBuf<'a>,
+    key: &K,
+    value: ReservedValue,
+) -> &'a mut [u8] {
+    // The page content is `{offset} {key} {value kind} {value}`.
+    let offset = mem::size_of::<u32>() + key.encode_size() + 1;
+    &mut page.content_mut()[offset..offset + value.0]
+}
+
 impl Codec for Index {
     fn encode_size(&self) -> usize {
         mem::size_of::<u64>() * 2
@@ -525,6 +707,8 @@ impl Codec for Index {
     }
 }
 
+impl SortedPageValue for Index {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -561,6 +745,143 @@ mod tests {
         }
     }
 
+    #[test]
+    fn value_codec() {
+        let values = [
+            Value::Put(b"value"),
+            Value::Put(&[]),
+            Value::Delete,
+            Value::Expiring(b"value", 7),
+            Value::Unchanged,
+            Value::RangeDelete(b"end"),
+            Value::Merge(b"operand"),
+        ];
+        for value in values {
+            let mut buf = vec![0; value.encode_size()];
+            unsafe {
+                let mut enc = Encoder::new(&mut buf);
+                value.encode_to(&mut enc);
+                assert_eq!(enc.remaining(), 0);
+                let mut dec = Decoder::new(&buf);
+                assert_eq!(Value::decode_from(&mut dec), value);
+            }
+        }
+        // The value kinds are persisted, so the layout must not change.
+        let mut buf = vec![0; 3];
+        unsafe { Value::Put(b"ab").encode_to(&mut Encoder::new(&mut buf)) };
+        assert_eq!(buf, [VALUE_KIND_PUT, b'a', b'b']);
+    }
+
+    #[test]
+    fn range_delete_page() {
+        let data = [
+            (Key::new(&[1], 2), Value::RangeDelete(&[3])),
+            (Key::new(&[2], 1), Value::Put(&[2])),
+        ];
+        let owned_page = OwnedSortedPage::from_slice(&data);
+        let page = owned_page.as_ref();
+        assert!(page.has_range_deletes());
+        assert_eq!(page.get(0), Some(data[0]));
+
+        let owned_page = OwnedSortedPage::from_slice(&data[1..]);
+        assert!(!owned_page.as_ref().has_range_deletes());
+    }
+
+    #[test]
+    fn index_page_round_trip() {
+        let data: [(&[u8], Index); 3] = [
+            (&[], Index::new(1, 1)),
+            (&[3], Index::new(2, 0)),
+            (&[5], Index::new(u64::MAX, u64::MAX)),
+        ];
+        let builder = SortedPageBuilder::new(PageTier::Inner, PageKind::Data).with_slice(&data);
+        let mut buf = alloc_page(builder.size());
+        let mut page = PageBuf::new(buf.as_mut());
+        builder.build(&mut page);
+
+        // Reads the children from a copy of the page, as if it is read from disk.
+        let mut copy = alloc_page(buf.len());
+        copy.copy_from_slice(&buf);
+        let page = SortedPageRef::<&[u8], Index>::from(copy.as_ref());
+        assert_eq!(page.len(), data.len());
+        for (i, item) in data.iter().enumerate() {
+            assert_eq!(page.get(i), Some(*item));
+        }
+        // The child that encloses a key is the last one not greater than it.
+        for (key, child) in [(0u8, 0), (3, 1), (4, 1), (5, 2), (9, 2)] {
+            let i = match page.rank([key].as_slice()) {
+                Ok(i) => i,
+                Err(i) => i - 1,
+            };
+            assert_eq!(i, child);
+        }
+    }
+
+    #[test]
+    fn sorted_page_key_range() {
+        let data = key_slice(&[([1], 2), ([1], 1), ([3], 3), ([5], 0)]);
+        let owned_page = OwnedSortedPage::from_slice(&data);
+        assert_eq!(
+            leaf_page_key_range(*owned_page.as_ref()),
+            Some(([1].as_slice(), [5].as_slice()))
+        );
+
+        let owned_page = OwnedSortedPage::<Key, &[u8]>::from_slice(&[]);
+        assert_eq!(leaf_page_key_range(*owned_page.as_ref()), None);
+
+        let data = [([1].as_slice(), Index::new(1, 0))];
+        let builder = SortedPageBuilder::new(PageTier::Inner, PageKind::Data).with_slice(&data);
+        let mut buf = alloc_page(builder.size());
+        let mut page = PageBuf::new(buf.as_mut());
+        builder.build(&mut page);
+        assert_eq!(leaf_page_key_range(page.into()), None);
+    }
+
+    #[test]
+    fn sorted_page_filter() {
+        let data = key_slice(&[([1], 2), ([1], 1), ([3], 3), ([5], 0)]);
+        let builder = SortedPageBuilder::new(PageTier::Leaf, PageKind::Data)
+            .with_filter(0.01)
+            .with_slice(&data);
+        let mut buf = alloc_page(builder.size());
+        let mut page = PageBuf::new(buf.as_mut());
+        builder.build(&mut page);
+
+        let page = SortedPageRef::<Key, &[u8]>::from(buf.as_ref());
+        assert_eq!(page.len(), data.len());
+        for (i, item) in data.iter().enumerate() {
+            assert_eq!(page.get(i), Some(*item));
+            assert!(page.may_contain(item.0.raw));
+        }
+        assert!(!page.may_contain(&[2]));
+        assert!(!page.may_contain(&[4]));
+
+        assert!(!page.may_contain(&[0]));
+        assert!(!page.may_contain(&[6]));
+
+        // Pages without a filter may contain any key within their span.
+        let owned_page = OwnedSortedPage::from_slice(&data);
+        assert!(owned_page.as_ref().may_contain(&[2]));
+        assert!(!owned_page.as_ref().may_contain(&[0]));
+        assert!(!owned_page.as_ref().may_contain(&[6]));
+    }
+
+    #[test]
+    fn sorted_page_reserved_value() {
+        let key = Key::new([1].as_slice(), 2);
+        let value = ReservedValue(3);
+        let builder =
+            SortedPageBuilder::new(PageTier::Leaf, PageKind::Data).with_item((key, value));
+        let mut buf = alloc_page(builder.size());
+        let mut page = PageBuf::new(buf.as_mut());
+        builder.build(&mut page);
+        reserved_value_mut(&mut page, &key, value).copy_from_slice(&[4, 5, 6]);
+
+        let page = SortedPageRef::<Key, Value>::from(buf.as_ref());
+        assert_eq!(page.len(), 1);
+        assert_eq!(page.get(0), Some((key, Value::Put([4, 5, 6].as_slice()))));
+    }
+
     #[test]
     fn sorted_page_split() {
         // The middle key is ([3], 2), but it should split at ([3], 3).
@@ -584,12 +905,40 @@ mod tests {
         }
     }
 
+    #[test]
+    fn sorted_page_split_by_size() {
+        let small = [0u8; 1];
+        let large = [0u8; 64];
+        let data = [
+            ([1].as_slice(), large.as_slice()),
+            ([2].as_slice(), small.as_slice()),
+            ([3].as_slice(), small.as_slice()),
+            ([4].as_slice(), small.as_slice()),
+            ([5].as_slice(), small.as_slice()),
+            ([6].as_slice(), small.as_slice()),
+        ];
+        let owned_page = OwnedSortedPage::from_slice(&data);
+
+        // Splitting by count puts the large item and two small items on the left.
+        let (split_key, ..) = owned_page.as_ref().into_split_iter().unwrap();
+        assert_eq!(split_key, [4].as_slice());
+
+        // Splitting by size puts the large item alone on the left.
+        let (split_key, mut left_iter, mut right_iter) =
+            owned_page.as_ref().into_split_iter_by_size().unwrap();
+        assert_eq!(split_key, [2].as_slice());
+        assert_eq!(left_iter.next(), Some(data[0]));
+        assert_eq!(left_iter.next(), None);
+        assert_eq!(right_iter.count(), 5);
+    }
+
     #[test]
     fn sorted_page_split_none() {
         {
             let data = raw_slice(&[[1]]);
             let owned_page = OwnedSortedPage::from_slice(&data);
             assert!(owned_page.as_ref().into_split_iter().is_none());
+            assert!(owned_page.as_ref().into_split_iter_by_size().is_none());
         }
         {
             let data = key_slice(&[([1], 2), ([1], 1), ([3], 3)]);
@@ -81,6 +81,22 @@ impl BufferSet {
         self.stats.snapshot()
     }
 
+    /// Tries to reclaim retired [`BufferSetVersion`]s.
+    ///
+    /// Versions still visible to some pinned readers can't be reclaimed.
+    /// Returns the number of versions waiting to be reclaimed.
+    pub(crate) fn collect(&self) -> u64 {
+        // Advancing the epoch might take a few rounds.
+        const MAX_ROUNDS: usize = 4;
+        for _ in 0..MAX_ROUNDS {
+            if self.stats.snapshot().pending_reclaims == 0 {
+                break;
+            }
+            buffer_set_guard::pin().flush();
+        }
+        self.stats.snapshot().pending_reclaims
+    }
+
     /// Obtains a reference of current [`BufferSetVersion`].
     pub(crate) fn current<'a>(&self) -> BufferSetRef<'a> {
         let guard = buffer_set_guard::pin();
@@ -246,10 +262,13 @@ impl BufferSet {
             Ordering::Acquire,
         ) {
             Ok(_) => {
+                self.stats.retired_versions.inc();
+                let reclaimed_versions = self.stats.reclaimed_versions.clone();
                 guard.defer(move || {
                     // Safety: the backing memory is obtained from [`Box::into_raw`] and there no
                     // any references to the memory, which guarrantted by epoch based reclamation.
                     drop(unsafe { Box::from_raw(current as *mut BufferSetVersion) });
+                    reclaimed_versions.inc();
                 });
 
                 // Get the defer function executed as soon as possible.
@@ -364,10 +383,22 @@ impl BufferSet {
         let buffer = {
             let current = self.current();
             if current.current_buffer.is_empty() {
-                return;
+                // Buffers are flushed in order, so waiting for the last sealed buffer is
+                // enough to wait for all the data.
+                current.sealed_buffers.last().cloned()
+            } else {
+                Some(current.current_buffer.clone())
             }
-            current.current_buffer.clone()
         };
+        let Some(buffer) = buffer else {
+            return;
+        };
+        if buffer.is_sealed() {
+            if opts.wait {
+                buffer.wait_flushed().await;
+            }
+            return;
+        }
 
         let group_id = buffer.group_id();
         if opts.allow_write_stall {
@@ -380,6 +411,45 @@ impl BufferSet {
             buffer.wait_flushed().await;
         }
     }
+
+    /// Waits until the writes made to the write buffers so far are flushed.
+    ///
+    /// Unlike [`Self::flush_active_buffer`], the active buffer is only sealed
+    /// after the sealed buffers before it are flushed. So the writes that are
+    /// made while a flush is in progress, by any number of waiters, are grouped
+    /// into the next flush, instead of each waiter sealing a buffer of its own.
+    pub(crate) async fn wait_for_writes_flushed(&self) {
+        let (buffer, prev) = {
+            let current = self.current();
+            let prev = current.sealed_buffers.last().cloned();
+            if current.current_buffer.is_empty() {
+                // Buffers are flushed in order, so waiting for the last sealed buffer is
+                // enough to wait for all the data.
+                (prev, None)
+            } else {
+                (Some(current.current_buffer.clone()), prev)
+            }
+        };
+        let Some(buffer) = buffer else {
+            return;
+        };
+        if let Some(prev) = prev {
+            prev.wait_flushed().await;
+        }
+        if !buffer.is_sealed() {
+            self.write_buffer_permits
+                .wait(buffer_permits::WaitKind::HasPermits)
+                .await;
+            // Other waiters may have sealed the buffer in the meantime.
+            if let Ok(release_state) = buffer.seal() {
+                self.install_successor(buffer.group_id()).await;
+                if matches!(release_state, ReleaseState::Flush) {
+                    self.notify_flush_job();
+                }
+            }
+        }
+        buffer.wait_flushed().await;
+    }
 }
 
 impl Drop for BufferSet {
@@ -678,6 +748,27 @@ mod tests {
         assert!(buffer_set.current().get(file_id + 1).is_some());
     }
 
+    #[test]
+    fn buffer_set_collect() {
+        let buffer_set = BufferSet::new(1, 1 << 10, 8);
+        let file_id = buffer_set.current().last_writer_buffer().group_id();
+
+        // The retired version can't be reclaimed while it is pinned.
+        let pinned = buffer_set.current();
+        pinned.last_writer_buffer().seal().unwrap();
+        let buf = WriteBuffer::with_capacity(file_id + 1, buffer_set.buffer_capacity);
+        buffer_set.install(Arc::new(buf));
+        assert_eq!(buffer_set.collect(), 1);
+        assert_eq!(buffer_set.stats().pending_reclaims, 1);
+
+        drop(pinned);
+        // Other tests might pin the collector concurrently.
+        while buffer_set.collect() > 0 {
+            std::thread::yield_now();
+        }
+        assert_eq!(buffer_set.stats().pending_reclaims, 0);
+    }
+
     #[photonio::test]
     async fn buffer_set_concurrent_update() {
         let buffer_set = Arc::new(BufferSet::new(1, 32, 8));
@@ -820,6 +911,38 @@ mod tests {
         buffer_set.flush_active_buffer(&opts).await;
     }
 
+    #[photonio::test]
+    async fn buffer_set_wait_for_writes_flushed() {
+        let buffer_set = BufferSet::new(1, 1 << 10, 8);
+        let opts = FlushOptions {
+            wait: false,
+            allow_write_stall: false,
+        };
+        {
+            let current = buffer_set.current();
+            let buf = current.current_buffer.clone();
+            unsafe { buf.alloc_page(1, 32, false).unwrap() };
+            buf.on_flushed();
+        }
+        buffer_set.flush_active_buffer(&opts).await;
+        {
+            let current = buffer_set.current();
+            let buf = current.current_buffer.clone();
+            assert_eq!(buf.group_id(), 2);
+            unsafe { buf.alloc_page(2, 32, false).unwrap() };
+            buf.on_flushed();
+        }
+
+        // The active buffer is sealed once the buffers before it are flushed.
+        buffer_set.wait_for_writes_flushed().await;
+        assert!(buffer_set.get(2).unwrap().is_sealed());
+        assert_eq!(buffer_set.current().current_buffer.group_id(), 3);
+
+        // An empty active buffer is not sealed.
+        buffer_set.wait_for_writes_flushed().await;
+        assert!(!buffer_set.get(3).unwrap().is_sealed());
+    }
+
     #[photonio::test]
     async fn write_buffer_permits_basic() {
         let write_permits = Arc::new(buffer_permits::WriteBufferPermits::new(2));
@@ -1,8 +1,12 @@
 use ::std::{
+    cell::UnsafeCell,
+    marker::PhantomData,
     mem,
     ptr::{self, null_mut},
     sync::{atomic::*, Arc},
+    thread,
 };
+use log::error;
 
 use super::*;
 use crate::page_store::{stats::CacheStats, CacheOption};
@@ -59,34 +63,40 @@ pub(crate) struct ClockCache<T: Clone> {
 )]
 struct ClockCacheShard<T: Clone> {
     table: ClockCacheHandleTable<T>,
-    capacity: usize,
+    capacity: AtomicUsize,
 }
 
-struct ClockHandlePtr<T: Clone> {
-    ptr: *mut ClockHandle<T>,
+/// A slot of the handle table.
+///
+/// Slots are stored inline in the table, so the handles are allocated at once
+/// and don't move until the table is dropped. Each slot takes a cache line if
+/// the handle fits into it.
+#[repr(align(64))]
+struct ClockHandleSlot<T: Clone> {
+    handle: UnsafeCell<ClockHandle<T>>,
 }
 
-impl<T: Clone> ClockHandlePtr<T> {
-    fn as_ref(&self) -> &ClockHandle<T> {
-        unsafe { &(*self.ptr) }
+impl<T: Clone> Default for ClockHandleSlot<T> {
+    fn default() -> Self {
+        Self {
+            handle: UnsafeCell::new(ClockHandle::default()),
+        }
     }
+}
 
-    fn mut_ptr(&self) -> *mut ClockHandle<T> {
-        self.ptr
+impl<T: Clone> ClockHandleSlot<T> {
+    fn as_ref(&self) -> &ClockHandle<T> {
+        unsafe { &(*self.handle.get()) }
     }
-}
 
-impl<T: Clone> Drop for ClockHandlePtr<T> {
-    fn drop(&mut self) {
-        unsafe {
-            drop(Box::from_raw(self.ptr));
-        }
+    fn mut_ptr(&self) -> *mut ClockHandle<T> {
+        self.handle.get()
     }
 }
 
-unsafe impl<T: Clone> Send for ClockHandlePtr<T> {}
+unsafe impl<T: Clone> Send for ClockHandleSlot<T> {}
 
-unsafe impl<T: Clone> Sync for ClockHandlePtr<T> {}
+unsafe impl<T: Clone> Sync for ClockHandleSlot<T> {}
 
 #[derive(Default)]
 struct ClockCacheHandleTable<T: Clone> {
@@ -95,7 +105,7 @@ struct ClockCacheHandleTable<T: Clone> {
     occupancy_limit: u32,
     strict_capacity_limit: bool,
 
-    handles: Vec<ClockHandlePtr<T>>,
+    handles: Box<[ClockHandleSlot<T>]>,
 
     occupancy: CachePadded<AtomicU32>,
     usage: CachePadded<AtomicUsize>,
@@ -109,20 +119,11 @@ impl<T: Clone> ClockCacheHandleTable<T> {
     fn new(length_bits: u64, strict_capacity_limit: bool, charge_metadata: bool) -> Self {
         let length_bits_mask = ((1 << length_bits) - 1) as u32;
         let occupancy_limit = ((1 << length_bits) as f64 * STRICT_LOAD_FACTOR) as u32;
-        let mut handles = Vec::with_capacity(1 << length_bits);
-        for _ in 0..handles.capacity() {
-            let h = Box::new(ClockHandle::default());
-            handles.push(ClockHandlePtr {
-                ptr: Box::into_raw(h),
-            });
-        }
-        assert_eq!(
-            mem::size_of::<Handle<T>>(),
-            64,
-            "expecting handle size / alignment with common cache line size"
-        );
+        let handles = (0..1usize << length_bits)
+            .map(|_| ClockHandleSlot::default())
+            .collect::<Box<[_]>>();
         let usage = CachePadded::new(AtomicUsize::new(if charge_metadata {
-            handles.len() * mem::size_of::<Handle<T>>()
+            handles.len() * mem::size_of::<ClockHandleSlot<T>>()
         } else {
             0
         }));
@@ -546,6 +547,50 @@ impl<T: Clone> ClockCacheHandleTable<T> {
         self.stats.active_evict.inc();
     }
 
+    /// Erases the entries of pages in the file.
+    ///
+    /// Like [`Self::erase`], entries referenced by others are only made
+    /// invisible, and they are freed once the last reference is released.
+    fn erase_file_pages(&self, file_id: u32) {
+        let mut erased = Vec::new();
+        for hp in self.handles.iter() {
+            let h = hp.as_ref();
+            // Take a reference so that the key can't be changed while reading it.
+            let old_meta = h.meta.fetch_add(ACQUIRE_INCREMENT, Ordering::Acquire);
+            let state = (old_meta >> STATE_SHIFT) as u8;
+            if state & STATE_SHAREABLE_BIT == 0 {
+                // The slot is empty or under construction, its meta will be overwritten.
+                continue;
+            }
+            if state == STATE_VISIBLE && (h.key >> 32) as u32 == file_id {
+                erased.push((h.key, h.hash));
+            }
+            h.meta.fetch_sub(ACQUIRE_INCREMENT, Ordering::Release);
+        }
+        for (key, hash) in erased {
+            self.erase(key, hash);
+        }
+    }
+
+    /// Returns the keys of the visible entries.
+    fn keys(&self) -> Vec<u64> {
+        let mut keys = Vec::new();
+        for hp in self.handles.iter() {
+            let h = hp.as_ref();
+            // Take a reference so that the key can't be changed while reading it.
+            let old_meta = h.meta.fetch_add(ACQUIRE_INCREMENT, Ordering::Acquire);
+            let state = (old_meta >> STATE_SHIFT) as u8;
+            if state & STATE_SHAREABLE_BIT == 0 {
+                continue;
+            }
+            if state == STATE_VISIBLE {
+                keys.push(h.key);
+            }
+            h.meta.fetch_sub(ACQUIRE_INCREMENT, Ordering::Release);
+        }
+        keys
+    }
+
     #[inline]
     fn reclaim_entry_usage(&self, total_charge: usize) {
         let old_occupancy = self.occupancy.fetch_sub(1, Ordering::Release);
@@ -578,9 +623,9 @@ impl<T: Clone> ClockCacheHandleTable<T> {
     fn find_slot(
         &self,
         hash: u32,
-        mut match_fn: impl FnMut(&ClockHandlePtr<T>) -> bool,
-        abort_fn: impl Fn(&ClockHandlePtr<T>) -> bool,
-        update_fn: impl Fn(&ClockHandlePtr<T>),
+        mut match_fn: impl FnMut(&ClockHandleSlot<T>) -> bool,
+        abort_fn: impl Fn(&ClockHandleSlot<T>) -> bool,
+        update_fn: impl Fn(&ClockHandleSlot<T>),
     ) -> (Option<usize>, u32) {
         let mut probe = 0;
         let base = self.mod_table_size(Self::remix1(hash));
@@ -648,7 +693,7 @@ impl<T: Clone> ClockCacheHandleTable<T> {
         assert!((meta >> (STATE_SHIFT as u64)) as u8 == STATE_CONSTRUCTION);
     }
 
-    fn clock_update(&self, hp: &ClockHandlePtr<T>) -> bool {
+    fn clock_update(&self, hp: &ClockHandleSlot<T>) -> bool {
         let h = hp.as_ref();
         let meta = h.meta.load(Ordering::Relaxed);
         let acquire_count = (meta >> ACQUIRE_COUNTER_SHIFT) & COUNTER_MASK;
@@ -712,7 +757,7 @@ impl<T: Clone> ClockCacheHandleTable<T> {
 
 impl<T: Clone> Drop for ClockCacheHandleTable<T> {
     fn drop(&mut self) {
-        for hp in &self.handles {
+        for hp in self.handles.iter() {
             let h = hp.as_ref();
             let meta = h.meta.load(Ordering::Relaxed);
             let ref_cnt = Self::ref_count(meta);
@@ -735,7 +780,7 @@ impl<T: Clone> Drop for ClockCacheHandleTable<T> {
         assert!(
             self.usage.load(Ordering::Relaxed) == 0
                 || self.usage.load(Ordering::Relaxed)
-                    == self.handles.len() * mem::size_of::<Handle<T>>()
+                    == self.handles.len() * mem::size_of::<ClockHandleSlot<T>>()
         );
         assert_eq!(self.occupancy.load(Ordering::Relaxed), 0);
     }
@@ -754,21 +799,7 @@ impl<T: Clone> ClockCache<T> {
         let num_shard_bits = if num_shard_bits >= 0 {
             num_shard_bits as u32
         } else {
-            const MIN_SHARD_SIZE: usize = 32 << 20;
-            let mut num_shard_bits = 0;
-            let mut num_shards = capacity / MIN_SHARD_SIZE;
-            loop {
-                num_shards >>= 1;
-                if num_shards == 0 {
-                    break;
-                }
-                num_shard_bits += 1;
-                if num_shard_bits >= 6 {
-                    // No more than 6.
-                    break;
-                }
-            }
-            num_shard_bits
+            auto_shard_bits(capacity)
         };
         let num_shards = 1u32 << num_shard_bits;
         let per_shard_cap = (capacity + (num_shards as usize - 1)) / num_shards as usize;
@@ -785,6 +816,57 @@ impl<T: Clone> ClockCache<T> {
         Self { shards, shard_mask }
     }
 
+    /// Returns a builder to configure a cache.
+    #[allow(dead_code)]
+    pub(crate) fn builder() -> ClockCacheBuilder<T> {
+        ClockCacheBuilder::default()
+    }
+
+    #[allow(dead_code)]
+    pub(crate) fn num_shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Returns the total capacity of the cache.
+    pub(crate) fn capacity(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.capacity.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    /// Returns the total charge of the entries in the cache.
+    ///
+    /// This includes the entries that are pinned by readers.
+    pub(crate) fn usage(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.table.usage.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    /// Sets the total capacity of the cache.
+    ///
+    /// Unlike [`LRUCache::set_capacity`](super::lru::LRUCache::set_capacity),
+    /// entries are not evicted immediately, but by the following inserts.
+    /// The number of slots is fixed at creation, so it doesn't change with
+    /// the capacity.
+    pub(crate) fn set_capacity(&self, capacity: usize) {
+        let num_shards = self.shards.len();
+        let per_shard_cap = (capacity + (num_shards - 1)) / num_shards;
+        for shard in &self.shards {
+            shard.capacity.store(per_shard_cap, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the keys of all entries in the cache.
+    pub(crate) fn keys(&self) -> Vec<u64> {
+        self.shards
+            .iter()
+            .flat_map(|shard| shard.table.keys())
+            .collect()
+    }
+
     fn est_vale_size_advice(&self) -> Option<String> {
         let shard_cnt = self.shards.len();
         let mut predicted_load_factors = Vec::with_capacity(shard_cnt);
@@ -854,6 +936,126 @@ impl<T: Clone> ClockCache<T> {
     }
 }
 
+/// The maximum number of shard bits chosen automatically.
+const MAX_AUTO_SHARD_BITS: u32 = 6;
+
+/// Returns the number of shard bits for a cache with the given capacity.
+///
+/// Each shard holds at least 32MB, and there are no more shards than the
+/// power of two that covers the number of CPUs.
+fn auto_shard_bits(capacity: usize) -> u32 {
+    const MIN_SHARD_SIZE: usize = 32 << 20;
+    let num_cpus = thread::available_parallelism().map_or(1, |n| n.get());
+    let max_shards = (capacity / MIN_SHARD_SIZE).min(num_cpus.next_power_of_two());
+    // Rounds down to a power of two.
+    let num_shard_bits = (usize::BITS - max_shards.leading_zeros()).saturating_sub(1);
+    num_shard_bits.min(MAX_AUTO_SHARD_BITS)
+}
+
+/// Builds a [`ClockCache`] with validated options.
+///
+/// All sizes are in bytes.
+pub(crate) struct ClockCacheBuilder<T> {
+    capacity_bytes: usize,
+    estimated_entry_charge: usize,
+    num_shard_bits: Option<u32>,
+    strict_capacity_limit: bool,
+    charge_metadata: bool,
+    _marker: PhantomData<T>,
+}
+
+impl<T> Default for ClockCacheBuilder<T> {
+    fn default() -> Self {
+        Self {
+            capacity_bytes: 8 << 20,
+            estimated_entry_charge: 8 << 10,
+            num_shard_bits: None,
+            strict_capacity_limit: false,
+            charge_metadata: false,
+            _marker: PhantomData,
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl<T: Clone> ClockCacheBuilder<T> {
+    /// Sets the total capacity of the cache in bytes.
+    ///
+    /// Default: 8MB
+    pub(crate) fn capacity_bytes(mut self, capacity: usize) -> Self {
+        self.capacity_bytes = capacity;
+        self
+    }
+
+    /// Sets the expected average charge of an entry in bytes.
+    ///
+    /// The handle table of each shard is sized to hold `capacity /
+    /// estimated_entry_charge` entries, so an estimate that is too large
+    /// limits the number of entries the cache can hold, and one that is too
+    /// small wastes memory on empty slots.
+    ///
+    /// Default: 8KB
+    pub(crate) fn estimated_entry_charge(mut self, charge: usize) -> Self {
+        self.estimated_entry_charge = charge;
+        self
+    }
+
+    /// Sets the number of shards to `1 << num_shard_bits`.
+    ///
+    /// If it is not set, the number of shards is derived from the capacity
+    /// and the number of CPUs.
+    pub(crate) fn num_shard_bits(mut self, num_shard_bits: u32) -> Self {
+        self.num_shard_bits = Some(num_shard_bits);
+        self
+    }
+
+    /// Fails inserts instead of exceeding the capacity if it is true.
+    ///
+    /// Default: false
+    pub(crate) fn strict_capacity_limit(mut self, strict: bool) -> Self {
+        self.strict_capacity_limit = strict;
+        self
+    }
+
+    /// Charges the memory of the handle tables to the capacity if it is true.
+    ///
+    /// Default: false
+    pub(crate) fn charge_metadata(mut self, charge_metadata: bool) -> Self {
+        self.charge_metadata = charge_metadata;
+        self
+    }
+
+    /// Builds the cache.
+    ///
+    /// Returns [`Error::InvalidArgument`] if the options are invalid.
+    pub(crate) fn build(self) -> Result<ClockCache<T>> {
+        let capacity = self.capacity_bytes;
+        let entry_charge = self.estimated_entry_charge;
+        if capacity == 0 || entry_charge == 0 || entry_charge > capacity {
+            error!(
+                "invalid clock cache options: capacity {capacity} bytes, estimated entry charge \
+                 {entry_charge} bytes"
+            );
+            return Err(Error::InvalidArgument);
+        }
+        let num_shard_bits = match self.num_shard_bits {
+            Some(bits) if bits >= 20 => {
+                error!("invalid clock cache options: {bits} shard bits");
+                return Err(Error::InvalidArgument);
+            }
+            Some(bits) => bits,
+            None => auto_shard_bits(capacity),
+        };
+        Ok(ClockCache::new(
+            capacity,
+            entry_charge,
+            num_shard_bits as i32,
+            self.strict_capacity_limit,
+            self.charge_metadata,
+        ))
+    }
+}
+
 impl<T: Clone> ClockCacheShard<T> {
     fn new(
         capacity: usize,
@@ -865,19 +1067,19 @@ impl<T: Clone> ClockCacheShard<T> {
         let data = ClockCacheHandleTable::new(hash_bits, strict_capacity_limit, charge_metadata);
         Self {
             table: data,
-            capacity,
+            capacity: AtomicUsize::new(capacity),
         }
     }
 
     fn hash_bits(capacity: usize, est_value_size: usize, charge_metadata: bool) -> u64 {
         let mut average_slot_charge = est_value_size as f64 * LOAD_FACTOR;
         if charge_metadata {
-            average_slot_charge += mem::size_of::<Handle<T>>() as f64;
+            average_slot_charge += mem::size_of::<ClockHandleSlot<T>>() as f64;
         }
         let num_slots = (capacity as f64 / average_slot_charge + 0.999999) as u64;
         let mut hash_bits = ((num_slots << 1) as f64 - 1.).log2().floor().min(32.) as u64;
         if charge_metadata {
-            while hash_bits > 0 && (mem::size_of::<Handle<T>>() << hash_bits) > capacity {
+            while hash_bits > 0 && (mem::size_of::<ClockHandleSlot<T>>() << hash_bits) > capacity {
                 hash_bits -= 1;
             }
         }
@@ -898,7 +1100,7 @@ impl<T: Clone> ClockCacheShard<T> {
             charge,
             ..Default::default()
         };
-        self.table.insert(h, self.capacity)
+        self.table.insert(h, self.capacity.load(Ordering::Relaxed))
     }
 
     fn lookup(&self, key: u64, hash: u32) -> *mut ClockHandle<T> {
@@ -913,14 +1115,25 @@ impl<T: Clone> ClockCacheShard<T> {
         self.table.erase(key, hash);
     }
 
+    fn erase_file_pages(&self, file_id: u32) {
+        self.table.erase_file_pages(file_id);
+    }
+
     fn shard_stats(&self) -> CacheStats {
-        self.table.stats.snapshot()
+        let table = &self.table;
+        CacheStats {
+            usage_bytes: table.usage.load(Ordering::Relaxed) as u64,
+            detached_usage_bytes: table.detached_usage.load(Ordering::Relaxed) as u64,
+            occupancy: table.occupancy.load(Ordering::Relaxed) as u64,
+            occupancy_limit: table.occupancy_limit as u64,
+            ..table.stats.snapshot()
+        }
     }
 
     fn shard_advice(&self, predicted_load_factors: &mut Vec<f64>, min_recommendation: &mut usize) {
         let usage = self.table.usage.load(Ordering::Relaxed)
             - self.table.detached_usage.load(Ordering::Relaxed);
-        let capacity = self.capacity;
+        let capacity = self.capacity.load(Ordering::Relaxed);
         let usage_ratio = 1. * (usage as f64) / (capacity as f64);
 
         let occupancy = self.table.occupancy.load(Ordering::Relaxed);
@@ -1001,8 +1214,10 @@ impl<T: Clone> Cache<T> for ClockCache<T> {
         shard.erase(key, hash)
     }
 
-    fn erase_file_pages(self: &std::sync::Arc<Self>, _file_id: u32) {
-        unimplemented!()
+    fn erase_file_pages(self: &std::sync::Arc<Self>, file_id: u32) {
+        for shard in self.shards.iter() {
+            shard.erase_file_pages(file_id);
+        }
     }
 
     fn stats(self: &Arc<Self>) -> CacheStats {
@@ -15,7 +15,7 @@ use super::{
     CACHE_DISCARD,
 };
 use crate::page_store::{
-    cache::CACHE_AS_RECENT, page_txn::CachePriority, stats::CacheStats, CacheOption, Result,
+    cache::CACHE_AS_RECENT, page_txn::CachePriority, stats::CacheStats, CacheOption, Error, Result,
 };
 
 pub(crate) struct LRUCache<T: Clone> {
@@ -27,6 +27,7 @@ pub(crate) struct LRUCache<T: Clone> {
 struct LRUCacheShard<T: Clone> {
     table: LRUCacheHandleTable<T>,
     capacity: usize,
+    strict_capacity_limit: bool,
 
     lru_usage: Arc<AtomicUsize>,
 
@@ -68,9 +69,16 @@ unsafe impl<T: Clone> Send for LRUHandlePtr<T> {}
 unsafe impl<T: Clone> Sync for LRUHandlePtr<T> {}
 
 impl<T: Clone> LRUCache<T> {
+    /// Creates a cache with the given capacity in bytes.
+    ///
+    /// If the entries can't be evicted to make room for a new entry, the new
+    /// entry is rejected with [`Error::MemoryLimit`] if
+    /// `strict_capacity_limit` is true. Otherwise, the new entry is returned
+    /// without being cached, and is dropped once it is released.
     pub(crate) fn new(
         capacity: usize,
         num_shard_bits: i32,
+        strict_capacity_limit: bool,
         high_pri_ratio: f64,
         low_pri_ratio: f64,
     ) -> Self {
@@ -100,7 +108,12 @@ impl<T: Clone> LRUCache<T> {
         let mut shards = Vec::with_capacity(num_shards as usize);
         let mut stats = Vec::with_capacity(num_shards as usize);
         for _ in 0..num_shards {
-            let shard = LRUCacheShard::new(per_shard_cap, high_pri_ratio, low_pri_ratio);
+            let shard = LRUCacheShard::new(
+                per_shard_cap,
+                strict_capacity_limit,
+                high_pri_ratio,
+                low_pri_ratio,
+            );
             stats.push(shard.stats.clone());
             shards.push(Mutex::new(shard));
         }
@@ -111,6 +124,44 @@ impl<T: Clone> LRUCache<T> {
         }
     }
 
+    /// Returns the total capacity of the cache.
+    pub(crate) fn capacity(&self) -> usize {
+        self.shards.iter().map(|shard| shard.lock().capacity).sum()
+    }
+
+    /// Returns the total charge of the entries in the cache.
+    ///
+    /// This includes the entries that are pinned by readers.
+    pub(crate) fn usage(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.lock().usage.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    /// Sets the total capacity of the cache.
+    ///
+    /// Unpinned entries are evicted immediately if the cache is over the new
+    /// capacity.
+    pub(crate) fn set_capacity(&self, capacity: usize) {
+        let num_shards = self.shards.len();
+        let per_shard_cap = (capacity + (num_shards - 1)) / num_shards;
+        for shard in &self.shards {
+            let mut shard = shard.lock();
+            unsafe { shard.set_capacity(per_shard_cap) };
+        }
+    }
+
+    /// Returns the keys of all entries in the cache.
+    pub(crate) fn keys(&self) -> Vec<u64> {
+        let mut keys = Vec::new();
+        for shard in &self.shards {
+            let shard = shard.lock();
+            keys.extend(shard.table.pages.keys());
+        }
+        keys
+    }
+
     #[inline]
     fn shard(&self, hash: u32) -> u32 {
         self.shard_mask & hash
@@ -227,12 +278,18 @@ impl<T: Clone> Cache<T> for LRUCache<T> {
             let shard_stats = s.snapshot();
             summary = summary.add(&shard_stats);
         }
+        summary.usage_bytes = self.usage() as u64;
         summary
     }
 }
 
 impl<T: Clone> LRUCacheShard<T> {
-    pub(crate) fn new(capacity: usize, high_pri_ratio: f64, low_pri_ratio: f64) -> Self {
+    pub(crate) fn new(
+        capacity: usize,
+        strict_capacity_limit: bool,
+        high_pri_ratio: f64,
+        low_pri_ratio: f64,
+    ) -> Self {
         let mut dummy = Box::new(LRUHandle::default());
         dummy.page_link.next = dummy.as_mut();
         dummy.page_link.prev = dummy.as_mut();
@@ -242,6 +299,7 @@ impl<T: Clone> LRUCacheShard<T> {
         Self {
             table: LRUCacheHandleTable::new(),
             capacity,
+            strict_capacity_limit,
             lru_usage: Default::default(),
             lru_high_pri: Box::new(LRUHandlePtr { ptr }),
             lru_high_usage: Default::default(),
@@ -257,6 +315,14 @@ impl<T: Clone> LRUCacheShard<T> {
         }
     }
 
+    unsafe fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.lru_high_capacity = ((capacity as f64) * self.high_pri_ratio) as usize;
+        self.lru_low_capacity = ((capacity as f64) * self.low_pri_ratio) as usize;
+        self.maintain_priority_size();
+        self.evict_lru(0, CacheOption::default());
+    }
+
     unsafe fn insert(
         &mut self,
         key: u64,
@@ -265,19 +331,19 @@ impl<T: Clone> LRUCacheShard<T> {
         charge: usize,
         option: CacheOption,
     ) -> Result<*mut LRUHandle<T>> {
-        if !self.evict_lru(charge, option) {
-            let mut h = Box::new(LRUHandle {
-                key: key.into(),
-                hash,
-                value,
-                charge,
-                ..Default::default()
-            });
-            h.set_detached(true);
-            h.file_link.next = h.as_mut();
-            h.file_link.prev = h.as_mut();
-            let handle = Box::into_raw(h);
-            return Ok(handle);
+        // Don't evict other entries for an entry that never fits.
+        let fits = charge <= self.capacity && {
+            if !self.evict_lru(charge, option) {
+                return Ok(Self::detached_handle(key, hash, value, charge));
+            }
+            // The remaining entries may be in use.
+            self.usage.load(Ordering::Relaxed) + charge <= self.capacity
+        };
+        if !fits {
+            if self.strict_capacity_limit {
+                return Err(Error::MemoryLimit);
+            }
+            return Ok(Self::detached_handle(key, hash, value, charge));
         }
         let mut h = Box::new(LRUHandle {
             key: key.into(),
@@ -300,6 +366,22 @@ impl<T: Clone> LRUCacheShard<T> {
         Ok(lhd)
     }
 
+    /// Returns a handle that is not in the cache, which is dropped once it is
+    /// released.
+    fn detached_handle(key: u64, hash: u32, value: Option<T>, charge: usize) -> *mut LRUHandle<T> {
+        let mut h = Box::new(LRUHandle {
+            key: key.into(),
+            hash,
+            value,
+            charge,
+            ..Default::default()
+        });
+        h.set_detached(true);
+        h.file_link.next = h.as_mut();
+        h.file_link.prev = h.as_mut();
+        Box::into_raw(h)
+    }
+
     unsafe fn release(&mut self, h: *mut LRUHandle<T>, token: CacheToken) {
         debug_assert!(!h.is_null());
         if (*h).is_detached() {
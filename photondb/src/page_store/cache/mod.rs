@@ -1,5 +1,5 @@
 use std::{
-    fmt,
+    fmt, mem,
     ops::{Deref, DerefMut},
     ptr,
     sync::atomic::{AtomicU8, Ordering},
@@ -138,6 +138,118 @@ where
     pub(crate) fn cache_token(&self) -> CacheToken {
         self.token.clone()
     }
+
+    /// Moves the handle to an entry of another cache that releases it to the
+    /// same place.
+    fn cast<D: Cache<T>>(self, cache: Arc<D>) -> CacheEntry<T, D> {
+        let entry = mem::ManuallyDrop::new(self);
+        // Safety: the fields are moved out of the entry exactly once, and the
+        // entry is not dropped, so the handle is not released here.
+        let (handle, token) = unsafe {
+            drop(ptr::read(&entry.cache));
+            (ptr::read(&entry.handle), ptr::read(&entry.token))
+        };
+        CacheEntry {
+            handle,
+            cache,
+            token,
+        }
+    }
+}
+
+/// A page cache with the policy chosen by the options.
+pub(crate) enum PageCache<T: Clone> {
+    Clock(Arc<clock::ClockCache<T>>),
+    Lru(Arc<lru::LRUCache<T>>),
+}
+
+impl<T: Clone> PageCache<T> {
+    /// Returns the total capacity of the cache.
+    pub(crate) fn capacity(&self) -> usize {
+        match self {
+            PageCache::Clock(c) => c.capacity(),
+            PageCache::Lru(c) => c.capacity(),
+        }
+    }
+
+    /// Returns the total charge of the entries in the cache.
+    pub(crate) fn usage(&self) -> usize {
+        match self {
+            PageCache::Clock(c) => c.usage(),
+            PageCache::Lru(c) => c.usage(),
+        }
+    }
+
+    /// Sets the total capacity of the cache.
+    pub(crate) fn set_capacity(&self, capacity: usize) {
+        match self {
+            PageCache::Clock(c) => c.set_capacity(capacity),
+            PageCache::Lru(c) => c.set_capacity(capacity),
+        }
+    }
+
+    /// Returns the keys of all entries in the cache.
+    pub(crate) fn keys(&self) -> Vec<u64> {
+        match self {
+            PageCache::Clock(c) => c.keys(),
+            PageCache::Lru(c) => c.keys(),
+        }
+    }
+}
+
+impl<T: Clone> Cache<T> for PageCache<T> {
+    fn insert(
+        self: &Arc<Self>,
+        key: u64,
+        value: Option<T>,
+        charge: usize,
+        option: CacheOption,
+    ) -> Result<Option<CacheEntry<T, Self>>> {
+        let entry = match self.as_ref() {
+            PageCache::Clock(c) => c
+                .insert(key, value, charge, option)?
+                .map(|e| e.cast(self.clone())),
+            PageCache::Lru(c) => c
+                .insert(key, value, charge, option)?
+                .map(|e| e.cast(self.clone())),
+        };
+        Ok(entry)
+    }
+
+    fn lookup(self: &Arc<Self>, key: u64) -> Option<CacheEntry<T, Self>> {
+        match self.as_ref() {
+            PageCache::Clock(c) => c.lookup(key).map(|e| e.cast(self.clone())),
+            PageCache::Lru(c) => c.lookup(key).map(|e| e.cast(self.clone())),
+        }
+    }
+
+    fn release(&self, h: &Handle<T>, cache_token: CacheToken) -> bool {
+        match self {
+            PageCache::Clock(c) => c.release(h, cache_token),
+            PageCache::Lru(c) => c.release(h, cache_token),
+        }
+    }
+
+    fn erase(self: &Arc<Self>, key: u64) {
+        match self.as_ref() {
+            PageCache::Clock(c) => c.erase(key),
+            PageCache::Lru(c) => c.erase(key),
+        }
+    }
+
+    fn erase_file_pages(self: &std::sync::Arc<Self>, file_id: u32) {
+        match self.as_ref() {
+            PageCache::Clock(c) => c.erase_file_pages(file_id),
+            PageCache::Lru(c) => c.erase_file_pages(file_id),
+        }
+    }
+
+    fn stats(self: &Arc<Self>) -> CacheStats {
+        match self.as_ref() {
+            PageCache::Clock(c) => c.stats(),
+            PageCache::Lru(c) => c.stats(),
+        }
+    }
 }
 
 #[repr(align(64))]
@@ -382,7 +494,7 @@ impl AtomicCacheStats {
             insert: self.insert.get(),
             active_evict: self.active_evict.get(),
             passive_evict: self.passive_evict.get(),
-            recommendation: vec![],
+            ..Default::default()
         }
     }
 }
@@ -439,7 +551,9 @@ impl<T> From<T> for CachePadded<T> {
 
 #[cfg(test)]
 mod tests {
-    use ::std::thread;
+    use ::std::{thread, time::Instant};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use rand_distr::{Distribution, Zipf};
 
     use super::*;
     use crate::page_store::CacheOption;
@@ -448,7 +562,7 @@ mod tests {
     fn test_lru_base_op() {
         use super::lru::*;
 
-        let c = Arc::new(LRUCache::new(2, -1, 0.0, 0.0));
+        let c = Arc::new(LRUCache::new(2, -1, false, 0.0, 0.0));
 
         let h = c
             .insert(1, Some(vec![1]), 1, CacheOption::default())
@@ -482,11 +596,47 @@ mod tests {
         assert!(h.is_none());
     }
 
+    #[test]
+    fn test_lru_strict_capacity_limit() {
+        use super::lru::*;
+
+        for strict in [false, true] {
+            let c = Arc::new(LRUCache::new(4, 0, strict, 0.0, 0.0));
+            let insert = |key: u64, charge: usize| {
+                c.insert(key, Some(vec![key]), charge, CacheOption::default())
+            };
+            drop(insert(1, 1).unwrap());
+
+            // An entry larger than the capacity is never cached, and doesn't
+            // evict the others.
+            match insert(2, 8) {
+                Ok(h) if !strict => assert_eq!(h.unwrap().value(), &vec![2]),
+                Err(Error::MemoryLimit) if strict => {}
+                _ => panic!("unexpected result of an oversized insert"),
+            }
+            assert!(c.lookup(2).is_none());
+            assert!(c.lookup(1).is_some());
+
+            // Entries in use can't be evicted.
+            let pinned: Vec<_> = (3..=6).map(|key| insert(key, 1).unwrap()).collect();
+            assert!(c.lookup(1).is_none());
+            match insert(7, 1) {
+                Ok(h) if !strict => assert_eq!(h.unwrap().value(), &vec![7]),
+                Err(Error::MemoryLimit) if strict => {}
+                _ => panic!("unexpected result of an insert to a full cache"),
+            }
+            assert!(c.lookup(7).is_none());
+            drop(pinned);
+            drop(insert(7, 1).unwrap());
+            assert!(c.lookup(7).is_some());
+        }
+    }
+
     #[test]
     fn test_lru_pri_op() {
         use super::lru::*;
 
-        let c = Arc::new(LRUCache::new(10, -1, 0.5, 0.2));
+        let c = Arc::new(LRUCache::new(10, -1, false, 0.5, 0.2));
 
         // fill 1-5 as high, 6-7 as low, 8-10 as bottom.
         for n in 1..=10 {
@@ -588,4 +738,310 @@ mod tests {
         c.erase(4);
         assert!(c.lookup(4).is_none());
     }
+
+    #[test]
+    fn test_clock_handle_table() {
+        use super::clock::*;
+
+        // Charges the handle table to the capacity too.
+        let c = Arc::new(ClockCache::new(1 << 20, 64, 0, false, true));
+        const N: u64 = 1 << 12;
+        for i in 0..N {
+            let v = c
+                .insert(i, Some(vec![i]), 64, CacheOption::default())
+                .unwrap()
+                .unwrap();
+            assert_eq!(v.value(), &vec![i]);
+        }
+        let mut hits = 0;
+        for i in 0..N {
+            if let Some(v) = c.lookup(i) {
+                assert_eq!(v.key(), i);
+                assert_eq!(v.value(), &vec![i]);
+                hits += 1;
+            }
+        }
+        assert!(hits > 0);
+        for i in 0..N {
+            c.erase(i);
+            assert!(c.lookup(i).is_none());
+        }
+    }
+
+    #[test]
+    fn test_clock_erase_referenced() {
+        use ::std::sync::mpsc;
+
+        use super::clock::*;
+
+        let c = Arc::new(
+            ClockCache::builder()
+                .capacity_bytes(1 << 20)
+                .estimated_entry_charge(1 << 10)
+                .build()
+                .unwrap(),
+        );
+        for key in [1, 2, (1 << 32) | 1, (1 << 32) | 2] {
+            let h = c.insert(key, Some(vec![key]), 1 << 10, CacheOption::default());
+            drop(h.unwrap());
+        }
+        // Erasing absent keys is a no-op.
+        c.erase(3);
+        assert_eq!(c.stats().occupancy, 4);
+
+        // Erase a key while another thread holds a handle of it.
+        let (looked_up_tx, looked_up_rx) = mpsc::channel();
+        let (erased_tx, erased_rx) = mpsc::channel();
+        let t = {
+            let c = c.clone();
+            thread::spawn(move || {
+                let h = c.lookup(1).unwrap();
+                looked_up_tx.send(()).unwrap();
+                erased_rx.recv().unwrap();
+                // The handle stays valid until it is released.
+                assert_eq!(h.value(), &vec![1]);
+                drop(h);
+            })
+        };
+        looked_up_rx.recv().unwrap();
+        c.erase(1);
+        assert!(c.lookup(1).is_none());
+        // The entry is freed once the last reference is released.
+        assert_eq!(c.stats().occupancy, 4);
+        erased_tx.send(()).unwrap();
+        t.join().unwrap();
+        let stats = c.stats();
+        assert_eq!(stats.occupancy, 3);
+        assert_eq!(stats.usage_bytes, 3 << 10);
+
+        // Erase the pages of a file.
+        c.erase_file_pages(1);
+        assert!(c.lookup(2).is_some());
+        assert!(c.lookup((1 << 32) | 1).is_none());
+        assert!(c.lookup((1 << 32) | 2).is_none());
+        assert_eq!(c.stats().occupancy, 1);
+    }
+
+    /// Access patterns of cache workloads.
+    #[derive(Clone, Copy, Debug)]
+    enum Pattern {
+        Zipf,
+        Uniform,
+    }
+
+    /// Runs a read-through workload against the cache, where a missed key is
+    /// inserted into the cache.
+    ///
+    /// Returns the hit ratio and the number of operations per second.
+    fn run_workload<C: Cache<Vec<u64>>>(
+        c: &Arc<C>,
+        pattern: Pattern,
+        num_keys: u64,
+        num_ops: usize,
+    ) -> (f64, f64) {
+        let mut rng = StdRng::seed_from_u64(0);
+        let zipf = Zipf::new(num_keys, 1.0).unwrap();
+        let mut run = |ops: usize| {
+            for _ in 0..ops {
+                let key = match pattern {
+                    Pattern::Zipf => zipf.sample(&mut rng) as u64,
+                    Pattern::Uniform => rng.gen_range(1..=num_keys),
+                };
+                if let Some(v) = c.lookup(key) {
+                    assert_eq!(v.value(), &vec![key]);
+                } else {
+                    let v = c
+                        .insert(key, Some(vec![key]), 1, CacheOption::default())
+                        .unwrap();
+                    drop(v);
+                }
+            }
+        };
+
+        // Warm up the cache before measuring.
+        run(num_ops / 10);
+        let stats = c.stats();
+        let start = Instant::now();
+        run(num_ops);
+        let ops_per_sec = num_ops as f64 / start.elapsed().as_secs_f64();
+        let stats = c.stats().sub(&stats);
+        assert_eq!(stats.lookup_hit + stats.lookup_miss, num_ops as u64);
+        let hit_ratio = stats.lookup_hit as f64 / num_ops as f64;
+        (hit_ratio, ops_per_sec)
+    }
+
+    #[test]
+    fn test_clock_hit_ratio() {
+        use super::clock::*;
+
+        let c = Arc::new(ClockCache::new(1000, 1, 0, false, false));
+        let (hit_ratio, _) = run_workload(&c, Pattern::Zipf, 10000, 200000);
+        // Keeping the hottest keys all the time hits about 76% of the lookups. A
+        // reasonable eviction policy should stay well above a half.
+        assert!(hit_ratio > 0.6, "hit ratio {hit_ratio:.4}");
+    }
+
+    #[test]
+    fn test_cache_workloads() {
+        use super::{clock::*, lru::*};
+
+        const NUM_KEYS: u64 = 10000;
+        const CAPACITY: usize = 1000;
+        const NUM_OPS: usize = 50000;
+
+        for pattern in [Pattern::Zipf, Pattern::Uniform] {
+            let lru = Arc::new(LRUCache::new(CAPACITY, 0, false, 0.0, 0.0));
+            let clock = Arc::new(ClockCache::new(CAPACITY, 1, 0, false, false));
+            let results = [
+                run_workload(&lru, pattern, NUM_KEYS, NUM_OPS),
+                run_workload(&clock, pattern, NUM_KEYS, NUM_OPS),
+            ];
+            for (hit_ratio, _) in results {
+                match pattern {
+                    Pattern::Zipf => assert!(hit_ratio > 0.5, "hit ratio {hit_ratio:.4}"),
+                    // Uniform accesses hit about `CAPACITY / NUM_KEYS` of the lookups.
+                    Pattern::Uniform => {
+                        assert!((0.05..0.2).contains(&hit_ratio), "hit ratio {hit_ratio:.4}")
+                    }
+                }
+            }
+        }
+    }
+
+    /// Compares the cache policies under the same workloads.
+    ///
+    /// Run with `cargo test --release -- --ignored bench_cache_policies
+    /// --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_cache_policies() {
+        use super::{clock::*, lru::*};
+
+        const NUM_KEYS: u64 = 1 << 20;
+        const CAPACITY: usize = 1 << 16;
+        const NUM_OPS: usize = 1 << 23;
+
+        for pattern in [Pattern::Zipf, Pattern::Uniform] {
+            let lru = Arc::new(LRUCache::new(CAPACITY, 0, false, 0.0, 0.0));
+            let (hit_ratio, ops) = run_workload(&lru, pattern, NUM_KEYS, NUM_OPS);
+            println!("{pattern:?} LRU: hit ratio {hit_ratio:.4}, {ops:.0} ops/s");
+            let clock = Arc::new(ClockCache::new(CAPACITY, 1, 0, false, false));
+            let (hit_ratio, ops) = run_workload(&clock, pattern, NUM_KEYS, NUM_OPS);
+            println!("{pattern:?} Clock: hit ratio {hit_ratio:.4}, {ops:.0} ops/s");
+        }
+    }
+
+    #[test]
+    fn test_clock_cache_builder() {
+        use super::clock::*;
+
+        // Small caches use a single shard.
+        let c = Arc::new(
+            ClockCache::builder()
+                .capacity_bytes(1 << 20)
+                .estimated_entry_charge(64)
+                .build()
+                .unwrap(),
+        );
+        assert_eq!(c.num_shards(), 1);
+        let v = c
+            .insert(1, Some(vec![1]), 64, CacheOption::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!(v.value(), &vec![1]);
+        drop(v);
+
+        // Large caches are sharded, but no more than the CPUs.
+        let c = ClockCache::<Vec<u64>>::builder()
+            .capacity_bytes(1 << 30)
+            .build()
+            .unwrap();
+        let num_cpus = thread::available_parallelism().unwrap().get();
+        assert!(c.num_shards() <= num_cpus.next_power_of_two());
+        assert!(c.num_shards() <= 32);
+
+        let c = ClockCache::<Vec<u64>>::builder()
+            .capacity_bytes(1 << 20)
+            .num_shard_bits(2)
+            .build()
+            .unwrap();
+        assert_eq!(c.num_shards(), 4);
+
+        for builder in [
+            ClockCache::<Vec<u64>>::builder().capacity_bytes(0),
+            ClockCache::builder().estimated_entry_charge(0),
+            ClockCache::builder()
+                .capacity_bytes(1 << 10)
+                .estimated_entry_charge(1 << 20),
+            ClockCache::builder().num_shard_bits(20),
+        ] {
+            assert!(matches!(builder.build(), Err(Error::InvalidArgument)));
+        }
+    }
+
+    #[test]
+    fn test_clock_cache_usage_stats() {
+        use super::clock::*;
+
+        let c = Arc::new(
+            ClockCache::builder()
+                .capacity_bytes(1 << 20)
+                .estimated_entry_charge(1 << 10)
+                .build()
+                .unwrap(),
+        );
+        let stats = c.stats();
+        assert_eq!(stats.usage_bytes, 0);
+        assert_eq!(stats.occupancy, 0);
+        assert!(stats.occupancy_limit > 0);
+
+        for key in 0..10 {
+            let charge = (key as usize + 1) << 10;
+            let handle = c
+                .insert(key, Some(vec![key]), charge, CacheOption::default())
+                .unwrap();
+            drop(handle);
+        }
+        let stats = c.stats();
+        assert_eq!(stats.usage_bytes, (1..=10).sum::<u64>() << 10);
+        assert_eq!(stats.detached_usage_bytes, 0);
+        assert_eq!(stats.occupancy, 10);
+
+        c.erase(0);
+        let stats = c.stats();
+        assert_eq!(stats.usage_bytes, (2..=10).sum::<u64>() << 10);
+        assert_eq!(stats.occupancy, 9);
+    }
+
+    #[test]
+    fn test_cache_charge_by_bytes() {
+        use super::{clock::*, lru::*};
+
+        const CAPACITY: usize = 64 << 10;
+        check_charge_by_bytes(Arc::new(LRUCache::new(CAPACITY, 0, false, 0.0, 0.0)));
+        check_charge_by_bytes(Arc::new(ClockCache::new(CAPACITY, 1 << 10, 0, true, false)));
+
+        fn check_charge_by_bytes<C: Cache<Vec<u64>>>(c: Arc<C>) {
+            let insert = |key: u64, charge: usize| {
+                let h = c.insert(key, Some(vec![key]), charge, CacheOption::default());
+                drop(h.unwrap());
+                assert!(c.stats().usage_bytes <= CAPACITY as u64);
+            };
+
+            for key in 0..64 {
+                insert(key, 1 << 10);
+            }
+            // A large page evicts as many small pages as its size.
+            insert(64, 32 << 10);
+            assert!(c.lookup(64).is_some());
+            let small = (0..64).filter(|&key| c.lookup(key).is_some()).count();
+            assert!(small <= 32, "{small} small pages are cached");
+
+            // Pages of varying sizes never exceed the byte budget, although the
+            // number of pages is far below the capacity.
+            for key in 0..256 {
+                insert(100 + key, ((key as usize % 16) + 1) << 10);
+            }
+        }
+    }
 }
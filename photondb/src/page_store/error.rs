@@ -12,6 +12,8 @@ pub(crate) enum Error {
     MemoryLimit,
     #[error("TooLarge put size")]
     TooLargeSize,
+    #[error("Snapshot too old")]
+    SnapshotTooOld,
     #[error("IO {0}")]
     Io(#[from] std::io::Error),
 }
@@ -0,0 +1,30 @@
+use std::sync::Arc;
+
+use futures::StreamExt;
+
+use crate::{
+    env::Env,
+    page_store::{page_file::PendingInsertReceiver, PageFiles},
+    util::shutdown::{with_shutdown, Shutdown},
+};
+
+/// Inserts pages read from files into the page cache in the background.
+pub(crate) struct CacheInsertCtx<E: Env> {
+    shutdown: Shutdown,
+    page_files: Arc<PageFiles<E>>,
+}
+
+impl<E: Env> CacheInsertCtx<E> {
+    pub(crate) fn new(shutdown: Shutdown, page_files: Arc<PageFiles<E>>) -> Self {
+        CacheInsertCtx {
+            shutdown,
+            page_files,
+        }
+    }
+
+    pub(crate) async fn run(mut self, mut receiver: PendingInsertReceiver) {
+        while let Some(Some(pending)) = with_shutdown(&mut self.shutdown, receiver.next()).await {
+            self.page_files.insert_pending_page(pending);
+        }
+    }
+}
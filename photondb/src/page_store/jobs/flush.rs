@@ -214,7 +214,7 @@ impl<E: Env> FlushCtx<E> {
                 }
                 let content = page.data();
                 group_builder
-                    .add_page(header.page_id(), page_addr, page.info(), content)
+                    .add_tree_page(header.page_id(), page_addr, page)
                     .await?;
                 write_bytes += content.len();
                 let _ = self.page_files.populate_cache(page_addr, content);
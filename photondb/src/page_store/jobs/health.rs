@@ -0,0 +1,225 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use log::warn;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use super::reclaim::{compute_base_size, compute_used_space};
+use crate::{
+    env::Env,
+    page_store::{stats::CacheStats, version::VersionOwner, HealthCheckOptions, PageFiles},
+    util::{
+        shutdown::{with_shutdown, Shutdown},
+        timer::sleep,
+    },
+};
+
+/// The minimum number of lookups in a check interval to evaluate the hit ratio
+/// of the page cache.
+const MIN_CACHE_LOOKUPS: u64 = 100;
+
+pub(crate) struct HealthCheckCtx<E: Env> {
+    shutdown: Shutdown,
+    checker: HealthChecker,
+    page_files: Arc<PageFiles<E>>,
+    version_owner: Arc<VersionOwner>,
+}
+
+impl<E: Env> HealthCheckCtx<E> {
+    pub(crate) fn new(
+        options: HealthCheckOptions,
+        shutdown: Shutdown,
+        page_files: Arc<PageFiles<E>>,
+        version_owner: Arc<VersionOwner>,
+    ) -> Self {
+        HealthCheckCtx {
+            shutdown,
+            checker: HealthChecker::new(options),
+            page_files,
+            version_owner,
+        }
+    }
+
+    pub(crate) async fn run(mut self, interval: Duration) {
+        loop {
+            if with_shutdown(&mut self.shutdown, sleep(interval))
+                .await
+                .is_none()
+            {
+                break;
+            }
+            let sample = self.sample();
+            self.checker.check(&sample, Instant::now());
+        }
+    }
+
+    fn sample(&self) -> HealthSample {
+        let version = self.version_owner.current();
+        let cleaned_files = FxHashSet::default();
+        let (page_cache, _) = self.page_files.stats();
+        HealthSample {
+            page_cache,
+            cache_capacity: self.page_files.page_cache_capacity() as u64,
+            used_space: compute_used_space(version.file_infos(), &cleaned_files),
+            base_size: compute_base_size(version.page_groups(), &cleaned_files),
+        }
+    }
+}
+
+/// Unhealthy conditions of a page store.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum HealthIssue {
+    LowCacheHitRatio,
+    HighSpaceAmplification,
+    HighDetachedCacheUsage,
+}
+
+/// The stats of a page store to check.
+struct HealthSample {
+    page_cache: CacheStats,
+    cache_capacity: u64,
+    used_space: u64,
+    base_size: u64,
+}
+
+/// Evaluates the health of a page store and warns about unhealthy
+/// conditions, at most once per [`HealthCheckOptions::warn_interval`] for each
+/// condition.
+struct HealthChecker {
+    options: HealthCheckOptions,
+    last_cache_stats: CacheStats,
+    last_warned: FxHashMap<HealthIssue, Instant>,
+}
+
+impl HealthChecker {
+    fn new(options: HealthCheckOptions) -> Self {
+        HealthChecker {
+            options,
+            last_cache_stats: CacheStats::default(),
+            last_warned: FxHashMap::default(),
+        }
+    }
+
+    /// Checks the sample and returns the issues warned about.
+    fn check(&mut self, sample: &HealthSample, now: Instant) -> Vec<HealthIssue> {
+        let mut issues = Vec::new();
+
+        let cache = sample.page_cache.sub(&self.last_cache_stats);
+        self.last_cache_stats = sample.page_cache.clone();
+        let lookups = cache.lookup_hit + cache.lookup_miss;
+        let hit_ratio = cache.hit_ratio();
+        if lookups >= MIN_CACHE_LOOKUPS
+            && hit_ratio < self.options.min_cache_hit_ratio
+            && self.should_warn(HealthIssue::LowCacheHitRatio, now)
+        {
+            warn!(
+                "The page cache hit ratio {:.2}% of the last {lookups} lookups \
+                is lower than {:.2}%, consider increasing `cache_capacity`",
+                hit_ratio * 100.0,
+                self.options.min_cache_hit_ratio * 100.0,
+            );
+            issues.push(HealthIssue::LowCacheHitRatio);
+        }
+
+        let additional_size = sample.used_space.saturating_sub(sample.base_size);
+        let max_space_amp = self.options.max_space_amplification_percent as u64;
+        if additional_size > 0
+            && max_space_amp * sample.base_size < additional_size * 100
+            && self.should_warn(HealthIssue::HighSpaceAmplification, now)
+        {
+            warn!(
+                "The space amplification {:.2}% is larger than {max_space_amp}% \
+                with {} bytes used, space reclaiming might fall behind writes, \
+                consider increasing `max_concurrent_compactions`",
+                additional_size as f64 * 100.0 / sample.base_size as f64,
+                sample.used_space,
+            );
+            issues.push(HealthIssue::HighSpaceAmplification);
+        }
+
+        let detached = sample.page_cache.detached_usage_bytes;
+        let max_detached = self.options.max_detached_cache_percent as u64;
+        if detached > 0
+            && max_detached * sample.cache_capacity < detached * 100
+            && self.should_warn(HealthIssue::HighDetachedCacheUsage, now)
+        {
+            warn!(
+                "{detached} bytes of pages are cached without slots in the page cache \
+                of {} bytes, consider increasing `cache_estimated_entry_charge` \
+                or `cache_capacity`",
+                sample.cache_capacity,
+            );
+            issues.push(HealthIssue::HighDetachedCacheUsage);
+        }
+
+        issues
+    }
+
+    fn should_warn(&mut self, issue: HealthIssue, now: Instant) -> bool {
+        match self.last_warned.get(&issue) {
+            Some(last) if now.saturating_duration_since(*last) < self.options.warn_interval => {
+                false
+            }
+            _ => {
+                self.last_warned.insert(issue, now);
+                true
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(lookup_hit: u64, lookup_miss: u64) -> HealthSample {
+        HealthSample {
+            page_cache: CacheStats {
+                lookup_hit,
+                lookup_miss,
+                ..Default::default()
+            },
+            cache_capacity: 1 << 20,
+            used_space: 100,
+            base_size: 100,
+        }
+    }
+
+    #[test]
+    fn health_check_rate_limit() {
+        let options = HealthCheckOptions {
+            warn_interval: Duration::from_secs(60),
+            ..Default::default()
+        };
+        let mut checker = HealthChecker::new(options);
+        let now = Instant::now();
+        let low_hit = vec![HealthIssue::LowCacheHitRatio];
+
+        assert_eq!(checker.check(&sample(10, 190), now), low_hit);
+        // The condition persists, but the warning is rate-limited.
+        let at = now + Duration::from_secs(30);
+        assert!(checker.check(&sample(20, 380), at).is_empty());
+        let at = now + Duration::from_secs(60);
+        assert_eq!(checker.check(&sample(30, 570), at), low_hit);
+
+        // The hit ratio is evaluated within each check interval.
+        let at = now + Duration::from_secs(120);
+        assert!(checker.check(&sample(230, 570), at).is_empty());
+        // Too few lookups to evaluate.
+        let at = now + Duration::from_secs(180);
+        assert!(checker.check(&sample(230, 600), at).is_empty());
+
+        let mut sample = sample(230, 600);
+        sample.used_space = 500;
+        sample.page_cache.detached_usage_bytes = 1 << 20;
+        assert_eq!(
+            checker.check(&sample, at),
+            vec![
+                HealthIssue::HighSpaceAmplification,
+                HealthIssue::HighDetachedCacheUsage
+            ]
+        );
+    }
+}
@@ -1,8 +1,11 @@
 //! A mod contains jobs used by `page_store`.
 
+pub(crate) mod cache_insert;
 pub(crate) mod cleanup;
 pub(crate) mod flush;
+pub(crate) mod health;
 pub(crate) mod reclaim;
+mod throttle;
 
 use flush::version_snapshot;
 pub(crate) use reclaim::wait_for_reclaiming;
@@ -1,14 +1,16 @@
 use std::{sync::Arc, time::Instant};
 
-use log::{debug, info, trace};
+use futures::future::join_all;
+use log::{debug, info, trace, warn};
 use rustc_hash::{FxHashMap, FxHashSet};
 
+use super::throttle::CompactionThrottle;
 use crate::{
     env::Env,
-    page::PageRef,
+    page::{leaf_page_key_range, PageRef},
     page_store::{
         page_file::{FileBuilder, FileMetaHolder, FileReader, PageGroupBuilder},
-        stats::AtomicJobStats,
+        stats::{AtomicJobStats, AtomicWritebufStats},
         strategy::ReclaimPickStrategy,
         version::{DeltaVersion, VersionOwner, VersionUpdateReason},
         FileInfo, Manifest, NewFile, Options, PageFiles, PageGroup, Result, StrategyBuilder,
@@ -31,6 +33,7 @@ where
     manifest: Arc<futures::lock::Mutex<Manifest<E>>>,
 
     cleaned_files: FxHashSet<u32>,
+    throttle: CompactionThrottle,
 
     job_stats: Arc<AtomicJobStats>,
 }
@@ -49,7 +52,7 @@ enum ReclaimJob {
     Compact(FxHashSet<u32>),
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 struct ReclaimProgress {
     // some options.
     target_space_amp: u64,
@@ -90,7 +93,9 @@ where
         version_owner: Arc<VersionOwner>,
         manifest: Arc<futures::lock::Mutex<Manifest<E>>>,
         job_stats: Arc<AtomicJobStats>,
+        writebuf_stats: Arc<AtomicWritebufStats>,
     ) -> Self {
+        let throttle = CompactionThrottle::new(&options, writebuf_stats);
         ReclaimCtx {
             options,
             shutdown,
@@ -99,6 +104,7 @@ where
             version_owner,
             manifest,
             cleaned_files: FxHashSet::default(),
+            throttle,
             job_stats,
         }
     }
@@ -141,30 +147,92 @@ where
         };
         let mut strategy = self.build_strategy(now, version, cleaned_files);
         let mut builder = ReclaimJobBuilder::new(self.options.file_base_size);
+        let max_jobs = self.options.max_concurrent_compactions.max(1);
+        let mut jobs = Vec::with_capacity(max_jobs);
+        // The progress as if the picked jobs have succeeded, which decides
+        // when to stop picking files.
+        let mut planned = progress.clone();
         while let Some((file, active_size)) = strategy.apply() {
             if let Some(job) = builder.add(file, active_size) {
                 match job {
                     ReclaimJob::Compact(victims) => {
-                        self.reclaim_files(progress, version, victims).await;
+                        for id in &victims {
+                            let info = version.file_infos().get(id).expect("Victim must exists");
+                            planned.track_file(info, version.page_groups());
+                        }
+                        jobs.push(victims);
                     }
                 }
             }
 
+            if jobs.len() >= max_jobs {
+                let jobs = std::mem::take(&mut jobs);
+                if !self.throttle_compactions(progress).await {
+                    break;
+                }
+                self.run_compactions(progress, version, jobs).await;
+            }
+
             if self.shutdown.is_terminated()
                 || version.has_next_version()
-                || !progress.is_reclaimable()
+                || !planned.is_reclaimable()
             {
                 break;
             }
         }
+        if !jobs.is_empty() && self.throttle_compactions(progress).await {
+            self.run_compactions(progress, version, jobs).await;
+        }
     }
 
-    async fn reclaim_files(
+    /// Delays compactions by the foreground load.
+    ///
+    /// Returns false if the store is shutting down.
+    async fn throttle_compactions(&mut self, progress: &ReclaimProgress) -> bool {
+        // Reclaims space as soon as possible if the space usage is too high.
+        if let ReclaimReason::HighSpaceUsage = progress.reclaim_reason() {
+            return true;
+        }
+        match self.throttle.wait(&mut self.shutdown).await {
+            Some(delay) => {
+                let delay_ms = delay.as_millis() as u64;
+                if delay_ms > 0 {
+                    self.job_stats.compact_throttle_ms.add(delay_ms);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Runs the compactions of disjoint sets of files concurrently.
+    ///
+    /// The strategy picks each file once, so the jobs never rewrite the same
+    /// file. Only the files of the succeeded jobs are marked as cleaned.
+    async fn run_compactions(
         &mut self,
         progress: &mut ReclaimProgress,
         version: &Arc<Version>,
-        victims: FxHashSet<u32>,
+        jobs: Vec<FxHashSet<u32>>,
     ) {
+        let compactions = jobs
+            .iter()
+            .map(|victims| self.reclaim_files(version, victims));
+        let results = join_all(compactions).await;
+        for (victims, result) in jobs.into_iter().zip(results) {
+            if let Err(err) = result {
+                warn!("Failed to compact files {victims:?}: {err}");
+                continue;
+            }
+            for id in victims {
+                let info = version.file_infos().get(&id).expect("Victim must exists");
+                progress.track_file(info, version.page_groups());
+                self.cleaned_files.insert(id);
+            }
+        }
+    }
+
+    async fn reclaim_files(&self, version: &Arc<Version>, victims: &FxHashSet<u32>) -> Result<()> {
         let file_id = {
             let mut lock = self.manifest.lock().await;
             lock.next_file_id()
@@ -173,18 +241,19 @@ where
         let file_infos = version.file_infos();
         let page_groups = version.page_groups();
         let (page_groups, file_info) = self
-            .compact_files(progress, file_id, file_infos, page_groups, &victims)
-            .await
-            .unwrap();
+            .compact_files(file_id, file_infos, page_groups, victims)
+            .await?;
 
         // All input are obsoleted, since it doesn't relocate pages.
-        let edit = make_compact_version_edit(&file_info, &victims);
+        let edit = make_compact_version_edit(&file_info, victims);
+        // Concurrent compactions install their versions one by one under the
+        // manifest lock, and their inputs are disjoint, so each of them applies
+        // to the current version without conflicts.
         let mut manifest = self.manifest.lock().await;
         let version = self.version_owner.current();
         manifest
             .record_version_edit(edit, || super::version_snapshot(&version))
-            .await
-            .unwrap();
+            .await?;
 
         let mut delta = DeltaVersion::from(version.as_ref());
         delta.reason = VersionUpdateReason::Compact;
@@ -192,9 +261,10 @@ where
         delta.file_infos.insert(file_id, file_info);
         // FIXME: need remove empty infos if it is not contained in.
         delta.page_groups.extend(page_groups.into_iter());
-        delta.obsoleted_files = victims.into_iter().collect();
+        delta.obsoleted_files = victims.iter().cloned().collect();
         // Safety: the mutable reference of [`Manifest`] is hold.
         unsafe { self.version_owner.install(delta) };
+        Ok(())
     }
 
     fn build_strategy(
@@ -220,8 +290,7 @@ where
     /// Compact a set of files into a new file, and release mark the compacted
     /// files as obsoleted to reclaim space.
     async fn compact_files(
-        &mut self,
-        progress: &mut ReclaimProgress,
+        &self,
         new_file_id: u32,
         file_infos: &FxHashMap<u32, FileInfo>,
         page_groups: &FxHashMap<u32, PageGroup>,
@@ -238,6 +307,16 @@ where
             .await?;
         let mut victims = victims.iter().cloned().collect::<Vec<_>>();
         victims.sort_unstable();
+        if self.options.compact_in_key_order {
+            victims.sort_by(|a, b| {
+                let min_key = |id: &u32| {
+                    let info = file_infos.get(id).expect("Victim must exists");
+                    let range = info.meta().key_range.as_ref();
+                    key_order(range.map(|r| r.min_key.as_slice()))
+                };
+                min_key(a).cmp(&min_key(b))
+            });
+        }
         let mut stats = CompactStats::default();
         let mut up2_sum = 0;
         for &id in &victims {
@@ -246,8 +325,6 @@ where
             builder = self
                 .compact_file(builder, &mut stats, info, page_groups)
                 .await?;
-            self.cleaned_files.insert(id);
-            progress.track_file(info, page_groups);
         }
 
         // When we include the page in a new segment that contains re-written pages from
@@ -353,24 +430,52 @@ where
 
         let page_table = file_meta.page_tables.get(&group_id).expect("Must exists");
         let mut page = vec![];
-        for page_addr in page_group.iter() {
-            let handle = page_group.get_page_handle(page_addr).expect("Must exists");
-            let page_size = handle.size as usize;
-            if page.len() < page_size {
-                page.resize(page_size, 0u8);
+        let mut page_addrs = page_group.iter().collect::<Vec<_>>();
+        if self.options.compact_in_key_order {
+            // Only the first keys are kept, so that the pages of a large group are
+            // not buffered. The pages are read again when they are rewritten.
+            let mut first_keys = FxHashMap::default();
+            for &page_addr in &page_addrs {
+                self.read_group_page(reader, file_info, page_group, page_addr, &mut page)
+                    .await?;
+                let first_key =
+                    leaf_page_key_range(PageRef::new(&page)).map(|(first, _)| first.to_vec());
+                first_keys.insert(page_addr, first_key);
             }
-            page.truncate(page_size);
-            self.page_files
-                .read_file_page_from_reader(reader, file_info.meta(), handle, &mut page)
+            // The sort is stable, so pages without keys keep their address order.
+            page_addrs.sort_by_cached_key(|addr| key_order(first_keys[addr].as_deref()));
+        }
+
+        for page_addr in page_addrs {
+            self.read_group_page(reader, file_info, page_group, page_addr, &mut page)
                 .await?;
             let page_id = *page_table.get(&page_addr).expect("Must exists");
-            let page_ref = PageRef::new(page.as_slice());
             builder
-                .add_page(page_id, page_addr, page_ref.info(), &page)
+                .add_tree_page(page_id, page_addr, PageRef::new(&page))
                 .await?;
         }
         Ok(())
     }
+
+    /// Reads the page of the page group into `page`.
+    async fn read_group_page(
+        &self,
+        reader: &FileReader<<E as Env>::PositionalReader>,
+        file_info: &FileInfo,
+        page_group: &PageGroup,
+        page_addr: u64,
+        page: &mut Vec<u8>,
+    ) -> Result<()> {
+        let handle = page_group.get_page_handle(page_addr).expect("Must exists");
+        let page_size = handle.size as usize;
+        if page.len() < page_size {
+            page.resize(page_size, 0u8);
+        }
+        page.truncate(page_size);
+        self.page_files
+            .read_file_page_from_reader(reader, file_info.meta(), handle, page)
+            .await
+    }
 }
 
 impl ReclaimJobBuilder {
@@ -512,7 +617,7 @@ pub(crate) async fn wait_for_reclaiming(options: &Options, mut version: Arc<Vers
     }
 }
 
-fn compute_base_size(
+pub(super) fn compute_base_size(
     page_files: &FxHashMap<u32, PageGroup>,
     cleaned_files: &FxHashSet<u32>,
 ) -> u64 {
@@ -526,7 +631,7 @@ fn compute_base_size(
         .sum::<usize>() as u64
 }
 
-fn compute_used_space(
+pub(super) fn compute_used_space(
     file_infos: &FxHashMap<u32, FileInfo>,
     cleaned_files: &FxHashSet<u32>,
 ) -> u64 {
@@ -537,6 +642,12 @@ fn compute_used_space(
         .sum::<usize>() as u64
 }
 
+/// Returns the sort key to rewrite pages or files in key order, which places
+/// the ones without keys last.
+fn key_order(key: Option<&[u8]>) -> (bool, Option<&[u8]>) {
+    (key.is_none(), key)
+}
+
 fn make_compact_version_edit(
     file_info: &FileInfo,
     obsoleted_files: &FxHashSet<u32>,
@@ -560,7 +671,10 @@ mod tests {
     use super::*;
     use crate::{
         env::Photon,
-        page::PageInfo,
+        page::{
+            tests::{key_slice, OwnedSortedPage},
+            PageInfo,
+        },
         page_store::{
             page_file::Compression, version::DeltaVersion, ChecksumType,
             MinDeclineRateStrategyBuilder,
@@ -586,6 +700,7 @@ mod tests {
             DeltaVersion::default(),
         )));
         let page_files = Arc::new(PageFiles::new(Photon, dir, &options).await);
+        let throttle = CompactionThrottle::new(&options, Arc::default());
         ReclaimCtx {
             options,
             shutdown,
@@ -594,6 +709,7 @@ mod tests {
             manifest,
             version_owner,
             cleaned_files: HashSet::default(),
+            throttle,
             job_stats: Arc::default(),
         }
     }
@@ -630,7 +746,7 @@ mod tests {
         let root = TempDir::new("compact_files").unwrap();
         let root = root.into_path();
 
-        let mut ctx = build_reclaim_ctx(&root).await;
+        let ctx = build_reclaim_ctx(&root).await;
 
         let (f1, f2, f3, f4) = (1, 2, 3, 4);
         let (m1, m2, m3) = (1, 2, 3);
@@ -650,10 +766,8 @@ mod tests {
         map_files.insert(m1, m1_info);
         map_files.insert(m2, m2_info);
         let victims = HashSet::from_iter(vec![m1, m2].into_iter());
-        let version = ctx.version_owner.current();
-        let mut progress = ReclaimProgress::new(&ctx.options, &version, &HashSet::default());
         let (virtual_infos, m3_info) = ctx
-            .compact_files(&mut progress, m3, &map_files, &page_files, &victims)
+            .compact_files(m3, &map_files, &page_files, &victims)
             .await
             .unwrap();
 
@@ -687,7 +801,7 @@ mod tests {
         let root = TempDir::new("map_files_reclaiming").unwrap();
         let root = root.into_path();
 
-        let mut ctx = build_reclaim_ctx(&root).await;
+        let ctx = build_reclaim_ctx(&root).await;
 
         let (f1, f2, f3, f4) = (1, 2, 3, 4);
         let (m1, m2, m3) = (1, 2, 3);
@@ -721,8 +835,7 @@ mod tests {
         // No concurrent operations.
         unsafe { ctx.version_owner.install(delta) };
         let version = ctx.version_owner.current();
-        let mut progress = ReclaimProgress::new(&ctx.options, &version, &HashSet::default());
-        ctx.reclaim_files(&mut progress, &version, victims).await;
+        ctx.reclaim_files(&version, &victims).await.unwrap();
 
         let version = ctx.version_owner.current();
         let page_groups = version.page_groups();
@@ -747,4 +860,218 @@ mod tests {
         assert!(!map_files.contains_key(&m2));
         assert!(map_files.contains_key(&m3));
     }
+
+    #[photonio::test]
+    async fn files_compacting_concurrently() {
+        let root = TempDir::new("files_compacting_concurrently").unwrap();
+        let root = root.into_path();
+
+        let mut ctx = build_reclaim_ctx(&root).await;
+
+        let (f1, f2, f3, f4) = (1, 2, 3, 4);
+        let (m1, m2, m3, m4) = (1, 2, 3, 4);
+        {
+            let mut lock = ctx.manifest.lock().await;
+            lock.reset_next_file_id(m3);
+        }
+        let mut pages = FxHashMap::default();
+        pages.insert(f1, vec![(1, pa(f1, 16)), (2, pa(f1, 32))]);
+        pages.insert(f2, vec![(3, pa(f2, 16)), (4, pa(f2, 32))]);
+        let (virtual_infos, m1_info) = build_file(&ctx.page_files, m1, pages).await;
+        let mut page_groups = virtual_infos;
+
+        let mut pages = FxHashMap::default();
+        pages.insert(f3, vec![(5, pa(f3, 16)), (6, pa(f3, 32))]);
+        pages.insert(f4, vec![(7, pa(f4, 16)), (8, pa(f4, 32))]);
+        let (virtual_infos, m2_info) = build_file(&ctx.page_files, m2, pages).await;
+        page_groups.extend(virtual_infos.into_iter());
+
+        let mut file_infos = FxHashMap::default();
+        file_infos.insert(m1, m1_info);
+        file_infos.insert(m2, m2_info);
+        let delta = DeltaVersion {
+            reason: VersionUpdateReason::Flush,
+            page_groups,
+            file_infos,
+            ..Default::default()
+        };
+        // No concurrent operations.
+        unsafe { ctx.version_owner.install(delta) };
+
+        // Each file is compacted into a new file by a separate compaction.
+        let version = ctx.version_owner.current();
+        let jobs = vec![HashSet::from_iter([m1]), HashSet::from_iter([m2])];
+        let mut progress = ReclaimProgress::new(&ctx.options, &version, &HashSet::default());
+        ctx.run_compactions(&mut progress, &version, jobs).await;
+
+        let version = ctx.version_owner.current();
+        let file_infos = version.file_infos();
+        assert!(!file_infos.contains_key(&m1));
+        assert!(!file_infos.contains_key(&m2));
+        assert!(file_infos.contains_key(&m3));
+        assert!(file_infos.contains_key(&m4));
+        let page_groups = version.page_groups();
+        for (group_id, page_addr) in [
+            (f1, pa(f1, 16)),
+            (f2, pa(f2, 32)),
+            (f3, pa(f3, 16)),
+            (f4, pa(f4, 32)),
+        ] {
+            let page_group = page_groups.get(&group_id).unwrap();
+            assert!(page_group.get_page_handle(page_addr).is_some());
+            assert!([m3, m4].contains(&page_group.meta().file_id));
+        }
+        assert_eq!(ctx.cleaned_files, HashSet::from_iter([m1, m2]));
+        assert_eq!(progress.used_space, 0);
+    }
+
+    #[photonio::test]
+    async fn files_compacting_failed() {
+        let root = TempDir::new("files_compacting_failed").unwrap();
+        let root = root.into_path();
+
+        let mut ctx = build_reclaim_ctx(&root).await;
+
+        let (f1, f2) = (1, 2);
+        let (m1, m2, m3) = (1, 2, 3);
+        {
+            let mut lock = ctx.manifest.lock().await;
+            lock.reset_next_file_id(m3);
+        }
+        let mut file_infos = FxHashMap::default();
+        let mut page_groups = FxHashMap::default();
+        for (group_id, file_id) in [(f1, m1), (f2, m2)] {
+            let mut pages = FxHashMap::default();
+            pages.insert(group_id, vec![(1, pa(group_id, 16))]);
+            let (virtual_infos, info) = build_file(&ctx.page_files, file_id, pages).await;
+            page_groups.extend(virtual_infos.into_iter());
+            file_infos.insert(file_id, info);
+        }
+        let delta = DeltaVersion {
+            reason: VersionUpdateReason::Flush,
+            page_groups,
+            file_infos,
+            ..Default::default()
+        };
+        // No concurrent operations.
+        unsafe { ctx.version_owner.install(delta) };
+
+        // The compaction of m2 fails, since the file is gone.
+        std::fs::remove_file(root.join(format!("map_{m2}"))).unwrap();
+        let version = ctx.version_owner.current();
+        let jobs = vec![HashSet::from_iter([m1]), HashSet::from_iter([m2])];
+        let mut progress = ReclaimProgress::new(&ctx.options, &version, &HashSet::default());
+        ctx.run_compactions(&mut progress, &version, jobs).await;
+
+        // Only the files of the succeeded compaction are cleaned.
+        assert_eq!(ctx.cleaned_files, HashSet::from_iter([m1]));
+        let m2_size = version.file_infos()[&m2].meta().file_size as u64;
+        assert_eq!(progress.used_space, m2_size);
+        let version = ctx.version_owner.current();
+        let file_infos = version.file_infos();
+        assert!(!file_infos.contains_key(&m1));
+        assert!(file_infos.contains_key(&m2));
+    }
+
+    /// Builds a file with a page group of leaf pages, each holding one key, in
+    /// the order of page addresses.
+    async fn build_leaf_file(
+        page_files: &PageFiles<Photon>,
+        file_id: u32,
+        group_id: u32,
+        keys: &[u8],
+        key_addrs: &mut FxHashMap<u8, u64>,
+    ) -> (FxHashMap<u32, PageGroup>, FileInfo) {
+        let mut builder = page_files
+            .new_file_builder(file_id, Compression::NONE, ChecksumType::NONE)
+            .await
+            .unwrap();
+        let mut group_builder = builder.add_page_group(group_id);
+        for (i, &key) in keys.iter().enumerate() {
+            let page = OwnedSortedPage::from_slice(&key_slice(&[([key], 1)]));
+            let page_addr = pa(group_id, (i as u32 + 1) * 64);
+            group_builder
+                .add_tree_page(key as u64, page_addr, *page.as_ref())
+                .await
+                .unwrap();
+            key_addrs.insert(key, page_addr);
+        }
+        builder = group_builder.finish().await.unwrap();
+        builder.finish(file_id).await.unwrap()
+    }
+
+    /// Returns the files of the reads to scan pages in key order, if
+    /// contiguous pages of a file are read at once.
+    fn scan_reads(
+        page_groups: &FxHashMap<u32, PageGroup>,
+        key_addrs: &FxHashMap<u8, u64>,
+    ) -> Vec<u32> {
+        let mut keys = key_addrs.keys().cloned().collect::<Vec<_>>();
+        keys.sort_unstable();
+        let mut reads = vec![];
+        let mut last = None;
+        for key in keys {
+            let page_addr = key_addrs[&key];
+            let page_group = &page_groups[&((page_addr >> 32) as u32)];
+            let file_id = page_group.meta().file_id;
+            let handle = page_group.get_page_handle(page_addr).unwrap();
+            if last != Some((file_id, handle.offset)) {
+                reads.push(file_id);
+            }
+            last = Some((file_id, handle.offset + handle.size));
+        }
+        reads
+    }
+
+    #[photonio::test]
+    async fn files_compacting_in_key_order() {
+        let root = TempDir::new("files_compacting_in_key_order").unwrap();
+        let root = root.into_path();
+
+        let mut ctx = build_reclaim_ctx(&root).await;
+
+        let (f1, f2) = (1, 2);
+        let (m1, m2, m3, m4) = (1, 2, 3, 4);
+        // The keys of m1 are larger than m2, and written in reverse order.
+        let mut key_addrs = FxHashMap::default();
+        let keys = (20..30).rev().collect::<Vec<_>>();
+        let (mut page_groups, m1_info) =
+            build_leaf_file(&ctx.page_files, m1, f2, &keys, &mut key_addrs).await;
+        let keys = (10..20).collect::<Vec<_>>();
+        let (virtual_infos, m2_info) =
+            build_leaf_file(&ctx.page_files, m2, f1, &keys, &mut key_addrs).await;
+        page_groups.extend(virtual_infos.into_iter());
+        assert_eq!(
+            scan_reads(&page_groups, &key_addrs),
+            [vec![m2], vec![m1; 10]].concat()
+        );
+
+        let mut file_infos = FxHashMap::default();
+        file_infos.insert(m1, m1_info);
+        file_infos.insert(m2, m2_info);
+        let victims = HashSet::from_iter([m1, m2]);
+
+        // By default, compactions keep the order of pages.
+        let (virtual_infos, _) = ctx
+            .compact_files(m3, &file_infos, &page_groups, &victims)
+            .await
+            .unwrap();
+        assert_eq!(scan_reads(&virtual_infos, &key_addrs).len(), 11);
+
+        ctx.options.compact_in_key_order = true;
+        let (virtual_infos, _) = ctx
+            .compact_files(m4, &file_infos, &page_groups, &victims)
+            .await
+            .unwrap();
+        // Pages are read sequentially, except the gap between page groups.
+        assert_eq!(scan_reads(&virtual_infos, &key_addrs), vec![m4; 2]);
+        let offsets = (10..30)
+            .map(|key| {
+                let page_addr = key_addrs[&key];
+                let page_group = &virtual_infos[&((page_addr >> 32) as u32)];
+                page_group.get_page_handle(page_addr).unwrap().offset
+            })
+            .collect::<Vec<_>>();
+        assert!(offsets.windows(2).all(|w| w[0] < w[1]));
+    }
 }
@@ -0,0 +1,144 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    page_store::{stats::AtomicWritebufStats, Options},
+    util::{
+        shutdown::{with_shutdown, Shutdown},
+        timer::sleep,
+    },
+};
+
+/// The window to measure the rate of foreground operations.
+const SAMPLE_WINDOW: Duration = Duration::from_millis(100);
+
+/// The maximum delay of a compaction round if compactions are not idle-only.
+const MAX_THROTTLE_DELAY: Duration = Duration::from_secs(1);
+
+/// Throttles compactions by the rate of foreground operations.
+pub(super) struct CompactionThrottle {
+    max_ops_per_sec: u64,
+    idle_only: bool,
+    max_delay: Duration,
+    writebuf_stats: Arc<AtomicWritebufStats>,
+}
+
+impl CompactionThrottle {
+    pub(super) fn new(options: &Options, writebuf_stats: Arc<AtomicWritebufStats>) -> Self {
+        CompactionThrottle {
+            max_ops_per_sec: options.compaction_throttle_ops_per_sec,
+            idle_only: options.compaction_idle_only,
+            max_delay: MAX_THROTTLE_DELAY,
+            writebuf_stats,
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.idle_only || self.max_ops_per_sec != u64::MAX
+    }
+
+    /// Waits until the foreground load allows compactions to run.
+    ///
+    /// Returns the time waited, or `None` if the store is shutting down.
+    pub(super) async fn wait(&self, shutdown: &mut Shutdown) -> Option<Duration> {
+        if !self.is_enabled() {
+            return Some(Duration::ZERO);
+        }
+        let start = Instant::now();
+        loop {
+            let ops_per_sec = with_shutdown(shutdown, self.foreground_ops_per_sec()).await?;
+            let throttled = if self.idle_only {
+                ops_per_sec > 0
+            } else {
+                ops_per_sec > self.max_ops_per_sec && start.elapsed() < self.max_delay
+            };
+            if !throttled {
+                return Some(start.elapsed());
+            }
+        }
+    }
+
+    /// Returns the rate of foreground operations in a sample window.
+    async fn foreground_ops_per_sec(&self) -> u64 {
+        let start = Instant::now();
+        let start_ops = self.foreground_ops();
+        sleep(SAMPLE_WINDOW).await;
+        let ops = self.foreground_ops().wrapping_sub(start_ops);
+        (ops as f64 / start.elapsed().as_secs_f64()) as u64
+    }
+
+    fn foreground_ops(&self) -> u64 {
+        let stats = &self.writebuf_stats;
+        stats.read_in_buf.get() + stats.read_in_file.get() + stats.alloc_pages.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicBool, Ordering},
+        thread,
+    };
+
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::util::shutdown::ShutdownNotifier;
+
+    /// Simulates foreground operations until the returned flag is cleared.
+    fn run_foreground_load(stats: Arc<AtomicWritebufStats>) -> Arc<AtomicBool> {
+        let running = Arc::new(AtomicBool::new(true));
+        let flag = running.clone();
+        thread::spawn(move || {
+            while flag.load(Ordering::Relaxed) {
+                stats.alloc_pages.inc();
+                thread::sleep(Duration::from_micros(100));
+            }
+        });
+        running
+    }
+
+    #[test]
+    fn compaction_throttle() {
+        let notifier = ShutdownNotifier::new();
+        let mut shutdown = notifier.subscribe();
+        let stats = Arc::new(AtomicWritebufStats::default());
+        let options = Options {
+            compaction_throttle_ops_per_sec: 100,
+            ..Default::default()
+        };
+        let mut throttle = CompactionThrottle::new(&options, stats.clone());
+        throttle.max_delay = Duration::from_millis(300);
+
+        // Compactions are delayed under load, but not longer than the max delay.
+        let running = run_foreground_load(stats.clone());
+        let delay = block_on(throttle.wait(&mut shutdown)).unwrap();
+        assert!(delay >= throttle.max_delay, "{delay:?}");
+        assert!(delay < throttle.max_delay + SAMPLE_WINDOW * 3, "{delay:?}");
+
+        // Compactions catch up once the load stops.
+        running.store(false, Ordering::Relaxed);
+        thread::sleep(SAMPLE_WINDOW);
+        let delay = block_on(throttle.wait(&mut shutdown)).unwrap();
+        assert!(delay < SAMPLE_WINDOW * 2, "{delay:?}");
+
+        // Idle-only compactions wait for the load to stop.
+        throttle.idle_only = true;
+        let running = run_foreground_load(stats.clone());
+        let stopper = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(500));
+            running.store(false, Ordering::Relaxed);
+        });
+        let delay = block_on(throttle.wait(&mut shutdown)).unwrap();
+        assert!(delay >= Duration::from_millis(500), "{delay:?}");
+        stopper.join().unwrap();
+
+        // Waiting stops on shutdown.
+        let running = run_foreground_load(stats);
+        notifier.terminate();
+        assert!(block_on(throttle.wait(&mut shutdown)).is_none());
+        running.store(false, Ordering::Relaxed);
+    }
+}
@@ -1,6 +1,7 @@
 use std::{io::ErrorKind, path::PathBuf};
 
 use prost::Message;
+use rustc_hash::FxHashSet;
 
 use super::{meta::VersionEdit, Error};
 use crate::{env::*, page_store::Result};
@@ -18,6 +19,11 @@ pub(crate) struct Manifest<E: Env> {
     max_file_size: u64,
 
     next_file_id: u32,
+    /// Files that are allocated but not recorded yet, which are being written.
+    writing_files: FxHashSet<u32>,
+    /// Files that are deleted from versions, which may wait to be removed
+    /// until the versions referencing them are released.
+    deleted_files: FxHashSet<u32>,
 
     current_file_num: Option<u32>,
     current_writer: Option<ManifestWriter<E::SequentialWriter>>,
@@ -40,6 +46,8 @@ impl<E: Env> Manifest<E> {
             base_dir: None,
             max_file_size: MAX_MANIFEST_SIZE,
             next_file_id: 0,
+            writing_files: FxHashSet::default(),
+            deleted_files: FxHashSet::default(),
             current_file_num: Default::default(),
             current_writer: None,
         };
@@ -87,6 +95,7 @@ impl<E: Env> Manifest<E> {
     pub(crate) fn next_file_id(&mut self) -> u32 {
         let id = self.next_file_id;
         self.next_file_id += 1;
+        self.writing_files.insert(id);
         id
     }
 
@@ -94,6 +103,29 @@ impl<E: Env> Manifest<E> {
         self.next_file_id
     }
 
+    /// Returns the files in `exist_files` that are neither live nor tracked by
+    /// the manifest.
+    ///
+    /// Files that are being written or waiting to be removed are tracked by
+    /// the manifest, so they are not orphans.
+    pub(crate) fn filter_orphan_files(
+        &mut self,
+        exist_files: Vec<u32>,
+        is_live: impl Fn(u32) -> bool,
+    ) -> Vec<u32> {
+        let exist_set = exist_files.iter().cloned().collect::<FxHashSet<_>>();
+        // Forget the deleted files that have been removed.
+        self.deleted_files.retain(|id| exist_set.contains(id));
+        exist_files
+            .into_iter()
+            .filter(|&id| {
+                !is_live(id)
+                    && !self.writing_files.contains(&id)
+                    && !self.deleted_files.contains(&id)
+            })
+            .collect()
+    }
+
     // Record a new version_edit to manifest file.
     // it will rolling file when the file size over `max_file_size`.
     // so it need pass-in a `version_snapshot` to get current snapshot when it
@@ -103,6 +135,14 @@ impl<E: Env> Manifest<E> {
         ve: VersionEdit,
         version_snapshot: impl FnOnce() -> VersionEdit,
     ) -> Result<()> {
+        let (new_files, deleted_files) = ve
+            .file_stream
+            .as_ref()
+            .map(|edit| {
+                let new_files = edit.new_files.iter().map(|f| f.id).collect::<Vec<_>>();
+                (new_files, edit.deleted_files.clone())
+            })
+            .unwrap_or_default();
         let mut current = self.current_writer.take();
         let mut file_num = self.current_file_num.as_ref().unwrap_or(&0).to_owned();
 
@@ -171,6 +211,11 @@ impl<E: Env> Manifest<E> {
 
         self.current_writer = Some(current);
 
+        for id in new_files {
+            self.writing_files.remove(&id);
+        }
+        self.deleted_files.extend(deleted_files);
+
         Ok(())
     }
 
@@ -280,8 +325,7 @@ impl<E: Env> Manifest<E> {
         }
 
         let mut wait_remove_paths = Vec::new();
-        for path in self.env.read_dir(&self.base).expect("open base dir fail") {
-            let file_path = path.unwrap().path();
+        for file_path in self.env.read_dir(&self.base).expect("open base dir fail") {
             if let Some(ext) = file_path.extension() {
                 if ext.to_str().unwrap() == TEMPFILE_SUFFIX {
                     wait_remove_paths.push(file_path.to_owned());
@@ -1,13 +1,18 @@
-use std::{fmt, mem, path::Path, sync::Arc};
+use std::{fmt, future::Future, mem, path::Path, sync::Arc, time::Duration};
 
-use crate::{env::Env, util::shutdown::ShutdownNotifier};
+use log::{info, warn};
+
+use crate::{
+    env::{is_valid_cpu, Env},
+    util::shutdown::ShutdownNotifier,
+};
 
 mod error;
 pub(crate) use error::{Error, Result};
 
 mod page_txn;
 use futures::lock::Mutex;
-pub(crate) use page_txn::{CacheOption, Guard};
+pub(crate) use page_txn::{CacheOption, Guard, PageTxn};
 
 mod page_table;
 use page_table::PageTable;
@@ -20,7 +25,10 @@ mod version;
 use version::{DeltaVersion, Version, VersionOwner, VersionUpdateReason};
 
 mod jobs;
-use jobs::{cleanup::CleanupCtx, flush::FlushCtx, reclaim::ReclaimCtx};
+use jobs::{
+    cache_insert::CacheInsertCtx, cleanup::CleanupCtx, flush::FlushCtx, health::HealthCheckCtx,
+    reclaim::ReclaimCtx,
+};
 
 mod write_buffer;
 pub(crate) use write_buffer::{RecordRef, WriteBuffer};
@@ -31,7 +39,7 @@ mod manifest;
 pub(crate) use manifest::Manifest;
 
 mod page_file;
-pub(crate) use page_file::{FileInfo, PageFiles, PageGroup};
+pub(crate) use page_file::{FileInfo, PageEntry, PageFiles, PageGroup};
 
 mod recover;
 mod strategy;
@@ -39,11 +47,13 @@ pub(crate) use strategy::{MinDeclineRateStrategyBuilder, StrategyBuilder};
 
 mod cache;
 #[allow(unused_imports)]
-pub(crate) use cache::{clock::ClockCache, lru::LRUCache, Cache, CacheEntry, CacheToken};
+pub(crate) use cache::{
+    clock::ClockCache, lru::LRUCache, Cache, CacheEntry, CacheToken, PageCache,
+};
 
 mod stats;
 pub use page_file::{ChecksumType, Compression};
-pub use stats::StoreStats;
+pub use stats::{CacheStats, StoreStats};
 
 use self::{
     jobs::wait_for_reclaiming,
@@ -69,7 +79,11 @@ pub struct Options {
 
     /// If true, use O_DIRECT to read/write page files.
     ///
-    /// Default: false
+    /// Files on filesystems that reject O_DIRECT with `EINVAL`, like tmpfs, are
+    /// read and written with buffered IO instead. Other errors to enable
+    /// O_DIRECT are returned.
+    ///
+    /// Default: true on Linux, false elsewhere
     pub use_direct_io: bool,
 
     /// If true, no space reclamation.
@@ -96,11 +110,57 @@ pub struct Options {
     /// Default: 64MB
     pub file_base_size: usize,
 
+    /// The maximum number of compactions to run at once.
+    ///
+    /// The files picked for reclaiming are split into up to this many disjoint
+    /// sets, which are compacted at once to use the parallel bandwidth of the
+    /// device and keep up with high ingest rates.
+    ///
+    /// Default: 1
+    pub max_concurrent_compactions: usize,
+
+    /// Rewrites pages in key order during compactions.
+    ///
+    /// The input files are rewritten in the order of their key ranges, and
+    /// the pages of each page group are sorted by their first keys, so range
+    /// scans over compacted files read mostly sequentially. Pages keep their
+    /// addresses, so pages of different page groups are never interleaved.
+    /// The pages of each page group are read twice, once to sort them and
+    /// once to rewrite them, so that they are not buffered in memory.
+    ///
+    /// Default: false
+    pub compact_in_key_order: bool,
+
+    /// The rate of foreground operations per second above which compactions
+    /// are throttled.
+    ///
+    /// Foreground operations are page reads and page allocations of user
+    /// operations. When the rate is higher than this, each round of
+    /// compactions is delayed until the rate drops, for at most one second,
+    /// unless the space usage exceeds [`Self::space_used_high`].
+    ///
+    /// Default: `u64::MAX`
+    pub compaction_throttle_ops_per_sec: u64,
+
+    /// If true, compactions only run when there are no foreground
+    /// operations, and they are delayed as long as the store is busy.
+    ///
+    /// Compactions still run if the space usage exceeds
+    /// [`Self::space_used_high`].
+    ///
+    /// Default: false
+    pub compaction_idle_only: bool,
+
     /// The capacity of the page read cache in bytes.
     ///
     /// Default: 8 Mib
     pub cache_capacity: usize,
 
+    /// The eviction policy of the page read cache.
+    ///
+    /// Default: [`CachePolicy::Lru`]
+    pub cache_policy: CachePolicy,
+
     /// The estimated average `charge` associated with cache entries.
     ///
     /// Default: 8 Kib
@@ -124,11 +184,31 @@ pub struct Options {
 
     /// The capacity of file_reader cache.
     ///
+    /// This also bounds the number of open file descriptors, since readers
+    /// evicted from the cache are closed once in-flight reads finish, and
+    /// reopened on demand.
+    ///
     /// Default: 5000 file_readers.
     pub cache_file_reader_capacity: u64,
 
+    /// The maximum number of pages waiting to be inserted into the page cache.
+    ///
+    /// If it is not zero, pages read from files are inserted into the page
+    /// cache by a background job, so that reads don't pay for evictions.
+    /// Pages are not cached if the queue is full.
+    ///
+    /// Default: 0
+    pub cache_async_insert_queue_size: usize,
+
     /// Whether report error when there is no enough memory for the page cache.
     ///
+    /// If false, pages that can't be cached, because they are larger than the
+    /// capacity or the cached pages are all in use, are read without being
+    /// cached. If true, such reads fail with [`Error::MemoryLimit`] instead,
+    /// so that the memory used by pages never exceeds the capacity.
+    ///
+    /// [`Error::MemoryLimit`]: crate::Error::MemoryLimit
+    ///
     /// Default: false
     pub cache_strict_capacity_limit: bool,
 
@@ -150,15 +230,64 @@ pub struct Options {
 
     /// ChecksumType for each page.
     ///
+    /// Opening a store with [`ChecksumType::XXHASH`] fails with
+    /// [`Error::InvalidArgument`], since it is not supported yet.
+    ///
+    /// [`Error::InvalidArgument`]: crate::Error::InvalidArgument
+    ///
     /// Default: NONE.
     pub page_checksum_type: ChecksumType,
 
+    /// If true, the checksums of pages are not verified when pages are read.
+    ///
+    /// This saves the cost of checksums for benchmarks, but corrupted pages
+    /// are not detected. Pages are still verified by
+    /// [`Table::verify_checksums`](crate::Table::verify_checksums).
+    ///
+    /// Default: false
+    pub disable_checksum_verification: bool,
+
+    /// If true, the addresses of cached pages are saved to a warm-set file on
+    /// close, and the pages are read into the page cache on the next open.
+    ///
+    /// The warm-set file is best-effort, it is ignored if it is corrupted, and
+    /// pages that don't exist anymore are skipped.
+    ///
+    /// Default: false
+    pub persist_cache_warmset: bool,
+
     /// PhotonDB will flush all write buffers on DB close, if there are
     /// unpersisted data. The flush can be skip to speed up DB close, but
     /// unpersisted data WILL BE LOST.
     ///
     /// Default: false
     pub avoid_flush_during_shutdown: bool,
+
+    /// The CPUs to run background jobs on.
+    ///
+    /// This only takes effect on Linux with environments that run background
+    /// jobs on dedicated threads, such as [`Std`](crate::env::Std). Other
+    /// environments, such as [`Photon`](crate::env::Photon), ignore it with a
+    /// warning. Opening a store with an empty set or a CPU out of the range
+    /// supported by the platform fails with [`Error::InvalidArgument`].
+    ///
+    /// [`Error::InvalidArgument`]: crate::Error::InvalidArgument
+    ///
+    /// Default: None
+    pub background_cpu_set: Option<Vec<usize>>,
+
+    /// The number of subdirectories to shard page files into.
+    ///
+    /// If it is not zero, a page file is placed in the subdirectory named by
+    /// its file id modulo this number, like `03/map_103`, so that a directory
+    /// doesn't hold too many files. This must not be changed for an existing
+    /// store, otherwise the page files can't be found.
+    ///
+    /// Default: 0
+    pub page_file_dir_shards: u32,
+
+    /// Options to configure the background health check.
+    pub health_check: HealthCheckOptions,
 }
 
 impl Default for Options {
@@ -166,20 +295,98 @@ impl Default for Options {
         Self {
             write_buffer_capacity: 128 << 20,
             max_write_buffers: 8,
-            use_direct_io: false,
+            use_direct_io: cfg!(target_os = "linux"),
             disable_space_reclaiming: false,
             max_space_amplification_percent: 100,
             space_used_high: u64::MAX,
             file_base_size: 64 << 20,
+            max_concurrent_compactions: 1,
+            compact_in_key_order: false,
+            compaction_throttle_ops_per_sec: u64::MAX,
+            compaction_idle_only: false,
             cache_capacity: 8 << 20,
+            cache_policy: CachePolicy::Lru,
             cache_estimated_entry_charge: 8 << 10,
             cache_file_reader_capacity: 5000,
+            cache_async_insert_queue_size: 0,
             cache_strict_capacity_limit: false,
             prepopulate_cache_on_flush: true,
             compression_on_flush: Compression::SNAPPY,
             compression_on_cold_compact: Compression::ZSTD,
             page_checksum_type: ChecksumType::NONE,
+            disable_checksum_verification: false,
+            persist_cache_warmset: false,
             avoid_flush_during_shutdown: false,
+            background_cpu_set: None,
+            page_file_dir_shards: 0,
+            health_check: HealthCheckOptions::default(),
+        }
+    }
+}
+
+/// The eviction policy of the page read cache.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CachePolicy {
+    /// Evicts entries that are not used recently with a clock scan, which
+    /// gives each entry a second chance before it is evicted.
+    ///
+    /// Lookups don't take locks, so it scales better with concurrent reads,
+    /// but its handle table is sized by
+    /// [`Options::cache_estimated_entry_charge`] at creation, and the
+    /// capacity must be non-zero and no less than that charge.
+    Clock,
+    /// Evicts the least recently used entries, which behaves better for small
+    /// working sets.
+    #[default]
+    Lru,
+}
+
+/// Options to configure the health check that warns about unhealthy
+/// conditions of a page store in the log.
+#[non_exhaustive]
+#[derive(Clone, Debug)]
+pub struct HealthCheckOptions {
+    /// The interval to check the health of the store.
+    ///
+    /// The health check is disabled if this is `None`.
+    ///
+    /// Default: None
+    pub interval: Option<Duration>,
+
+    /// The minimum interval between two warnings of the same condition.
+    ///
+    /// Default: 10 minutes
+    pub warn_interval: Duration,
+
+    /// Warns if the hit ratio of the page cache within a check interval is
+    /// lower than this.
+    ///
+    /// Default: 0.5
+    pub min_cache_hit_ratio: f64,
+
+    /// Warns if the space amplification is larger than this percentage.
+    ///
+    /// This should be larger than [`Options::max_space_amplification_percent`]
+    /// to detect that space reclaiming falls behind.
+    ///
+    /// Default: 300
+    pub max_space_amplification_percent: usize,
+
+    /// Warns if the pages cached without slots in the page cache take more than
+    /// this percentage of the cache capacity.
+    ///
+    /// Default: 10
+    pub max_detached_cache_percent: usize,
+}
+
+impl Default for HealthCheckOptions {
+    fn default() -> Self {
+        Self {
+            interval: None,
+            warn_interval: Duration::from_secs(600),
+            min_cache_hit_ratio: 0.5,
+            max_space_amplification_percent: 300,
+            max_detached_cache_percent: 10,
         }
     }
 }
@@ -208,6 +415,28 @@ impl Default for FlushOptions {
     }
 }
 
+/// The result of a checksum verification.
+#[derive(Clone, Debug, Default)]
+pub struct ChecksumReport {
+    /// The number of verified files.
+    pub num_files: usize,
+    /// The number of verified pages.
+    pub num_pages: usize,
+    /// The number of pages failed the verification.
+    pub num_failures: usize,
+    /// The first failures, ordered by file id and offset.
+    pub failures: Vec<ChecksumFailure>,
+}
+
+/// A page that fails the checksum verification.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChecksumFailure {
+    /// The id of the file.
+    pub file_id: u32,
+    /// The offset of the page in the file.
+    pub offset: u64,
+}
+
 pub(crate) struct PageStore<E: Env> {
     options: Options,
     #[allow(unused)]
@@ -230,6 +459,14 @@ impl<E: Env> PageStore<E> {
     where
         P: AsRef<Path>,
     {
+        if let Some(cpus) = &options.background_cpu_set {
+            if cpus.is_empty() || !cpus.iter().all(|&cpu| is_valid_cpu(cpu)) {
+                return Err(Error::InvalidArgument);
+            }
+        }
+        if !options.page_checksum_type.is_supported() {
+            return Err(Error::InvalidArgument);
+        }
         let (next_page_file_id, manifest, table, page_files, delta) =
             Self::recover(env.to_owned(), path, &options).await?;
 
@@ -264,6 +501,12 @@ impl<E: Env> PageStore<E> {
         store.spawn_flush_job();
         store.spawn_cleanup_job();
         store.spawn_reclaim_job();
+        store.spawn_cache_insert_job();
+        store.spawn_health_check_job();
+
+        if store.options.persist_cache_warmset {
+            store.warm_cache().await;
+        }
 
         Ok(store)
     }
@@ -292,12 +535,119 @@ impl<E: Env> PageStore<E> {
         }
     }
 
+    pub(crate) fn cache_capacity(&self) -> usize {
+        self.page_files.page_cache_capacity()
+    }
+
+    pub(crate) fn cache_usage(&self) -> usize {
+        self.page_files.page_cache_usage()
+    }
+
+    pub(crate) fn set_cache_capacity(&self, capacity: usize) {
+        self.page_files.set_page_cache_capacity(capacity)
+    }
+
+    /// Tries to reclaim memory retired by writes and flushes.
+    ///
+    /// Memory visible to pinned readers can't be reclaimed until they are
+    /// released.
+    pub(crate) fn collect(&self) {
+        let pending = self.version().buffer_set.collect();
+        if pending > 0 {
+            warn!(
+                "{pending} buffer set versions are not reclaimed, \
+                some readers might be held for too long"
+            );
+        }
+    }
+
+    /// Returns the id and the key range of each live file, ordered by file id.
+    ///
+    /// Files without leaf data pages are skipped.
+    pub(crate) fn file_key_ranges(&self) -> Vec<(u32, Vec<u8>, Vec<u8>)> {
+        let version = self.version();
+        let mut ranges = version
+            .file_infos()
+            .iter()
+            .filter_map(|(&file_id, info)| {
+                let range = info.meta().key_range.as_ref()?;
+                Some((file_id, range.min_key.clone(), range.max_key.clone()))
+            })
+            .collect::<Vec<_>>();
+        ranges.sort_unstable_by_key(|(file_id, ..)| *file_id);
+        ranges
+    }
+
+    /// Returns the ids of page files that are not referenced by the store,
+    /// ordered by file id.
+    pub(crate) async fn find_orphan_files(&self) -> Result<Vec<u32>> {
+        let mut manifest = self.manifest.lock().await;
+        self.orphan_files(&mut manifest)
+    }
+
+    /// Removes the page files that are not referenced by the store.
+    ///
+    /// Returns the ids of the removed files.
+    pub(crate) async fn remove_orphan_files(&self) -> Result<Vec<u32>> {
+        let mut manifest = self.manifest.lock().await;
+        let files = self.orphan_files(&mut manifest)?;
+        self.page_files.remove_files(files.clone()).await;
+        Ok(files)
+    }
+
+    fn orphan_files(&self, manifest: &mut Manifest<E>) -> Result<Vec<u32>> {
+        // Versions are installed with the manifest locked, so the version is
+        // consistent with the files tracked by the manifest.
+        let version = self.version();
+        let exist_files = self.page_files.list_files()?;
+        let mut files =
+            manifest.filter_orphan_files(exist_files, |id| version.file_infos().contains_key(&id));
+        files.sort_unstable();
+        Ok(files)
+    }
+
+    /// Verifies the checksums of all pages in live files.
+    pub(crate) async fn verify_checksums(&self) -> Result<ChecksumReport> {
+        /// The maximum number of failures to report.
+        const MAX_REPORTED_FAILURES: usize = 100;
+
+        let version = self.version();
+        let mut file_ids = version.file_infos().keys().cloned().collect::<Vec<_>>();
+        file_ids.sort_unstable();
+
+        let mut report = ChecksumReport::default();
+        for file_id in file_ids {
+            let file_info = version.file_infos().get(&file_id).expect("Must exists");
+            let (num_pages, offsets) = self
+                .page_files
+                .verify_file_checksums(file_info.meta())
+                .await?;
+            report.num_files += 1;
+            report.num_pages += num_pages;
+            report.num_failures += offsets.len();
+            for offset in offsets {
+                if report.failures.len() < MAX_REPORTED_FAILURES {
+                    report.failures.push(ChecksumFailure { file_id, offset });
+                }
+            }
+        }
+        Ok(report)
+    }
+
     pub(crate) async fn close(mut self) {
         self.shutdown.terminate();
         let jobs = mem::take(&mut self.jobs);
         for job in jobs {
             job.await;
         }
+        if self.options.persist_cache_warmset {
+            if let Err(err) = self.page_files.save_warmset().await {
+                warn!("Failed to save the cache warm-set: {err}");
+            }
+        }
+        // Close the files now, instead of when the last reference to the page
+        // files is dropped.
+        self.page_files.close_readers();
     }
 
     /// Flush the active write buffer if it is not empty.
@@ -306,17 +656,68 @@ impl<E: Env> PageStore<E> {
         self.version().buffer_set.flush_active_buffer(opts).await
     }
 
+    /// Waits until the writes made so far are flushed to page files.
+    ///
+    /// Concurrent waiters are grouped into the same flush, see
+    /// `BufferSet::wait_for_writes_flushed`.
+    pub(crate) async fn wait_for_writes_flushed(&self) {
+        self.version().buffer_set.wait_for_writes_flushed().await
+    }
+
     /// Wait all pending reclaiming to finish.
     #[inline]
     pub(crate) async fn wait_for_reclaiming(&self) {
         wait_for_reclaiming(&self.options, self.version()).await;
     }
 
+    /// Reads the pages recorded in the warm-set file into the page cache.
+    async fn warm_cache(&self) {
+        let version = self.version();
+        let mut num_pages = 0;
+        for addr in self.page_files.load_warmset().await {
+            // Skip pages that have been deallocated or reclaimed since.
+            let group_id = (addr >> 32) as u32;
+            let Some(page_group) = version.page_groups().get(&group_id) else {
+                continue;
+            };
+            let Some(handle) = page_group.get_page_handle(addr) else {
+                continue;
+            };
+            let file_id = page_group.meta().file_id;
+            let Some(file_info) = version.file_infos().get(&file_id) else {
+                continue;
+            };
+            let hint = CacheOption::default();
+            match self
+                .page_files
+                .read_page(file_id, file_info.meta(), addr, handle, hint)
+                .await
+            {
+                Ok(_) => num_pages += 1,
+                Err(err) => {
+                    warn!("Failed to warm the page cache: {err}");
+                    break;
+                }
+            }
+        }
+        info!("Warm the page cache with {num_pages} pages");
+    }
+
     #[inline]
     fn version(&self) -> Arc<Version> {
         self.version_owner.current()
     }
 
+    fn spawn_background<F>(&self, f: F) -> E::JoinHandle<()>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        match &self.options.background_cpu_set {
+            Some(cpus) => self.env.spawn_background_on(cpus, f),
+            None => self.env.spawn_background(f),
+        }
+    }
+
     fn spawn_flush_job(&mut self) {
         let job = FlushCtx::new(
             self.options.clone(),
@@ -326,13 +727,13 @@ impl<E: Env> PageStore<E> {
             self.page_files.clone(),
             self.manifest.clone(),
         );
-        let handle = self.env.spawn_background(job.run());
+        let handle = self.spawn_background(job.run());
         self.jobs.push(handle);
     }
 
     fn spawn_cleanup_job(&mut self) {
         let job = CleanupCtx::new(self.shutdown.subscribe(), self.page_files.clone());
-        let handle = self.env.spawn_background(job.run(self.version()));
+        let handle = self.spawn_background(job.run(self.version()));
         self.jobs.push(handle);
     }
 
@@ -346,8 +747,32 @@ impl<E: Env> PageStore<E> {
             self.version_owner.clone(),
             self.manifest.clone(),
             self.job_stats.clone(),
+            self.writebuf_stats.clone(),
+        );
+        let handle = self.spawn_background(job.run(self.version()));
+        self.jobs.push(handle);
+    }
+
+    fn spawn_cache_insert_job(&mut self) {
+        let Some(receiver) = self.page_files.take_insert_receiver() else {
+            return;
+        };
+        let job = CacheInsertCtx::new(self.shutdown.subscribe(), self.page_files.clone());
+        let handle = self.spawn_background(job.run(receiver));
+        self.jobs.push(handle);
+    }
+
+    fn spawn_health_check_job(&mut self) {
+        let Some(interval) = self.options.health_check.interval else {
+            return;
+        };
+        let job = HealthCheckCtx::new(
+            self.options.health_check.clone(),
+            self.shutdown.subscribe(),
+            self.page_files.clone(),
+            self.version_owner.clone(),
         );
-        let handle = self.env.spawn_background(job.run(self.version()));
+        let handle = self.spawn_background(job.run(interval));
         self.jobs.push(handle);
     }
 }
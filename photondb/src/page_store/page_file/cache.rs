@@ -15,7 +15,7 @@ pub(super) struct FileReaderCache<E: Env> {
 
 impl<E: Env> FileReaderCache<E> {
     pub(super) fn new(max_size: u64) -> Self {
-        let cache = Arc::new(LRUCache::new(max_size as usize, -1, 0.0, 0.0));
+        let cache = Arc::new(LRUCache::new(max_size as usize, -1, false, 0.0, 0.0));
         Self {
             cache,
             _marker: PhantomData,
@@ -25,13 +25,13 @@ impl<E: Env> FileReaderCache<E> {
     pub(super) async fn get_with(
         &self,
         file_id: u32,
-        init: impl Future<Output = Arc<FileReader<E::PositionalReader>>>,
+        init: impl Future<Output = Result<Arc<FileReader<E::PositionalReader>>>>,
     ) -> Result<Arc<FileReader<E::PositionalReader>>> {
         let key = file_id as u64;
         if let Some(cached) = self.cache.lookup(key) {
             return Ok(cached.value().clone());
         }
-        let reader = init.await;
+        let reader = init.await?;
         match self
             .cache
             .insert(key, Some(reader.clone()), 1, CacheOption::default())
@@ -46,6 +46,19 @@ impl<E: Env> FileReaderCache<E> {
         self.cache.erase(file_id as u64);
     }
 
+    /// Drops all the cached readers, files are closed once in-flight reads
+    /// finish.
+    pub(super) fn clear(&self) {
+        for key in self.cache.keys() {
+            self.cache.erase(key);
+        }
+    }
+
+    #[cfg(test)]
+    pub(super) fn len(&self) -> usize {
+        self.cache.keys().len()
+    }
+
     pub(super) fn stats(&self) -> CacheStats {
         self.cache.stats()
     }
@@ -10,17 +10,58 @@ pub struct ChecksumType: u8 {
     /// Checksum with CRC32.
     const CRC32 = 1;
     /// Checksum with XXHASH.
-    #[allow(dead_code)]
+    ///
+    /// It is not supported yet, opening a store with it fails.
     const XXHASH = 2;
 }
 }
 
+impl ChecksumType {
+    /// Returns true if pages can be checksummed with this type.
+    pub(crate) fn is_supported(self) -> bool {
+        self == Self::NONE || self == Self::CRC32
+    }
+}
+
 pub(crate) fn checksum(typ: ChecksumType, content: &[u8]) -> Option<u32> {
     match typ {
         ChecksumType::NONE => None,
         ChecksumType::CRC32 => Some(crc32fast::hash(content)),
-        ChecksumType::XXHASH => todo!(),
-        _ => unreachable!(),
+        _ => unreachable!("unsupported checksum type {typ:?}"),
+    }
+}
+
+/// Computes the checksum of content incrementally.
+pub(crate) enum Checksummer {
+    None,
+    Crc32(crc32fast::Hasher),
+}
+
+impl Checksummer {
+    pub(crate) fn new(typ: ChecksumType) -> Self {
+        match typ {
+            ChecksumType::NONE => Self::None,
+            ChecksumType::CRC32 => Self::Crc32(crc32fast::Hasher::new()),
+            _ => unreachable!("unsupported checksum type {typ:?}"),
+        }
+    }
+
+    /// Feeds the next part of the content.
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::None => {}
+            Self::Crc32(hasher) => hasher.update(data),
+        }
+    }
+
+    /// Returns the checksum of all fed content.
+    ///
+    /// It is the same as [`checksum`] over the whole content.
+    pub(crate) fn finalize(self) -> Option<u32> {
+        match self {
+            Self::None => None,
+            Self::Crc32(hasher) => Some(hasher.finalize()),
+        }
     }
 }
 
@@ -32,3 +73,22 @@ pub(crate) fn check_checksum(typ: ChecksumType, content: &[u8], checksum_val: u3
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksummer_matches_full_checksum() {
+        let content = (0..(1 << 16)).map(|v| v as u8).collect::<Vec<_>>();
+        for typ in [ChecksumType::NONE, ChecksumType::CRC32] {
+            for chunk_size in [1, 7, 4096, content.len()] {
+                let mut checksummer = Checksummer::new(typ);
+                for chunk in content.chunks(chunk_size) {
+                    checksummer.update(chunk);
+                }
+                assert_eq!(checksummer.finalize(), checksum(typ, &content));
+            }
+        }
+    }
+}
@@ -11,6 +11,8 @@ bitflags! {
         const SNAPPY = 2;
         /// Compression with ZSTD.
         const ZSTD = 4;
+        /// Compression with LZ4.
+        const LZ4 = 8;
     }
 }
 
@@ -36,6 +38,12 @@ pub(crate) fn decompress_into(
             target.truncate(len);
             Ok(())
         }
+        Compression::LZ4 => {
+            let len = lz4_flex::block::decompress_into(input, &mut target[..])
+                .map_err(|_| Error::Corrupted)?;
+            target.truncate(len);
+            Ok(())
+        }
         _ => unreachable!(),
     }
 }
@@ -50,9 +58,11 @@ pub(crate) fn decompress_len(
             let len = snap::raw::decompress_len(input).map_err(|_| Error::Corrupted)?;
             Ok((len, 0))
         }
-        Compression::ZSTD => {
+        Compression::ZSTD | Compression::LZ4 => {
             let raw_len = u64::from_le_bytes(
-                input[..std::mem::size_of::<u64>()]
+                input
+                    .get(..std::mem::size_of::<u64>())
+                    .ok_or(Error::Corrupted)?
                     .try_into()
                     .map_err(|_| Error::Corrupted)?,
             );
@@ -86,6 +96,18 @@ pub(crate) fn compress_page<'a>(
             } + std::mem::size_of::<u64>();
             Ok(&compress_buf[..dat_size])
         }
+        Compression::LZ4 => {
+            // Like ZSTD, the raw length is stored before the compressed data.
+            let raw_len = raw.len() as u64;
+            compress_buf[0..std::mem::size_of::<u64>()].copy_from_slice(&raw_len.to_le_bytes());
+            let dat_size = lz4_flex::block::compress_into(
+                raw,
+                &mut compress_buf[std::mem::size_of::<u64>()..],
+            )
+            .map_err(|_| Error::Corrupted)?
+                + std::mem::size_of::<u64>();
+            Ok(&compress_buf[..dat_size])
+        }
         _ => unreachable!(),
     }
 }
@@ -95,6 +117,9 @@ pub(crate) fn compress_max_len(compression: Compression, input: &[u8]) -> usize
         Compression::NONE => 0,
         Compression::SNAPPY => snap::raw::max_compress_len(input.len()),
         Compression::ZSTD => zstd_safe::compress_bound(input.len()) + std::mem::size_of::<u64>(),
+        Compression::LZ4 => {
+            lz4_flex::block::get_maximum_output_size(input.len()) + std::mem::size_of::<u64>()
+        }
         _ => unreachable!(),
     }
 }
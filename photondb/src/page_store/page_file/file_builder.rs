@@ -1,7 +1,7 @@
 use std::{alloc::Layout, collections::BTreeMap, marker::PhantomData, sync::Arc};
 
 use super::{
-    checksum,
+    checksum::Checksummer,
     compression::{compress_max_len, compress_page, Compression},
     ChecksumType, PageGroupMeta,
 };
@@ -42,8 +42,9 @@ impl CommonFileBuilder {
     ) -> Result<()> {
         let mut tmp_buf = vec![0u8; compress_max_len(self.compression, page_content)]; // TODO: pool this.
         let page_content = compress_page(self.compression, page_content, &mut tmp_buf)?;
-        let checksum = checksum::checksum(self.checksum, page_content);
-        let file_offset = writer.write_with_checksum(page_content, checksum).await?;
+        let file_offset = writer
+            .write_with_checksum(page_content, self.checksum)
+            .await?;
         self.index.add_data_block(page_addr, file_offset, page_info);
         self.page_table.0.insert(page_addr, page_id);
         Ok(())
@@ -345,19 +346,26 @@ impl<'a, E: Env> BufferedWriter<'a, E> {
     }
 
     pub(super) async fn write(&mut self, page: &[u8]) -> Result<u64> {
-        self.write_with_checksum(page, None).await
+        self.write_with_checksum(page, ChecksumType::NONE).await
     }
 
+    /// Writes the page followed by its checksum.
+    ///
+    /// The checksum is computed while the page is copied into the buffer, so
+    /// the page is only read once.
     pub(super) async fn write_with_checksum(
         &mut self,
         page: &[u8],
-        checksum: Option<u32>,
+        checksum_type: ChecksumType,
     ) -> Result<u64> {
-        self.fill_buf(page).await?;
+        let mut checksummer = Checksummer::new(checksum_type);
+        self.fill_buf(page, &mut checksummer).await?;
 
+        let checksum = checksummer.finalize();
         if let Some(checksum) = checksum {
             let checksum_bytes = checksum.to_le_bytes();
-            self.fill_buf(&checksum_bytes).await?;
+            self.fill_buf(&checksum_bytes, &mut Checksummer::None)
+                .await?;
         }
 
         let page_offset = self.next_page_offset;
@@ -370,14 +378,16 @@ impl<'a, E: Env> BufferedWriter<'a, E> {
         Ok(page_offset)
     }
 
-    async fn fill_buf(&mut self, data: &[u8]) -> Result<()> {
+    async fn fill_buf(&mut self, data: &[u8], checksummer: &mut Checksummer) -> Result<()> {
         let buf_cap = self.buffer.len();
         let mut consumed = 0;
         while consumed < data.len() {
             if self.buf_pos < buf_cap {
                 let free_buf = &mut self.buffer.as_bytes_mut()[self.buf_pos..buf_cap];
                 let fill_end = (consumed + free_buf.len()).min(data.len());
-                free_buf[..(fill_end - consumed)].copy_from_slice(&data[consumed..fill_end]);
+                let chunk = &data[consumed..fill_end];
+                free_buf[..chunk.len()].copy_from_slice(chunk);
+                checksummer.update(chunk);
                 self.buf_pos += fill_end - consumed;
                 consumed = fill_end;
             } else {
@@ -394,6 +404,8 @@ impl<'a, E: Env> BufferedWriter<'a, E> {
         if self.use_direct {
             self.actual_data_size += self.buf_pos;
             let align_len = ceil_to_block_hi_pos(self.buf_pos, self.align_size);
+            // Don't write the stale data of previous flushes as padding.
+            self.buffer.as_bytes_mut()[self.buf_pos..align_len].fill(0);
             self.buf_pos = align_len;
         }
         self.file
@@ -435,9 +447,12 @@ impl AlignBuffer {
         assert!(n > 0);
         let size = ceil_to_block_hi_pos(n, align);
         let layout = Layout::from_size_align(size, align).expect("Invalid layout");
+        // The buffer is zeroed, so that the uninitialized memory is never exposed
+        // through short reads or padding writes.
         let data = unsafe {
             // Safety: it is guaranteed that layout size > 0.
-            std::ptr::NonNull::new(std::alloc::alloc(layout)).expect("The memory is exhausted")
+            std::ptr::NonNull::new(std::alloc::alloc_zeroed(layout))
+                .expect("The memory is exhausted")
         };
         Self { data, layout, size }
     }
@@ -539,4 +554,41 @@ mod tests {
             assert_eq!(length, 10 + (4096 * 2 + 1) * 2)
         }
     }
+
+    #[cfg(unix)]
+    #[photonio::test]
+    async fn test_buffered_writer_checksum() {
+        use tempdir::TempDir;
+
+        use crate::{env::PositionalReaderExt, page_store::page_file::checksum::checksum};
+
+        let env = crate::env::Photon;
+
+        let base_dir = TempDir::new("buffer_writer_checksum").unwrap();
+        let path = base_dir.path().join("buf_test");
+        let base = env.open_dir(base_dir.path()).await.unwrap();
+        let page = (0..(4096 * 3 + 7)).map(|v| v as u8).collect::<Vec<_>>();
+        let offset = {
+            let file = env.open_sequential_writer(path.to_owned()).await.unwrap();
+            let mut bw = BufferedWriter::<crate::env::Photon>::new(file, 4096, true, 512, &base);
+            bw.write(&[1].repeat(10)).await.unwrap();
+            // The page spans several flushes, so the checksum is computed from
+            // multiple chunks.
+            let offset = bw
+                .write_with_checksum(&page, ChecksumType::CRC32)
+                .await
+                .unwrap();
+            bw.flush_and_sync().await.unwrap();
+            offset
+        };
+        assert_eq!(offset, 10);
+
+        let file = env.open_positional_reader(path.to_owned()).await.unwrap();
+        let mut buf = vec![0u8; page.len() + 4];
+        file.read_exact_at(&mut buf, offset).await.unwrap();
+        let (content, checksum_bytes) = buf.split_at(page.len());
+        assert_eq!(content, page);
+        let checksum_val = u32::from_le_bytes(checksum_bytes.try_into().unwrap());
+        assert_eq!(Some(checksum_val), checksum(ChecksumType::CRC32, &page));
+    }
 }
@@ -1,7 +1,9 @@
-use super::file_builder::*;
+use log::error;
+
+use super::{constant::DIRECT_READ_CHUNK_SIZE, file_builder::*};
 use crate::{
     env::{PositionalReader, PositionalReaderExt},
-    page_store::Result,
+    page_store::{Error, Result},
     util::atomic::Counter,
 };
 
@@ -34,43 +36,69 @@ impl<R: PositionalReader> FileReader<R> {
             self.reader
                 .read_exact_at(buf, req_offset)
                 .await
-                .expect("read page data fail");
+                .map_err(|err| read_error(err, req_offset))?;
             self.read_bytes.add(buf.len() as u64);
             return Ok(());
         }
 
         let align_offset = floor_to_block_lo_pos(req_offset as usize, self.align_size);
-        let offset_ahead = (req_offset as usize) - align_offset;
-        let align_buf_size =
-            ceil_to_block_hi_pos(req_offset as usize + buf.len(), self.align_size) - align_offset;
+        let align_end = ceil_to_block_hi_pos(req_offset as usize + buf.len(), self.align_size);
+        let mut offset_ahead = (req_offset as usize) - align_offset;
 
+        // Large reads are split into chunks, so that the aligned buffer doesn't
+        // double the memory usage of oversized pages.
+        let chunk_size = ceil_to_block_hi_pos(DIRECT_READ_CHUNK_SIZE, self.align_size);
+        let align_buf_size = (align_end - align_offset).min(chunk_size);
         let mut align_buf = AlignBuffer::new(align_buf_size, self.align_size); // TODO: pool this buf?
-        let read_buf = align_buf.as_bytes_mut();
 
-        self.inner_read_exact_at(&self.reader, read_buf, align_offset as u64)
-            .await
-            .expect("read page data fail");
+        let mut pos = align_offset;
+        let mut copied = 0;
+        while copied < buf.len() {
+            let read_size = (align_end - pos).min(align_buf_size);
+            let read_buf = &mut align_buf.as_bytes_mut()[..read_size];
+            let read = self
+                .inner_read_exact_at(&self.reader, read_buf, pos as u64)
+                .await
+                .map_err(|err| read_error(err, pos as u64))?;
 
-        buf.copy_from_slice(&read_buf[offset_ahead..offset_ahead + buf.len()]);
+            let n = (read_size - offset_ahead).min(buf.len() - copied);
+            if read < offset_ahead + n {
+                // The rest of the buffer holds the data of previous reads, which must not
+                // be exposed.
+                error!(
+                    "read {read} bytes at offset {pos}, expect at least {} bytes",
+                    offset_ahead + n
+                );
+                return Err(Error::Corrupted);
+            }
+            buf[copied..copied + n].copy_from_slice(&read_buf[offset_ahead..offset_ahead + n]);
+            copied += n;
+            pos += read_size;
+            offset_ahead = 0;
+        }
         self.read_bytes.add(buf.len() as u64);
 
         Ok(())
     }
 
+    /// Reads the aligned range, and returns the number of bytes read, which is
+    /// less than the length of `buf` only at the end of file.
     async fn inner_read_exact_at(
         &self,
         r: &R,
         mut buf: &mut [u8],
         mut pos: u64,
-    ) -> std::io::Result<()> {
+    ) -> std::io::Result<usize> {
         assert!(is_block_aligned_ptr(buf.as_ptr(), self.align_size));
         assert!(is_block_aligned_pos(pos as usize, self.align_size));
+        let mut read = 0;
         while !buf.is_empty() {
             match r.read_at(buf, pos).await {
                 Ok(0) => return Err(std::io::ErrorKind::UnexpectedEof.into()),
                 Ok(n) => {
                     buf = &mut buf[n..];
                     pos += n as u64;
+                    read += n;
                     if !is_block_aligned_pos(n, self.align_size) {
                         // only happen when end of file.
                         break;
@@ -80,7 +108,7 @@ impl<R: PositionalReader> FileReader<R> {
                 Err(e) => return Err(e),
             }
         }
-        Ok(())
+        Ok(read)
     }
 
     pub(crate) async fn read_block(&self, block_handle: BlockHandle) -> Result<Vec<u8>> {
@@ -94,3 +122,101 @@ impl<R: PositionalReader> FileReader<R> {
         self.read_bytes.get()
     }
 }
+
+/// Converts the error of a read, where reading past the end of the file means
+/// that the file is truncated.
+fn read_error(err: std::io::Error, offset: u64) -> Error {
+    if err.kind() == std::io::ErrorKind::UnexpectedEof {
+        error!("read at offset {offset} reaches the end of file");
+        Error::Corrupted
+    } else {
+        Error::Io(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::env::{Env, Photon, SequentialWriter, SequentialWriterExt};
+
+    #[photonio::test]
+    async fn read_larger_than_direct_read_chunk() {
+        let env = Photon;
+        let base = TempDir::new("read_larger_than_direct_read_chunk").unwrap();
+        let path = base.path().join("large");
+
+        let prefix = [0u8; 123];
+        let value = (0..(DIRECT_READ_CHUNK_SIZE * 2 + 4567))
+            .map(|v| (v % 251) as u8)
+            .collect::<Vec<_>>();
+        {
+            let mut file = env.open_sequential_writer(path.clone()).await.unwrap();
+            file.write_all(&prefix).await.unwrap();
+            file.write_all(&value).await.unwrap();
+            file.sync_all().await.unwrap();
+        }
+
+        let file_size = prefix.len() + value.len();
+        for use_direct in [true, false] {
+            let file = env.open_positional_reader(path.clone()).await.unwrap();
+            let reader = FileReader::from(file, use_direct, 4096, file_size);
+            let mut buf = vec![0u8; value.len()];
+            reader
+                .read_exact_at(&mut buf, prefix.len() as u64)
+                .await
+                .unwrap();
+            assert!(buf == value, "use_direct: {use_direct}");
+            assert_eq!(reader.total_read_bytes(), value.len() as u64);
+        }
+    }
+
+    #[photonio::test]
+    async fn short_read_not_expose_stale_data() {
+        let env = Photon;
+        let base = TempDir::new("short_read_not_expose_stale_data").unwrap();
+        let path = base.path().join("short");
+
+        let value = vec![1u8; DIRECT_READ_CHUNK_SIZE + 100];
+        {
+            let mut file = env.open_sequential_writer(path.clone()).await.unwrap();
+            file.write_all(&value).await.unwrap();
+            file.sync_all().await.unwrap();
+        }
+
+        // The second chunk is short, the buffer must not expose the data of the first
+        // chunk.
+        let file = env.open_positional_reader(path.clone()).await.unwrap();
+        let reader = FileReader::from(file, true, 4096, value.len());
+        let mut buf = vec![0u8; DIRECT_READ_CHUNK_SIZE + 1000];
+        let result = reader.read_exact_at(&mut buf, 0).await;
+        assert!(matches!(result, Err(Error::Corrupted)));
+        assert!(buf[DIRECT_READ_CHUNK_SIZE + 100..].iter().all(|&b| b == 0));
+    }
+
+    #[photonio::test]
+    async fn read_past_end_of_file() {
+        let env = Photon;
+        let base = TempDir::new("read_past_end_of_file").unwrap();
+        let path = base.path().join("truncated");
+
+        let value = vec![1u8; 100];
+        {
+            let mut file = env.open_sequential_writer(path.clone()).await.unwrap();
+            file.write_all(&value).await.unwrap();
+            file.sync_all().await.unwrap();
+        }
+
+        for use_direct in [true, false] {
+            let file = env.open_positional_reader(path.clone()).await.unwrap();
+            let reader = FileReader::from(file, use_direct, 4096, value.len());
+            let mut buf = vec![0u8; 10];
+            let result = reader.read_exact_at(&mut buf, 200).await;
+            assert!(
+                matches!(result, Err(Error::Corrupted)),
+                "use_direct: {use_direct}"
+            );
+        }
+    }
+}
@@ -10,12 +10,12 @@ use super::{
     compression::Compression,
     constant::*,
     file_builder::CommonFileBuilder,
-    types::{split_page_addr, FileMeta},
+    types::{split_page_addr, FileMeta, KeyRange},
     BlockHandle, BufferedWriter, ChecksumType, FileInfo, PageGroup,
 };
 use crate::{
     env::Env,
-    page::PageInfo,
+    page::{leaf_page_key_range, PageInfo, PageRef},
     page_store::{Error, Result},
 };
 
@@ -23,7 +23,8 @@ use crate::{
 ///
 /// File format:
 ///
-/// File = [{page group}] {page block index} {dealloc pages block} {footer}
+/// File = [{page group}] {page block index} {dealloc pages block} {key range
+/// block} {footer}
 /// page group = {data blocks} {meta blocks} {index blocks}
 /// data blocks = [{data block}] --- one block per tree page
 /// meta blocks = {page table block}
@@ -33,13 +34,20 @@ use crate::{
 /// meta block index = {file_offset}
 /// page block index = [(page_id, {data block index}, {meta block index})]
 /// dealloc pages block = [dealloc_page_addr]
-/// footer = {magic_number} { page block index}
+/// key range block = {min_key_len} {min_key} {max_key} --- empty if no leaf
+/// data pages
+/// footer = {magic_number} {page block index handle} {dealloc pages block
+/// handle} {key range block handle} {compression} {checksum type}
+///
+/// Files with [`FILE_MAGIC_V1`] have neither the key range block nor its
+/// handle in the footer.
 pub(crate) struct FileBuilder<'a, E: Env> {
     file_id: u32,
     writer: BufferedWriter<'a, E>,
     dealloc_pages: BTreeSet<u64>,
     page_index: PageIndexBuilder,
     page_groups: FxHashMap<u32, PageGroup>,
+    key_range: Option<KeyRange>,
     block_size: usize,
     file_offset: usize,
     compression: Compression,
@@ -73,6 +81,9 @@ pub(crate) struct Footer {
     pub(super) magic: u64,
     pub(super) page_index_handle: BlockHandle,
     pub(super) dealloc_pages_handle: BlockHandle,
+    /// The handle of the key range block, which is absent from files with
+    /// [`FILE_MAGIC_V1`].
+    pub(super) key_range_handle: Option<BlockHandle>,
     pub(super) compression: Compression,
     pub(super) checksum_type: ChecksumType,
 }
@@ -94,6 +105,7 @@ impl<'a, E: Env> FileBuilder<'a, E> {
             dealloc_pages: BTreeSet::default(),
             page_index: PageIndexBuilder::default(),
             page_groups: HashMap::default(),
+            key_range: None,
             file_offset: 0,
             block_size,
             compression,
@@ -136,6 +148,7 @@ impl<'a, E: Env> FileBuilder<'a, E> {
             self.compression,
             self.get_referenced_groups(),
             page_groups,
+            self.key_range.take(),
         ));
         let file_info = FileInfo::new(up2, up2, file_meta);
         Ok((self.page_groups, file_info))
@@ -144,10 +157,12 @@ impl<'a, E: Env> FileBuilder<'a, E> {
     async fn finish_tail_blocks(&mut self) -> Result<usize> {
         let page_index_handle = self.finish_page_index_block().await?;
         let dealloc_pages_handle = self.finish_dealloc_pages_block().await?;
+        let key_range_handle = self.finish_key_range_block().await?;
         let footer = Footer {
             magic: FILE_MAGIC,
             page_index_handle,
             dealloc_pages_handle,
+            key_range_handle: Some(key_range_handle),
             compression: self.compression,
             checksum_type: self.checksum,
         };
@@ -174,6 +189,24 @@ impl<'a, E: Env> FileBuilder<'a, E> {
         Ok(BlockHandle { offset, length })
     }
 
+    async fn finish_key_range_block(&mut self) -> Result<BlockHandle> {
+        let buf = self
+            .key_range
+            .as_ref()
+            .map(KeyRange::encode)
+            .unwrap_or_default();
+        let offset = self.writer.write(&buf).await?;
+        let length = buf.len() as u64;
+        Ok(BlockHandle { offset, length })
+    }
+
+    fn extend_key_range(&mut self, min_key: &[u8], max_key: &[u8]) {
+        match &mut self.key_range {
+            Some(range) => range.extend(min_key, max_key),
+            None => self.key_range = Some(KeyRange::new(min_key, max_key)),
+        }
+    }
+
     fn get_referenced_groups(&self) -> FxHashSet<u32> {
         let mut groups = FxHashSet::default();
         for page_addr in &self.dealloc_pages {
@@ -204,6 +237,21 @@ impl<'a, E: Env> PageGroupBuilder<'a, E> {
             .await
     }
 
+    /// Add a new tree page to builder, and extend the key range of the file
+    /// with the keys of the page.
+    pub(crate) async fn add_tree_page(
+        &mut self,
+        page_id: u64,
+        page_addr: u64,
+        page: PageRef<'_>,
+    ) -> Result<()> {
+        if let Some((min_key, max_key)) = leaf_page_key_range(page) {
+            self.builder.extend_key_range(min_key, max_key);
+        }
+        self.add_page(page_id, page_addr, page.info(), page.data())
+            .await
+    }
+
     /// Add some dealloc pages to builder.
     pub(crate) fn add_dealloc_pages(&mut self, dealloc_pages: &[u64]) {
         self.builder.dealloc_pages.extend(dealloc_pages);
@@ -299,47 +347,114 @@ impl PageIndex {
     }
 }
 
+impl KeyRange {
+    fn encode(&self) -> Vec<u8> {
+        let len = core::mem::size_of::<u32>() + self.min_key.len() + self.max_key.len();
+        let mut bytes = Vec::with_capacity(len);
+        bytes.extend_from_slice(&(self.min_key.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.min_key);
+        bytes.extend_from_slice(&self.max_key);
+        bytes
+    }
+
+    pub(super) fn decode(bytes: &[u8]) -> Result<Self> {
+        let end = core::mem::size_of::<u32>();
+        if bytes.len() < end {
+            return Err(Error::Corrupted);
+        }
+        let min_key_len =
+            u32::from_le_bytes(bytes[..end].try_into().map_err(|_| Error::Corrupted)?) as usize;
+        if bytes.len() < end + min_key_len {
+            return Err(Error::Corrupted);
+        }
+        let (min_key, max_key) = bytes[end..].split_at(min_key_len);
+        Ok(KeyRange::new(min_key, max_key))
+    }
+}
+
 impl Footer {
+    /// Returns the size of footers with [`FILE_MAGIC`].
     #[inline]
     pub(super) const fn encoded_size() -> usize {
+        core::mem::size_of::<u64>() + BlockHandle::encoded_size() * 3 + 2
+    }
+
+    /// Returns the size of footers with [`FILE_MAGIC_V1`].
+    #[inline]
+    pub(super) const fn encoded_size_v1() -> usize {
         core::mem::size_of::<u64>() + BlockHandle::encoded_size() * 2 + 2
     }
 
+    /// Returns the encoded size of the footer.
+    #[inline]
+    pub(super) fn size(&self) -> usize {
+        if self.key_range_handle.is_some() {
+            Self::encoded_size()
+        } else {
+            Self::encoded_size_v1()
+        }
+    }
+
     #[inline]
     fn encode(&self) -> Vec<u8> {
-        let mut bytes = Vec::with_capacity(Self::encoded_size());
+        let mut bytes = Vec::with_capacity(self.size());
         bytes.extend_from_slice(&self.magic.to_le_bytes());
         self.page_index_handle.encode(&mut bytes);
         self.dealloc_pages_handle.encode(&mut bytes);
+        if let Some(key_range_handle) = &self.key_range_handle {
+            key_range_handle.encode(&mut bytes);
+        }
         bytes.push(self.compression.bits());
         bytes.push(self.checksum_type.bits());
         bytes
     }
 
+    /// Decodes the footer at the end of the bytes, which are read from the tail
+    /// of a file.
+    ///
+    /// The version of the footer is told by the magic number at its start.
     pub(super) fn decode(bytes: &[u8]) -> Result<Self> {
-        if bytes.len() != Self::encoded_size() {
-            return Err(Error::Corrupted);
-        }
+        const MAGIC_LEN: usize = core::mem::size_of::<u64>();
 
-        let idx = 0;
-        let end = core::mem::size_of::<u64>();
-        let magic = u64::from_le_bytes(bytes[idx..end].try_into().map_err(|_| Error::Corrupted)?);
-
-        let idx = end;
-        let end = idx + BlockHandle::encoded_size();
-        let page_index_handle = BlockHandle::decode(&bytes[idx..end])?;
-
-        let idx = end;
-        let end = idx + BlockHandle::encoded_size();
-        let dealloc_pages_handle = BlockHandle::decode(&bytes[idx..end])?;
+        let magic_at = |size: usize| {
+            let idx = bytes.len().checked_sub(size)?;
+            let magic = bytes[idx..idx + MAGIC_LEN].try_into().ok()?;
+            Some(u64::from_le_bytes(magic))
+        };
+        let (magic, size) = if magic_at(Self::encoded_size()) == Some(FILE_MAGIC) {
+            (FILE_MAGIC, Self::encoded_size())
+        } else if magic_at(Self::encoded_size_v1()) == Some(FILE_MAGIC_V1) {
+            (FILE_MAGIC_V1, Self::encoded_size_v1())
+        } else {
+            return Err(Error::Corrupted);
+        };
+        let bytes = &bytes[bytes.len() - size..];
+
+        let mut idx = MAGIC_LEN;
+        let mut next_handle = || {
+            let end = idx + BlockHandle::encoded_size();
+            let handle = BlockHandle::decode(&bytes[idx..end]);
+            idx = end;
+            handle
+        };
+        let page_index_handle = next_handle()?;
+        let dealloc_pages_handle = next_handle()?;
+        let key_range_handle = if magic == FILE_MAGIC {
+            Some(next_handle()?)
+        } else {
+            None
+        };
 
-        let compression = Compression::from_bits(bytes[end]).ok_or(Error::Corrupted)?;
-        let checksum_type = ChecksumType::from_bits(bytes[end + 1]).ok_or(Error::Corrupted)?;
+        let compression = Compression::from_bits(bytes[idx]).ok_or(Error::Corrupted)?;
+        let checksum_type = ChecksumType::from_bits(bytes[idx + 1])
+            .filter(|typ| typ.is_supported())
+            .ok_or(Error::Corrupted)?;
 
         Ok(Self {
             magic,
             page_index_handle,
             dealloc_pages_handle,
+            key_range_handle,
             compression,
             checksum_type,
         })
@@ -353,8 +468,8 @@ mod tests {
 
     #[test]
     fn footer_encode_and_decode() {
-        let footer = Footer {
-            magic: 123,
+        let mut footer = Footer {
+            magic: FILE_MAGIC,
             page_index_handle: BlockHandle {
                 offset: 1234,
                 length: 64234,
@@ -363,13 +478,45 @@ mod tests {
                 offset: 1231231,
                 length: 123,
             },
+            key_range_handle: Some(BlockHandle {
+                offset: 1231354,
+                length: 12,
+            }),
             compression: Compression::NONE,
             checksum_type: ChecksumType::NONE,
         };
 
         let payload = footer.encode();
+        assert_eq!(payload.len(), Footer::encoded_size());
         let new = Footer::decode(&payload).unwrap();
         assert_eq!(new, footer);
+
+        // The footer is decoded from the tail of a file.
+        let tail = [vec![0xFF; 16], payload].concat();
+        assert_eq!(Footer::decode(&tail).unwrap(), footer);
+
+        // Footers of files written before key ranges were recorded.
+        footer.magic = FILE_MAGIC_V1;
+        footer.key_range_handle = None;
+        let payload = footer.encode();
+        assert_eq!(payload.len(), Footer::encoded_size_v1());
+        assert_eq!(Footer::decode(&payload).unwrap(), footer);
+        let tail = [vec![0xFF; 16], payload].concat();
+        assert_eq!(Footer::decode(&tail).unwrap(), footer);
+
+        // The magic number doesn't match the version of the footer.
+        footer.magic = FILE_MAGIC;
+        assert!(Footer::decode(&footer.encode()).is_err());
+        assert!(Footer::decode(&[0; 8]).is_err());
+    }
+
+    #[test]
+    fn key_range_encode_and_decode() {
+        let key_range = KeyRange::new(b"abc", b"xyz0");
+        let payload = key_range.encode();
+        assert_eq!(KeyRange::decode(&payload).unwrap(), key_range);
+        assert!(KeyRange::decode(&payload[..2]).is_err());
+        assert!(KeyRange::decode(&payload[..6]).is_err());
     }
 
     #[test]
@@ -447,6 +594,66 @@ mod tests {
         builder.finish(1).await.unwrap();
     }
 
+    #[photonio::test]
+    async fn file_layout_is_little_endian() {
+        use tempdir::TempDir;
+
+        use crate::env::Photon;
+
+        let env = Photon;
+        let base_dir = TempDir::new("file_layout_is_little_endian").unwrap();
+        let path = base_dir.path().join("file");
+        let base = env.open_dir(base_dir.path()).await.unwrap();
+        let file = env.open_sequential_writer(&path).await.unwrap();
+        let builder = FileBuilder::<Photon>::new(
+            1,
+            &base,
+            file,
+            false,
+            4096,
+            Compression::NONE,
+            ChecksumType::NONE,
+        );
+
+        let (page_id, page_addr, dealloc_addr) = (7u64, (2u64 << 32) | 64, (1u64 << 32) | 8);
+        let page_info = PageInfo::from_raw(0x0102_0304, 0x0506_0708, 16);
+        let content = [0xAAu8; 16];
+        let mut group_builder = builder.add_page_group(2);
+        group_builder
+            .add_page(page_id, page_addr, page_info, &content)
+            .await
+            .unwrap();
+        group_builder.add_dealloc_pages(&[dealloc_addr]);
+        let mut builder = group_builder.finish().await.unwrap();
+        builder.finish(1).await.unwrap();
+
+        // Decodes the integers from the raw bytes as little-endian, so the file
+        // can be read on hosts of any endianness.
+        let bytes = std::fs::read(&path).unwrap();
+        let u32_at = |pos: usize| u32::from_le_bytes(bytes[pos..pos + 4].try_into().unwrap());
+        let u64_at = |pos: usize| u64::from_le_bytes(bytes[pos..pos + 8].try_into().unwrap());
+        assert_eq!(&bytes[..content.len()], content.as_slice());
+
+        let footer = bytes.len() - Footer::encoded_size();
+        assert_eq!(u64_at(footer), FILE_MAGIC);
+        let (page_index, dealloc_pages) = (u64_at(footer + 8), u64_at(footer + 24));
+        assert_eq!(u64_at(footer + 32), 8);
+        assert_eq!(u64_at(dealloc_pages as usize), dealloc_addr);
+
+        // The page index of the group, and its data index.
+        let page_index = page_index as usize;
+        assert_eq!(u32_at(page_index), 2);
+        let data_index = u64_at(page_index + 4) as usize;
+        assert_eq!(u64_at(page_index + 12), 32);
+        assert_eq!(u64_at(data_index), page_addr);
+        assert_eq!(u64_at(data_index + 8), 0);
+        assert_eq!(u64_at(data_index + 16), 0x0102_0304);
+        assert_eq!(u64_at(data_index + 24), 0x0506_0708);
+
+        let decoded = Footer::decode(&bytes[footer..]).unwrap();
+        assert_eq!(decoded.page_index_handle.offset, page_index as u64);
+    }
+
     fn empty_page_info() -> PageInfo {
         PageInfo::from_raw(0, 0, 0)
     }
@@ -7,7 +7,7 @@ mod file_reader;
 pub(crate) use file_reader::FileReader;
 
 mod types;
-pub(crate) use facade::PageFiles;
+pub(crate) use facade::{PageEntry, PageFiles, PendingInsertReceiver};
 pub(crate) use types::{FileInfo, PageGroup, PageGroupMeta};
 
 mod map_file_builder;
@@ -30,31 +30,77 @@ pub(crate) mod constant {
 
     pub(crate) const IO_BUFFER_SIZE: usize = 8 << 20;
 
-    pub(crate) const FILE_MAGIC: u64 = 0x179394;
+    /// The maximum size of the aligned buffer used by a direct IO read. Larger
+    /// reads are split into chunks of this size.
+    pub(crate) const DIRECT_READ_CHUNK_SIZE: usize = 1 << 20;
+
+    /// The magic number of page files whose footer references a key range
+    /// block.
+    pub(crate) const FILE_MAGIC: u64 = 0x179395;
+
+    /// The magic number of page files written before key ranges were
+    /// recorded, whose footer has no key range handle.
+    pub(crate) const FILE_MAGIC_V1: u64 = 0x179394;
 }
 
 pub(crate) mod facade {
-    use std::{path::PathBuf, sync::Arc};
+    use std::{
+        io::ErrorKind,
+        path::PathBuf,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+    };
+
+    use futures::channel::mpsc;
+    use log::warn;
+    use parking_lot::Mutex;
 
     use super::{
         cache::FileReaderCache,
-        constant::DEFAULT_BLOCK_SIZE,
+        constant::{DEFAULT_BLOCK_SIZE, IO_BUFFER_SIZE},
         file_reader::FileReader,
         types::{FileMeta, PageHandle},
         *,
     };
     use crate::{
-        env::{Env, PositionalReader, SequentialWriter},
+        env::{Env, PositionalReader, PositionalReaderExt, SequentialWriter, SequentialWriterExt},
         page::PageRef,
         page_store::{
             page_txn::{CacheOption, CachePriority},
             stats::CacheStats,
-            Cache, CacheEntry, Error, LRUCache, Result,
+            Cache, CacheEntry, CachePolicy, ClockCache, Error, LRUCache, PageCache, Result,
         },
         PageStoreOptions,
     };
 
     pub(crate) const FILE_PREFIX: &str = "map";
+    const WARMSET_FILE_NAME: &str = "WARMSET";
+
+    pub(crate) type PageCacheEntry = CacheEntry<Arc<Vec<u8>>, PageCache<Arc<Vec<u8>>>>;
+
+    fn shard_dir_name(shard: u32) -> String {
+        format!("{shard:02}")
+    }
+
+    /// A page read from page files.
+    pub(crate) enum PageEntry {
+        /// The page is pinned in the page cache.
+        Cached(PageCacheEntry),
+        /// The page is owned by the reader, it will be inserted into the page
+        /// cache asynchronously.
+        Owned(Arc<Vec<u8>>),
+    }
+
+    /// A page waiting to be inserted into the page cache.
+    pub(crate) struct PendingInsert {
+        addr: u64,
+        page: Arc<Vec<u8>>,
+        hint: CacheOption,
+    }
+
+    pub(crate) type PendingInsertReceiver = mpsc::Receiver<PendingInsert>;
 
     /// The facade for page_file module.
     /// it hides the detail about disk location for caller(after it be created).
@@ -62,12 +108,20 @@ pub(crate) mod facade {
         env: E,
         base: PathBuf,
         base_dir: E::Directory,
+        // The subdirectories that page files are sharded into, if any.
+        shard_dirs: Vec<E::Directory>,
 
         use_direct: bool,
+        // Whether a fallback to buffered IO has been logged.
+        direct_io_warned: AtomicBool,
         prepopulate_cache_on_flush: bool,
+        verify_checksums: bool,
 
         reader_cache: cache::FileReaderCache<E>,
-        page_cache: Arc<LRUCache<Vec<u8>>>,
+        page_cache: Arc<PageCache<Arc<Vec<u8>>>>,
+
+        insert_sender: Option<Mutex<mpsc::Sender<PendingInsert>>>,
+        insert_receiver: Mutex<Option<PendingInsertReceiver>>,
     }
 
     impl<E: Env> PageFiles<E> {
@@ -80,18 +134,58 @@ pub(crate) mod facade {
         ) -> Self {
             let base = base.into();
             let base_dir = env.open_dir(&base).await.expect("open base dir fail");
+            let mut shard_dirs = Vec::with_capacity(options.page_file_dir_shards as usize);
+            for shard in 0..options.page_file_dir_shards {
+                let path = base.join(shard_dir_name(shard));
+                env.create_dir_all(&path)
+                    .await
+                    .expect("create shard dir fail");
+                shard_dirs.push(env.open_dir(&path).await.expect("open shard dir fail"));
+            }
+            if !shard_dirs.is_empty() {
+                base_dir.sync_all().await.expect("sync base dir fail");
+            }
             let reader_cache = FileReaderCache::new(options.cache_file_reader_capacity);
-            let page_cache = Arc::new(LRUCache::new(options.cache_capacity, -1, 0.5, 0.0));
+            let page_cache = Arc::new(match options.cache_policy {
+                CachePolicy::Clock => PageCache::Clock(Arc::new(
+                    ClockCache::builder()
+                        .capacity_bytes(options.cache_capacity)
+                        .estimated_entry_charge(options.cache_estimated_entry_charge)
+                        .strict_capacity_limit(options.cache_strict_capacity_limit)
+                        .build()
+                        .expect("invalid page cache options"),
+                )),
+                CachePolicy::Lru => PageCache::Lru(Arc::new(LRUCache::new(
+                    options.cache_capacity,
+                    -1,
+                    options.cache_strict_capacity_limit,
+                    0.5,
+                    0.0,
+                ))),
+            });
             let use_direct = options.use_direct_io;
             let prepopulate_cache_on_flush = options.prepopulate_cache_on_flush;
+            let verify_checksums = !options.disable_checksum_verification;
+            let (insert_sender, insert_receiver) = match options.cache_async_insert_queue_size {
+                0 => (None, None),
+                size => {
+                    let (sender, receiver) = mpsc::channel(size);
+                    (Some(Mutex::new(sender)), Some(receiver))
+                }
+            };
             Self {
                 env,
                 base,
                 base_dir,
+                shard_dirs,
                 use_direct,
+                direct_io_warned: AtomicBool::new(false),
                 prepopulate_cache_on_flush,
+                verify_checksums,
                 reader_cache,
                 page_cache,
+                insert_sender,
+                insert_receiver: Mutex::new(insert_receiver),
             }
         }
 
@@ -102,17 +196,12 @@ pub(crate) mod facade {
             compression: Compression,
             checksum: ChecksumType,
         ) -> Result<FileBuilder<E>> {
-            // TODO: switch to env in suitable time.
-            let path = self.base.join(format!("{}_{file_id}", FILE_PREFIX));
-            let writer = self
-                .env
-                .open_sequential_writer(path.to_owned())
-                .await
-                .expect("open writer for file_id: {file_id} fail");
-            let use_direct = self.use_direct && writer.direct_io_ify().is_ok();
+            let path = self.file_path(FILE_PREFIX, file_id);
+            let writer = self.env.open_sequential_writer(path.to_owned()).await?;
+            let use_direct = self.use_direct && self.check_direct_io(writer.direct_io_ify())?;
             Ok(FileBuilder::new(
                 file_id,
-                &self.base_dir,
+                self.file_dir(file_id),
                 writer,
                 use_direct,
                 DEFAULT_BLOCK_SIZE,
@@ -128,12 +217,12 @@ pub(crate) mod facade {
             addr: u64,
             handle: PageHandle,
             mut hint: CacheOption,
-        ) -> Result<(CacheEntry<Vec<u8>, LRUCache<Vec<u8>>>, /* hit */ bool)> {
+        ) -> Result<(PageEntry, /* hit */ bool)> {
             if let Some(cache_entry) = self.page_cache.lookup(addr) {
-                return Ok((cache_entry, true));
+                return Ok((PageEntry::Cached(cache_entry), true));
             }
 
-            let buf = self.read_file_page(file_id, file_meta, handle).await?;
+            let buf = Arc::new(self.read_file_page(file_id, file_meta, handle).await?);
 
             let is_inner = {
                 let page =
@@ -146,9 +235,39 @@ pub(crate) mod facade {
                 CachePriority::Low
             });
 
+            if let Some(sender) = &self.insert_sender {
+                // Defer the insertion so that the read doesn't pay for evictions. If
+                // the queue is full, the page is not cached. The buffer is shared
+                // with the pending insertion instead of being copied.
+                let pending = PendingInsert {
+                    addr,
+                    page: buf.clone(),
+                    hint,
+                };
+                let _ = sender.lock().try_send(pending);
+                return Ok((PageEntry::Owned(buf), false));
+            }
+
             let charge = buf.len();
             let cache_entry = self.page_cache.insert(addr, Some(buf), charge, hint)?;
-            Ok((cache_entry.unwrap(), false))
+            Ok((PageEntry::Cached(cache_entry.unwrap()), false))
+        }
+
+        /// Takes the receiver of pages waiting to be inserted into the page
+        /// cache.
+        ///
+        /// Returns `None` if the asynchronous insertion is disabled or the
+        /// receiver has been taken.
+        pub(crate) fn take_insert_receiver(&self) -> Option<PendingInsertReceiver> {
+            self.insert_receiver.lock().take()
+        }
+
+        /// Inserts a pending page into the page cache.
+        pub(crate) fn insert_pending_page(&self, pending: PendingInsert) {
+            let PendingInsert { addr, page, hint } = pending;
+            let charge = page.len();
+            // The cache may be full, it is fine to skip the page.
+            let _ = self.page_cache.insert(addr, Some(page), charge, hint);
         }
 
         pub(crate) async fn read_file_page(
@@ -185,7 +304,9 @@ pub(crate) mod facade {
                         .map_err(|_| Error::Corrupted)?,
                 );
                 output.truncate(output.len() - CHECKSUM_LEN);
-                checksum::check_checksum(file_meta.checksum_type, output, checksum)?;
+                if self.verify_checksums {
+                    checksum::check_checksum(file_meta.checksum_type, output, checksum)?;
+                }
             }
 
             let compression = file_meta.compression;
@@ -213,21 +334,102 @@ pub(crate) mod facade {
             self.reader_cache
                 .get_with(file_id, async move {
                     let (prefix, id) = (FILE_PREFIX, file_id);
-                    let (file, file_size) = self
-                        .open_positional_reader(prefix, id)
-                        .await
-                        .expect("open reader for file_id: {file_id} fail");
-                    let use_direct = self.use_direct && file.direct_io_ify().is_ok();
-                    Arc::new(FileReader::from(
+                    let (file, file_size) = self.open_positional_reader(prefix, id).await?;
+                    let use_direct =
+                        self.use_direct && self.check_direct_io(file.direct_io_ify())?;
+                    Ok(Arc::new(FileReader::from(
                         file,
                         use_direct,
                         block_size,
                         file_size as usize,
-                    ))
+                    )))
                 })
                 .await
         }
 
+        /// Returns true if direct IO is enabled for a file.
+        ///
+        /// Files that don't support direct IO, like those on tmpfs, reject it
+        /// with `EINVAL`, or it is unsupported on the platform. They fall back
+        /// to buffered IO, which is logged once. Other errors are returned.
+        fn check_direct_io(&self, result: std::io::Result<()>) -> Result<bool> {
+            match result {
+                Ok(()) => Ok(true),
+                Err(err)
+                    if matches!(err.kind(), ErrorKind::InvalidInput | ErrorKind::Unsupported) =>
+                {
+                    if !self.direct_io_warned.swap(true, Ordering::Relaxed) {
+                        warn!(
+                            "Failed to enable direct IO in {}, fall back to buffered IO: {err}",
+                            self.base.display()
+                        );
+                    }
+                    Ok(false)
+                }
+                Err(err) => Err(err.into()),
+            }
+        }
+
+        /// Verifies the checksums of all pages in the file.
+        ///
+        /// Pages are read in large sequential chunks. Returns the number of
+        /// verified pages and the offsets of corrupted pages.
+        pub(crate) async fn verify_file_checksums(
+            &self,
+            file_meta: &FileMeta,
+        ) -> Result<(usize, Vec<u64>)> {
+            const CHECKSUM_LEN: usize = std::mem::size_of::<u32>();
+
+            if file_meta.checksum_type == ChecksumType::NONE {
+                return Ok((0, Vec::new()));
+            }
+
+            let mut handles = file_meta
+                .page_groups
+                .values()
+                .flat_map(|group| group.page_handles())
+                .collect::<Vec<_>>();
+            handles.sort_unstable_by_key(|handle| handle.offset);
+
+            let reader = self
+                .open_page_reader(file_meta.file_id, file_meta.block_size)
+                .await?;
+            let mut buf = Vec::new();
+            let mut corrupted = Vec::new();
+            let mut i = 0;
+            while i < handles.len() {
+                // Reads as many pages as possible in one chunk.
+                let start = handles[i].offset as u64;
+                let mut end = start + handles[i].size as u64;
+                let mut j = i + 1;
+                while j < handles.len() {
+                    let page_end = handles[j].offset as u64 + handles[j].size as u64;
+                    if (page_end - start) as usize > IO_BUFFER_SIZE {
+                        break;
+                    }
+                    end = end.max(page_end);
+                    j += 1;
+                }
+                buf.resize((end - start) as usize, 0u8);
+                reader.read_exact_at(&mut buf, start).await?;
+
+                for handle in &handles[i..j] {
+                    let offset = (handle.offset as u64 - start) as usize;
+                    let page = &buf[offset..offset + handle.size as usize];
+                    let valid = page.len() >= CHECKSUM_LEN && {
+                        let (content, checksum) = page.split_at(page.len() - CHECKSUM_LEN);
+                        let checksum = u32::from_le_bytes(checksum.try_into().unwrap());
+                        checksum::check_checksum(file_meta.checksum_type, content, checksum).is_ok()
+                    };
+                    if !valid {
+                        corrupted.push(handle.offset as u64);
+                    }
+                }
+                i = j;
+            }
+            Ok((handles.len(), corrupted))
+        }
+
         pub(crate) async fn read_file_meta(&self, file_id: u32) -> Result<FileMetaHolder> {
             let (file, file_size) = self.open_positional_reader(FILE_PREFIX, file_id).await?;
             let page_file_reader = Arc::new(FileReader::from(
@@ -244,21 +446,19 @@ pub(crate) mod facade {
             prefix: &str,
             file_id: u32,
         ) -> Result<(E::PositionalReader, u64)> {
-            let path = self.base.join(format!("{}_{file_id}", prefix));
-            let file_size = self
-                .env
-                .metadata(&path)
-                .await
-                .expect("read fs metadata fail")
-                .len;
-            let file = self
-                .env
-                .open_positional_reader(path)
-                .await
-                .expect("open reader for file_id: {file_id} fail");
+            let path = self.file_path(prefix, file_id);
+            let file_size = self.env.metadata(&path).await?.len;
+            let file = self.env.open_positional_reader(path).await?;
             Ok((file, file_size))
         }
 
+        /// Closes the readers of all page files.
+        ///
+        /// Files are reopened on demand if they are read again.
+        pub(crate) fn close_readers(&self) {
+            self.reader_cache.clear();
+        }
+
         pub(crate) async fn remove_files(&self, files: Vec<u32>) {
             for file_id in files {
                 self.remove_file(file_id).await;
@@ -267,7 +467,7 @@ pub(crate) mod facade {
         }
 
         async fn remove_file(&self, file_id: u32) {
-            let path = self.base.join(format!("{}_{file_id}", FILE_PREFIX));
+            let path = self.file_path(FILE_PREFIX, file_id);
             let _ = self.env.remove_file(&path).await;
         }
 
@@ -275,7 +475,7 @@ pub(crate) mod facade {
             if !self.prepopulate_cache_on_flush {
                 return Ok(());
             }
-            let val = page_content.to_owned(); // TODO: aligned buffer pool
+            let val = Arc::new(page_content.to_owned()); // TODO: aligned buffer pool
             let guard = match self.page_cache.insert(
                 page_addr,
                 Some(val),
@@ -290,6 +490,80 @@ pub(crate) mod facade {
             Ok(())
         }
 
+        /// Saves the addresses of cached pages to the warm-set file.
+        ///
+        /// File format: [page_addr] {crc32 of page addrs}
+        pub(crate) async fn save_warmset(&self) -> Result<()> {
+            let addrs = self.page_cache.keys();
+            let mut buf = Vec::with_capacity((addrs.len() + 1) * std::mem::size_of::<u64>());
+            for addr in addrs {
+                buf.extend_from_slice(&addr.to_le_bytes());
+            }
+            let checksum = crc32fast::hash(&buf);
+            buf.extend_from_slice(&checksum.to_le_bytes());
+
+            let tmp_path = self.base.join(format!("{WARMSET_FILE_NAME}.tmp"));
+            {
+                let mut file = self.env.open_sequential_writer(&tmp_path).await?;
+                file.write_all(&buf).await?;
+                file.sync_all().await?;
+            }
+            self.env
+                .rename(&tmp_path, self.base.join(WARMSET_FILE_NAME))
+                .await?;
+            Ok(())
+        }
+
+        /// Loads the page addresses saved by [`Self::save_warmset`].
+        ///
+        /// Returns an empty list if the warm-set file doesn't exist or is
+        /// corrupted.
+        pub(crate) async fn load_warmset(&self) -> Vec<u64> {
+            const CHECKSUM_LEN: usize = std::mem::size_of::<u32>();
+            const ADDR_LEN: usize = std::mem::size_of::<u64>();
+
+            let path = self.base.join(WARMSET_FILE_NAME);
+            let Ok(metadata) = self.env.metadata(&path).await else {
+                return Vec::new();
+            };
+            let Ok(file) = self.env.open_positional_reader(&path).await else {
+                return Vec::new();
+            };
+            let mut buf = vec![0u8; metadata.len as usize];
+            if file.read_exact_at(&mut buf, 0).await.is_err()
+                || buf.len() < CHECKSUM_LEN
+                || (buf.len() - CHECKSUM_LEN) % ADDR_LEN != 0
+            {
+                return Vec::new();
+            }
+            let (content, checksum) = buf.split_at(buf.len() - CHECKSUM_LEN);
+            if crc32fast::hash(content).to_le_bytes() != checksum {
+                return Vec::new();
+            }
+            content
+                .chunks_exact(ADDR_LEN)
+                .map(|addr| u64::from_le_bytes(addr.try_into().unwrap()))
+                .collect()
+        }
+
+        /// Returns the path of the file, which is in the shard directory of
+        /// the file if page files are sharded.
+        fn file_path(&self, prefix: &str, file_id: u32) -> PathBuf {
+            let name = format!("{prefix}_{file_id}");
+            match self.shard_dirs.len() as u32 {
+                0 => self.base.join(name),
+                shards => self.base.join(shard_dir_name(file_id % shards)).join(name),
+            }
+        }
+
+        /// Returns the directory that contains the file.
+        fn file_dir(&self, file_id: u32) -> &E::Directory {
+            match self.shard_dirs.len() {
+                0 => &self.base_dir,
+                shards => &self.shard_dirs[file_id as usize % shards],
+            }
+        }
+
         pub(crate) fn evict_cached_pages(&self, files: &[u32]) {
             for file_id in files {
                 self.page_cache.erase_file_pages(*file_id);
@@ -304,10 +578,15 @@ pub(crate) mod facade {
         fn list_files_with_prefix(&self, prefix: &[u8]) -> Result<Vec<u32>> {
             use std::os::unix::ffi::OsStrExt;
 
-            let dir = self.env.read_dir(&self.base)?;
+            let mut paths = self.env.read_dir(&self.base)?;
+            for shard in 0..self.shard_dirs.len() as u32 {
+                paths.extend(self.env.read_dir(self.base.join(shard_dir_name(shard)))?);
+            }
             let mut files = Vec::default();
-            for entry in dir {
-                let file_name = entry?.file_name();
+            for path in paths {
+                let Some(file_name) = path.file_name() else {
+                    continue;
+                };
                 let bytes = file_name.as_bytes();
                 if !bytes.starts_with(prefix) {
                     continue;
@@ -321,6 +600,21 @@ pub(crate) mod facade {
             Ok(files)
         }
 
+        /// Returns the capacity of the page cache.
+        pub(crate) fn page_cache_capacity(&self) -> usize {
+            self.page_cache.capacity()
+        }
+
+        /// Returns the memory used by the page cache.
+        pub(crate) fn page_cache_usage(&self) -> usize {
+            self.page_cache.usage()
+        }
+
+        /// Sets the capacity of the page cache.
+        pub(crate) fn set_page_cache_capacity(&self, capacity: usize) {
+            self.page_cache.set_capacity(capacity)
+        }
+
         pub(crate) fn stats(&self) -> (CacheStats, CacheStats) {
             let page_cache = self.page_cache.stats();
             let table_cache = self.reader_cache.stats();
@@ -333,7 +627,10 @@ pub(crate) mod facade {
         use tempdir::TempDir;
 
         use super::*;
-        use crate::page::PageInfo;
+        use crate::{
+            env::{Fault, FaultEnv, FaultOp, FaultRule},
+            page::PageInfo,
+        };
 
         #[photonio::test]
         fn test_file_builder() {
@@ -412,6 +709,278 @@ pub(crate) mod facade {
             }
         }
 
+        #[photonio::test]
+        fn test_direct_io_fallback() {
+            let env = FaultEnv::new(crate::env::Photon);
+            let base = TempDir::new("test_direct_io_fallback").unwrap();
+            let mut opt = test_option();
+            opt.use_direct_io = true;
+            let files = PageFiles::new(env.clone(), base.path(), &opt).await;
+
+            // Files that reject direct IO with EINVAL are written and read with
+            // buffered IO.
+            env.inject(FaultRule::new(
+                FaultOp::DirectIo,
+                Fault::Error(ErrorKind::InvalidInput),
+            ));
+            let (group, info) = {
+                let b = files
+                    .new_file_builder(3, Compression::NONE, ChecksumType::NONE)
+                    .await
+                    .unwrap();
+                let mut b = b.add_page_group(123);
+                b.add_page(1, page_addr(3, 0), empty_page_info(), &[7].repeat(8192))
+                    .await
+                    .unwrap();
+                let builder = b.finish().await.unwrap();
+                let (groups, info) = builder.finish(1).await.unwrap();
+                (groups.get(&123).unwrap().clone(), info)
+            };
+            let hd = group.get_page_handle(page_addr(3, 0)).unwrap();
+            files.read_file_page(3, info.meta(), hd).await.unwrap();
+            assert!(env.issued(FaultOp::DirectIo) >= 2);
+
+            // Other errors are returned instead of falling back.
+            env.clear();
+            env.inject(FaultRule::new(
+                FaultOp::DirectIo,
+                Fault::Error(ErrorKind::PermissionDenied),
+            ));
+            assert!(matches!(
+                files
+                    .new_file_builder(4, Compression::NONE, ChecksumType::NONE)
+                    .await,
+                Err(Error::Io(_))
+            ));
+        }
+
+        #[photonio::test]
+        fn test_open_missing_file() {
+            let env = crate::env::Photon;
+            let base = TempDir::new("test_open_missing_file").unwrap();
+            let files = PageFiles::new(env, base.path(), &test_option()).await;
+
+            assert!(matches!(
+                files.open_page_reader(404, 4096).await,
+                Err(Error::Io(_))
+            ));
+            assert!(matches!(files.read_file_meta(404).await, Err(Error::Io(_))));
+
+            // The directory of page files is gone.
+            ::std::fs::remove_dir_all(base.path()).unwrap();
+            assert!(matches!(
+                files
+                    .new_file_builder(1, Compression::NONE, ChecksumType::NONE)
+                    .await,
+                Err(Error::Io(_))
+            ));
+        }
+
+        #[photonio::test]
+        fn test_read_corrupted_page() {
+            for verify in [true, false] {
+                let env = crate::env::Photon;
+                let base = TempDir::new("test_read_corrupted_page").unwrap();
+                let mut opt = test_option();
+                opt.disable_checksum_verification = !verify;
+                let files = PageFiles::new(env, base.path(), &opt).await;
+                let file_id = 2;
+                let addr = page_addr(file_id, 1);
+                let (group, info) = {
+                    let b = files
+                        .new_file_builder(file_id, Compression::NONE, ChecksumType::CRC32)
+                        .await
+                        .unwrap();
+                    let mut b = b.add_page_group(1);
+                    b.add_page(1, addr, empty_page_info(), &[7].repeat(256))
+                        .await
+                        .unwrap();
+                    let builder = b.finish().await.unwrap();
+                    let (groups, info) = builder.finish(1).await.unwrap();
+                    (groups.get(&1).unwrap().clone(), info)
+                };
+                let hd = group.get_page_handle(addr).unwrap();
+
+                // Flip a byte in the middle of the page.
+                let path = base.path().join(format!("{FILE_PREFIX}_{file_id}"));
+                let mut content = ::std::fs::read(&path).unwrap();
+                content[hd.offset as usize + 128] ^= 0xff;
+                ::std::fs::write(&path, content).unwrap();
+
+                let result = files.read_file_page(file_id, info.meta(), hd).await;
+                if verify {
+                    assert!(matches!(result, Err(Error::Corrupted)));
+                } else {
+                    let page = result.unwrap();
+                    assert_eq!(page.len(), 256);
+                    assert_eq!(page[128], !7);
+                }
+            }
+        }
+
+        #[photonio::test]
+        fn test_active_page_handles() {
+            let env = crate::env::Photon;
+            let base = TempDir::new("test_active_page_handles").unwrap();
+            let files = PageFiles::new(env, base.path(), &test_option()).await;
+            let b = files
+                .new_file_builder(2, Compression::NONE, ChecksumType::NONE)
+                .await
+                .unwrap();
+            let mut b = b.add_page_group(2);
+            for (i, size) in [100, 200, 300].into_iter().enumerate() {
+                let addr = page_addr(2, i as u32);
+                b.add_page(
+                    i as u64 + 1,
+                    addr,
+                    empty_page_info(),
+                    &[i as u8].repeat(size),
+                )
+                .await
+                .unwrap();
+            }
+            let b = b.finish().await.unwrap();
+            let (groups, _) = b.finish(1).await.unwrap();
+            let mut group = groups.get(&2).unwrap().clone();
+
+            assert!(group.deactivate_page(page_addr(2, 1)));
+            let handles = group.active_page_handles().collect::<Vec<_>>();
+            assert_eq!(handles.len(), 2);
+            for (handle, addr) in handles.iter().zip([page_addr(2, 0), page_addr(2, 2)]) {
+                let expect = group.get_page_handle(addr).unwrap();
+                assert_eq!((handle.offset, handle.size), (expect.offset, expect.size));
+            }
+            let total_size: usize = handles.iter().map(|h| h.size as usize).sum();
+            assert_eq!(total_size, group.effective_size());
+        }
+
+        #[photonio::test]
+        fn test_recovered_active_page_handles() {
+            let env = crate::env::Photon;
+            let base = TempDir::new("test_recovered_active_page_handles").unwrap();
+            let files = PageFiles::new(env, base.path(), &test_option()).await;
+            let file_id = 5;
+            let b = files
+                .new_file_builder(file_id, Compression::NONE, ChecksumType::NONE)
+                .await
+                .unwrap();
+            // The second group doesn't start at the beginning of the file.
+            let mut b = b.add_page_group(3);
+            b.add_page(10, page_addr(3, 0), empty_page_info(), &[10].repeat(100))
+                .await
+                .unwrap();
+            let mut b = b.finish().await.unwrap().add_page_group(4);
+            for i in 0..3u32 {
+                let value = [i as u8].repeat(100 * (i as usize + 1));
+                b.add_page(i as u64 + 1, page_addr(4, i), empty_page_info(), &value)
+                    .await
+                    .unwrap();
+            }
+            let b = b.finish().await.unwrap();
+            b.finish(1).await.unwrap();
+
+            // Page groups are recovered from the file meta.
+            let meta = files.read_file_meta(file_id).await.unwrap();
+            let mut group = PageGroup::new(meta.page_groups.get(&4).unwrap().clone());
+            assert!(group.deactivate_page(page_addr(4, 0)));
+            let mut total_size = 0;
+            for (i, handle) in (1..3u8).zip(group.active_page_handles()) {
+                let page = files
+                    .read_file_page(file_id, &meta.file_meta, handle)
+                    .await
+                    .unwrap();
+                assert_eq!(page, [i].repeat(100 * (i as usize + 1)));
+                total_size += handle.size as usize;
+            }
+            assert_eq!(total_size, group.effective_size());
+            assert_eq!(group.active_page_handles().count(), 2);
+        }
+
+        #[photonio::test]
+        fn test_other_envs() {
+            async fn write_and_read<E: Env>(env: E) {
+                let base = TempDir::new("test_other_envs").unwrap();
+                let files = PageFiles::new(env, base.path(), &test_option()).await;
+                let file_id = 3;
+                let b = files
+                    .new_file_builder(file_id, Compression::NONE, ChecksumType::CRC32)
+                    .await
+                    .unwrap();
+                let mut b = b.add_page_group(1);
+                b.add_page(1, page_addr(1, 0), empty_page_info(), &[7].repeat(128))
+                    .await
+                    .unwrap();
+                let b = b.finish().await.unwrap();
+                b.finish(file_id).await.unwrap();
+                assert_eq!(files.list_files().unwrap(), vec![file_id]);
+
+                let meta = files.read_file_meta(file_id).await.unwrap();
+                let group = meta.page_groups.get(&1).unwrap();
+                let (_, handle) = group.get_page_handle(page_addr(1, 0)).unwrap();
+                let buf = files
+                    .read_file_page(file_id, &meta.file_meta, handle)
+                    .await
+                    .unwrap();
+                assert_eq!(buf, [7].repeat(128));
+
+                files.remove_files(vec![file_id]).await;
+                assert!(files.list_files().unwrap().is_empty());
+            }
+
+            // Page files only access files through the environment.
+            write_and_read(crate::env::Std).await;
+            write_and_read(crate::env::FaultEnv::new(crate::env::Std)).await;
+        }
+
+        #[photonio::test]
+        fn test_sharded_files() {
+            let env = crate::env::Photon;
+            let base = TempDir::new("test_sharded_files").unwrap();
+            let mut opt = test_option();
+            opt.page_file_dir_shards = 4;
+            let files = PageFiles::new(env.clone(), base.path(), &opt).await;
+            let file_ids = (1..=10).collect::<Vec<u32>>();
+            for &file_id in &file_ids {
+                let b = files
+                    .new_file_builder(file_id, Compression::NONE, ChecksumType::CRC32)
+                    .await
+                    .unwrap();
+                let mut b = b.add_page_group(file_id);
+                let addr = page_addr(file_id, 0);
+                b.add_page(1, addr, empty_page_info(), &[file_id as u8].repeat(128))
+                    .await
+                    .unwrap();
+                let b = b.finish().await.unwrap();
+                b.finish(file_id).await.unwrap();
+            }
+
+            for &file_id in &file_ids {
+                let shard = format!("{:02}", file_id % 4);
+                let path = base
+                    .path()
+                    .join(shard)
+                    .join(format!("{FILE_PREFIX}_{file_id}"));
+                assert!(path.exists(), "{path:?}");
+            }
+            let mut listed = files.list_files().unwrap();
+            listed.sort_unstable();
+            assert_eq!(listed, file_ids);
+
+            // Files are still readable after the page files are reopened.
+            drop(files);
+            let files = PageFiles::new(env, base.path(), &opt).await;
+            for &file_id in &file_ids {
+                let meta = files.read_file_meta(file_id).await.unwrap();
+                let group = meta.page_groups.get(&file_id).unwrap();
+                let (_, handle) = group.get_page_handle(page_addr(file_id, 0)).unwrap();
+                let buf = files
+                    .read_file_page(file_id, &meta.file_meta, handle)
+                    .await
+                    .unwrap();
+                assert_eq!(buf, [file_id as u8].repeat(128));
+            }
+        }
+
         #[photonio::test]
         fn test_simple_write_reader() {
             let env = crate::env::Photon;
@@ -452,6 +1021,51 @@ pub(crate) mod facade {
             }
         }
 
+        #[photonio::test]
+        fn test_read_mixed_compression() {
+            let env = crate::env::Photon;
+            let base = TempDir::new("test_read_mixed_compression").unwrap();
+            let files = PageFiles::new(env, base.path(), &test_option()).await;
+
+            // Files written with different compressions are read by the same store.
+            let compressions = [
+                Compression::NONE,
+                Compression::SNAPPY,
+                Compression::ZSTD,
+                Compression::LZ4,
+            ];
+            let page = (0..8192).map(|i| (i / 64) as u8).collect::<Vec<_>>();
+            let mut infos = Vec::new();
+            for (i, compression) in compressions.into_iter().enumerate() {
+                let file_id = i as u32 + 1;
+                let b = files
+                    .new_file_builder(file_id, compression, ChecksumType::CRC32)
+                    .await
+                    .unwrap();
+                let mut b = b.add_page_group(1);
+                b.add_page(1, page_addr(file_id, 0), empty_page_info(), &page)
+                    .await
+                    .unwrap();
+                let b = b.finish().await.unwrap();
+                let (groups, info) = b.finish(1).await.unwrap();
+                infos.push((file_id, groups.get(&1).unwrap().clone(), info));
+            }
+
+            let uncompressed_size = infos[0].2.meta().file_size;
+            for (file_id, group, info) in infos {
+                let hd = group.get_page_handle(page_addr(file_id, 0)).unwrap();
+                let buf = files
+                    .read_file_page(file_id, info.meta(), hd)
+                    .await
+                    .unwrap();
+                assert_eq!(buf, page);
+                if info.meta().compression != Compression::NONE {
+                    // The sizes of files count the compressed pages.
+                    assert!(info.meta().file_size < uncompressed_size);
+                }
+            }
+        }
+
         #[photonio::test]
         fn test_query_page_id_by_addr() {
             let env = crate::env::Photon;
@@ -548,6 +1162,250 @@ pub(crate) mod facade {
             assert_eq!(files, vec![0, 1, 3, 5, 7, 9, 123321, u32::MAX]);
         }
 
+        #[photonio::test]
+        fn test_async_cache_insert() {
+            let env = crate::env::Photon;
+            let base = TempDir::new("test_async_cache_insert").unwrap();
+            let mut opt = test_option();
+            opt.cache_capacity = 1 << 20;
+            opt.cache_async_insert_queue_size = 8;
+            let files = PageFiles::new(env, base.path(), &opt).await;
+            let mut receiver = files.take_insert_receiver().unwrap();
+            assert!(files.take_insert_receiver().is_none());
+
+            let file_id = 2;
+            let addr = page_addr(2, 2);
+            let (group, info) = {
+                let b = files
+                    .new_file_builder(file_id, Compression::NONE, ChecksumType::NONE)
+                    .await
+                    .unwrap();
+                let mut b = b.add_page_group(123);
+                b.add_page(1, addr, empty_page_info(), &[0].repeat(64))
+                    .await
+                    .unwrap();
+                let builder = b.finish().await.unwrap();
+                let (groups, info) = builder.finish(1).await.unwrap();
+                (groups.get(&123).unwrap().clone(), info)
+            };
+            let hd = group.get_page_handle(addr).unwrap();
+
+            // The page is returned without being inserted into the cache.
+            let (entry, hit) = files
+                .read_page(file_id, info.meta(), addr, hd, CacheOption::default())
+                .await
+                .unwrap();
+            assert!(!hit);
+            assert!(matches!(entry, PageEntry::Owned(ref page) if page.len() == 64));
+
+            let pending = receiver.try_next().unwrap().unwrap();
+            assert!(receiver.try_next().is_err());
+            files.insert_pending_page(pending);
+
+            let (entry, hit) = files
+                .read_page(file_id, info.meta(), addr, hd, CacheOption::default())
+                .await
+                .unwrap();
+            assert!(hit);
+            assert!(matches!(entry, PageEntry::Cached(_)));
+        }
+
+        #[photonio::test]
+        fn test_cache_charge_by_page_size() {
+            for policy in [CachePolicy::Clock, CachePolicy::Lru] {
+                let env = crate::env::Photon;
+                let base = TempDir::new("test_cache_charge_by_page_size").unwrap();
+                let mut opt = test_option();
+                opt.cache_capacity = 4 << 10;
+                opt.cache_estimated_entry_charge = 1 << 10;
+                opt.cache_policy = policy;
+                let files = PageFiles::new(env, base.path(), &opt).await;
+
+                // The pages take 16KB in total, which is four times the capacity.
+                const N: u32 = 16;
+                let file_id = 1;
+                let (group, info) = {
+                    let b = files
+                        .new_file_builder(file_id, Compression::NONE, ChecksumType::NONE)
+                        .await
+                        .unwrap();
+                    let mut b = b.add_page_group(1);
+                    for i in 0..N {
+                        let addr = page_addr(file_id, i);
+                        b.add_page(i as u64, addr, empty_page_info(), &[0].repeat(1 << 10))
+                            .await
+                            .unwrap();
+                    }
+                    let builder = b.finish().await.unwrap();
+                    let (groups, info) = builder.finish(1).await.unwrap();
+                    (groups.get(&1).unwrap().clone(), info)
+                };
+
+                for i in 0..N {
+                    let addr = page_addr(file_id, i);
+                    let hd = group.get_page_handle(addr).unwrap();
+                    let (entry, hit) = files
+                        .read_page(file_id, info.meta(), addr, hd, CacheOption::default())
+                        .await
+                        .unwrap();
+                    assert!(!hit);
+                    drop(entry);
+                    assert!(files.page_cache_usage() <= opt.cache_capacity, "{policy:?}");
+                }
+                let (stats, _) = files.stats();
+                assert_eq!(stats.insert, N as u64, "{policy:?}");
+                assert!(stats.passive_evict >= (N - 4) as u64, "{policy:?}");
+            }
+        }
+
+        #[photonio::test]
+        fn test_bounded_open_readers() {
+            let env = crate::env::Photon;
+            let base = TempDir::new("test_bounded_open_readers").unwrap();
+            let mut opt = test_option();
+            opt.cache_file_reader_capacity = 2;
+            let files = PageFiles::new(env, base.path(), &opt).await;
+
+            const N: u32 = 8;
+            let mut pages = Vec::new();
+            for file_id in 1..=N {
+                let addr = page_addr(file_id, 1);
+                let b = files
+                    .new_file_builder(file_id, Compression::NONE, ChecksumType::NONE)
+                    .await
+                    .unwrap();
+                let mut b = b.add_page_group(file_id);
+                b.add_page(1, addr, empty_page_info(), &[file_id as u8].repeat(64))
+                    .await
+                    .unwrap();
+                let builder = b.finish().await.unwrap();
+                let (groups, info) = builder.finish(1).await.unwrap();
+                let hd = groups.get(&file_id).unwrap().get_page_handle(addr).unwrap();
+                pages.push((file_id, info, hd));
+            }
+
+            for _ in 0..2 {
+                for (file_id, info, hd) in &pages {
+                    let page = files
+                        .read_file_page(*file_id, info.meta(), *hd)
+                        .await
+                        .unwrap();
+                    assert_eq!(page, [*file_id as u8].repeat(64));
+                    assert!(files.reader_cache.len() <= 2);
+                }
+            }
+            let (_, reader_cache) = files.stats();
+            assert_eq!(reader_cache.lookup_miss, (N * 2) as u64);
+
+            files.close_readers();
+            assert_eq!(files.reader_cache.len(), 0);
+            let (file_id, info, hd) = &pages[0];
+            files
+                .read_file_page(*file_id, info.meta(), *hd)
+                .await
+                .unwrap();
+            assert_eq!(files.reader_cache.len(), 1);
+        }
+
+        #[photonio::test]
+        fn test_read_truncated_file_meta() {
+            use super::super::map_file_builder::Footer;
+
+            let env = crate::env::Photon;
+            let base = TempDir::new("test_read_truncated_file_meta").unwrap();
+            let files = PageFiles::new(env, base.path(), &test_option()).await;
+
+            let file_id = 1;
+            {
+                let b = files
+                    .new_file_builder(file_id, Compression::NONE, ChecksumType::NONE)
+                    .await
+                    .unwrap();
+                let mut b = b.add_page_group(1);
+                b.add_page(1, page_addr(file_id, 1), empty_page_info(), &[1].repeat(64))
+                    .await
+                    .unwrap();
+                let b = b.finish().await.unwrap();
+                b.finish(1).await.unwrap();
+            }
+            files.read_file_meta(file_id).await.unwrap();
+
+            let path = base.path().join(format!("{FILE_PREFIX}_{file_id}"));
+            let content = ::std::fs::read(&path).unwrap();
+            let (body, footer) = content.split_at(content.len() - Footer::encoded_size());
+
+            // The footer is cut off.
+            ::std::fs::write(&path, body).unwrap();
+            assert!(matches!(
+                files.read_file_meta(file_id).await,
+                Err(Error::Corrupted)
+            ));
+
+            // The footer is intact, but the blocks before it are cut off.
+            let truncated = [&body[..body.len() / 2], footer].concat();
+            ::std::fs::write(&path, truncated).unwrap();
+            assert!(matches!(
+                files.read_file_meta(file_id).await,
+                Err(Error::Corrupted)
+            ));
+
+            // The end of the page index block overflows.
+            let mut footer = footer.to_owned();
+            footer[8..16].copy_from_slice(&u64::MAX.to_le_bytes());
+            ::std::fs::write(&path, [body, &footer].concat()).unwrap();
+            assert!(matches!(
+                files.read_file_meta(file_id).await,
+                Err(Error::Corrupted)
+            ));
+        }
+
+        #[photonio::test]
+        fn test_read_file_meta_v1() {
+            use super::super::{constant::FILE_MAGIC_V1, map_file_builder::Footer};
+            use crate::page::tests::{key_slice, OwnedSortedPage};
+
+            let env = crate::env::Photon;
+            let base = TempDir::new("test_read_file_meta_v1").unwrap();
+            let files = PageFiles::new(env, base.path(), &test_option()).await;
+
+            let file_id = 1;
+            {
+                let page = OwnedSortedPage::from_slice(&key_slice(&[([1], 1), ([3], 1)]));
+                let b = files
+                    .new_file_builder(file_id, Compression::NONE, ChecksumType::NONE)
+                    .await
+                    .unwrap();
+                let mut b = b.add_page_group(1);
+                b.add_tree_page(1, page_addr(file_id, 1), *page.as_ref())
+                    .await
+                    .unwrap();
+                let b = b.finish().await.unwrap();
+                b.finish(1).await.unwrap();
+            }
+            let holder = files.read_file_meta(file_id).await.unwrap();
+            let key_range = holder.file_meta.key_range.as_ref().unwrap();
+            assert_eq!(key_range.min_key, [1]);
+            assert_eq!(key_range.max_key, [3]);
+
+            // Rewrites the footer as the one of files written before key ranges
+            // were recorded, which has no key range handle.
+            let path = base.path().join(format!("{FILE_PREFIX}_{file_id}"));
+            let content = ::std::fs::read(&path).unwrap();
+            let (body, footer) = content.split_at(content.len() - Footer::encoded_size());
+            let handles_end = std::mem::size_of::<u64>() + BlockHandle::encoded_size() * 2;
+            let footer = [
+                FILE_MAGIC_V1.to_le_bytes().as_slice(),
+                &footer[std::mem::size_of::<u64>()..handles_end],
+                &footer[footer.len() - 2..],
+            ]
+            .concat();
+            ::std::fs::write(&path, [body, &footer].concat()).unwrap();
+
+            let holder = files.read_file_meta(file_id).await.unwrap();
+            assert!(holder.file_meta.key_range.is_none());
+            assert_eq!(holder.page_groups.len(), 1);
+        }
+
         fn page_addr(file_id: u32, index: u32) -> u64 {
             ((file_id as u64) << 32) | (index as u64)
         }
@@ -1,12 +1,13 @@
 use std::{collections::BTreeMap, sync::Arc};
 
+use log::error;
 use rustc_hash::{FxHashMap, FxHashSet};
 
 use super::{
     file_builder::IndexBlock,
     file_reader::FileReader,
     map_file_builder::{Footer, PageIndex},
-    types::FileMeta,
+    types::{FileMeta, KeyRange},
     PageGroupMeta,
 };
 use crate::{
@@ -31,7 +32,7 @@ impl FileMetaHolder {
         file_id: u32,
         reader: Arc<FileReader<R>>,
     ) -> Result<Self> {
-        let footer = Self::read_footer(&reader).await?;
+        let footer = Self::read_footer(file_id, &reader).await?;
         let page_indexes = Self::read_page_indexes(&reader, &footer).await?;
         let mut file_meta_map = FxHashMap::default();
         let mut page_tables = FxHashMap::default();
@@ -44,9 +45,14 @@ impl FileMetaHolder {
             let page_table = Self::read_page_table(&reader, &file_meta).await?;
             file_meta_map.insert(page_index.file_id, Arc::new(file_meta));
             page_tables.insert(page_index.file_id, page_table);
-            offset = page_index.meta_handle.offset + page_index.meta_handle.length;
+            offset = page_index
+                .meta_handle
+                .offset
+                .checked_add(page_index.meta_handle.length)
+                .ok_or(Error::Corrupted)?;
         }
         let dealloc_pages = Self::read_dealloc_pages(&reader, &footer).await?;
+        let key_range = Self::read_key_range(&reader, &footer).await?;
 
         let mut referenced_groups = FxHashSet::default();
         if !dealloc_pages.is_empty() {
@@ -62,6 +68,7 @@ impl FileMetaHolder {
             footer.compression,
             referenced_groups,
             file_meta_map.clone(),
+            key_range,
         ));
         Ok(FileMetaHolder {
             page_groups: file_meta_map,
@@ -95,16 +102,62 @@ impl FileMetaHolder {
     }
 
     /// Read [`Footer`] according to file reader.
-    async fn read_footer<R: PositionalReader>(reader: &FileReader<R>) -> Result<Footer> {
+    ///
+    /// The footer is validated against the file size, so that a truncated file
+    /// is reported here instead of failing in a later read.
+    async fn read_footer<R: PositionalReader>(
+        file_id: u32,
+        reader: &FileReader<R>,
+    ) -> Result<Footer> {
         let file_size = reader.file_size;
-        if file_size < Footer::encoded_size() {
+        if file_size < Footer::encoded_size_v1() {
+            error!(
+                "page file {file_id} is truncated: file size {file_size} is smaller than the \
+                 footer"
+            );
             return Err(Error::Corrupted);
         }
 
-        let footer_offset = (file_size - Footer::encoded_size()) as u64;
-        let mut buf = vec![0u8; Footer::encoded_size() as usize];
-        reader.read_exact_at(&mut buf, footer_offset).await?;
-        Footer::decode(&buf)
+        // Files written before key ranges were recorded have a shorter footer,
+        // so the tail is read for the longer one.
+        let tail_size = file_size.min(Footer::encoded_size());
+        let mut buf = vec![0u8; tail_size];
+        reader
+            .read_exact_at(&mut buf, (file_size - tail_size) as u64)
+            .await?;
+        let footer = match Footer::decode(&buf) {
+            Ok(footer) => footer,
+            Err(err) => {
+                error!("page file {file_id} is corrupted or truncated: invalid footer");
+                return Err(err);
+            }
+        };
+        let footer_offset = (file_size - footer.size()) as u64;
+        // The blocks referenced by the footer must end before the footer.
+        let mut blocks_end = 0;
+        for handle in [
+            Some(footer.page_index_handle),
+            Some(footer.dealloc_pages_handle),
+            footer.key_range_handle,
+        ]
+        .iter()
+        .flatten()
+        {
+            let Some(end) = handle.offset.checked_add(handle.length) else {
+                error!("page file {file_id} is corrupted: invalid block handle {handle:?}");
+                return Err(Error::Corrupted);
+            };
+            blocks_end = blocks_end.max(end);
+        }
+        if blocks_end > footer_offset {
+            error!(
+                "page file {file_id} is truncated: the footer implies at least {} bytes, but \
+                 the file has {file_size} bytes",
+                blocks_end.saturating_add(footer.size() as u64),
+            );
+            return Err(Error::Corrupted);
+        }
+        Ok(footer)
     }
 
     /// Read [`PageIndex`] of the corresponding file, according to the file
@@ -145,4 +198,19 @@ impl FileMetaHolder {
         }
         Ok(dealloc_pages)
     }
+
+    /// Read the key range block.
+    async fn read_key_range<R: PositionalReader>(
+        reader: &FileReader<R>,
+        footer: &Footer,
+    ) -> Result<Option<KeyRange>> {
+        let Some(handle) = footer.key_range_handle else {
+            return Ok(None);
+        };
+        if handle.length == 0 {
+            return Ok(None);
+        }
+        let buf = reader.read_block(handle).await?;
+        KeyRange::decode(&buf).map(Some)
+    }
 }
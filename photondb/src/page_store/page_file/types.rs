@@ -75,6 +75,17 @@ pub(crate) struct FileMeta {
     pub(crate) checksum_type: ChecksumType,
     pub(crate) compression: Compression,
     pub(crate) page_groups: FxHashMap<u32, Arc<PageGroupMeta>>,
+
+    /// The range of keys written to the file, `None` if the file has no leaf
+    /// data pages.
+    pub(crate) key_range: Option<KeyRange>,
+}
+
+/// The smallest and the largest raw keys of a file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct KeyRange {
+    pub(crate) min_key: Vec<u8>,
+    pub(crate) max_key: Vec<u8>,
 }
 
 impl PageGroup {
@@ -159,6 +170,21 @@ impl PageGroup {
     pub(crate) fn iter(&self) -> PageGroupIterator {
         PageGroupIterator::new(self)
     }
+
+    /// Returns the handles of all active pages in the order they are written.
+    ///
+    /// The total size of the handles is equal to [`Self::effective_size`].
+    pub(crate) fn active_page_handles(&self) -> impl Iterator<Item = PageHandle> {
+        let mut active_pages = self
+            .meta
+            .page_meta_map
+            .values()
+            .filter(|meta| !self.dealloc_pages.test(meta.index))
+            .map(|meta| (meta.index, meta.handle))
+            .collect::<Vec<_>>();
+        active_pages.sort_by_key(|(index, _)| *index);
+        active_pages.into_iter().map(|(_, handle)| handle)
+    }
 }
 
 impl PageGroupMeta {
@@ -202,6 +228,11 @@ impl PageGroupMeta {
         None
     }
 
+    /// Returns the handles of all pages (include inactive pages).
+    pub(crate) fn page_handles(&self) -> impl Iterator<Item = PageHandle> + '_ {
+        self.page_meta_map.values().map(|meta| meta.handle)
+    }
+
     /// Return the total page (include inactive page).
     #[inline]
     pub(crate) fn total_pages(&self) -> usize {
@@ -309,6 +340,7 @@ impl FileMeta {
         compression: Compression,
         referenced_groups: FxHashSet<u32>,
         page_groups: FxHashMap<u32, Arc<PageGroupMeta>>,
+        key_range: Option<KeyRange>,
     ) -> Self {
         FileMeta {
             file_id,
@@ -318,6 +350,26 @@ impl FileMeta {
             compression,
             referenced_groups,
             page_groups,
+            key_range,
+        }
+    }
+}
+
+impl KeyRange {
+    pub(crate) fn new(min_key: &[u8], max_key: &[u8]) -> Self {
+        KeyRange {
+            min_key: min_key.to_owned(),
+            max_key: max_key.to_owned(),
+        }
+    }
+
+    /// Extends the range to cover `[min_key, max_key]`.
+    pub(crate) fn extend(&mut self, min_key: &[u8], max_key: &[u8]) {
+        if min_key < self.min_key.as_slice() {
+            self.min_key = min_key.to_owned();
+        }
+        if max_key > self.max_key.as_slice() {
+            self.max_key = max_key.to_owned();
         }
     }
 }
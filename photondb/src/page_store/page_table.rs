@@ -77,6 +77,25 @@ impl PageTable {
     pub(crate) unsafe fn dealloc(&self, id: u64) {
         self.inner.dealloc(id)
     }
+
+    /// Returns the ids and addresses of all live pages, ordered by ids.
+    ///
+    /// This must not be called concurrently with allocations.
+    #[cfg(test)]
+    pub(crate) fn live_pages(&self) -> Vec<(u64, u64)> {
+        let mut free = std::collections::HashSet::new();
+        let mut id = self.inner.free.load(Ordering::Acquire);
+        while id != NAN_ID {
+            free.insert(id);
+            id = self.get(id);
+        }
+        let next = self.inner.next.load(Ordering::Acquire);
+        (MIN_ID..next)
+            .filter(|id| !free.contains(id))
+            .map(|id| (id, self.get(id)))
+            .filter(|&(_, addr)| addr != 0)
+            .collect()
+    }
 }
 
 struct Inner {
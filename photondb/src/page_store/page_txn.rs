@@ -10,7 +10,7 @@ use super::{
     stats::AtomicWritebufStats,
     version::Version,
     write_buffer::{RecordHeader, ReleaseState},
-    CacheEntry, Error, LRUCache, PageFiles, PageTable, Result, WriteBuffer, NAN_ID,
+    Error, PageEntry, PageFiles, PageTable, Result, WriteBuffer, NAN_ID,
 };
 use crate::{
     env::Env,
@@ -84,8 +84,6 @@ pub(crate) enum CachePriority {
     Bottom,
 }
 
-type CacheEntryGuard = CacheEntry<Vec<u8>, LRUCache<Vec<u8>>>;
-
 pub(crate) struct Guard<E: Env>
 where
     Self: Send,
@@ -93,7 +91,7 @@ where
     version: Arc<Version>,
     page_table: PageTable,
     page_files: Arc<PageFiles<E>>,
-    cache_guards: Mutex<Vec<CacheEntryGuard>>,
+    cache_guards: Mutex<Vec<PageEntry>>,
     writebuf_stats: Arc<AtomicWritebufStats>,
 }
 
@@ -190,12 +188,13 @@ impl<E: Env> Guard<E> {
         let mut owned_pages = self.cache_guards.lock().expect("Poisoned");
         owned_pages.push(entry);
 
-        let last_guard = owned_pages.last().unwrap();
-        let page = last_guard.value();
+        let (page, cache_token) = match owned_pages.last().unwrap() {
+            PageEntry::Cached(entry) => (entry.value(), Some(entry.cache_token())),
+            PageEntry::Owned(page) => (page, None),
+        };
         if !hit {
             self.writebuf_stats.read_file_bytes.add(page.len() as u64);
         }
-        let cache_token = last_guard.cache_token();
 
         let page = PageRef::new(unsafe {
             // Safety: the lifetime is guarranted by `guard`.
@@ -206,7 +205,7 @@ impl<E: Env> Guard<E> {
             self.writebuf_stats.miss_inner.inc();
         }
 
-        Ok((page, Some(cache_token)))
+        Ok((page, cache_token))
     }
 }
 
@@ -240,11 +239,17 @@ impl<'a, E: Env> PageTxn<'a, E> {
     ///
     /// Returns the address and buffer of the allocated page.
     ///
+    /// Pages are allocated sequentially in the current write buffer, so the
+    /// address is `buffer_id << 32 | offset`, where `offset` follows the
+    /// header of the page record. The same sequence of allocations always
+    /// yields the same addresses.
+    ///
     /// If the transaction aborts, all pages allocated by this transaction will
     /// be deallocated.
     pub(crate) async fn alloc_page(&mut self, size: usize) -> Result<(u64, PageBuf<'a>)> {
         let page_size = size as u32;
         let (addr, header, buf) = self.alloc_page_impl(page_size).await?;
+        self.guard.writebuf_stats.alloc_pages.inc();
         self.records.insert(addr, header);
         Ok((addr, buf))
     }
@@ -282,12 +287,23 @@ impl<'a, E: Env> PageTxn<'a, E> {
     ///
     /// # Panics
     ///
-    /// Panics if `new_addr` is not allocated by this transaction.
+    /// Panics if `new_addr` is not allocated by this transaction. In debug
+    /// builds, also panics if the new page doesn't link to `old_addr`.
     pub(crate) fn update_page(
+        self,
+        id: u64,
+        old_addr: u64,
+        new_addr: u64,
+    ) -> Result<(), Option<(Self, u64)>> {
+        self.update_page_impl(id, old_addr, new_addr, &[])
+    }
+
+    fn update_page_impl(
         mut self,
         id: u64,
         old_addr: u64,
         new_addr: u64,
+        dealloc_addrs: &[u64],
     ) -> Result<(), Option<(Self, u64)>> {
         if new_addr <= old_addr {
             return Err(None);
@@ -297,6 +313,11 @@ impl<'a, E: Env> PageTxn<'a, E> {
             return Err(Some((self, addr)));
         }
 
+        #[cfg(debug_assertions)]
+        self.assert_valid_successor(id, old_addr, new_addr, dealloc_addrs);
+        #[cfg(not(debug_assertions))]
+        let _ = dealloc_addrs;
+
         let record_header = self
             .records
             .get_mut(&new_addr)
@@ -315,7 +336,9 @@ impl<'a, E: Env> PageTxn<'a, E> {
     ///
     /// # Panics
     ///
-    /// Panics if `new_addr` is not allocated by this transaction.
+    /// Panics if `new_addr` is not allocated by this transaction. In debug
+    /// builds, also panics if the deallocated pages don't start from
+    /// `old_addr`, or the new page links to any of them.
     pub(crate) async fn replace_page(
         mut self,
         id: u64,
@@ -328,13 +351,45 @@ impl<'a, E: Env> PageTxn<'a, E> {
         }
 
         let dealloc_pages = self.dealloc_pages_impl(dealloc_addrs).await?;
-        self.update_page(id, old_addr, new_addr).map_err(|_| {
-            dealloc_pages.set_tombstone();
-            Error::Again
-        })?;
+        self.update_page_impl(id, old_addr, new_addr, dealloc_addrs)
+            .map_err(|_| {
+                dealloc_pages.set_tombstone();
+                Error::Again
+            })?;
         Ok(())
     }
 
+    /// Asserts that the new page is a valid successor of the replaced page.
+    ///
+    /// If no pages are deallocated, the new page must be a delta linked to the
+    /// replaced page. Otherwise, the deallocated pages must start from the
+    /// replaced page and the new page must not link to any of them.
+    #[cfg(debug_assertions)]
+    fn assert_valid_successor(&self, id: u64, old_addr: u64, new_addr: u64, dealloc_addrs: &[u64]) {
+        let new_page = self
+            .guard
+            .read_page_info(new_addr)
+            .expect("The new page must exists");
+        let next = new_page.chain_next();
+        if dealloc_addrs.is_empty() {
+            assert_eq!(
+                next, old_addr,
+                "page {id} is updated to {new_addr} which links to {next} \
+                instead of the replaced page {old_addr}",
+            );
+        } else {
+            assert_eq!(
+                dealloc_addrs[0], old_addr,
+                "page {id} is replaced by {new_addr} but the deallocated pages \
+                {dealloc_addrs:?} don't start from the replaced page {old_addr}",
+            );
+            assert!(
+                !dealloc_addrs.contains(&next),
+                "page {id} is replaced by {new_addr} which links to a deallocated page {next}",
+            );
+        }
+    }
+
     #[inline]
     async fn alloc_page_impl(
         &mut self,
@@ -347,6 +402,22 @@ impl<'a, E: Env> PageTxn<'a, E> {
         .await
     }
 
+    /// Deletes the pages inserted by committed transactions, and deallocates
+    /// their addresses.
+    ///
+    /// This is used to drop the pages that never got linked into the tree, so
+    /// the pages must not be reachable by others.
+    pub(crate) async fn delete_pages(mut self, pages: &[(u64, u64)]) -> Result<()> {
+        let addrs: Vec<_> = pages.iter().map(|&(_, addr)| addr).collect();
+        self.dealloc_pages_impl(&addrs).await?;
+        for &(id, _) in pages {
+            // Safety: the pages are not reachable by others.
+            unsafe { self.guard.page_table.dealloc(id) };
+        }
+        self.commit();
+        Ok(())
+    }
+
     #[inline]
     async fn dealloc_pages_impl(&mut self, page_addrs: &[u64]) -> Result<&'a mut RecordHeader> {
         self.with_write_guard(|buf, is_first_op| unsafe {
@@ -457,12 +528,32 @@ mod tests {
         let mut page_txn = guard.begin().await;
         let (addr, _) = page_txn.alloc_page(123).await.unwrap();
         let id = page_txn.insert_page(addr);
-        let (new, _) = page_txn.alloc_page(123).await.unwrap();
+        let (new, mut new_page) = page_txn.alloc_page(123).await.unwrap();
+        new_page.set_chain_next(addr);
         assert!(page_txn.update_page(id, addr, new).is_ok());
 
         assert_current_buffer_is_flushable(version);
     }
 
+    #[cfg(debug_assertions)]
+    #[photonio::test]
+    #[should_panic(expected = "instead of the replaced page")]
+    async fn page_txn_update_page_not_successor() {
+        let env = crate::env::Photon;
+        let base = tempdir::TempDir::new("test_page_txn_update_page_not_successor").unwrap();
+        let files = Arc::new(PageFiles::new(env, base.path(), &test_option()).await);
+        let version = new_version(512);
+        let page_table = PageTable::default();
+        let guard = Guard::new(version, page_table, files, Default::default());
+        let mut page_txn = guard.begin().await;
+        let (addr, _) = page_txn.alloc_page(123).await.unwrap();
+        let id = page_txn.insert_page(addr);
+        // The new page skips the replaced page.
+        let (new, mut new_page) = page_txn.alloc_page(123).await.unwrap();
+        new_page.set_chain_next(0);
+        let _ = page_txn.update_page(id, addr, new);
+    }
+
     #[photonio::test]
     async fn page_txn_failed_update_page() {
         let env = crate::env::Photon;
@@ -477,7 +568,8 @@ mod tests {
         let mut page_txn = guard.begin().await;
         let (addr, _) = page_txn.alloc_page(123).await.unwrap();
         let id = page_txn.insert_page(addr);
-        let (new, _) = page_txn.alloc_page(123).await.unwrap();
+        let (new, mut new_page) = page_txn.alloc_page(123).await.unwrap();
+        new_page.set_chain_next(addr);
         assert!(page_txn.update_page(id, addr, new).is_ok());
 
         // operate is failed.
@@ -513,15 +605,35 @@ mod tests {
         let mut page_txn = guard.begin().await;
         let (addr, _) = page_txn.alloc_page(123).await.unwrap();
         let id = page_txn.insert_page(addr);
-        let (new, _) = page_txn.alloc_page(123).await.unwrap();
+        let (new, mut new_page) = page_txn.alloc_page(123).await.unwrap();
+        new_page.set_chain_next(0);
         assert!(page_txn
-            .replace_page(id, addr, new, &[1, 2, 3])
+            .replace_page(id, addr, new, &[addr, 2, 3])
             .await
             .is_ok());
 
         assert_current_buffer_is_flushable(version);
     }
 
+    #[cfg(debug_assertions)]
+    #[photonio::test]
+    #[should_panic(expected = "links to a deallocated page")]
+    async fn page_txn_replace_page_not_successor() {
+        let env = crate::env::Photon;
+        let base = tempdir::TempDir::new("test_page_txn_replace_page_not_successor").unwrap();
+        let files = Arc::new(PageFiles::new(env, base.path(), &test_option()).await);
+        let version = new_version(1 << 10);
+        let page_table = PageTable::default();
+        let guard = Guard::new(version, page_table, files, Default::default());
+        let mut page_txn = guard.begin().await;
+        let (addr, _) = page_txn.alloc_page(123).await.unwrap();
+        let id = page_txn.insert_page(addr);
+        // The new page links to the deallocated page.
+        let (new, mut new_page) = page_txn.alloc_page(123).await.unwrap();
+        new_page.set_chain_next(addr);
+        let _ = page_txn.replace_page(id, addr, new, &[addr]).await;
+    }
+
     impl<'a, E: Env> PageTxn<'a, E> {
         async fn seal_write_buffer(&mut self) {
             self.guard
@@ -590,6 +702,44 @@ mod tests {
         assert!(buf.is_flushable());
     }
 
+    #[photonio::test]
+    async fn page_txn_deterministic_addrs() {
+        let env = crate::env::Photon;
+        let base = tempdir::TempDir::new("test_page_txn_deterministic_addrs").unwrap();
+        let files = Arc::new(PageFiles::new(env, base.path(), &test_option()).await);
+        let version = new_version(1 << 10);
+        let page_table = PageTable::default();
+        let guard = Guard::new(
+            version.clone(),
+            page_table.clone(),
+            files,
+            Default::default(),
+        );
+
+        // Each record is a 16 bytes header followed by the page aligned to 8
+        // bytes, and the first buffer id is 1.
+        let addr = |offset: u64| (1 << 32) | offset;
+        let mut page_txn = guard.begin().await;
+        let (a, _) = page_txn.alloc_page(123).await.unwrap();
+        assert_eq!(a, addr(16));
+        let id = page_txn.insert_page(a);
+        let (b, mut page) = page_txn.alloc_page(123).await.unwrap();
+        assert_eq!(b, addr(16 + 128 + 16));
+        page.set_chain_next(a);
+        assert!(page_txn.update_page(id, a, b).is_ok());
+
+        let mut page_txn = guard.begin().await;
+        let (c, _) = page_txn.alloc_page(40).await.unwrap();
+        assert_eq!(c, addr(2 * (16 + 128) + 16));
+        let other = page_txn.insert_page(c);
+        page_txn.commit();
+        assert_eq!(page_table.live_pages(), vec![(id, b), (other, c)]);
+
+        // Deallocated ids are not live.
+        unsafe { page_table.dealloc(other) };
+        assert_eq!(page_table.live_pages(), vec![(id, b)]);
+    }
+
     fn test_option() -> PageStoreOptions {
         PageStoreOptions {
             cache_capacity: 2 << 10,
@@ -1,4 +1,4 @@
-use std::fmt::Display;
+use std::{fmt::Display, sync::Arc};
 
 use crate::util::atomic::Counter;
 
@@ -35,26 +35,30 @@ impl Display for StoreStats {
         self.writebuf.fmt(f)?;
         writeln!(
             f,
-            "PageCacheStats: lookup_hit: {}, lookup_miss: {}, hit_rate: {:.2}%, insert: {}, active_evict: {}, passive_evict: {}, recommendation: {:?}",
+            "PageCacheStats: lookup_hit: {}, lookup_miss: {}, hit_rate: {:.2}%, insert: {}, active_evict: {}, passive_evict: {}, usage_bytes: {}, occupancy: {}/{}, recommendation: {:?}",
             self.page_cache.lookup_hit,
             self.page_cache.lookup_miss,
-            (self.page_cache.lookup_hit as f64) * 100.
-                / (self.page_cache.lookup_hit + self.page_cache.lookup_miss) as f64,
+            self.page_cache.hit_ratio() * 100.,
             self.page_cache.insert,
             self.page_cache.active_evict,
             self.page_cache.passive_evict,
+            self.page_cache.usage_bytes,
+            self.page_cache.occupancy,
+            self.page_cache.occupancy_limit,
             self.page_cache.recommendation,
         )?;
         writeln!(
             f,
-            "FileReaderCacheStats: lookup_hit: {}, lookup_miss: {}, hit_rate: {}%, insert: {}, active_evict: {}, passive_evict: {}, recommendation: {:?}",
+            "FileReaderCacheStats: lookup_hit: {}, lookup_miss: {}, hit_rate: {}%, insert: {}, active_evict: {}, passive_evict: {}, usage_bytes: {}, occupancy: {}/{}, recommendation: {:?}",
             self.file_reader_cache.lookup_hit,
             self.file_reader_cache.lookup_miss,
-            (self.file_reader_cache.lookup_hit as f64) * 100.
-                / (self.file_reader_cache.lookup_hit + self.file_reader_cache.lookup_miss) as f64,
+            self.file_reader_cache.hit_ratio() * 100.,
             self.file_reader_cache.insert,
             self.file_reader_cache.active_evict,
             self.file_reader_cache.passive_evict,
+            self.file_reader_cache.usage_bytes,
+            self.file_reader_cache.occupancy,
+            self.file_reader_cache.occupancy_limit,
             self.file_reader_cache.recommendation,
         )?;
         self.buffer_set.fmt(f)?;
@@ -65,16 +69,44 @@ impl Display for StoreStats {
 /// Statistics of cache.
 #[derive(Default, Clone, Debug)]
 pub struct CacheStats {
+    /// The number of lookups that found the entry in the cache.
     pub lookup_hit: u64,
+    /// The number of lookups that didn't find the entry in the cache.
     pub lookup_miss: u64,
+    /// The number of entries inserted into the cache.
     pub insert: u64,
+    /// The number of entries erased from the cache explicitly.
     pub active_evict: u64,
+    /// The number of entries evicted from the cache to make room for others.
     pub passive_evict: u64,
+    /// Suggestions to tune the cache based on the statistics.
     pub recommendation: Vec<String>,
+    /// The total charge of the entries in the cache, including the pinned
+    /// ones.
+    pub usage_bytes: u64,
+    /// The charge of the entries inserted without a slot in the cache. They
+    /// are released once unpinned, so a steady growth of this suggests the
+    /// cache is undersized. Only tracked by the clock cache.
+    pub detached_usage_bytes: u64,
+    /// The number of occupied slots. Only tracked by the clock cache.
+    pub occupancy: u64,
+    /// The number of slots that can be occupied. Only tracked by the clock
+    /// cache.
+    pub occupancy_limit: u64,
 }
 
 impl CacheStats {
-    fn sub(&self, o: &CacheStats) -> CacheStats {
+    /// Returns the ratio of lookups that found the entry in the cache, or 0 if
+    /// there is no lookup.
+    pub fn hit_ratio(&self) -> f64 {
+        let lookups = self.lookup_hit + self.lookup_miss;
+        if lookups == 0 {
+            return 0.0;
+        }
+        self.lookup_hit as f64 / lookups as f64
+    }
+
+    pub(crate) fn sub(&self, o: &CacheStats) -> CacheStats {
         CacheStats {
             lookup_hit: self.lookup_hit.wrapping_sub(o.lookup_hit),
             lookup_miss: self.lookup_miss.wrapping_sub(o.lookup_miss),
@@ -82,6 +114,11 @@ impl CacheStats {
             active_evict: self.active_evict.wrapping_sub(o.active_evict),
             passive_evict: self.passive_evict.wrapping_sub(o.passive_evict),
             recommendation: self.recommendation.to_owned(),
+            // Gauges are not accumulated, so the current values are kept.
+            usage_bytes: self.usage_bytes,
+            detached_usage_bytes: self.detached_usage_bytes,
+            occupancy: self.occupancy,
+            occupancy_limit: self.occupancy_limit,
         }
     }
 
@@ -93,6 +130,12 @@ impl CacheStats {
             active_evict: self.active_evict.wrapping_add(o.active_evict),
             passive_evict: self.passive_evict.wrapping_add(o.passive_evict),
             recommendation: [self.recommendation.to_owned(), o.recommendation.to_owned()].concat(),
+            usage_bytes: self.usage_bytes.wrapping_add(o.usage_bytes),
+            detached_usage_bytes: self
+                .detached_usage_bytes
+                .wrapping_add(o.detached_usage_bytes),
+            occupancy: self.occupancy.wrapping_add(o.occupancy),
+            occupancy_limit: self.occupancy_limit.wrapping_add(o.occupancy_limit),
         }
     }
 }
@@ -103,6 +146,8 @@ pub struct WritebufStats {
     pub read_in_file: u64,
     pub read_file_bytes: u64,
     pub miss_inner: u64,
+    /// The number of pages allocated by foreground writes.
+    pub alloc_pages: u64,
 }
 
 impl WritebufStats {
@@ -112,6 +157,7 @@ impl WritebufStats {
             read_in_file: self.read_in_file.wrapping_sub(o.read_in_file),
             read_file_bytes: self.read_file_bytes.wrapping_sub(o.read_file_bytes),
             miss_inner: self.miss_inner.wrapping_sub(o.miss_inner),
+            alloc_pages: self.alloc_pages.wrapping_sub(o.alloc_pages),
         }
     }
 }
@@ -126,8 +172,13 @@ impl Display for WritebufStats {
                 read_in_files: {}, \
                 read_file_bytes: {}, \
                 read_hit_rate: {read_hit_rate:.2}%, \
-                miss_inner: {}",
-            self.read_in_buf, self.read_in_file, self.read_file_bytes, self.miss_inner,
+                miss_inner: {}, \
+                alloc_pages: {}",
+            self.read_in_buf,
+            self.read_in_file,
+            self.read_file_bytes,
+            self.miss_inner,
+            self.alloc_pages,
         )
     }
 }
@@ -138,6 +189,7 @@ pub(crate) struct AtomicWritebufStats {
     pub(super) read_in_file: Counter,
     pub(super) read_file_bytes: Counter,
     pub(super) miss_inner: Counter,
+    pub(super) alloc_pages: Counter,
 }
 
 impl AtomicWritebufStats {
@@ -147,6 +199,7 @@ impl AtomicWritebufStats {
             read_in_file: self.read_in_file.get(),
             read_file_bytes: self.read_file_bytes.get(),
             miss_inner: self.miss_inner.get(),
+            alloc_pages: self.alloc_pages.get(),
         }
     }
 }
@@ -163,6 +216,8 @@ pub struct JobStats {
     pub compact_input_bytes: u64,
     /// The total bytes read during compaction.
     pub read_file_bytes: u64,
+    /// The total time compactions are delayed by foreground load.
+    pub compact_throttle_ms: u64,
 }
 
 #[derive(Default, Debug)]
@@ -172,6 +227,7 @@ pub(crate) struct AtomicJobStats {
     pub(super) compact_write_bytes: Counter,
     pub(super) compact_input_bytes: Counter,
     pub(super) read_file_bytes: Counter,
+    pub(super) compact_throttle_ms: Counter,
 }
 
 impl JobStats {
@@ -182,6 +238,7 @@ impl JobStats {
             compact_write_bytes: self.compact_write_bytes.wrapping_sub(o.compact_write_bytes),
             compact_input_bytes: self.compact_input_bytes.wrapping_sub(o.compact_input_bytes),
             read_file_bytes: self.read_file_bytes.wrapping_sub(o.read_file_bytes),
+            compact_throttle_ms: self.compact_throttle_ms.wrapping_sub(o.compact_throttle_ms),
         }
     }
 }
@@ -202,13 +259,15 @@ impl Display for JobStats {
             compact_input_bytes: {}, \
             compact_write_bytes: {}, \
             read_file_bytes: {}, \
-            write_amp: {:.2}",
+            write_amp: {:.2}, \
+            compact_throttle_ms: {}",
             self.flush_write_bytes,
             self.flush_discard_bytes,
             self.compact_input_bytes,
             self.compact_write_bytes,
             self.read_file_bytes,
-            write_amp
+            write_amp,
+            self.compact_throttle_ms,
         )
     }
 }
@@ -221,6 +280,7 @@ impl AtomicJobStats {
             compact_write_bytes: self.compact_write_bytes.get(),
             compact_input_bytes: self.compact_input_bytes.get(),
             read_file_bytes: self.read_file_bytes.get(),
+            compact_throttle_ms: self.compact_throttle_ms.get(),
         }
     }
 }
@@ -231,6 +291,11 @@ pub struct BufferSetStats {
     pub stall_writes: u64,
     /// The total interval of stalling writes.
     pub stall_intervals_ms: u64,
+    /// The number of retired buffer set versions waiting to be reclaimed.
+    ///
+    /// Retired versions keep their write buffers alive. This number keeps
+    /// growing if some readers pin the store for a long time.
+    pub pending_reclaims: u64,
 }
 
 impl BufferSetStats {
@@ -238,6 +303,7 @@ impl BufferSetStats {
         BufferSetStats {
             stall_writes: self.stall_writes.wrapping_sub(o.stall_writes),
             stall_intervals_ms: self.stall_intervals_ms.wrapping_sub(o.stall_intervals_ms),
+            pending_reclaims: self.pending_reclaims,
         }
     }
 }
@@ -246,8 +312,8 @@ impl Display for BufferSetStats {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(
             f,
-            "BufferSet: stall_writes: {} stall_intervals_ms: {}",
-            self.stall_writes, self.stall_intervals_ms,
+            "BufferSet: stall_writes: {} stall_intervals_ms: {} pending_reclaims: {}",
+            self.stall_writes, self.stall_intervals_ms, self.pending_reclaims,
         )
     }
 }
@@ -256,6 +322,8 @@ impl Display for BufferSetStats {
 pub(crate) struct AtomicBufferSetStats {
     pub(crate) stall_writes: Counter,
     pub(crate) stall_intervals_ms: Counter,
+    pub(crate) retired_versions: Counter,
+    pub(crate) reclaimed_versions: Arc<Counter>,
 }
 
 impl AtomicBufferSetStats {
@@ -263,6 +331,24 @@ impl AtomicBufferSetStats {
         BufferSetStats {
             stall_writes: self.stall_writes.get(),
             stall_intervals_ms: self.stall_intervals_ms.get(),
+            pending_reclaims: self
+                .retired_versions
+                .get()
+                .saturating_sub(self.reclaimed_versions.get()),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_hit_ratio() {
+        let mut stats = CacheStats::default();
+        assert_eq!(stats.hit_ratio(), 0.0);
+        stats.lookup_hit = 3;
+        stats.lookup_miss = 1;
+        assert_eq!(stats.hit_ratio(), 0.75);
+    }
+}
@@ -25,6 +25,17 @@ impl Table {
         Ok(Self(table))
     }
 
+    /// Opens a table in the path with the default options and the given page
+    /// cache capacity in bytes.
+    pub async fn open_with_cache_capacity<P: AsRef<Path>>(
+        path: P,
+        cache_capacity: usize,
+    ) -> Result<Self> {
+        let mut options = TableOptions::default();
+        options.page_store.cache_capacity = cache_capacity;
+        Self::open(path, options).await
+    }
+
     /// Closes the table if this is the only reference to it.
     ///
     /// This is the same as [`raw::Table::close`] with the [`Photon`]
@@ -47,3 +58,18 @@ pub type Guard<'a> = raw::Guard<'a, Photon>;
 
 /// An iterator over pages in a table.
 pub type Pages<'a, 't> = raw::Pages<'a, 't, Photon>;
+
+/// An optimistic transaction on a table.
+pub type ReadTxn<'a> = raw::ReadTxn<'a, Photon>;
+
+/// A consistent view of a table as of an LSN.
+pub type Snapshot<'a> = raw::Snapshot<'a, Photon>;
+
+/// A cursor over the entries in a key range of a table.
+pub type Cursor<'a> = raw::Cursor<'a, Photon>;
+
+/// A cursor over the changes made to a table in an LSN window.
+pub type ChangeCursor<'a> = raw::ChangeCursor<'a, Photon>;
+
+/// A value that borrows the page that contains it.
+pub type PinnedValue<'a> = raw::PinnedValue<'a, Photon>;
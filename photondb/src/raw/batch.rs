@@ -0,0 +1,50 @@
+/// A batch of writes to apply to a table with a single LSN.
+///
+/// See [`Table::write_batch`] for how the writes are applied.
+///
+/// [`Table::write_batch`]: super::Table::write_batch
+#[derive(Clone, Debug, Default)]
+pub struct WriteBatch {
+    writes: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+}
+
+impl WriteBatch {
+    /// Creates an empty batch.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a put of the key-value entry to the batch.
+    pub fn put(&mut self, key: &[u8], value: &[u8]) {
+        self.writes.push((key.to_vec(), Some(value.to_vec())));
+    }
+
+    /// Adds a delete of the key to the batch.
+    pub fn delete(&mut self, key: &[u8]) {
+        self.writes.push((key.to_vec(), None));
+    }
+
+    /// Returns the number of writes in the batch.
+    pub fn len(&self) -> usize {
+        self.writes.len()
+    }
+
+    /// Returns true if the batch contains no writes.
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+
+    /// Removes all the writes from the batch.
+    pub fn clear(&mut self) {
+        self.writes.clear();
+    }
+
+    /// Returns an iterator over the writes in the order they are added.
+    ///
+    /// A write with a `None` value is a delete.
+    pub(super) fn iter(&self) -> impl Iterator<Item = (&[u8], Option<&[u8]>)> {
+        self.writes
+            .iter()
+            .map(|(key, value)| (key.as_slice(), value.as_deref()))
+    }
+}
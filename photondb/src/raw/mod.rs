@@ -1,7 +1,16 @@
 //! Raw PhotonDB APIs that can can run with different environments.
 
+mod batch;
+pub use batch::WriteBatch;
+
+mod registry;
 mod table;
-pub use table::{Guard, Pages, Table, TableStats};
+pub use table::{
+    ChangeCursor, Cursor, Guard, Pages, PinnedValue, ReadTxn, Snapshot, Table, TableStats,
+};
+
+#[cfg(feature = "prometheus")]
+mod prometheus;
 
 #[cfg(test)]
 mod tree_test {
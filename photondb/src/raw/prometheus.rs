@@ -0,0 +1,285 @@
+//! Renders table statistics in the Prometheus text exposition format.
+//!
+//! See <https://prometheus.io/docs/instrumenting/exposition_formats/>.
+
+use std::fmt::{Display, Write};
+
+use super::TableStats;
+use crate::{page_store::CacheStats, tree::TxnStats};
+
+/// The prefix of all metric names.
+const PREFIX: &str = "photondb";
+
+/// Renders the statistics as Prometheus metrics.
+pub(super) fn render(stats: &TableStats) -> String {
+    let mut w = MetricWriter::default();
+    render_tree(&mut w, stats);
+    render_store(&mut w, stats);
+    w.buf
+}
+
+fn render_tree(w: &mut MetricWriter, stats: &TableStats) {
+    let txns = [
+        ("success", &stats.tree.success),
+        ("conflict", &stats.tree.conflict),
+    ];
+
+    w.family("tree_ops_total", "counter", "Number of tree operations.");
+    for (result, txn) in txns {
+        w.sample(
+            "tree_ops_total",
+            &[("op", "read"), ("result", result)],
+            txn.read,
+        );
+        w.sample(
+            "tree_ops_total",
+            &[("op", "write"), ("result", result)],
+            txn.write,
+        );
+    }
+
+    w.family(
+        "tree_smo_total",
+        "counter",
+        "Number of structure modification operations on tree pages.",
+    );
+    for (result, txn) in txns {
+        for (kind, value) in smo_counters(txn) {
+            w.sample(
+                "tree_smo_total",
+                &[("kind", kind), ("result", result)],
+                value,
+            );
+        }
+    }
+
+    w.family(
+        "tree_skipped_pages_total",
+        "counter",
+        "Number of data pages skipped by point lookups.",
+    );
+    w.sample(
+        "tree_skipped_pages_total",
+        &[],
+        stats.tree.success.skip_page,
+    );
+
+    w.family(
+        "tree_bytes_total",
+        "counter",
+        "Number of bytes read from or written to the tree by users.",
+    );
+    let success = &stats.tree.success;
+    w.sample("tree_bytes_total", &[("op", "read")], success.read_bytes);
+    w.sample("tree_bytes_total", &[("op", "write")], success.write_bytes);
+
+    w.family(
+        "tree_consolidate_bytes_total",
+        "counter",
+        "Number of bytes of pages built by consolidations.",
+    );
+    w.sample(
+        "tree_consolidate_bytes_total",
+        &[],
+        success.consolidate_bytes,
+    );
+}
+
+fn smo_counters(txn: &TxnStats) -> [(&'static str, u64); 4] {
+    [
+        ("split_page", txn.split_page),
+        ("reconcile_page", txn.reconcile_page),
+        ("consolidate_page", txn.consolidate_page),
+        ("rewrite_page", txn.rewrite_page),
+    ]
+}
+
+fn render_store(w: &mut MetricWriter, stats: &TableStats) {
+    let store = &stats.store;
+    let caches = [
+        ("page", &store.page_cache),
+        ("file_reader", &store.file_reader_cache),
+    ];
+    render_caches(w, &caches);
+
+    let writebuf = &store.writebuf;
+    w.family(
+        "writebuf_reads_total",
+        "counter",
+        "Number of page reads served from write buffers or files.",
+    );
+    w.sample(
+        "writebuf_reads_total",
+        &[("source", "buffer")],
+        writebuf.read_in_buf,
+    );
+    w.sample(
+        "writebuf_reads_total",
+        &[("source", "file")],
+        writebuf.read_in_file,
+    );
+    w.family(
+        "writebuf_read_file_bytes_total",
+        "counter",
+        "Number of bytes read from files by foreground reads.",
+    );
+    w.sample(
+        "writebuf_read_file_bytes_total",
+        &[],
+        writebuf.read_file_bytes,
+    );
+
+    let jobs = &store.jobs;
+    w.family(
+        "job_bytes_total",
+        "counter",
+        "Number of bytes processed by background jobs.",
+    );
+    let job_bytes = [
+        ("flush", "write", jobs.flush_write_bytes),
+        ("flush", "discard", jobs.flush_discard_bytes),
+        ("compact", "input", jobs.compact_input_bytes),
+        ("compact", "write", jobs.compact_write_bytes),
+        ("compact", "read_file", jobs.read_file_bytes),
+    ];
+    for (job, kind, value) in job_bytes {
+        w.sample("job_bytes_total", &[("job", job), ("kind", kind)], value);
+    }
+
+    let buffer_set = &store.buffer_set;
+    w.family(
+        "write_stalls_total",
+        "counter",
+        "Number of writes stalled by full write buffers.",
+    );
+    w.sample("write_stalls_total", &[], buffer_set.stall_writes);
+    w.family(
+        "write_stall_seconds_total",
+        "counter",
+        "Total time writes spent stalled by full write buffers.",
+    );
+    let stall_seconds = buffer_set.stall_intervals_ms as f64 / 1000.0;
+    w.sample("write_stall_seconds_total", &[], stall_seconds);
+    w.family(
+        "pending_reclaims",
+        "gauge",
+        "Number of retired buffer set versions waiting to be reclaimed.",
+    );
+    w.sample("pending_reclaims", &[], buffer_set.pending_reclaims);
+}
+
+fn render_caches(w: &mut MetricWriter, caches: &[(&str, &CacheStats)]) {
+    w.family("cache_lookups_total", "counter", "Number of cache lookups.");
+    for (cache, stats) in caches {
+        let hit = [("cache", *cache), ("result", "hit")];
+        w.sample("cache_lookups_total", &hit, stats.lookup_hit);
+        let miss = [("cache", *cache), ("result", "miss")];
+        w.sample("cache_lookups_total", &miss, stats.lookup_miss);
+    }
+    w.family("cache_inserts_total", "counter", "Number of cache inserts.");
+    for (cache, stats) in caches {
+        w.sample("cache_inserts_total", &[("cache", *cache)], stats.insert);
+    }
+    w.family(
+        "cache_evictions_total",
+        "counter",
+        "Number of cache evictions.",
+    );
+    for (cache, stats) in caches {
+        let active = [("cache", *cache), ("kind", "active")];
+        w.sample("cache_evictions_total", &active, stats.active_evict);
+        let passive = [("cache", *cache), ("kind", "passive")];
+        w.sample("cache_evictions_total", &passive, stats.passive_evict);
+    }
+    w.family(
+        "cache_usage_bytes",
+        "gauge",
+        "Total charge of the entries in the cache.",
+    );
+    for (cache, stats) in caches {
+        let total = [("cache", *cache), ("kind", "total")];
+        w.sample("cache_usage_bytes", &total, stats.usage_bytes);
+        let detached = [("cache", *cache), ("kind", "detached")];
+        w.sample("cache_usage_bytes", &detached, stats.detached_usage_bytes);
+    }
+    w.family(
+        "cache_occupancy",
+        "gauge",
+        "Number of occupied slots in the cache.",
+    );
+    for (cache, stats) in caches {
+        w.sample("cache_occupancy", &[("cache", *cache)], stats.occupancy);
+    }
+    w.family(
+        "cache_occupancy_limit",
+        "gauge",
+        "Number of slots that can be occupied in the cache.",
+    );
+    for (cache, stats) in caches {
+        let limit = stats.occupancy_limit;
+        w.sample("cache_occupancy_limit", &[("cache", *cache)], limit);
+    }
+}
+
+#[derive(Default)]
+struct MetricWriter {
+    buf: String,
+}
+
+impl MetricWriter {
+    /// Writes the metadata of a metric family.
+    fn family(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.buf, "# HELP {PREFIX}_{name} {help}");
+        let _ = writeln!(self.buf, "# TYPE {PREFIX}_{name} {kind}");
+    }
+
+    /// Writes a sample of a metric family.
+    ///
+    /// Label values are static names, so they don't need to be escaped.
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl Display) {
+        let _ = write!(self.buf, "{PREFIX}_{name}");
+        for (i, (key, value)) in labels.iter().enumerate() {
+            let sep = if i == 0 { '{' } else { ',' };
+            let _ = write!(self.buf, "{sep}{key}=\"{value}\"");
+        }
+        if !labels.is_empty() {
+            self.buf.push('}');
+        }
+        let _ = writeln!(self.buf, " {value}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_stats() {
+        let mut stats = TableStats::default();
+        stats.tree.success.read = 3;
+        stats.tree.conflict.split_page = 2;
+        stats.store.page_cache.lookup_hit = 5;
+        stats.store.buffer_set.stall_intervals_ms = 1500;
+        let text = render(&stats);
+
+        let lines: Vec<&str> = text.lines().collect();
+        assert!(lines.contains(&"# TYPE photondb_tree_ops_total counter"));
+        assert!(lines.contains(&"photondb_tree_ops_total{op=\"read\",result=\"success\"} 3"));
+        assert!(
+            lines.contains(&"photondb_tree_smo_total{kind=\"split_page\",result=\"conflict\"} 2")
+        );
+        assert!(lines.contains(&"photondb_cache_lookups_total{cache=\"page\",result=\"hit\"} 5"));
+        assert!(lines.contains(&"photondb_write_stall_seconds_total 1.5"));
+        assert!(lines.contains(&"# TYPE photondb_pending_reclaims gauge"));
+
+        // Every sample must belong to a declared family.
+        let mut family = "";
+        for line in lines {
+            if let Some(name) = line.strip_prefix("# TYPE ") {
+                family = name.split(' ').next().unwrap();
+            } else if !line.starts_with('#') {
+                assert!(line.starts_with(family), "{line}");
+            }
+        }
+    }
+}
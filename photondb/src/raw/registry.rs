@@ -0,0 +1,150 @@
+use std::{
+    any::Any,
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Weak},
+};
+
+use futures::lock::{Mutex as AsyncMutex, OwnedMutexGuard};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use crate::env::Env;
+
+/// The tables open in this process, keyed by their canonical paths.
+///
+/// The map is only locked briefly. Opens and closes of a table hold the lock
+/// of its slot instead, so that they don't block the tables in other paths.
+static OPEN_TABLES: Lazy<Mutex<HashMap<PathBuf, Slot>>> = Lazy::new(Mutex::default);
+
+#[derive(Default)]
+struct Slot {
+    // Serializes the opens and closes of the table in the path.
+    lock: Arc<AsyncMutex<()>>,
+    entry: Option<Entry>,
+}
+
+struct Entry {
+    // The object that owns the files of the table, which is alive as long as
+    // the files are in use.
+    owner: Weak<dyn Any + Send + Sync>,
+    // A handle to share the table with.
+    handle: Box<dyn Any + Send>,
+}
+
+impl Entry {
+    fn is_open(&self) -> bool {
+        self.owner.strong_count() > 0
+    }
+
+    fn is_owned_by<O>(&self, owner: &Arc<O>) -> bool {
+        self.owner.as_ptr() as *const () == Arc::as_ptr(owner) as *const ()
+    }
+}
+
+/// A locked path in the registry of the tables open in this process.
+///
+/// The table in the path can't be opened or closed by others until the path
+/// is released, so it should be held until the table is opened or closed.
+pub(super) struct Registry {
+    path: PathBuf,
+    _lock: OwnedMutexGuard<()>,
+}
+
+impl Registry {
+    /// Locks the path in the registry.
+    pub(super) async fn lock(path: PathBuf) -> Self {
+        let lock = OPEN_TABLES
+            .lock()
+            .entry(path.clone())
+            .or_default()
+            .lock
+            .clone();
+        Self {
+            path,
+            _lock: lock.lock_owned().await,
+        }
+    }
+
+    /// Locks the path of the table whose files are owned by `owner`.
+    ///
+    /// Returns `None` if the table is not registered.
+    pub(super) async fn lock_owner<O>(owner: &Arc<O>) -> Option<Self> {
+        let path = OPEN_TABLES
+            .lock()
+            .iter()
+            .find(|(_, slot)| slot.entry.as_ref().map_or(false, |e| e.is_owned_by(owner)))
+            .map(|(path, _)| path.clone())?;
+        Some(Self::lock(path).await)
+    }
+
+    /// Returns the canonical path of the table to register.
+    ///
+    /// The directory is created if it doesn't exist, so that different paths
+    /// to the same table are resolved to the same one.
+    pub(super) async fn canonical_path<E: Env>(env: &E, path: &Path) -> PathBuf {
+        // Errors are reported when the table is opened.
+        let _ = env.create_dir_all(path).await;
+        env.canonicalize(path)
+            .await
+            .unwrap_or_else(|_| path.to_owned())
+    }
+
+    /// Returns true if the table in the path is open.
+    pub(super) fn is_open(&self) -> bool {
+        OPEN_TABLES
+            .lock()
+            .get(&self.path)
+            .and_then(|slot| slot.entry.as_ref())
+            .map_or(false, Entry::is_open)
+    }
+
+    /// Returns the handle of the table in the path, if it is open and the
+    /// handle is of type `T`.
+    pub(super) fn handle<T: Any + Clone>(&self) -> Option<T> {
+        OPEN_TABLES
+            .lock()
+            .get(&self.path)
+            .and_then(|slot| slot.entry.as_ref())
+            .filter(|entry| entry.is_open())
+            .and_then(|entry| entry.handle.downcast_ref().cloned())
+    }
+
+    /// Registers the table in the path, which stays open until `owner` is
+    /// dropped.
+    pub(super) fn register<O, T>(&mut self, owner: &Arc<O>, handle: T)
+    where
+        O: Any + Send + Sync,
+        T: Any + Send,
+    {
+        let owner: Arc<dyn Any + Send + Sync> = owner.clone();
+        let entry = Entry {
+            owner: Arc::downgrade(&owner),
+            handle: Box::new(handle),
+        };
+        let mut tables = OPEN_TABLES.lock();
+        tables.entry(self.path.clone()).or_default().entry = Some(entry);
+        // Drop the slots of closed tables, unless they are locked by others.
+        tables.retain(|_, slot| {
+            slot.entry.as_ref().map_or(false, Entry::is_open) || Arc::strong_count(&slot.lock) > 1
+        });
+    }
+
+    /// Replaces the handle of the table whose files are owned by `owner`.
+    ///
+    /// This is used when the files are moved to another table, so that
+    /// opening the path shares the table that owns the files now.
+    pub(super) fn set_handle<O, T>(owner: &Arc<O>, handle: T)
+    where
+        T: Any + Send,
+    {
+        let mut tables = OPEN_TABLES.lock();
+        let entry = tables
+            .values_mut()
+            .filter_map(|slot| slot.entry.as_mut())
+            .find(|entry| entry.is_owned_by(owner));
+        if let Some(entry) = entry {
+            entry.handle = Box::new(handle);
+        }
+    }
+}
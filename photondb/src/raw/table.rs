@@ -1,11 +1,29 @@
-use std::{path::Path, sync::Arc};
+use std::{
+    collections::VecDeque,
+    mem,
+    ops::{Bound, Deref},
+    path::Path,
+    ptr::NonNull,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Weak,
+    },
+};
+
+use futures::{
+    lock::Mutex,
+    stream::{self, LocalBoxStream, StreamExt},
+};
+use log::warn;
+use parking_lot::RwLock;
 
+use super::{registry::Registry, WriteBatch};
 use crate::{
     env::Env,
-    page::{Key, Value},
-    page_store::{FlushOptions, PageStore, StoreStats},
+    page::{is_valid_fp_rate, Key, Value},
+    page_store::{ChecksumReport, FlushOptions, PageStore, StoreStats},
     tree::*,
-    Result,
+    Error, Result,
 };
 
 /// A reference to a latch-free, log-structured table that stores sorted
@@ -15,20 +33,65 @@ use crate::{
 #[derive(Clone, Debug)]
 pub struct Table<E: Env> {
     tree: Arc<Tree>,
-    store: Arc<PageStore<E>>,
+    // The store is swappable, see `Table::swap`.
+    store: Arc<RwLock<Arc<PageStore<E>>>>,
+    sequencer: Arc<Sequencer>,
+}
+
+/// Assigns increasing LSNs to writes without explicit LSNs.
+#[derive(Debug, Default)]
+struct Sequencer {
+    last_lsn: AtomicU64,
+    // Whether `last_lsn` covers the entries in the table when it is opened.
+    recovered: AtomicBool,
+    // Serializes the validations and writes of optimistic transactions.
+    commit_lock: Mutex<()>,
+}
+
+impl Sequencer {
+    /// Advances `last_lsn` past a write with an explicit LSN.
+    fn observe(&self, lsn: u64) {
+        self.last_lsn.fetch_max(lsn, Ordering::AcqRel);
+    }
 }
 
 impl<E: Env> Table<E> {
     /// Opens a table in the path with the given options.
+    ///
+    /// A table can be open only once in a process, see
+    /// [`Options::share_open_tables`] for how to open it again.
     pub async fn open<P: AsRef<Path>>(env: E, path: P, options: Options) -> Result<Self> {
+        if !is_valid_fp_rate(options.bloom_fp_rate) || options.presplit_keys().is_none() {
+            return Err(Error::InvalidArgument);
+        }
+        let path = Registry::canonical_path(&env, path.as_ref()).await;
+        let mut registry = Registry::lock(path.clone()).await;
+        if registry.is_open() {
+            if options.share_open_tables {
+                let table = registry.handle::<WeakTable<E>>().and_then(|t| t.upgrade());
+                if let Some(table) = table {
+                    return Ok(table);
+                }
+            }
+            return Err(Error::AlreadyOpen);
+        }
+
         let tree = Arc::new(Tree::new(options.clone()));
-        let store = PageStore::open(env, path, options.page_store).await?;
+        let mut store_options = options.page_store;
+        if options.disable_background {
+            store_options.disable_space_reclaiming = true;
+        }
+        let store = PageStore::open(env, &path, store_options).await?;
         let txn = tree.begin(store.guard());
         txn.init().await?;
-        Ok(Self {
+        let store = Arc::new(store);
+        let table = Self {
             tree,
-            store: Arc::new(store),
-        })
+            store: Arc::new(RwLock::new(store.clone())),
+            sequencer: Arc::default(),
+        };
+        registry.register(&store, WeakTable::new(&table, &store));
+        Ok(table)
     }
 
     /// Closes the table if this is the only reference to it.
@@ -36,21 +99,95 @@ impl<E: Env> Table<E> {
     /// If this is not the only reference, returns [`Result::Err`] with this
     /// reference.
     pub async fn close(self) -> Result<(), Self> {
-        match Arc::try_unwrap(self.store) {
+        // Holds the path of the table so that it can't be opened again until
+        // it is closed.
+        let _registry = Registry::lock_owner(&self.store()).await;
+        let store = match Arc::try_unwrap(self.store) {
+            Ok(store) => store.into_inner(),
+            Err(store) => {
+                return Err(Self {
+                    tree: self.tree,
+                    store,
+                    sequencer: self.sequencer,
+                })
+            }
+        };
+        match Arc::try_unwrap(store) {
             Ok(store) => {
                 store.close().await;
                 Ok(())
             }
             Err(store) => Err(Self {
                 tree: self.tree,
-                store,
+                store: Arc::new(RwLock::new(store)),
+                sequencer: self.sequencer,
             }),
         }
     }
 
+    /// Atomically exchanges the contents of this table with another one.
+    ///
+    /// The page stores of the two tables are swapped, so that this table reads
+    /// and writes the entries of `other` and vice versa. Operations that have
+    /// started before the swap, including the ones through pinned [`Guard`]s,
+    /// keep working on the store they started with, so readers observe either
+    /// the old or the new contents, never a mix of both.
+    ///
+    /// The options, statistics and safe LSNs of the tables are not swapped.
+    /// LSNs assigned by [`Self::put_auto`] after the swap are larger than the
+    /// ones assigned by both tables before.
+    ///
+    /// Snapshots are not swapped either, and the versions they retain would be
+    /// dropped by the other table after the swap. So this fails with
+    /// [`Error::SnapshotOpen`] if either table has open snapshots.
+    pub fn swap(&self, other: &Table<E>) -> Result<()> {
+        if Arc::ptr_eq(&self.store, &other.store) {
+            return Ok(());
+        }
+        // Lock the tables in a consistent order to avoid deadlocks.
+        let (first, second) = if Arc::as_ptr(&self.store) < Arc::as_ptr(&other.store) {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        let mut first_store = first.store.write();
+        let mut second_store = second.store.write();
+        // Snapshots pin the store after they are registered, so a snapshot
+        // registered after this check pins the swapped store.
+        if self.tree.has_snapshots() || other.tree.has_snapshots() {
+            return Err(Error::SnapshotOpen);
+        }
+        mem::swap(&mut *first_store, &mut *second_store);
+        // Tables are shared by the paths of their stores, so the registry must
+        // follow the stores before they are released.
+        Registry::set_handle(&*first_store, WeakTable::new(first, &first_store));
+        Registry::set_handle(&*second_store, WeakTable::new(second, &second_store));
+
+        // The entries of the new store may have larger LSNs than the ones
+        // assigned so far, so make sure the LSNs are recovered again.
+        let last_lsn = self.sequencer.last_lsn.load(Ordering::Acquire);
+        other.sequencer.observe(last_lsn);
+        let last_lsn = other.sequencer.last_lsn.load(Ordering::Acquire);
+        self.sequencer.observe(last_lsn);
+        self.sequencer.recovered.store(false, Ordering::Release);
+        other.sequencer.recovered.store(false, Ordering::Release);
+        Ok(())
+    }
+
+    /// Returns the current store of the table.
+    fn store(&self) -> Arc<PageStore<E>> {
+        self.store.read().clone()
+    }
+
     /// Begins a tree transaction.
     fn begin(&self) -> TreeTxn<'_, E> {
-        self.tree.begin(self.store.guard())
+        self.tree.begin(self.store.read().guard())
+    }
+
+    /// Begins a tree transaction on `store`, which may have been swapped out
+    /// of the table since it was obtained.
+    fn begin_on(&self, store: &PageStore<E>) -> TreeTxn<'_, E> {
+        self.tree.begin(store.guard())
     }
 
     /// Returns a [`Guard`] that pins the table for user operations.
@@ -58,99 +195,816 @@ impl<E: Env> Table<E> {
         Guard::new(self)
     }
 
+    /// Returns a snapshot that reads the table as of the largest LSN written
+    /// to it so far.
+    ///
+    /// The entries visible to the snapshot are retained until it is dropped,
+    /// even if the safe LSN is advanced past it in the meantime. If the safe
+    /// LSN is already larger than the largest LSN written, the snapshot reads
+    /// at the safe LSN instead.
+    pub async fn snapshot(&self) -> Result<Snapshot<'_, E>> {
+        let lsn = self.last_lsn().await?;
+        Ok(Snapshot::new(self, lsn))
+    }
+
+    /// Begins an optimistic transaction.
+    ///
+    /// See [`ReadTxn`] for details.
+    pub fn begin_txn(&self) -> ReadTxn<'_, E> {
+        ReadTxn::new(self)
+    }
+
     /// Gets the value corresponding to the key.
     pub async fn get(&self, key: &[u8], lsn: u64) -> Result<Option<Vec<u8>>> {
+        self.get_with(key, lsn, |value| value.map(|v| v.to_vec()))
+            .await
+    }
+
+    /// Gets the value corresponding to the key and passes it to `f`.
+    ///
+    /// The value is borrowed from the page that contains it without any
+    /// copy, so it is only valid within `f`. Use [`Table::pin`] to keep values
+    /// beyond a single call.
+    pub async fn get_with<F, R>(&self, key: &[u8], lsn: u64, f: F) -> Result<R>
+    where
+        F: FnOnce(Option<&[u8]>) -> R,
+    {
         let key = Key::new(key, lsn);
         let txn = self.begin();
         let value = txn.get(key).await?;
-        Ok(value.map(|v| v.to_vec()))
+        Ok(f(value))
+    }
+
+    /// Gets the value corresponding to the key without any copy.
+    ///
+    /// The returned value borrows the page that contains it, and keeps the
+    /// page alive until it is dropped, even if the page is consolidated or
+    /// evicted from the cache in the meantime. So the value should be dropped
+    /// as soon as possible, because it also holds back the reclamation of
+    /// other resources of the table, like [`Guard`].
+    pub async fn get_pinned(&self, key: &[u8], lsn: u64) -> Result<Option<PinnedValue<'_, E>>> {
+        let key = Key::new(key, lsn);
+        let txn = self.begin();
+        let value = txn.get(key).await?.map(NonNull::from);
+        Ok(value.map(|value| PinnedValue { _txn: txn, value }))
+    }
+
+    /// Returns true if the key has a value visible to `lsn`.
+    ///
+    /// This stops at the latest version of the key visible to `lsn`, and the
+    /// value is not copied.
+    pub async fn contains_key(&self, key: &[u8], lsn: u64) -> Result<bool> {
+        self.get_with(key, lsn, |value| value.is_some()).await
+    }
+
+    /// Gets the values corresponding to the keys.
+    ///
+    /// The keys are looked up in key order, and keys in the same leaf page
+    /// share a single lookup of the page, which is cheaper than getting them
+    /// one by one. Repeated keys are looked up only once. The values are
+    /// returned in the order of the given keys.
+    pub async fn multi_get(&self, keys: &[&[u8]], lsn: u64) -> Result<Vec<Option<Vec<u8>>>> {
+        let mut order = (0..keys.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&i| keys[i]);
+        // Maps each given key to its index in the sorted, deduplicated keys.
+        let mut slots = vec![0; keys.len()];
+        let mut sorted_keys = Vec::with_capacity(keys.len());
+        for i in order {
+            if sorted_keys.last() != Some(&keys[i]) {
+                sorted_keys.push(keys[i]);
+            }
+            slots[i] = sorted_keys.len() - 1;
+        }
+        let txn = self.begin();
+        let sorted_values = txn.multi_get(&sorted_keys, lsn).await?;
+        let values = slots
+            .into_iter()
+            .map(|slot| sorted_values[slot].map(|v| v.to_vec()))
+            .collect();
+        Ok(values)
     }
 
     /// Puts a key-value entry to the table.
     pub async fn put(&self, key: &[u8], lsn: u64, value: &[u8]) -> Result<()> {
+        self.write(key, lsn, Value::Put(value)).await
+    }
+
+    async fn write(&self, key: &[u8], lsn: u64, value: Value<'_>) -> Result<()> {
         let key = Key::new(key, lsn);
-        let value = Value::Put(value);
         let txn = self.begin();
         txn.write(key, value).await?;
+        self.sequencer.observe(lsn);
         Ok(())
     }
 
+    /// Puts a key-value entry to the table, with a value of `value_size` bytes
+    /// written by `f`.
+    ///
+    /// `f` writes the value directly into the page buffer allocated for the
+    /// entry, so no intermediate buffer is needed. Note that `f` may be called
+    /// more than once if the write conflicts with other writes, and it should
+    /// produce the same value each time.
+    pub async fn put_with<F>(&self, key: &[u8], lsn: u64, value_size: usize, f: F) -> Result<()>
+    where
+        F: FnMut(&mut [u8]),
+    {
+        let key = Key::new(key, lsn);
+        let txn = self.begin();
+        txn.write_with(key, value_size, f).await?;
+        self.sequencer.observe(lsn);
+        Ok(())
+    }
+
+    /// Puts a key-value entry to the table with the given options.
+    ///
+    /// It returns once the entry is as durable as required by
+    /// [`WriteOptions::durability`]. The entry expires at
+    /// [`WriteOptions::expire_at`] if it is set.
+    pub async fn put_with_options(
+        &self,
+        key: &[u8],
+        lsn: u64,
+        value: &[u8],
+        options: &WriteOptions,
+    ) -> Result<()> {
+        let value = match options.expire_at {
+            Some(expire_at) => Value::Expiring(value, expire_at),
+            None => Value::Put(value),
+        };
+        self.write(key, lsn, value).await?;
+        self.wait_for_durability(options.durability).await;
+        Ok(())
+    }
+
+    /// Deletes the entry corresponding to the key from the table with the
+    /// given options.
+    ///
+    /// It returns once the delete is as durable as required by
+    /// [`WriteOptions::durability`].
+    pub async fn delete_with_options(
+        &self,
+        key: &[u8],
+        lsn: u64,
+        options: &WriteOptions,
+    ) -> Result<()> {
+        self.delete(key, lsn).await?;
+        self.wait_for_durability(options.durability).await;
+        Ok(())
+    }
+
+    async fn wait_for_durability(&self, durability: Durability) {
+        match durability {
+            Durability::Memory => {}
+            // Page files are synced when they are flushed.
+            Durability::Flushed | Durability::Synced => {
+                self.store().wait_for_writes_flushed().await;
+            }
+        }
+    }
+
     /// Deletes the entry corresponding to the key from the table.
     pub async fn delete(&self, key: &[u8], lsn: u64) -> Result<()> {
+        self.write(key, lsn, Value::Delete).await
+    }
+
+    /// Merges the operand into the value of the key with
+    /// [`Options::merge_operator`].
+    ///
+    /// The operand is written at `lsn` without reading the value of the key.
+    /// Reads at `lsn` or above merge it into the value of the previous version
+    /// of the key, along with the other operands written since that version,
+    /// and consolidations merge it once it is invisible to the safe LSN. A
+    /// merged value expires with the value it is merged into.
+    ///
+    /// Returns [`Error::InvalidArgument`] if no merge operator is configured.
+    pub async fn merge(&self, key: &[u8], lsn: u64, operand: &[u8]) -> Result<()> {
         let key = Key::new(key, lsn);
-        let value = Value::Delete;
         let txn = self.begin();
-        txn.write(key, value).await?;
+        txn.merge(key, operand).await?;
+        self.sequencer.observe(lsn);
+        Ok(())
+    }
+
+    /// Deletes the entries in the key range `[start, end)` from the table.
+    ///
+    /// A range tombstone is written at `lsn`, so gets of the keys in the range
+    /// at `lsn` or above return `None` for all entries whose LSNs are not
+    /// larger than `lsn`, including the ones written after this returns. The
+    /// tombstone is written to each leaf page in the range one by one, so
+    /// reads may see it in some pages before this returns.
+    pub async fn delete_range(&self, start: &[u8], end: &[u8], lsn: u64) -> Result<()> {
+        let txn = self.begin();
+        txn.delete_range(start, end, lsn).await?;
+        self.sequencer.observe(lsn);
+        Ok(())
+    }
+
+    /// Applies the writes in the batch to the table with the same LSN.
+    ///
+    /// The writes share a single transaction and are installed one by one in
+    /// the order they are added, so a later write of a key overrides earlier
+    /// ones. Since they are not installed atomically, reads at `lsn` or above
+    /// may see some of the writes before this returns.
+    ///
+    /// If a write fails, the writes installed so far are rolled back by writing
+    /// the values visible right below `lsn` back at `lsn`, and the error of the
+    /// write is returned. The rollback assumes that no other writes of the keys
+    /// use `lsn`. It may fail for some keys as well (e.g. if the table runs out
+    /// of memory), in which case the writes of those keys remain installed and
+    /// the failures are logged.
+    pub async fn write_batch(&self, batch: WriteBatch, lsn: u64) -> Result<()> {
+        let txn = self.begin();
+        for (i, (key, value)) in batch.iter().enumerate() {
+            let value = value.map_or(Value::Delete, Value::Put);
+            if let Err(err) = txn.write(Key::new(key, lsn), value).await {
+                let installed = batch.iter().take(i).map(|(key, _)| key);
+                Self::rollback(&txn, installed, lsn).await;
+                return Err(err);
+            }
+        }
+        self.sequencer.observe(lsn);
+        Ok(())
+    }
+
+    /// Loads the key-value entries, which must be sorted by key, into the
+    /// table with the same LSN.
+    ///
+    /// If the table is empty, the entries are built into full leaf pages and
+    /// index pages, which are installed under a new root without any delta
+    /// pages. The pages are written in batches, so the entries can be larger
+    /// than a write buffer. Otherwise, or if the table is written concurrently,
+    /// each run of entries in the same leaf page is written as one delta page.
+    /// Either way, this is much faster than putting the entries one by one, but
+    /// unlike [`Self::write_batch`], the written entries are not rolled back on
+    /// errors.
+    ///
+    /// Returns [`Error::InvalidArgument`] if the keys are not strictly
+    /// increasing, in which case nothing is written.
+    pub async fn ingest<'k, I>(&self, sorted: I, lsn: u64) -> Result<()>
+    where
+        I: IntoIterator<Item = (&'k [u8], &'k [u8])>,
+    {
+        let entries: Vec<_> = sorted
+            .into_iter()
+            .map(|(key, value)| (Key::new(key, lsn), Value::Put(value)))
+            .collect();
+        if entries.windows(2).any(|w| w[0].0.raw >= w[1].0.raw) {
+            return Err(Error::InvalidArgument);
+        }
+        if entries.is_empty() {
+            return Ok(());
+        }
+        self.begin().ingest(&entries).await?;
+        self.sequencer.observe(lsn);
+        Ok(())
+    }
+
+    /// Writes the values of the keys visible right below `lsn` back at `lsn`.
+    ///
+    /// Keys that fail to roll back are logged and skipped, so that the rest of
+    /// the keys are still rolled back.
+    async fn rollback<'k>(txn: &TreeTxn<'_, E>, keys: impl Iterator<Item = &'k [u8]>, lsn: u64) {
+        for key in keys {
+            if let Err(err) = Self::rollback_key(txn, key, lsn).await {
+                warn!("failed to roll back key {key:?} at LSN {lsn}: {err}");
+            }
+        }
+    }
+
+    async fn rollback_key(txn: &TreeTxn<'_, E>, key: &[u8], lsn: u64) -> Result<()> {
+        // The version right below `lsn` may be below the safe LSN, so read it
+        // without rejecting old reads. The value is written back as it is
+        // stored, so that an expiring value keeps its expiration.
+        let prev = match lsn.checked_sub(1) {
+            Some(prev_lsn) => txn.get_entry(Key::new(key, prev_lsn)).await?,
+            None => None,
+        };
+        let value = prev.map_or(Value::Delete, |(_, value)| value);
+        txn.write(Key::new(key, lsn), value).await
+    }
+
+    /// Puts a key-value entry to the table with an LSN assigned by the table.
+    ///
+    /// LSNs are assigned in increasing order, larger than the LSNs of all the
+    /// entries in the table when the first LSN is assigned. So the latest
+    /// entry of a key is always visible to reads at `u64::MAX`, and callers
+    /// don't need to track LSNs. This should not be mixed with writes with
+    /// explicit LSNs after the first LSN is assigned.
+    ///
+    /// Returns the assigned LSN.
+    pub async fn put_auto(&self, key: &[u8], value: &[u8]) -> Result<u64> {
+        let lsn = self.next_lsn().await?;
+        self.put(key, lsn, value).await?;
+        Ok(lsn)
+    }
+
+    /// Deletes the entry corresponding to the key from the table with an LSN
+    /// assigned by the table.
+    ///
+    /// See [`Self::put_auto`] for how LSNs are assigned. Returns the assigned
+    /// LSN.
+    pub async fn delete_auto(&self, key: &[u8]) -> Result<u64> {
+        let lsn = self.next_lsn().await?;
+        self.delete(key, lsn).await?;
+        Ok(lsn)
+    }
+
+    async fn next_lsn(&self) -> Result<u64> {
+        self.recover_lsn().await?;
+        Ok(self.sequencer.last_lsn.fetch_add(1, Ordering::AcqRel) + 1)
+    }
+
+    /// Returns the largest LSN written to the table so far.
+    async fn last_lsn(&self) -> Result<u64> {
+        self.recover_lsn().await?;
+        Ok(self.sequencer.last_lsn.load(Ordering::Acquire))
+    }
+
+    async fn recover_lsn(&self) -> Result<()> {
+        let sequencer = &self.sequencer;
+        if !sequencer.recovered.load(Ordering::Acquire) {
+            // Concurrent recoveries are fine, since the LSNs written in the
+            // meantime are already covered by `last_lsn`.
+            let max_lsn = self.max_lsn().await?;
+            sequencer.last_lsn.fetch_max(max_lsn, Ordering::AcqRel);
+            sequencer.recovered.store(true, Ordering::Release);
+        }
         Ok(())
     }
 
+    /// Returns the largest LSN of the entries in the table.
+    async fn max_lsn(&self) -> Result<u64> {
+        let txn = self.begin();
+        let options = ReadOptions {
+            fill_cache: false,
+            ..Default::default()
+        };
+        let mut iter = TreeIter::new(&txn, options);
+        let mut max_lsn = 0;
+        while let Some(lsn) = iter.next_page_max_lsn().await? {
+            max_lsn = max_lsn.max(lsn);
+        }
+        Ok(max_lsn)
+    }
+
+    /// Returns a cursor over the entries in the key range `[start, end)`
+    /// visible to `lsn`.
+    ///
+    /// If `end` is `None`, the range extends to the end of the table. The
+    /// table is pinned only while a page is read, so the cursor doesn't hold
+    /// resources between pages, and entries written in the meantime may be
+    /// observed if they are visible to `lsn`. The cursor keeps reading the
+    /// contents the table has when it is created, even if the table is
+    /// swapped in the meantime, see [`Self::swap`].
+    pub fn scan(&self, start: &[u8], end: Option<&[u8]>, lsn: u64) -> Cursor<'_, E> {
+        let options = ScanOptions {
+            start: Some(start.to_vec()),
+            end: end.map(|end| end.to_vec()),
+            lsn,
+        };
+        self.scan_with_options(options)
+    }
+
+    /// Returns a cursor over the entries in the table with the given options.
+    ///
+    /// See [`Self::scan`] for details.
+    pub fn scan_with_options(&self, options: ScanOptions) -> Cursor<'_, E> {
+        Cursor::new(self, self.store(), options)
+    }
+
+    /// Returns a cursor over the entries in the key range between `start` and
+    /// `end` visible to `lsn`.
+    ///
+    /// Unlike [`Self::scan`], both bounds can be inclusive, exclusive, or
+    /// unbounded.
+    pub fn range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>, lsn: u64) -> Cursor<'_, E> {
+        // The smallest key after `key` is `key` followed by a zero byte.
+        let after = |key: &[u8]| [key, &[0]].concat();
+        let options = ScanOptions {
+            start: match start {
+                Bound::Included(start) => Some(start.to_vec()),
+                Bound::Excluded(start) => Some(after(start)),
+                Bound::Unbounded => None,
+            },
+            end: match end {
+                Bound::Included(end) => Some(after(end)),
+                Bound::Excluded(end) => Some(end.to_vec()),
+                Bound::Unbounded => None,
+            },
+            lsn,
+        };
+        self.scan_with_options(options)
+    }
+
+    /// Returns a cursor over the entries whose keys start with `prefix`
+    /// visible to `lsn`.
+    ///
+    /// See [`Self::scan`] for details.
+    pub fn scan_prefix(&self, prefix: &[u8], lsn: u64) -> Cursor<'_, E> {
+        let options = ScanOptions {
+            start: Some(prefix.to_vec()),
+            end: prefix_end(prefix),
+            lsn,
+        };
+        self.scan_with_options(options)
+    }
+
+    /// Returns a cursor over the changes made to the table with LSNs in
+    /// `(since_lsn, lsn]`.
+    ///
+    /// Every version written in the window is returned, without collapsing
+    /// the versions of the same key, in key order and from the latest to the
+    /// oldest version of each key. Deletes are reported as
+    /// [`ChangeKind::Delete`]. Like [`Self::scan`], the table is pinned only
+    /// while a page is read, and the cursor keeps reading the contents the
+    /// table has when it is created.
+    ///
+    /// Note that `since_lsn` should not be smaller than the [`safe_lsn`] of
+    /// the table, otherwise some versions may have been dropped.
+    ///
+    /// [`safe_lsn`]: Self::safe_lsn
+    pub fn scan_changes(&self, since_lsn: u64, lsn: u64) -> ChangeCursor<'_, E> {
+        ChangeCursor::new(self, self.store(), since_lsn, lsn)
+    }
+
+    /// Compacts the pages that overlap with the key range `[start, end)`.
+    ///
+    /// Delta pages are consolidated into base pages, and pages that are too
+    /// large are split. If `end` is `None`, the range extends to the end of
+    /// the table.
+    ///
+    /// Returns the number of consolidated pages. Pages changed by concurrent
+    /// writes during the compaction are retried.
+    pub async fn compact_range(&self, start: &[u8], end: Option<&[u8]>) -> Result<usize> {
+        let txn = self.begin();
+        txn.compact_range(start, end).await
+    }
+
+    /// Tries to reclaim memory that is no longer used by the table.
+    ///
+    /// Memory is reclaimed in the background as well, but long-lived guards
+    /// or iterators can hold it back. Check `store.buffer_set.pending_reclaims`
+    /// in [`Table::stats`] to see if reclamation falls behind.
+    pub fn collect(&self) {
+        self.store.read().collect();
+    }
+
+    /// Verifies the checksums of all pages in the table files.
+    ///
+    /// Unlike a full verification, this only reads the pages and checks their
+    /// checksums, without validating the tree structure. Pages in memory are
+    /// not verified.
+    pub async fn verify_checksums(&self) -> Result<ChecksumReport> {
+        Ok(self.store().verify_checksums().await?)
+    }
+
     /// Returns the statistics of the table.
     pub fn stats(&self) -> TableStats {
         TableStats {
             tree: self.tree.stats(),
-            store: self.store.stats(),
+            store: self.store.read().stats(),
         }
     }
 
+    /// Returns the statistics of the table in the Prometheus text exposition
+    /// format.
+    ///
+    /// Metric names are prefixed with `photondb_`. Counters are cumulative
+    /// since the table was opened.
+    #[cfg(feature = "prometheus")]
+    pub fn metrics_prometheus(&self) -> String {
+        super::prometheus::render(&self.stats())
+    }
+
+    /// Returns the capacity of the page cache in bytes.
+    pub fn cache_capacity(&self) -> usize {
+        self.store.read().cache_capacity()
+    }
+
+    /// Returns the memory used by the page cache in bytes.
+    ///
+    /// This includes the pages pinned by readers, so it may exceed the
+    /// capacity temporarily.
+    pub fn cache_usage(&self) -> usize {
+        self.store.read().cache_usage()
+    }
+
+    /// Sets the capacity of the page cache in bytes.
+    ///
+    /// If the cache is over the new capacity, unpinned pages are evicted
+    /// immediately.
+    pub fn set_cache_capacity(&self, capacity: usize) {
+        self.store.read().set_cache_capacity(capacity)
+    }
+
+    /// Returns the id, the smallest key and the largest key of each page file.
+    ///
+    /// The keys are bounds of the keys written to the file, some of them may
+    /// have been removed or superseded since. Files without any keys are
+    /// skipped.
+    pub fn file_key_ranges(&self) -> Vec<(u32, Vec<u8>, Vec<u8>)> {
+        self.store.read().file_key_ranges()
+    }
+
+    /// Returns the ids of page files in the table directory that the table
+    /// doesn't reference.
+    ///
+    /// Orphan files are removed when the table is opened, but files left by
+    /// other processes or failed jobs may still waste space. Files that are
+    /// being written or waiting to be removed are not orphans.
+    pub async fn find_orphan_files(&self) -> Result<Vec<u32>> {
+        Ok(self.store().find_orphan_files().await?)
+    }
+
+    /// Removes the page files returned by [`Self::find_orphan_files`].
+    ///
+    /// Returns the ids of the removed files.
+    pub async fn gc_orphans(&self) -> Result<Vec<u32>> {
+        Ok(self.store().remove_orphan_files().await?)
+    }
+
     /// Returns the minimal LSN that the table can safely read with.
     ///
     /// The table guarantees that entries visible to the returned LSN are
-    /// retained for reads.
+    /// retained for reads. Reads with smaller LSNs fail with
+    /// [`Error::SnapshotTooOld`] if
+    /// [`Options::reject_reads_below_safe_lsn`] is set.
     pub fn safe_lsn(&self) -> u64 {
         self.tree.safe_lsn()
     }
 
+    /// Returns true if the table is poisoned.
+    ///
+    /// A table is poisoned once a corrupted inner page is found, after which
+    /// all operations that go through the tree fail with
+    /// [`Error::Corrupted`] until the table is reopened.
+    pub fn is_poisoned(&self) -> bool {
+        self.tree.is_poisoned()
+    }
+
     /// Updates the minimal LSN that the table can safely read with.
     ///
     /// The safe LSN must be increasing, so updating it with a smaller value has
     /// no effect. When the safe LSN is advanced, the table will gradually drop
-    /// entries that are not visible to the LSN anymore.
+    /// entries that are not visible to the LSN anymore, except for those
+    /// visible to open snapshots, see [`Self::snapshot`].
     pub fn set_safe_lsn(&self, lsn: u64) {
         self.tree.set_safe_lsn(lsn);
     }
 
     /// Flush all write buffer data.
+    ///
+    /// Pages are written to page files as they are, so delta pages are made
+    /// durable without being consolidated. Readers follow delta chains across
+    /// page files, and the chains are consolidated later, once they grow too
+    /// long or by [`Self::compact_range`].
     pub async fn flush(&self, opts: &FlushOptions) {
-        self.store.flush(opts).await;
+        self.store().flush(opts).await;
     }
 
     /// Wait all pending reclaiming to finish.
     pub async fn wait_for_reclaiming(&self) {
-        self.store.wait_for_reclaiming().await;
+        self.store().wait_for_reclaiming().await;
+    }
+}
+
+/// A weak reference to a table, which is registered to share the table.
+#[derive(Clone)]
+struct WeakTable<E: Env> {
+    tree: Weak<Tree>,
+    store: Weak<RwLock<Arc<PageStore<E>>>>,
+    sequencer: Weak<Sequencer>,
+    // The store opened with the table.
+    opened_store: Weak<PageStore<E>>,
+}
+
+impl<E: Env> WeakTable<E> {
+    fn new(table: &Table<E>, store: &Arc<PageStore<E>>) -> Self {
+        Self {
+            tree: Arc::downgrade(&table.tree),
+            store: Arc::downgrade(&table.store),
+            sequencer: Arc::downgrade(&table.sequencer),
+            opened_store: Arc::downgrade(store),
+        }
+    }
+
+    /// Returns the table if it is still alive and it still owns the store it
+    /// is registered with.
+    fn upgrade(&self) -> Option<Table<E>> {
+        let table = Table {
+            tree: self.tree.upgrade()?,
+            store: self.store.upgrade()?,
+            sequencer: self.sequencer.upgrade()?,
+        };
+        let opened_store = self.opened_store.upgrade()?;
+        Arc::ptr_eq(&table.store(), &opened_store).then_some(table)
     }
 }
 
 /// A handle that holds some resources of a table for user operations.
 pub struct Guard<'a, E: Env> {
     table: &'a Table<E>,
+    // The store that the guard is pinned on.
+    store: Arc<PageStore<E>>,
     txn: TreeTxn<'a, E>,
 }
 
 impl<'a, E: Env> Guard<'a, E> {
     fn new(table: &'a Table<E>) -> Self {
-        Self {
-            table,
-            txn: table.begin(),
-        }
+        let store = table.store();
+        let txn = table.begin_on(&store);
+        Self { table, store, txn }
     }
 
     /// Re-pins the table so that the current pinned resources can be released.
     pub fn repin(&mut self) {
-        self.txn = self.table.begin();
+        self.store = self.table.store();
+        self.txn = self.table.begin_on(&self.store);
     }
 
     /// Gets the value corresponding to the key.
     //
     /// On success, if the value is found, returns [`Option::Some`] with the
-    /// value; if the value is not found, returns [`Option::None`].
+    /// value; if the value is not found, returns [`Option::None`]. Values
+    /// merged from merge operands are kept until the guard is repinned or
+    /// dropped.
     pub async fn get(&self, key: &[u8], lsn: u64) -> Result<Option<&[u8]>> {
         let key = Key::new(key, lsn);
         Ok(self.txn.get(key).await?)
     }
 
     /// Returns an iterator over pages in the table.
+    ///
+    /// The iterator reads the latest entries of each page, the same as
+    /// [`Isolation::Latest`].
     pub fn pages(&self) -> Pages<'_, 'a, E> {
-        Pages::new(&self.txn)
+        Pages::new(&self.txn, u64::MAX)
+    }
+
+    /// Returns an iterator over pages in the table with the given isolation.
+    ///
+    /// With [`Isolation::Snapshot`], the iterator captures the largest LSN
+    /// written to the table so far, and only reads entries visible at that
+    /// LSN. Note that writes with explicit LSNs not larger than the captured
+    /// one may still be observed if they are made during the scan.
+    pub async fn iter(&self, isolation: Isolation) -> Result<Pages<'_, 'a, E>> {
+        let read_lsn = match isolation {
+            Isolation::Snapshot => self.table.last_lsn().await?,
+            Isolation::Latest => u64::MAX,
+        };
+        Ok(Pages::new(&self.txn, read_lsn))
+    }
+}
+
+/// A consistent view of a table as of an LSN.
+///
+/// Like a [`Guard`], a snapshot pins the table while it is alive, so it should
+/// not be held for long.
+pub struct Snapshot<'a, E: Env> {
+    guard: Guard<'a, E>,
+    lsn: u64,
+}
+
+impl<'a, E: Env> Snapshot<'a, E> {
+    fn new(table: &'a Table<E>, lsn: u64) -> Self {
+        // Registers the snapshot before pinning the store, see `Table::swap`.
+        let lsn = table.tree.acquire_snapshot(lsn);
+        Self {
+            guard: Guard::new(table),
+            lsn,
+        }
+    }
+
+    /// Returns the LSN that the snapshot reads at.
+    pub fn lsn(&self) -> u64 {
+        self.lsn
+    }
+
+    /// Gets the value corresponding to the key as of the snapshot.
+    pub async fn get(&self, key: &[u8]) -> Result<Option<&[u8]>> {
+        self.guard.get(key, self.lsn).await
+    }
+
+    /// Returns an iterator over pages in the table as of the snapshot.
+    pub fn pages(&self) -> Pages<'_, 'a, E> {
+        Pages::new(&self.guard.txn, self.lsn)
+    }
+
+    /// Returns a cursor over the entries in the key range `[start, end)` as of
+    /// the snapshot.
+    ///
+    /// Unlike [`Self::pages`], the cursor only pins the table while a page is
+    /// read, see [`Table::scan`].
+    pub fn scan(&self, start: &[u8], end: Option<&[u8]>) -> Cursor<'_, E> {
+        let options = ScanOptions {
+            start: Some(start.to_vec()),
+            end: end.map(|end| end.to_vec()),
+            lsn: self.lsn,
+        };
+        Cursor::new(self.guard.table, self.guard.store.clone(), options)
+    }
+}
+
+impl<'a, E: Env> Drop for Snapshot<'a, E> {
+    fn drop(&mut self) {
+        self.guard.table.tree.release_snapshot(self.lsn);
+    }
+}
+
+/// An optimistic transaction that reads the latest entries of a table, and
+/// writes entries only if the keys it reads have not changed since.
+///
+/// Conflicts are detected against transactions committed by the same table
+/// and writes that complete before the validation of the commit. Note that
+/// the writes of a commit are not atomic to concurrent readers, they may
+/// observe some of the writes before the commit returns.
+///
+/// All reads are made on the contents the table has when the transaction
+/// begins, and the commit fails with [`Error::Conflict`] if the table has
+/// been swapped since, see [`Table::swap`].
+pub struct ReadTxn<'a, E: Env> {
+    table: &'a Table<E>,
+    // The store that the transaction reads.
+    store: Arc<PageStore<E>>,
+    // The keys read and the LSNs of their versions observed by the reads.
+    reads: Vec<(Vec<u8>, Option<u64>)>,
+}
+
+impl<'a, E: Env> ReadTxn<'a, E> {
+    fn new(table: &'a Table<E>) -> Self {
+        Self {
+            table,
+            store: table.store(),
+            reads: Vec::new(),
+        }
+    }
+
+    /// Gets the latest value corresponding to the key, and adds the key to the
+    /// read set of the transaction.
+    pub async fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let txn = self.table.begin_on(&self.store);
+        let version = txn.get_version(Key::new(key, u64::MAX)).await?;
+        if !self.reads.iter().any(|(k, _)| k == key) {
+            self.reads.push((key.to_vec(), version.map(|(lsn, _)| lsn)));
+        }
+        Ok(version.and_then(|(_, value)| value.map(|v| v.to_vec())))
+    }
+
+    /// Applies the writes if none of the keys read by the transaction have
+    /// changed since they were read.
+    ///
+    /// Each write puts the value to the key, or deletes the key if the value
+    /// is `None`. All the writes are made with the same LSN assigned by the
+    /// table, see [`Table::put_auto`] for how LSNs are assigned, so each key
+    /// should be written at most once.
+    ///
+    /// Returns the assigned LSN on success, or [`Error::Conflict`] if any key
+    /// read has changed or the table has been swapped, in which case nothing
+    /// is written.
+    pub async fn commit_if_unchanged(self, writes: &[(&[u8], Option<&[u8]>)]) -> Result<u64> {
+        let table = self.table;
+        let _lock = table.sequencer.commit_lock.lock().await;
+        if !Arc::ptr_eq(&table.store(), &self.store) {
+            return Err(Error::Conflict);
+        }
+        for (key, read_lsn) in &self.reads {
+            let txn = table.begin_on(&self.store);
+            let version = txn.get_version(Key::new(key, u64::MAX)).await?;
+            if version.map(|(lsn, _)| lsn) != *read_lsn {
+                return Err(Error::Conflict);
+            }
+        }
+        let lsn = table.next_lsn().await?;
+        for &(key, value) in writes {
+            match value {
+                Some(value) => table.put(key, lsn, value).await?,
+                None => table.delete(key, lsn).await?,
+            }
+        }
+        Ok(lsn)
+    }
+}
+
+/// A value that borrows the page that contains it.
+///
+/// See [`Table::get_pinned`] for details.
+pub struct PinnedValue<'a, E: Env> {
+    // Keeps the page that contains the value alive.
+    _txn: TreeTxn<'a, E>,
+    value: NonNull<[u8]>,
+}
+
+// SAFETY: the value is immutable and lives as long as the transaction, which
+// can be sent and shared across threads.
+unsafe impl<E: Env> Send for PinnedValue<'_, E> {}
+unsafe impl<E: Env> Sync for PinnedValue<'_, E> {}
+
+impl<E: Env> Deref for PinnedValue<'_, E> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // SAFETY: the value is valid until the transaction is dropped.
+        unsafe { self.value.as_ref() }
     }
 }
 
@@ -160,16 +1014,190 @@ pub struct Pages<'a, 't: 'a, E: Env> {
 }
 
 impl<'a, 't: 'a, E: Env> Pages<'a, 't, E> {
-    fn new(txn: &'a TreeTxn<'t, E>) -> Self {
+    fn new(txn: &'a TreeTxn<'t, E>, read_lsn: u64) -> Self {
+        let options = ReadOptions {
+            max_lsn: read_lsn,
+            ..Default::default()
+        };
         Self {
-            iter: TreeIter::new(txn, ReadOptions::default()),
+            iter: TreeIter::new(txn, options),
         }
     }
 
+    /// Returns the largest LSN of the entries that the iterator reads.
+    pub fn read_lsn(&self) -> u64 {
+        self.iter.read_lsn()
+    }
+
     /// Returns the next page in the table.
     pub async fn next(&mut self) -> Result<Option<PageIter<'_>>> {
         Ok(self.iter.next_page().await?)
     }
+
+    /// Converts the iterator into a stream of the entries in the table.
+    ///
+    /// Pages are read lazily as the stream is polled, and only the entries of
+    /// the current page are buffered. Dropping the stream stops the scan and
+    /// releases the page, but the table is still pinned by the [`Guard`].
+    pub fn into_stream(self) -> LocalBoxStream<'a, Result<(Vec<u8>, Vec<u8>)>> {
+        stream::try_unfold(
+            (self, VecDeque::new()),
+            |(mut pages, mut entries)| async move {
+                loop {
+                    if let Some(entry) = entries.pop_front() {
+                        return Ok::<_, Error>(Some((entry, (pages, entries))));
+                    }
+                    match pages.next().await? {
+                        Some(page) => entries.extend(page.map(|(k, v)| (k.to_vec(), v.to_vec()))),
+                        None => return Ok(None),
+                    }
+                }
+            },
+        )
+        .boxed_local()
+    }
+}
+
+/// A cursor over the entries in a key range of a table.
+pub struct Cursor<'a, E: Env> {
+    table: &'a Table<E>,
+    // The store that the cursor reads, which stays the same across swaps.
+    store: Arc<PageStore<E>>,
+    // The start key of the pages to read, or `None` if the range is exhausted.
+    next: Option<Vec<u8>>,
+    start: Vec<u8>,
+    end: Option<Vec<u8>>,
+    lsn: u64,
+    entries: VecDeque<(Vec<u8>, Vec<u8>)>,
+}
+
+impl<'a, E: Env> Cursor<'a, E> {
+    fn new(table: &'a Table<E>, store: Arc<PageStore<E>>, options: ScanOptions) -> Self {
+        let mut cursor = Self {
+            table,
+            store,
+            next: None,
+            start: options.start.unwrap_or_default(),
+            end: options.end,
+            lsn: options.lsn,
+            entries: VecDeque::new(),
+        };
+        cursor.seek(&[]);
+        cursor
+    }
+
+    /// Repositions the cursor to the first entry in the range with a key no
+    /// less than `target`.
+    ///
+    /// The target can be before or after the current position. If it is
+    /// before the start of the range, the cursor is repositioned to the start.
+    pub fn seek(&mut self, target: &[u8]) {
+        let target = target.max(self.start.as_slice());
+        self.entries.clear();
+        self.next = match &self.end {
+            Some(end) if end.as_slice() <= target => None,
+            _ => Some(target.to_vec()),
+        };
+    }
+
+    /// Returns the next entry in the range.
+    pub async fn next(&mut self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        loop {
+            if let Some(entry) = self.entries.pop_front() {
+                return Ok(Some(entry));
+            }
+            let start = match self.next.take() {
+                Some(start) => start,
+                None => return Ok(None),
+            };
+            // Re-pins the table for each page, so that the resources pinned by
+            // the previous page can be released.
+            let txn = self.table.begin_on(&self.store);
+            let (page, next) = txn.read_leaf(&start, self.lsn).await?;
+            let end = self.end.as_deref();
+            let mut reached_end = false;
+            for (k, v) in page {
+                if end.map_or(false, |end| k >= end) {
+                    reached_end = true;
+                    break;
+                }
+                self.entries.push_back((k.to_vec(), v.to_vec()));
+            }
+            if !reached_end {
+                self.next = next
+                    .filter(|next| end.map_or(true, |end| *next < end))
+                    .map(|next| next.to_vec());
+            }
+        }
+    }
+
+    /// Converts the cursor into a stream of the remaining entries in the range.
+    ///
+    /// Like [`Self::next`], pages are read lazily as the stream is polled, and
+    /// the table is pinned only while a page is read.
+    pub fn into_stream(self) -> LocalBoxStream<'a, Result<(Vec<u8>, Vec<u8>)>> {
+        stream::try_unfold(self, |mut cursor| async move {
+            Ok::<_, Error>(cursor.next().await?.map(|entry| (entry, cursor)))
+        })
+        .boxed_local()
+    }
+}
+
+/// A cursor over the changes made to a table in an LSN window.
+///
+/// See [`Table::scan_changes`] for details.
+pub struct ChangeCursor<'a, E: Env> {
+    table: &'a Table<E>,
+    // The store that the cursor reads, see `Cursor`.
+    store: Arc<PageStore<E>>,
+    // The start key of the pages to read, or `None` if the table is exhausted.
+    next: Option<Vec<u8>>,
+    since_lsn: u64,
+    lsn: u64,
+    changes: VecDeque<(Vec<u8>, u64, ChangeKind)>,
+}
+
+impl<'a, E: Env> ChangeCursor<'a, E> {
+    fn new(table: &'a Table<E>, store: Arc<PageStore<E>>, since_lsn: u64, lsn: u64) -> Self {
+        Self {
+            table,
+            store,
+            next: Some(Vec::new()),
+            since_lsn,
+            lsn,
+            changes: VecDeque::new(),
+        }
+    }
+
+    /// Returns the next change, with the key and the LSN of the change.
+    pub async fn next(&mut self) -> Result<Option<(Vec<u8>, u64, ChangeKind)>> {
+        loop {
+            if let Some(change) = self.changes.pop_front() {
+                return Ok(Some(change));
+            }
+            let start = match self.next.take() {
+                Some(start) => start,
+                None => return Ok(None),
+            };
+            let txn = self.table.begin_on(&self.store);
+            let (page, next) = txn
+                .read_leaf_changes(&start, self.since_lsn, self.lsn)
+                .await?;
+            self.changes
+                .extend(page.map(|(k, v)| (k.raw.to_vec(), k.lsn, v.into())));
+            self.next = next.map(|next| next.to_vec());
+        }
+    }
+
+    /// Converts the cursor into a stream of the remaining changes.
+    ///
+    /// See [`Cursor::into_stream`] for details.
+    pub fn into_stream(self) -> LocalBoxStream<'a, Result<(Vec<u8>, u64, ChangeKind)>> {
+        stream::try_unfold(self, |mut cursor| async move {
+            Ok::<_, Error>(cursor.next().await?.map(|change| (change, cursor)))
+        })
+        .boxed_local()
+    }
 }
 
 /// Statstistic of a table.
@@ -226,3 +1254,12 @@ impl std::fmt::Display for TableStats {
         )
     }
 }
+
+/// Returns the smallest key that is larger than all keys starting with
+/// `prefix`, or `None` if there is no such key.
+fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+    let last = prefix.iter().rposition(|&b| b != u8::MAX)?;
+    let mut end = prefix[..=last].to_vec();
+    end[last] += 1;
+    Some(end)
+}
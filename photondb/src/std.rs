@@ -11,7 +11,7 @@
 
 use std::{
     future::Future,
-    ops::Deref,
+    ops::{Bound, Deref},
     path::Path,
     pin::Pin,
     task::{Context, Poll},
@@ -19,7 +19,10 @@ use std::{
 
 use futures::task::noop_waker_ref;
 
-use crate::{env::Std, raw, PageIter, Result, TableOptions};
+use crate::{
+    env::Std, raw, ChangeKind, ChecksumReport, Isolation, PageIter, Result, ScanOptions,
+    TableOptions, WriteBatch, WriteOptions,
+};
 
 /// A reference to a latch-free, log-structured table that stores sorted
 /// key-value entries.
@@ -38,6 +41,17 @@ impl Table {
         Ok(Self(table))
     }
 
+    /// Opens a table in the path with the default options and the given page
+    /// cache capacity in bytes.
+    pub fn open_with_cache_capacity<P: AsRef<Path>>(
+        path: P,
+        cache_capacity: usize,
+    ) -> Result<Self> {
+        let mut options = TableOptions::default();
+        options.page_store.cache_capacity = cache_capacity;
+        Self::open(path, options)
+    }
+
     /// Closes the table if this is the only reference to it.
     ///
     /// This is a synchronous version of [`raw::Table::close`].
@@ -50,6 +64,19 @@ impl Table {
         Guard(self.0.pin())
     }
 
+    /// Begins an optimistic transaction.
+    pub fn begin_txn(&self) -> ReadTxn<'_> {
+        ReadTxn(self.0.begin_txn())
+    }
+
+    /// Returns a snapshot that reads the table as of the largest LSN written
+    /// to it so far.
+    ///
+    /// This is a synchronous version of [`raw::Table::snapshot`].
+    pub fn snapshot(&self) -> Result<Snapshot<'_>> {
+        poll(self.0.snapshot()).map(Snapshot)
+    }
+
     /// Gets the value corresponding to the key.
     ///
     /// This is a synchronous version of [`raw::Table::get`].
@@ -57,6 +84,37 @@ impl Table {
         poll(self.0.get(key, lsn))
     }
 
+    /// Gets the value corresponding to the key and passes it to `f`.
+    ///
+    /// This is a synchronous version of [`raw::Table::get_with`].
+    pub fn get_with<F, R>(&self, key: &[u8], lsn: u64, f: F) -> Result<R>
+    where
+        F: FnOnce(Option<&[u8]>) -> R,
+    {
+        poll(self.0.get_with(key, lsn, f))
+    }
+
+    /// Gets the value corresponding to the key without any copy.
+    ///
+    /// This is a synchronous version of [`raw::Table::get_pinned`].
+    pub fn get_pinned(&self, key: &[u8], lsn: u64) -> Result<Option<raw::PinnedValue<'_, Std>>> {
+        poll(self.0.get_pinned(key, lsn))
+    }
+
+    /// Returns true if the key has a value visible to `lsn`.
+    ///
+    /// This is a synchronous version of [`raw::Table::contains_key`].
+    pub fn contains_key(&self, key: &[u8], lsn: u64) -> Result<bool> {
+        poll(self.0.contains_key(key, lsn))
+    }
+
+    /// Gets the values corresponding to the keys.
+    ///
+    /// This is a synchronous version of [`raw::Table::multi_get`].
+    pub fn multi_get(&self, keys: &[&[u8]], lsn: u64) -> Result<Vec<Option<Vec<u8>>>> {
+        poll(self.0.multi_get(keys, lsn))
+    }
+
     /// Puts a key-value entry to the table.
     ///
     /// This is a synchronous version of [`raw::Table::put`].
@@ -64,12 +122,148 @@ impl Table {
         poll(self.0.put(key, lsn, value))
     }
 
+    /// Puts a key-value entry to the table, with a value written by `f`.
+    ///
+    /// This is a synchronous version of [`raw::Table::put_with`].
+    pub fn put_with<F>(&self, key: &[u8], lsn: u64, value_size: usize, f: F) -> Result<()>
+    where
+        F: FnMut(&mut [u8]),
+    {
+        poll(self.0.put_with(key, lsn, value_size, f))
+    }
+
+    /// Puts a key-value entry to the table with the given options.
+    ///
+    /// This is a synchronous version of [`raw::Table::put_with_options`].
+    pub fn put_with_options(
+        &self,
+        key: &[u8],
+        lsn: u64,
+        value: &[u8],
+        options: &WriteOptions,
+    ) -> Result<()> {
+        poll(self.0.put_with_options(key, lsn, value, options))
+    }
+
     /// Deletes the entry corresponding to the key from the table.
     ///
     /// This is a synchronous version of [`raw::Table::delete`].
     pub fn delete(&self, key: &[u8], lsn: u64) -> Result<()> {
         poll(self.0.delete(key, lsn))
     }
+
+    /// Deletes the entry corresponding to the key from the table with the
+    /// given options.
+    ///
+    /// This is a synchronous version of [`raw::Table::delete_with_options`].
+    pub fn delete_with_options(&self, key: &[u8], lsn: u64, options: &WriteOptions) -> Result<()> {
+        poll(self.0.delete_with_options(key, lsn, options))
+    }
+
+    /// Merges the operand into the value of the key with the merge operator.
+    ///
+    /// This is a synchronous version of [`raw::Table::merge`].
+    pub fn merge(&self, key: &[u8], lsn: u64, operand: &[u8]) -> Result<()> {
+        poll(self.0.merge(key, lsn, operand))
+    }
+
+    /// Deletes the entries in the key range `[start, end)` from the table.
+    ///
+    /// This is a synchronous version of [`raw::Table::delete_range`].
+    pub fn delete_range(&self, start: &[u8], end: &[u8], lsn: u64) -> Result<()> {
+        poll(self.0.delete_range(start, end, lsn))
+    }
+
+    /// Applies the writes in the batch to the table with the same LSN.
+    ///
+    /// This is a synchronous version of [`raw::Table::write_batch`].
+    pub fn write_batch(&self, batch: WriteBatch, lsn: u64) -> Result<()> {
+        poll(self.0.write_batch(batch, lsn))
+    }
+
+    /// Loads the key-value entries, which must be sorted by key, into the
+    /// table with the same LSN.
+    ///
+    /// This is a synchronous version of [`raw::Table::ingest`].
+    pub fn ingest<'k, I>(&self, sorted: I, lsn: u64) -> Result<()>
+    where
+        I: IntoIterator<Item = (&'k [u8], &'k [u8])>,
+    {
+        poll(self.0.ingest(sorted, lsn))
+    }
+
+    /// Puts a key-value entry to the table with an LSN assigned by the table.
+    ///
+    /// This is a synchronous version of [`raw::Table::put_auto`].
+    pub fn put_auto(&self, key: &[u8], value: &[u8]) -> Result<u64> {
+        poll(self.0.put_auto(key, value))
+    }
+
+    /// Deletes the entry corresponding to the key from the table with an LSN
+    /// assigned by the table.
+    ///
+    /// This is a synchronous version of [`raw::Table::delete_auto`].
+    pub fn delete_auto(&self, key: &[u8]) -> Result<u64> {
+        poll(self.0.delete_auto(key))
+    }
+
+    /// Returns a cursor over the entries in the key range `[start, end)`
+    /// visible to `lsn`.
+    pub fn scan(&self, start: &[u8], end: Option<&[u8]>, lsn: u64) -> Cursor<'_> {
+        Cursor(self.0.scan(start, end, lsn))
+    }
+
+    /// Returns a cursor over the entries in the table with the given options.
+    pub fn scan_with_options(&self, options: ScanOptions) -> Cursor<'_> {
+        Cursor(self.0.scan_with_options(options))
+    }
+
+    /// Returns a cursor over the entries in the key range between `start` and
+    /// `end` visible to `lsn`.
+    pub fn range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>, lsn: u64) -> Cursor<'_> {
+        Cursor(self.0.range(start, end, lsn))
+    }
+
+    /// Returns a cursor over the entries whose keys start with `prefix`
+    /// visible to `lsn`.
+    pub fn scan_prefix(&self, prefix: &[u8], lsn: u64) -> Cursor<'_> {
+        Cursor(self.0.scan_prefix(prefix, lsn))
+    }
+
+    /// Returns a cursor over the changes made to the table with LSNs in
+    /// `(since_lsn, lsn]`.
+    pub fn scan_changes(&self, since_lsn: u64, lsn: u64) -> ChangeCursor<'_> {
+        ChangeCursor(self.0.scan_changes(since_lsn, lsn))
+    }
+
+    /// Verifies the checksums of all pages in the table files.
+    ///
+    /// This is a synchronous version of [`raw::Table::verify_checksums`].
+    pub fn verify_checksums(&self) -> Result<ChecksumReport> {
+        poll(self.0.verify_checksums())
+    }
+
+    /// Returns the ids of page files in the table directory that the table
+    /// doesn't reference.
+    ///
+    /// This is a synchronous version of [`raw::Table::find_orphan_files`].
+    pub fn find_orphan_files(&self) -> Result<Vec<u32>> {
+        poll(self.0.find_orphan_files())
+    }
+
+    /// Removes the page files that the table doesn't reference.
+    ///
+    /// This is a synchronous version of [`raw::Table::gc_orphans`].
+    pub fn gc_orphans(&self) -> Result<Vec<u32>> {
+        poll(self.0.gc_orphans())
+    }
+
+    /// Compacts the pages that overlap with the key range `[start, end)`.
+    ///
+    /// This is a synchronous version of [`raw::Table::compact_range`].
+    pub fn compact_range(&self, start: &[u8], end: Option<&[u8]>) -> Result<usize> {
+        poll(self.0.compact_range(start, end))
+    }
 }
 
 impl Deref for Table {
@@ -95,6 +289,13 @@ impl<'a> Guard<'a> {
     pub fn pages(&self) -> Pages<'_, 'a> {
         Pages(self.0.pages())
     }
+
+    /// Returns an iterator over pages in the table with the given isolation.
+    ///
+    /// This is a synchronous version of [`raw::Guard::iter`].
+    pub fn iter(&self, isolation: Isolation) -> Result<Pages<'_, 'a>> {
+        poll(self.0.iter(isolation)).map(Pages)
+    }
 }
 
 impl<'a> Deref for Guard<'a> {
@@ -127,6 +328,88 @@ impl<'a, 't> Deref for Pages<'a, 't> {
     }
 }
 
+/// An optimistic transaction on a table.
+pub struct ReadTxn<'a>(raw::ReadTxn<'a, Std>);
+
+impl<'a> ReadTxn<'a> {
+    /// Gets the latest value corresponding to the key.
+    ///
+    /// This is a synchronous version of [`raw::ReadTxn::get`].
+    pub fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        poll(self.0.get(key))
+    }
+
+    /// Applies the writes if none of the keys read have changed since.
+    ///
+    /// This is a synchronous version of [`raw::ReadTxn::commit_if_unchanged`].
+    pub fn commit_if_unchanged(self, writes: &[(&[u8], Option<&[u8]>)]) -> Result<u64> {
+        poll(self.0.commit_if_unchanged(writes))
+    }
+}
+
+/// A consistent view of a table as of an LSN.
+pub struct Snapshot<'a>(raw::Snapshot<'a, Std>);
+
+impl<'a> Snapshot<'a> {
+    /// Gets the value corresponding to the key as of the snapshot.
+    ///
+    /// This is a synchronous version of [`raw::Snapshot::get`].
+    pub fn get(&self, key: &[u8]) -> Result<Option<&[u8]>> {
+        poll(self.0.get(key))
+    }
+
+    /// Returns an iterator over pages in the table as of the snapshot.
+    pub fn pages(&self) -> Pages<'_, 'a> {
+        Pages(self.0.pages())
+    }
+
+    /// Returns a cursor over the entries in the key range `[start, end)` as of
+    /// the snapshot.
+    pub fn scan(&self, start: &[u8], end: Option<&[u8]>) -> Cursor<'_> {
+        Cursor(self.0.scan(start, end))
+    }
+}
+
+impl<'a> Deref for Snapshot<'a> {
+    type Target = raw::Snapshot<'a, Std>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// A cursor over the entries in a key range of a table.
+pub struct Cursor<'a>(raw::Cursor<'a, Std>);
+
+impl<'a> Cursor<'a> {
+    /// Returns the next entry in the range.
+    ///
+    /// This is a synchronous version of [`raw::Cursor::next`].
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        poll(self.0.next())
+    }
+
+    /// Repositions the cursor to the first entry in the range with a key no
+    /// less than `target`.
+    pub fn seek(&mut self, target: &[u8]) {
+        self.0.seek(target)
+    }
+}
+
+/// A cursor over the changes made to a table in an LSN window.
+pub struct ChangeCursor<'a>(raw::ChangeCursor<'a, Std>);
+
+impl<'a> ChangeCursor<'a> {
+    /// Returns the next change, with the key and the LSN of the change.
+    ///
+    /// This is a synchronous version of [`raw::ChangeCursor::next`].
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<(Vec<u8>, u64, ChangeKind)>> {
+        poll(self.0.next())
+    }
+}
+
 fn poll<F: Future>(mut future: F) -> F::Output {
     let cx = &mut Context::from_waker(noop_waker_ref());
     loop {
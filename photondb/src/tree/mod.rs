@@ -1,27 +1,38 @@
 use std::{
+    collections::{btree_map, BTreeMap, VecDeque},
     fmt,
-    sync::atomic::{AtomicU64, Ordering},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
 };
 
-use log::trace;
+use futures::future::try_join_all;
+use log::{error, trace, warn};
+use parking_lot::Mutex;
 
-use crate::{env::Env, page::*, page_store::*};
+use crate::{env::Env, page::*, page_store::*, util::timer::unix_timestamp};
 
 mod page;
-pub use page::PageIter;
 use page::*;
+pub use page::{ChangeKind, PageIter};
 
 mod stats;
 use stats::AtomicStats;
 pub use stats::TreeStats;
+#[cfg(feature = "prometheus")]
+pub(crate) use stats::TxnStats;
 
 mod options;
-pub use options::{Options, ReadOptions, WriteOptions};
+pub use options::{
+    Durability, Isolation, MergeOperator, Options, ReadOptions, ScanOptions, SplitPolicy,
+    WriteOptions,
+};
 
 pub(crate) struct Tree {
     options: Options,
     stats: AtomicStats,
     safe_lsn: AtomicU64,
+    // The number of open snapshots at each LSN.
+    snapshots: Mutex<BTreeMap<u64, usize>>,
+    poisoned: AtomicBool,
 }
 
 impl Tree {
@@ -30,6 +41,8 @@ impl Tree {
             options,
             stats: AtomicStats::default(),
             safe_lsn: AtomicU64::new(0),
+            snapshots: Mutex::default(),
+            poisoned: AtomicBool::new(false),
         }
     }
 
@@ -46,21 +59,59 @@ impl Tree {
     }
 
     pub(crate) fn set_safe_lsn(&self, lsn: u64) {
-        loop {
-            let safe_lsn = self.safe_lsn.load(Ordering::Acquire);
-            // Make sure that the safe LSN is increasing.
-            if safe_lsn >= lsn {
-                return;
-            }
-            if self
-                .safe_lsn
-                .compare_exchange(safe_lsn, lsn, Ordering::AcqRel, Ordering::Acquire)
-                .is_ok()
-            {
-                return;
+        // Serialized with snapshots, see `Self::acquire_snapshot`.
+        let _snapshots = self.snapshots.lock();
+        // Make sure that the safe LSN is increasing.
+        self.safe_lsn.fetch_max(lsn, Ordering::AcqRel);
+    }
+
+    /// Returns the minimal LSN that entries must stay visible to, which is
+    /// the safe LSN or the LSN of the oldest snapshot, whichever is smaller.
+    fn retain_lsn(&self) -> u64 {
+        let snapshots = self.snapshots.lock();
+        let safe_lsn = self.safe_lsn();
+        snapshots
+            .keys()
+            .next()
+            .map_or(safe_lsn, |&lsn| lsn.min(safe_lsn))
+    }
+
+    /// Registers a snapshot at `lsn`, and returns the LSN of the snapshot.
+    ///
+    /// Entries below the safe LSN may have been dropped, so the snapshot
+    /// reads at the safe LSN instead if `lsn` is smaller than it.
+    pub(crate) fn acquire_snapshot(&self, lsn: u64) -> u64 {
+        // The safe LSN can't be advanced until the snapshot is registered.
+        let mut snapshots = self.snapshots.lock();
+        let lsn = lsn.max(self.safe_lsn());
+        *snapshots.entry(lsn).or_default() += 1;
+        lsn
+    }
+
+    /// Releases a snapshot returned by [`Self::acquire_snapshot`].
+    pub(crate) fn release_snapshot(&self, lsn: u64) {
+        let mut snapshots = self.snapshots.lock();
+        if let btree_map::Entry::Occupied(mut entry) = snapshots.entry(lsn) {
+            *entry.get_mut() -= 1;
+            if *entry.get() == 0 {
+                entry.remove();
             }
         }
     }
+
+    /// Returns true if any snapshot is registered.
+    pub(crate) fn has_snapshots(&self) -> bool {
+        !self.snapshots.lock().is_empty()
+    }
+
+    /// Returns true if the tree structure is found corrupted.
+    pub(crate) fn is_poisoned(&self) -> bool {
+        self.poisoned.load(Ordering::Acquire)
+    }
+
+    fn poison(&self) {
+        self.poisoned.store(true, Ordering::Release);
+    }
 }
 
 impl fmt::Debug for Tree {
@@ -68,18 +119,29 @@ impl fmt::Debug for Tree {
         f.debug_struct("Tree")
             .field("options", &self.options)
             .field("safe_lsn", &self.safe_lsn())
+            .field("poisoned", &self.is_poisoned())
             .finish()
     }
 }
 
+/// The maximum number of pages that an ingest inserts in one transaction.
+const INGEST_BATCH_PAGES: usize = 64;
+
 pub(crate) struct TreeTxn<'a, E: Env> {
     tree: &'a Tree,
     guard: Guard<E>,
+    // The values merged by this transaction, which live as long as the pages it
+    // reads.
+    merged_values: MergedValues,
 }
 
 impl<'a, E: Env> TreeTxn<'a, E> {
     fn new(tree: &'a Tree, guard: Guard<E>) -> Self {
-        Self { tree, guard }
+        Self {
+            tree,
+            guard,
+            merged_values: MergedValues::default(),
+        }
     }
 
     /// Initializes the tree if it is not initialized yet.
@@ -99,11 +161,251 @@ impl<'a, E: Env> TreeTxn<'a, E> {
         assert_eq!(root_id, ROOT_ID);
         txn.commit();
 
+        let split_keys = self.tree.options.presplit_keys().unwrap_or_default();
+        if !split_keys.is_empty() {
+            self.presplit_root(new_addr, &split_keys).await?;
+        }
+        Ok(())
+    }
+
+    /// Splits the empty root into leaf pages with the given split keys.
+    async fn presplit_root(&self, root_addr: u64, split_keys: &[Vec<u8>]) -> Result<()> {
+        let mut txn = self.guard.begin().await;
+        let mut delta = Vec::with_capacity(split_keys.len() + 1);
+        let start_keys = [[].as_slice()]
+            .into_iter()
+            .chain(split_keys.iter().map(|k| k.as_slice()));
+        for start_key in start_keys {
+            let iter: ItemIter<(Key, Value)> = None.into();
+            let builder = SortedPageBuilder::new(PageTier::Leaf, PageKind::Data).with_iter(iter);
+            let (new_addr, mut new_page) = txn.alloc_page(builder.size()).await?;
+            builder.build(&mut new_page);
+            let id = txn.insert_page(new_addr);
+            delta.push((start_key, Index::new(id, 0)));
+        }
+        let builder = SortedPageBuilder::new(PageTier::Inner, PageKind::Data).with_slice(&delta);
+        let (new_addr, mut new_page) = txn.alloc_page(builder.size()).await?;
+        builder.build(&mut new_page);
+        // Nobody else can access the table yet, so this should never conflict.
+        txn.replace_page(ROOT_ID, root_addr, new_addr, &[root_addr])
+            .await
+            .map_err(|_| Error::Again)
+    }
+
+    /// Loads the sorted entries into the tree.
+    ///
+    /// If the tree is empty, the entries are built into leaf pages of about
+    /// the page size, which are installed under a new root directly, without
+    /// any delta pages. Otherwise, or if the tree is changed in the meantime,
+    /// the entries are written as one delta page per run of entries in the
+    /// same leaf page.
+    pub(crate) async fn ingest(&self, entries: &[(Key<'_>, Value<'_>)]) -> Result<()> {
+        debug_assert!(entries.windows(2).all(|w| w[0].0.raw < w[1].0.raw));
+        if let Some(root_addr) = self.empty_root().await? {
+            let mut inserted = Vec::new();
+            match self.ingest_root(root_addr, entries, &mut inserted).await {
+                Ok(()) => return Ok(()),
+                Err(err) => {
+                    // The pages are not linked into the tree, so nobody else
+                    // can reach them.
+                    self.delete_pages(&inserted).await?;
+                    if !matches!(err, Error::Again) {
+                        return Err(err);
+                    }
+                }
+            }
+        }
+        self.ingest_deltas(entries).await
+    }
+
+    /// Returns the address of the root if the tree is empty.
+    async fn empty_root(&self) -> Result<Option<u64>> {
+        let root = self.page_view(ROOT_ID, None).await?;
+        if !root.page.tier().is_leaf() || root.page.chain_next() != 0 {
+            return Ok(None);
+        }
+        let mut is_empty = false;
+        self.walk_page(
+            root.addr,
+            |_, page, _| {
+                is_empty = page.kind().is_data() && ValuePageRef::from(page).len() == 0;
+                true
+            },
+            CacheOption::default(),
+        )
+        .await?;
+        Ok(is_empty.then_some(root.addr))
+    }
+
+    /// Builds the entries into leaf pages and the index pages above them, and
+    /// replaces the empty root at `root_addr` with the top index page.
+    ///
+    /// The ids and addresses of the inserted pages are appended to `inserted`.
+    /// Returns [`Error::Again`] if the root is changed in the meantime.
+    async fn ingest_root(
+        &self,
+        root_addr: u64,
+        entries: &[(Key<'_>, Value<'_>)],
+        inserted: &mut Vec<(u64, u64)>,
+    ) -> Result<()> {
+        let pages = self.insert_pages(PageTier::Leaf, entries, inserted).await?;
+        let mut index: Vec<_> = pages
+            .into_iter()
+            .map(|(i, index)| (entries[i].0.raw, index))
+            .collect();
+        // The first page covers the range below its first key too.
+        index[0].0 = [].as_slice();
+        // Split the index into inner pages until it fits in the root.
+        while self.ingest_page_len(&index) < index.len() {
+            let pages = self.insert_pages(PageTier::Inner, &index, inserted).await?;
+            index = pages.into_iter().map(|(i, id)| (index[i].0, id)).collect();
+        }
+        let builder = SortedPageBuilder::new(PageTier::Inner, PageKind::Data).with_slice(&index);
+        let (mut txn, new_addr, mut new_page) = loop {
+            let mut txn = self.guard.begin().await;
+            match txn.alloc_page(builder.size()).await {
+                Ok((new_addr, new_page)) => break (txn, new_addr, new_page),
+                // Retry in the next write buffer.
+                Err(Error::Again) => continue,
+                Err(e) => return Err(e),
+            }
+        };
+        builder.build(&mut new_page);
+        txn.replace_page(ROOT_ID, root_addr, new_addr, &[root_addr])
+            .await
+    }
+
+    /// Builds the items into pages of about the page size, and inserts them in
+    /// batches of at most [`INGEST_BATCH_PAGES`] pages, each committed in its
+    /// own transaction. So a batch is also cut short when the write buffer is
+    /// full, and the rest of the items go to the next write buffer.
+    ///
+    /// Returns the position of the first item and the index of each page.
+    async fn insert_pages<K, V>(
+        &self,
+        tier: PageTier,
+        items: &[(K, V)],
+        inserted: &mut Vec<(u64, u64)>,
+    ) -> Result<Vec<(usize, Index)>>
+    where
+        K: SortedPageKey,
+        V: SortedPageValue,
+    {
+        let mut pages = Vec::new();
+        let mut pos = 0;
+        while pos < items.len() {
+            let mut txn = self.guard.begin().await;
+            let mut batch = Vec::with_capacity(INGEST_BATCH_PAGES);
+            while pos < items.len() && batch.len() < INGEST_BATCH_PAGES {
+                let len = self.ingest_page_len(&items[pos..]);
+                let builder =
+                    SortedPageBuilder::new(tier, PageKind::Data).with_slice(&items[pos..pos + len]);
+                let (new_addr, mut new_page) = match txn.alloc_page(builder.size()).await {
+                    Ok(page) => page,
+                    Err(Error::Again) => break,
+                    Err(e) => return Err(e),
+                };
+                builder.build(&mut new_page);
+                let id = txn.insert_page(new_addr);
+                batch.push((id, new_addr));
+                pages.push((pos, Index::new(id, 0)));
+                pos += len;
+            }
+            txn.commit();
+            inserted.extend(batch);
+        }
+        Ok(pages)
+    }
+
+    /// Returns the number of the first items that fit in a page of about the
+    /// page size, which is at least two if there are, so that each level of
+    /// the index has fewer pages than the level below.
+    fn ingest_page_len<K, V>(&self, items: &[(K, V)]) -> usize
+    where
+        K: SortedPageKey,
+        V: SortedPageValue,
+    {
+        let mut size = 0;
+        items
+            .iter()
+            .position(|(k, v)| {
+                size += k.encode_size() + v.encode_size();
+                size > self.tree.options.page_size
+            })
+            .unwrap_or(items.len())
+            .max(2)
+            .min(items.len())
+    }
+
+    /// Deletes the pages that are inserted but not linked into the tree.
+    async fn delete_pages(&self, pages: &[(u64, u64)]) -> Result<()> {
+        if pages.is_empty() {
+            return Ok(());
+        }
+        loop {
+            let txn = self.guard.begin().await;
+            match txn.delete_pages(pages).await {
+                // Retry in the next write buffer.
+                Err(Error::Again) => continue,
+                result => return result,
+            }
+        }
+    }
+
+    /// Writes the sorted entries as delta pages, one for each run of entries
+    /// that belong to the same leaf page and fit in a page.
+    async fn ingest_deltas(&self, entries: &[(Key<'_>, Value<'_>)]) -> Result<()> {
+        let mut rest = entries;
+        while !rest.is_empty() {
+            match self.try_ingest_delta(rest).await {
+                Ok(len) => {
+                    let bytes: usize = rest[..len].iter().map(|(k, v)| k.len() + v.len()).sum();
+                    self.tree.stats.success.write.add(len as u64);
+                    self.tree.stats.success.write_bytes.add(bytes as u64);
+                    rest = &rest[len..];
+                }
+                Err(Error::Again) => {
+                    self.tree.stats.conflict.write.inc();
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
+        }
         Ok(())
     }
 
+    /// Returns the number of the first entries written to the leaf page of
+    /// the first entry.
+    async fn try_ingest_delta(&self, entries: &[(Key<'_>, Value<'_>)]) -> Result<usize> {
+        let (view, _) = self.find_leaf(entries[0].0.raw).await?;
+        if !self.tree.options.disable_background
+            && self.should_split_page(&view.page)
+            && matches!(self.split_page(view.clone()).await, Ok(true))
+        {
+            return Err(Error::Again);
+        }
+
+        let mut len = self.ingest_page_len(entries);
+        if let Some(end) = view.range.and_then(|range| range.end) {
+            len = entries[..len]
+                .iter()
+                .position(|(k, _)| k.raw >= end)
+                .unwrap_or(len);
+        }
+        let builder =
+            SortedPageBuilder::new(PageTier::Leaf, PageKind::Data).with_slice(&entries[..len]);
+        let mut txn = self.guard.begin().await;
+        let (new_addr, mut new_page) = txn.alloc_page(builder.size()).await?;
+        builder.build(&mut new_page);
+        self.link_delta(view, txn, new_addr, new_page, true).await?;
+        Ok(len)
+    }
+
     /// Gets the value corresponding to the key.
     pub(crate) async fn get(&self, key: Key<'_>) -> Result<Option<&[u8]>> {
+        if self.tree.options.reject_reads_below_safe_lsn && key.lsn < self.tree.retain_lsn() {
+            return Err(Error::SnapshotTooOld);
+        }
         let (view, _) = self.find_leaf(key.raw).await?;
         let value = self.find_value(&key, &view).await?;
 
@@ -118,12 +420,202 @@ impl<'a, E: Env> TreeTxn<'a, E> {
         Ok(value)
     }
 
+    /// Gets the values corresponding to the keys, which must be sorted.
+    ///
+    /// Keys in the same leaf page are looked up in the page found for the
+    /// first of them, so each leaf page is found only once.
+    pub(crate) async fn multi_get(&self, keys: &[&[u8]], lsn: u64) -> Result<Vec<Option<&[u8]>>> {
+        debug_assert!(keys.windows(2).all(|w| w[0] <= w[1]));
+        if self.tree.options.reject_reads_below_safe_lsn && lsn < self.tree.retain_lsn() {
+            return Err(Error::SnapshotTooOld);
+        }
+        let mut values = Vec::with_capacity(keys.len());
+        let mut leaf: Option<PageView<'_>> = None;
+        let mut read_bytes = 0;
+        for &raw in keys {
+            let view = match leaf {
+                Some(ref view) if view.range.map_or(false, |range| range.contains(raw)) => view,
+                _ => {
+                    let (view, _) = self.find_leaf(raw).await?;
+                    &*leaf.insert(view)
+                }
+            };
+            let key = Key::new(raw, lsn);
+            let value = self.find_value(&key, view).await?;
+            read_bytes += key.len() + value.map(|v| v.len()).unwrap_or_default();
+            values.push(value);
+        }
+        self.tree.stats.success.read_bytes.add(read_bytes as u64);
+        Ok(values)
+    }
+
+    /// Gets the latest version of the key visible to the key LSN.
+    ///
+    /// Returns the LSN and the value of the version, or `None` if the key has
+    /// never been written. Deletes are returned as versions without values.
+    /// Unlike [`Self::get`], reads below the safe LSN are not rejected.
+    pub(crate) async fn get_version(&self, key: Key<'_>) -> Result<Option<(u64, Option<&[u8]>)>> {
+        let now = unix_timestamp();
+        let entry = self.get_entry(key).await?;
+        Ok(entry.map(|(lsn, value)| (lsn, value.live_at(now))))
+    }
+
+    /// Gets the latest version of the key visible to the key LSN, with the
+    /// value as it is stored.
+    ///
+    /// Unlike [`Self::get_version`], expiring values are returned with their
+    /// expirations, whether they have expired or not, and deletes are returned
+    /// as [`Value::Delete`].
+    pub(crate) async fn get_entry(&self, key: Key<'_>) -> Result<Option<(u64, Value<'_>)>> {
+        let (view, _) = self.find_leaf(key.raw).await?;
+        self.find_entry(&key, &view).await
+    }
+
+    /// Reads the leaf page that contains `start`, from `start` on.
+    ///
+    /// Returns the entries visible to `read_lsn` and the start key of the
+    /// next leaf page, or `None` if this is the last leaf page.
+    pub(crate) async fn read_leaf(
+        &self,
+        start: &[u8],
+        read_lsn: u64,
+    ) -> Result<(PageIter<'_>, Option<&[u8]>)> {
+        if self.tree.options.reject_reads_below_safe_lsn && read_lsn < self.tree.retain_lsn() {
+            return Err(Error::SnapshotTooOld);
+        }
+        let (view, _) = self.find_leaf(start).await?;
+        let next = view.range.and_then(|range| range.end);
+        let iter = self.iter_page(&view).await?;
+        let range_deletes = self.range_deletes(&view).await?;
+        let mut iter = PageIter::new(iter, range_deletes, self.merger(), read_lsn);
+        iter.seek(start);
+        Ok((iter, next))
+    }
+
+    /// Reads the changes made after `since_lsn` in the leaf page that contains
+    /// `start`, starting from `start`.
+    ///
+    /// Returns the changes and the end of the page range, from which the next
+    /// leaf page can be read.
+    pub(crate) async fn read_leaf_changes(
+        &self,
+        start: &[u8],
+        since_lsn: u64,
+        read_lsn: u64,
+    ) -> Result<(ChangeIter<'_>, Option<&[u8]>)> {
+        if self.tree.options.reject_reads_below_safe_lsn && read_lsn < self.tree.retain_lsn() {
+            return Err(Error::SnapshotTooOld);
+        }
+        let (view, _) = self.find_leaf(start).await?;
+        let next = view.range.and_then(|range| range.end);
+        let iter = self.iter_page(&view).await?;
+        let range_deletes = self.range_deletes(&view).await?;
+        let mut iter = ChangeIter::new(iter, range_deletes, self.merger(), since_lsn, read_lsn);
+        iter.seek(start);
+        Ok((iter, next))
+    }
+
     /// Writes the key-value pair to the tree.
     pub(crate) async fn write(&self, key: Key<'_>, value: Value<'_>) -> Result<()> {
-        let bytes = key.len() + value.len();
+        let delta = (key, value);
+        let builder = SortedPageBuilder::new(PageTier::Leaf, PageKind::Data).with_item(delta);
+        let size = builder.size();
+        let options = &self.tree.options;
+        let skip = match value {
+            Value::Put(value) if options.dedup_identical_puts => SkipWrite::Identical(value),
+            Value::Delete if options.skip_tombstone_for_absent => SkipWrite::Absent,
+            _ => SkipWrite::Never,
+        };
+        self.write_delta(key, value.len(), size, skip, |page| {
+            SortedPageBuilder::new(PageTier::Leaf, PageKind::Data)
+                .with_item(delta)
+                .build(page);
+        })
+        .await
+    }
+
+    /// Deletes the keys in the range `[start, end)` at the LSN.
+    ///
+    /// A range delete is written to each leaf page that overlaps with the
+    /// range, from the start of the page or the range, whichever is larger.
+    /// The pages are written one by one, and a page split in the meantime
+    /// copies the range deletes that cross the split key to the new page.
+    pub(crate) async fn delete_range(&self, start: &[u8], end: &[u8], lsn: u64) -> Result<()> {
+        let mut next = start;
+        while next < end {
+            self.write(Key::new(next, lsn), Value::RangeDelete(end))
+                .await?;
+            // Find the page again, since it may have been split before the write.
+            let (view, _) = self.find_leaf(next).await?;
+            match view.range.and_then(|range| range.end) {
+                Some(range_end) => next = range_end,
+                None => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes a merge operand of the key to the tree.
+    ///
+    /// The operand is merged into the previous versions of the key with the
+    /// merge operator when the key is read or consolidated.
+    pub(crate) async fn merge(&self, key: Key<'_>, operand: &[u8]) -> Result<()> {
+        if self.tree.options.merge_operator.is_none() {
+            return Err(Error::InvalidArgument);
+        }
+        self.write(key, Value::Merge(operand)).await
+    }
+
+    /// Writes the key with a value of `value_size` bytes to the tree.
+    ///
+    /// The value is written into the delta page by `f`. Note that `f` is called
+    /// again if the write conflicts with other writes.
+    pub(crate) async fn write_with<F>(
+        &self,
+        key: Key<'_>,
+        value_size: usize,
+        mut f: F,
+    ) -> Result<()>
+    where
+        F: FnMut(&mut [u8]),
+    {
+        let value = ReservedValue(value_size);
+        let delta = (key, value);
+        let builder = SortedPageBuilder::new(PageTier::Leaf, PageKind::Data).with_item(delta);
+        let size = builder.size();
+        self.write_delta(key, value_size, size, SkipWrite::Never, |page| {
+            SortedPageBuilder::new(PageTier::Leaf, PageKind::Data)
+                .with_item(delta)
+                .build(page);
+            f(reserved_value_mut(page, &key, value));
+        })
+        .await
+    }
+
+    /// Writes a delta page of `size` bytes built by `build` to the tree.
+    ///
+    /// Nothing is written if the live value of the key matches `skip`, except
+    /// that a deduplicated put may still record its version, see
+    /// [`Options::dedup_keeps_versions`].
+    async fn write_delta<F>(
+        &self,
+        key: Key<'_>,
+        value_size: usize,
+        size: usize,
+        skip: SkipWrite<'_>,
+        mut build: F,
+    ) -> Result<()>
+    where
+        F: FnMut(&mut PageBuf<'_>),
+    {
+        let bytes = key.len() + value_size;
         loop {
-            match self.try_write(key, value).await {
-                Ok(_) => {
+            match self.try_write_delta(key, size, skip, &mut build).await {
+                Ok(false) => {
+                    self.tree.stats.success.skip_write.inc();
+                    return Ok(());
+                }
+                Ok(true) => {
                     self.tree.stats.success.write.inc();
                     self.tree.stats.success.write_bytes.add(bytes as u64);
                     return Ok(());
@@ -137,27 +629,79 @@ impl<'a, E: Env> TreeTxn<'a, E> {
         }
     }
 
-    async fn try_write(&self, key: Key<'_>, value: Value<'_>) -> Result<()> {
+    /// Returns false if the value is not written because of `skip`.
+    async fn try_write_delta<F>(
+        &self,
+        key: Key<'_>,
+        size: usize,
+        skip: SkipWrite<'_>,
+        build: &mut F,
+    ) -> Result<bool>
+    where
+        F: FnMut(&mut PageBuf<'_>),
+    {
         let (mut view, _) = self.find_leaf(key.raw).await?;
+        let mut skipped = false;
+        if !matches!(skip, SkipWrite::Never) {
+            let live = self.find_entry(&key, &view).await?.map(|(_, v)| v);
+            if skip.matches(live) {
+                let keep_version = matches!(skip, SkipWrite::Identical(_))
+                    && self.tree.options.dedup_keeps_versions;
+                if !keep_version {
+                    return Ok(false);
+                }
+                skipped = true;
+            }
+        }
 
         // Try to split the page before every write to avoid starving the split
         // operation due to contentions.
-        if self.should_split_page(&view.page) && self.split_page(view.clone()).await.is_ok() {
+        if !self.tree.options.disable_background
+            && self.should_split_page(&view.page)
+            && matches!(self.split_page(view.clone()).await, Ok(true))
+        {
             return Err(Error::Again);
         }
 
-        // Build a delta page with the given key-value pair.
-        let delta = (key, value);
-        let builder = SortedPageBuilder::new(PageTier::Leaf, PageKind::Data).with_item(delta);
+        // Build a delta page with the given key-value pair, or only the version of
+        // the key if the value is unchanged.
         let mut txn = self.guard.begin().await;
-        let (new_addr, mut new_page) = txn.alloc_page(builder.size()).await?;
-        builder.build(&mut new_page);
+        let (new_addr, new_page) = if skipped {
+            let builder = SortedPageBuilder::new(PageTier::Leaf, PageKind::Data)
+                .with_item((key, Value::Unchanged));
+            let (new_addr, mut new_page) = txn.alloc_page(builder.size()).await?;
+            builder.build(&mut new_page);
+            (new_addr, new_page)
+        } else {
+            let (new_addr, mut new_page) = txn.alloc_page(size).await?;
+            build(&mut new_page);
+            (new_addr, new_page)
+        };
+        let retry = matches!(skip, SkipWrite::Never);
+        self.link_delta(view, txn, new_addr, new_page, retry)
+            .await?;
+        Ok(!skipped)
+    }
 
-        // Update the corresponding leaf page with the delta.
+    /// Updates the leaf page with the delta page, and consolidates the page if
+    /// its chain is too long.
+    ///
+    /// If the page has been updated by other transactions, the delta is linked
+    /// to the updated page if `retry` is true and the page epoch remains the
+    /// same. Otherwise, [`Error::Again`] is returned.
+    async fn link_delta(
+        &self,
+        mut view: PageView<'_>,
+        mut txn: PageTxn<'_, E>,
+        new_addr: u64,
+        mut new_page: PageBuf<'_>,
+        retry: bool,
+    ) -> Result<()> {
         loop {
             new_page.set_epoch(view.page.epoch());
             new_page.set_chain_len(view.page.chain_len().saturating_add(1));
             new_page.set_chain_next(view.addr);
+            new_page.set_chain_range_deletes(view.page.chain_has_range_deletes());
             match txn.update_page(view.id, view.addr, new_addr) {
                 Ok(_) => {
                     view.addr = new_addr;
@@ -170,7 +714,9 @@ impl<'a, E: Env> TreeTxn<'a, E> {
                     // We can keep retrying as long as the page epoch remains
                     // the same. However, this doesn't work for the root
                     // because we split the root without updating its epoch.
-                    if view.id != ROOT_ID {
+                    // If the write depends on the live value of the key, the
+                    // key must be checked again on the updated page.
+                    if view.id != ROOT_ID && retry {
                         let page = self.guard.read_page_info(addr)?;
                         if page.epoch() == view.page.epoch() {
                             txn = _txn;
@@ -185,12 +731,45 @@ impl<'a, E: Env> TreeTxn<'a, E> {
         }
 
         // Try to consolidate the page if it is too long.
-        if self.should_consolidate_page(&view.page) {
+        if !self.tree.options.disable_background && self.should_consolidate_page(&view.page) {
             let _ = self.consolidate_and_restructure_page(view).await;
         }
         Ok(())
     }
 
+    /// Consolidates and restructures leaf pages that overlap with the range
+    /// `[start, end)`. If `end` is `None`, the range is unbounded.
+    ///
+    /// Returns the number of consolidated pages.
+    pub(crate) async fn compact_range(&self, start: &[u8], end: Option<&[u8]>) -> Result<usize> {
+        let mut consolidated = 0;
+        let mut next = Some(start);
+        while let Some(key) = next {
+            let (view, _) = self.find_leaf(key).await?;
+            let range_end = view.range.and_then(|range| range.end);
+            let result = if view.page.chain_next() != 0 {
+                self.consolidate_and_restructure_page(view)
+                    .await
+                    .map(|_| consolidated += 1)
+            } else if self.should_split_page(&view.page) {
+                self.split_page(view).await.map(|_| ())
+            } else {
+                Ok(())
+            };
+            match result {
+                Ok(_) => {}
+                // Retry the same page.
+                Err(Error::Again) => continue,
+                Err(e) => return Err(e),
+            }
+            next = match (range_end, end) {
+                (Some(range_end), Some(end)) if range_end >= end => None,
+                _ => range_end,
+            };
+        }
+        Ok(consolidated)
+    }
+
     /// Returns a view to the page.
     async fn page_view<'g>(&'g self, id: u64, range: Option<Range<'g>>) -> Result<PageView<'g>> {
         let addr = self.guard.page_addr(id);
@@ -206,8 +785,16 @@ impl<'a, E: Env> TreeTxn<'a, E> {
     /// Finds the leaf page that may contain the key.
     ///
     /// Returns the leaf page and its parent.
+    ///
+    /// If an inner page on the path is corrupted, the tree is poisoned, and all
+    /// the following operations fail with [`Error::Corrupted`], since results
+    /// routed through a corrupted inner page can't be trusted. A corrupted leaf
+    /// page only fails the operations on it.
     async fn find_leaf(&self, key: &[u8]) -> Result<(PageView<'_>, Option<PageView<'_>>)> {
         loop {
+            if self.tree.is_poisoned() {
+                return Err(Error::Corrupted);
+            }
             match self.try_find_leaf(key).await {
                 Ok((view, parent)) => {
                     self.tree.stats.success.read.inc();
@@ -253,13 +840,26 @@ impl<'a, E: Env> TreeTxn<'a, E> {
                 return Err(Error::Again);
             }
             if view.page.tier().is_leaf() {
+                // The first child of each inner page starts at the start of the parent, so
+                // the leftmost leaf starts at the empty key, and every leaf covers the keys
+                // routed to it.
+                debug_assert!(range.contains(key), "{range:?} doesn't cover {key:?}");
                 return Ok((view, parent));
             }
             // Find the child page that may contain the key.
-            let (child_index, child_range) = self
-                .find_child(key, &view)
-                .await?
-                .expect("child page must exist");
+            let child = match self.find_child(key, &view).await {
+                Ok(child) => child,
+                Err(Error::Corrupted) => {
+                    error!(
+                        "poison the tree because of a corrupted inner page {}, key {key:?}",
+                        view.id
+                    );
+                    self.tree.poison();
+                    return Err(Error::Corrupted);
+                }
+                Err(e) => return Err(e),
+            };
+            let (child_index, child_range) = child.expect("child page must exist");
             index = child_index;
             range.start = child_range.start;
             // If the child has no range end, use the current one instead.
@@ -288,6 +888,20 @@ impl<'a, E: Env> TreeTxn<'a, E> {
         Ok(())
     }
 
+    /// Reads the first pages of the chains of the given pages concurrently.
+    ///
+    /// The reads are issued together, so that the IO runtime can submit them
+    /// in one batch. The pages are kept by the guard, so later reads of them
+    /// are served without IO.
+    async fn prefetch_pages(&self, ids: impl Iterator<Item = u64>) -> Result<()> {
+        let reads = ids.map(|id| {
+            let addr = self.guard.page_addr(id);
+            self.guard.read_page(addr, CacheOption::default())
+        });
+        try_join_all(reads).await?;
+        Ok(())
+    }
+
     /// Creates an iterator over the key-value pairs in the page.
     async fn iter_page<'g, K, V>(&'g self, view: &PageView<'g>) -> Result<MergingPageIter<'g, K, V>>
     where
@@ -330,7 +944,28 @@ impl<'a, E: Env> TreeTxn<'a, E> {
         key: &Key<'_>,
         view: &PageView<'g>,
     ) -> Result<Option<&'g [u8]>> {
-        let mut value = None;
+        let entry = self.find_entry(key, view).await?;
+        Ok(entry.and_then(|(_, value)| value.live_at(unix_timestamp())))
+    }
+
+    /// Finds the LSN and value of the latest version of the key in the page.
+    ///
+    /// If the latest version is unchanged or a merge operand, the value is
+    /// resolved from the previous versions of the key. Versions covered by a
+    /// range delete are deletes, and the range delete is the latest version if
+    /// it is later than the others.
+    async fn find_entry<'g>(
+        &'g self,
+        key: &Key<'_>,
+        view: &PageView<'g>,
+    ) -> Result<Option<(u64, Value<'g>)>> {
+        let range_deletes = self.range_deletes(view).await?;
+        let deleted_lsn = range_deletes.deleted_lsn(key.raw, key.lsn);
+        // Each data page in the chain only covers a part of the leaf range, and pages
+        // that can't contain the key are skipped without searching.
+        let mut base = None;
+        let mut operands = Vec::new();
+        let mut latest_lsn = None;
         self.walk_page(
             view.addr,
             |_, page, _| {
@@ -338,18 +973,34 @@ impl<'a, E: Env> TreeTxn<'a, E> {
                 // We only care about data pages here.
                 if page.kind().is_data() {
                     let page = ValuePageRef::from(page);
-                    let index = match page.rank(key) {
+                    if !page.may_contain(key.raw) {
+                        self.tree.stats.success.skip_page.inc();
+                        return false;
+                    }
+                    let mut index = match page.rank(key) {
                         Ok(i) => i,
                         Err(i) => i,
                     };
-                    if let Some((k, v)) = page.get(index) {
-                        if k.raw == key.raw {
-                            debug_assert!(k.lsn <= key.lsn);
-                            if let Value::Put(v) = v {
-                                value = Some(v);
-                            }
+                    while let Some((k, v)) = page.get(index) {
+                        if k.raw != key.raw {
+                            break;
+                        }
+                        debug_assert!(k.lsn <= key.lsn);
+                        latest_lsn.get_or_insert(k.lsn);
+                        if deleted_lsn.map_or(false, |deleted| k.lsn <= deleted) {
+                            base = Some(Value::Delete);
                             return true;
                         }
+                        match v {
+                            Value::Unchanged => {}
+                            Value::Merge(operand) => operands.push(operand),
+                            v => {
+                                base = Some(v);
+                                return true;
+                            }
+                        }
+                        // Look for the value in the previous versions.
+                        index += 1;
                     }
                 }
                 false
@@ -357,7 +1008,40 @@ impl<'a, E: Env> TreeTxn<'a, E> {
             CacheOption::default(),
         )
         .await?;
-        Ok(value)
+        // An unchanged version without previous versions has no value.
+        let base = base.unwrap_or(Value::Delete);
+        let entry = latest_lsn.map(|lsn| (lsn, self.merger().merge(key.raw, base, &operands)));
+        Ok(match deleted_lsn {
+            Some(deleted) if entry.map_or(true, |(lsn, _)| lsn < deleted) => {
+                Some((deleted, Value::Delete))
+            }
+            _ => entry,
+        })
+    }
+
+    /// Returns a merger that merges operands with the merge operator.
+    fn merger(&self) -> ValueMerger<'_> {
+        let operator = self.tree.options.merge_operator.as_deref();
+        ValueMerger::new(operator, &self.merged_values)
+    }
+
+    /// Collects the range deletes in the leaf page.
+    async fn range_deletes<'g>(&'g self, view: &PageView<'g>) -> Result<RangeDeletes<'g>> {
+        let mut range_deletes = RangeDeletes::default();
+        if view.page.chain_has_range_deletes() {
+            self.walk_page(
+                view.addr,
+                |_, page, _| {
+                    if page.has_range_deletes() {
+                        range_deletes.add(SortedPageIter::<Key, Value>::from(page));
+                    }
+                    false
+                },
+                CacheOption::default(),
+            )
+            .await?;
+        }
+        Ok(range_deletes)
     }
 
     /// Finds the child page that may contain the key from the page.
@@ -403,7 +1087,10 @@ impl<'a, E: Env> TreeTxn<'a, E> {
     }
 
     // Splits the page into two halves.
-    async fn split_page(&self, view: PageView<'_>) -> Result<()> {
+    //
+    // Returns false if the page can't be split, for example, if it only
+    // contains a single key that is larger than the page size.
+    async fn split_page(&self, view: PageView<'_>) -> Result<bool> {
         // We can only split base data pages.
         if !view.page.kind().is_data() || view.page.chain_next() != 0 {
             return Err(Error::InvalidArgument);
@@ -414,10 +1101,10 @@ impl<'a, E: Env> TreeTxn<'a, E> {
         }
     }
 
-    async fn split_page_impl<K, V>(&self, mut view: PageView<'_>) -> Result<()>
+    async fn split_page_impl<K, V>(&self, mut view: PageView<'_>) -> Result<bool>
     where
         K: SortedPageKey,
-        V: SortedPageValue,
+        V: SortedPageValue + SplitValue<K>,
     {
         if view.id == ROOT_ID {
             return self.split_root_impl::<K, V>(view).await;
@@ -427,20 +1114,25 @@ impl<'a, E: Env> TreeTxn<'a, E> {
             .guard
             .read_page(view.addr, CacheOption::default())
             .await?;
+        let has_range_deletes = page.has_range_deletes();
         let page = SortedPageRef::<K, V>::from(page);
-        let Some((split_key, _, right_iter)) = page.into_split_iter() else {
-            return Ok(());
+        let Some((split_key, left_iter, right_iter)) = self.split_page_iter(page) else {
+            self.warn_unsplittable_page(&view);
+            return Ok(false);
         };
 
         let mut txn = self.guard.begin().await;
         // Build and insert the right page.
-        let right_id = {
-            let builder =
-                SortedPageBuilder::new(view.page.tier(), PageKind::Data).with_iter(right_iter);
-            let (new_addr, mut new_page) = txn.alloc_page(builder.size()).await?;
-            builder.build(&mut new_page);
-            txn.insert_page(new_addr)
-        };
+        let left_iter = has_range_deletes.then_some(left_iter);
+        let right_id = self
+            .insert_right_page(
+                &mut txn,
+                view.page.tier(),
+                &split_key,
+                left_iter,
+                right_iter,
+            )
+            .await?;
         // Build a delta page with the right index.
         let delta = (split_key.as_raw(), Index::new(right_id, 0));
         let builder = SortedPageBuilder::new(view.page.tier(), PageKind::Split).with_item(delta);
@@ -451,6 +1143,7 @@ impl<'a, E: Env> TreeTxn<'a, E> {
         new_page.set_epoch(view.page.epoch() + 1);
         new_page.set_chain_len(view.page.chain_len().saturating_add(1));
         new_page.set_chain_next(view.addr);
+        new_page.set_chain_range_deletes(view.page.chain_has_range_deletes());
         txn.update_page(view.id, view.addr, new_addr)
             .map(|_| {
                 trace!("split page {:?} with delta {:?}", view, delta);
@@ -463,13 +1156,13 @@ impl<'a, E: Env> TreeTxn<'a, E> {
                 Error::Again
             })?;
 
-        Ok(())
+        Ok(true)
     }
 
-    async fn split_root_impl<K, V>(&self, view: PageView<'_>) -> Result<()>
+    async fn split_root_impl<K, V>(&self, view: PageView<'_>) -> Result<bool>
     where
         K: SortedPageKey,
-        V: SortedPageValue,
+        V: SortedPageValue + SplitValue<K>,
     {
         assert_eq!(view.id, ROOT_ID);
         assert_eq!(view.page.epoch(), 0);
@@ -479,28 +1172,33 @@ impl<'a, E: Env> TreeTxn<'a, E> {
             .guard
             .read_page(view.addr, CacheOption::default())
             .await?;
+        let has_range_deletes = page.has_range_deletes();
         let page = SortedPageRef::<K, V>::from(page);
-        let Some((split_key, left_iter, right_iter)) = page.into_split_iter() else {
-            return Ok(());
+        let Some((split_key, left_iter, right_iter)) = self.split_page_iter(page) else {
+            self.warn_unsplittable_page(&view);
+            return Ok(false);
         };
 
         let mut txn = self.guard.begin().await;
         // Build and insert the left page.
         let left_id = {
-            let builder =
-                SortedPageBuilder::new(view.page.tier(), PageKind::Data).with_iter(left_iter);
+            let builder = SortedPageBuilder::new(view.page.tier(), PageKind::Data)
+                .with_iter(left_iter.clone());
             let (new_addr, mut new_page) = txn.alloc_page(builder.size()).await?;
             builder.build(&mut new_page);
             txn.insert_page(new_addr)
         };
         // Build and insert the right page.
-        let right_id = {
-            let builder =
-                SortedPageBuilder::new(view.page.tier(), PageKind::Data).with_iter(right_iter);
-            let (new_addr, mut new_page) = txn.alloc_page(builder.size()).await?;
-            builder.build(&mut new_page);
-            txn.insert_page(new_addr)
-        };
+        let left_iter = has_range_deletes.then_some(left_iter);
+        let right_id = self
+            .insert_right_page(
+                &mut txn,
+                view.page.tier(),
+                &split_key,
+                left_iter,
+                right_iter,
+            )
+            .await?;
         // Build a delta page with the right index.
         let delta = [
             ([].as_slice(), Index::new(left_id, 0)),
@@ -515,6 +1213,7 @@ impl<'a, E: Env> TreeTxn<'a, E> {
             .map(|_| {
                 trace!("split root {:?} with delta {:?}", view, delta);
                 self.tree.stats.success.split_page.inc();
+                true
             })
             .map_err(|_| {
                 self.tree.stats.conflict.split_page.inc();
@@ -522,6 +1221,69 @@ impl<'a, E: Env> TreeTxn<'a, E> {
             })
     }
 
+    /// Builds and inserts the right page of a split.
+    ///
+    /// The right page also takes the items it needs from the left page, such
+    /// as range deletes that cross the split key, if `left_iter` is given.
+    async fn insert_right_page<'p, K, V>(
+        &self,
+        txn: &mut PageTxn<'_, E>,
+        tier: PageTier,
+        split_key: &K,
+        left_iter: Option<SortedPageRangeIter<'p, K, V>>,
+        right_iter: SortedPageRangeIter<'p, K, V>,
+    ) -> Result<u64>
+    where
+        K: SortedPageKey,
+        V: SortedPageValue + SplitValue<K>,
+    {
+        let left_items = left_iter.map_or_else(Vec::new, |iter| V::right_items(split_key, iter));
+        let new_addr = if left_items.is_empty() {
+            let builder = SortedPageBuilder::new(tier, PageKind::Data).with_iter(right_iter);
+            let (new_addr, mut new_page) = txn.alloc_page(builder.size()).await?;
+            builder.build(&mut new_page);
+            new_addr
+        } else {
+            let mut items: Vec<_> = right_iter.chain(left_items).collect();
+            items.sort_by(|a, b| a.0.cmp(&b.0));
+            let builder = SortedPageBuilder::new(tier, PageKind::Data).with_slice(&items);
+            let (new_addr, mut new_page) = txn.alloc_page(builder.size()).await?;
+            builder.build(&mut new_page);
+            new_addr
+        };
+        Ok(txn.insert_page(new_addr))
+    }
+
+    fn warn_unsplittable_page(&self, view: &PageView<'_>) {
+        // The page is kept over-large, since a key can't span pages.
+        warn!(
+            "page {} of {} bytes exceeds the page size {} but can't be split",
+            view.id,
+            view.page.size(),
+            self.tree.options.page_size
+        );
+    }
+
+    /// Splits the page with the configured policy.
+    #[allow(clippy::type_complexity)]
+    fn split_page_iter<'p, K, V>(
+        &self,
+        page: SortedPageRef<'p, K, V>,
+    ) -> Option<(
+        K,
+        SortedPageRangeIter<'p, K, V>,
+        SortedPageRangeIter<'p, K, V>,
+    )>
+    where
+        K: SortedPageKey,
+        V: SortedPageValue,
+    {
+        match self.tree.options.split_policy {
+            SplitPolicy::Count => page.into_split_iter(),
+            SplitPolicy::Size => page.into_split_iter_by_size(),
+        }
+    }
+
     /// Reconciles any conflicts on the page.
     async fn reconcile_page(&self, view: PageView<'_>, parent: Option<PageView<'_>>) -> Result<()> {
         let result = match view.page.kind() {
@@ -594,7 +1356,7 @@ impl<'a, E: Env> TreeTxn<'a, E> {
             .map_err(|_| Error::Again)?;
 
         // Try to consolidate the parent page if it is too long.
-        if self.should_consolidate_page(&parent.page) {
+        if !self.tree.options.disable_background && self.should_consolidate_page(&parent.page) {
             let _ = self.consolidate_and_restructure_page(parent).await;
         }
         Ok(())
@@ -604,12 +1366,27 @@ impl<'a, E: Env> TreeTxn<'a, E> {
     async fn consolidate_page<'g>(&'g self, view: PageView<'g>) -> Result<PageView<'g>> {
         match view.page.tier() {
             PageTier::Leaf => {
-                let safe_lsn = self.tree.safe_lsn();
-                self.consolidate_page_impl(view, |iter| MergingLeafPageIter::new(iter, safe_lsn))
-                    .await
+                let safe_lsn = self.tree.retain_lsn();
+                let retain_versions = self.tree.options.retain_versions;
+                self.consolidate_page_impl(view, |iter, info| {
+                    let whole_chain = info.last_page.chain_next() == 0;
+                    let iter = MergingLeafPageIter::new(
+                        iter,
+                        safe_lsn,
+                        retain_versions,
+                        self.merger(),
+                        whole_chain,
+                    );
+                    if info.has_range_deletes {
+                        iter.with_range_deletes()
+                    } else {
+                        iter
+                    }
+                })
+                .await
             }
             PageTier::Inner => {
-                self.consolidate_page_impl(view, MergingInnerPageIter::new)
+                self.consolidate_page_impl(view, |iter, _| MergingInnerPageIter::new(iter))
                     .await
             }
         }
@@ -621,27 +1398,35 @@ impl<'a, E: Env> TreeTxn<'a, E> {
         f: F,
     ) -> Result<PageView<'g>>
     where
-        F: Fn(MergingPageIter<'g, K, V>) -> I,
+        F: Fn(MergingPageIter<'g, K, V>, &ConsolidationInfo) -> I,
         I: RewindableIterator<Item = (K, V)>,
         K: SortedPageKey,
         V: SortedPageValue,
     {
         // Collect information for this consolidation.
-        let info = self.collect_consolidation_info(&view).await?;
-        let iter = f(info.iter);
-        let builder = SortedPageBuilder::new(view.page.tier(), PageKind::Data).with_iter(iter);
+        let (iter, info) = self.collect_consolidation_info(&view).await?;
+        let iter = f(iter, &info);
+        let mut builder = SortedPageBuilder::new(view.page.tier(), PageKind::Data);
+        // Attach a key filter to leaf pages to speed up lookups of absent keys.
+        if view.page.tier().is_leaf() {
+            builder = builder.with_filter(self.tree.options.bloom_fp_rate);
+        }
+        let builder = builder.with_iter(iter);
+        let size = builder.size();
         let mut txn = self.guard.begin().await;
-        let (new_addr, mut new_page) = txn.alloc_page(builder.size()).await?;
+        let (new_addr, mut new_page) = txn.alloc_page(size).await?;
         builder.build(&mut new_page);
         new_page.set_epoch(view.page.epoch());
         new_page.set_chain_len(info.last_page.chain_len());
         new_page.set_chain_next(info.last_page.chain_next());
+        new_page.set_chain_range_deletes(info.rest_has_range_deletes);
         // Update the page and deallocate the consolidated delta pages.
         txn.replace_page(view.id, view.addr, new_addr, &info.page_addrs)
             .await
             .map(|_| {
                 trace!("consolidate page {:?}", view);
                 self.tree.stats.success.consolidate_page.inc();
+                self.tree.stats.success.consolidate_bytes.add(size as u64);
                 view.addr = new_addr;
                 view.page = new_page.info();
                 view
@@ -653,10 +1438,12 @@ impl<'a, E: Env> TreeTxn<'a, E> {
     }
 
     /// Collects some information to consolidate a page.
+    ///
+    /// Returns an iterator over the pages to consolidate and the information.
     async fn collect_consolidation_info<'g, K, V>(
         &'g self,
         view: &PageView<'g>,
-    ) -> Result<ConsolidationInfo<'g, K, V>>
+    ) -> Result<(MergingPageIter<'g, K, V>, ConsolidationInfo)>
     where
         K: SortedPageKey,
         V: SortedPageValue,
@@ -667,6 +1454,9 @@ impl<'a, E: Env> TreeTxn<'a, E> {
         let mut last_page = view.page.clone();
         let mut page_addrs = Vec::with_capacity(chain_len);
         let mut range_limit = None;
+        let mut has_range_deletes = false;
+        let mut rest_has_range_deletes = false;
+        let partial_percent = self.tree.options.partial_consolidation_percent;
         let opt = CacheOption::default().set_refill_cold_when_not_full(true);
         self.walk_page(
             view.addr,
@@ -678,10 +1468,11 @@ impl<'a, E: Env> TreeTxn<'a, E> {
                         // doesn't consolidate as often as leaf pages.
                         if page.tier().is_leaf()
                             && builder.len() >= 2
-                            && page_size < page.size() / 2
+                            && page_size * 100 < page.size() * partial_percent
                             && range_limit.is_none()
                             && !self.should_consolidate_page(&page.info())
                         {
+                            rest_has_range_deletes = page.chain_has_range_deletes();
                             return true;
                         }
                         if let Some(ctoken) = ctoken {
@@ -689,6 +1480,7 @@ impl<'a, E: Env> TreeTxn<'a, E> {
                         }
                         builder.add(SortedPageIter::from(page));
                         page_size += page.size();
+                        has_range_deletes |= page.has_range_deletes();
                     }
                     PageKind::Split => {
                         if range_limit.is_none() {
@@ -705,11 +1497,13 @@ impl<'a, E: Env> TreeTxn<'a, E> {
         )
         .await?;
         let iter = MergingPageIter::new(builder.build(), range_limit);
-        Ok(ConsolidationInfo {
-            iter,
+        let info = ConsolidationInfo {
             last_page,
             page_addrs,
-        })
+            has_range_deletes,
+            rest_has_range_deletes,
+        };
+        Ok((iter, info))
     }
 
     /// Consolidates and restructures a page.
@@ -734,21 +1528,26 @@ impl<'a, E: Env> TreeTxn<'a, E> {
 
     // Returns true if the page should be consolidated.
     fn should_consolidate_page(&self, page: &PageInfo) -> bool {
-        let mut max_chain_len = self.tree.options.page_chain_length;
-        if page.tier().is_inner() {
-            // Adjust the chain length for inner pages.
-            max_chain_len /= 2;
-        }
+        let max_chain_len = if page.tier().is_inner() {
+            self.tree.options.index_chain_length
+        } else {
+            self.tree.options.data_chain_length
+        };
         page.chain_len() as usize > max_chain_len.max(1)
     }
 }
 
+/// The items and range deletes of a leaf page.
+type LeafPage<'a> = (MergingPageIter<'a, Key<'a>, Value<'a>>, RangeDeletes<'a>);
+
 /// An iterator over leaf pages in a tree.
 pub(crate) struct TreeIter<'a, 't: 'a, E: Env> {
     txn: &'a TreeTxn<'t, E>,
     options: ReadOptions,
     inner_iter: Option<MergingInnerPageIter<'a>>,
     inner_next: Option<&'a [u8]>,
+    // Children taken from the inner iterator whose pages have been prefetched.
+    prefetched: VecDeque<(&'a [u8], Index)>,
 }
 
 impl<'a, 't: 'a, E: Env> TreeIter<'a, 't, E> {
@@ -758,14 +1557,16 @@ impl<'a, 't: 'a, E: Env> TreeIter<'a, 't, E> {
             options,
             inner_iter: None,
             inner_next: Some(&[]),
+            prefetched: VecDeque::new(),
         }
     }
 
-    async fn seek(&mut self, target: &[u8]) -> Result<PageIter<'_>> {
+    async fn seek(&mut self, target: &[u8]) -> Result<LeafPage<'a>> {
         let (view, parent) = self.txn.find_leaf(target).await?;
-        let iter = self.txn.iter_page(&view).await?;
-        let mut leaf_iter = PageIter::new(iter, self.options.max_lsn);
-        leaf_iter.seek(target);
+        let mut leaf_iter = self.txn.iter_page(&view).await?;
+        leaf_iter.seek(&Key::new(target, u64::MAX));
+        let range_deletes = self.txn.range_deletes(&view).await?;
+        self.prefetched.clear();
         if let Some(parent) = parent {
             let iter = self.txn.iter_page(&parent).await?;
             let mut iter = MergingInnerPageIter::new(iter);
@@ -778,18 +1579,29 @@ impl<'a, 't: 'a, E: Env> TreeIter<'a, 't, E> {
             self.inner_iter = None;
             self.inner_next = None;
         }
-        Ok(leaf_iter)
+        Ok((leaf_iter, range_deletes))
     }
 
-    pub(crate) async fn next_page(&mut self) -> Result<Option<PageIter<'_>>> {
+    async fn next_leaf_page(&mut self) -> Result<Option<LeafPage<'a>>> {
         let mut inner_next = self.inner_next.take();
         if let Some(inner_iter) = self.inner_iter.as_mut() {
-            if let Some((start, index)) = inner_iter.next() {
+            let io_depth = self.txn.tree.options.read_io_depth;
+            if self.prefetched.is_empty() && io_depth > 1 {
+                self.prefetched.extend(inner_iter.take(io_depth));
+                let ids = self.prefetched.iter().map(|(_, index)| index.id);
+                self.txn.prefetch_pages(ids).await?;
+            }
+            let child = match self.prefetched.pop_front() {
+                Some(child) => Some(child),
+                None => inner_iter.next(),
+            };
+            if let Some((start, index)) = child {
                 let view = self.txn.page_view(index.id, None).await?;
                 if view.page.epoch() == index.epoch {
                     let iter = self.txn.iter_page(&view).await?;
+                    let range_deletes = self.txn.range_deletes(&view).await?;
                     self.inner_next = inner_next;
-                    return Ok(Some(PageIter::new(iter, self.options.max_lsn)));
+                    return Ok(Some((iter, range_deletes)));
                 } else {
                     // The page epoch has changed, we need to restart from this.
                     inner_next = Some(start);
@@ -804,16 +1616,58 @@ impl<'a, 't: 'a, E: Env> TreeIter<'a, 't, E> {
             Ok(None)
         }
     }
+
+    pub(crate) fn read_lsn(&self) -> u64 {
+        self.options.max_lsn
+    }
+
+    pub(crate) async fn next_page(&mut self) -> Result<Option<PageIter<'_>>> {
+        let page = self.next_leaf_page().await?;
+        Ok(page.map(|(iter, range_deletes)| {
+            PageIter::new(iter, range_deletes, self.txn.merger(), self.options.max_lsn)
+        }))
+    }
+
+    /// Returns the largest LSN of the entries in the next leaf page, including
+    /// deletes.
+    pub(crate) async fn next_page_max_lsn(&mut self) -> Result<Option<u64>> {
+        let page = self.next_leaf_page().await?;
+        Ok(page.map(|(iter, _)| iter.map(|(k, _)| k.lsn).max().unwrap_or_default()))
+    }
+}
+
+/// Conditions to skip a write, which are checked against the live value of
+/// the key.
+#[derive(Clone, Copy)]
+enum SkipWrite<'a> {
+    /// Never skips the write.
+    Never,
+    /// Skips the write if the key has no live value.
+    Absent,
+    /// Skips the write if the live value is identical to the given value.
+    Identical(&'a [u8]),
+}
+
+impl SkipWrite<'_> {
+    /// Returns true if the latest version of the key matches.
+    fn matches(&self, latest: Option<Value<'_>>) -> bool {
+        match self {
+            SkipWrite::Never => false,
+            SkipWrite::Absent => latest.and_then(|v| v.live_at(unix_timestamp())).is_none(),
+            // An identical value with an expiry is not skipped, so that the
+            // write clears the expiry.
+            SkipWrite::Identical(value) => latest == Some(Value::Put(*value)),
+        }
+    }
 }
 
-struct ConsolidationInfo<'a, K, V>
-where
-    K: SortedPageKey,
-    V: SortedPageValue,
-{
-    iter: MergingPageIter<'a, K, V>,
+struct ConsolidationInfo {
     last_page: PageInfo,
     page_addrs: Vec<u64>,
+    // Whether the pages to consolidate contain range deletes.
+    has_range_deletes: bool,
+    // Whether the pages after them in the chain contain range deletes.
+    rest_has_range_deletes: bool,
 }
 
 fn split_delta_from_page(page: PageRef<'_>) -> (&[u8], Index) {
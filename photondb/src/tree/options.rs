@@ -1,3 +1,5 @@
+use std::{fmt, sync::Arc};
+
 use crate::PageStoreOptions;
 
 /// Options to configure a table.
@@ -6,16 +8,184 @@ use crate::PageStoreOptions;
 pub struct Options {
     /// Approximate size of user data packed per page before it is split.
     ///
-    /// Note that the size specified here corresponds to uncompressed data.
+    /// Note that the size specified here corresponds to uncompressed data. A
+    /// page that only contains a single key larger than this size is kept
+    /// as it is, since a key can't be split across pages.
     ///
     /// Default: 8KB
     pub page_size: usize,
 
-    /// Approximate number of delta pages chained per page before it is
+    /// Approximate number of delta pages chained per leaf page before it is
     /// consolidated.
     ///
     /// Default: 4
-    pub page_chain_length: usize,
+    pub data_chain_length: usize,
+
+    /// Approximate number of delta pages chained per inner page before it is
+    /// consolidated.
+    ///
+    /// Inner pages are read by every operation but rarely updated, so they are
+    /// usually kept flatter than leaf pages.
+    ///
+    /// Default: 2
+    pub index_chain_length: usize,
+
+    /// Target false-positive rate of the bloom filters built over page keys.
+    ///
+    /// Bloom filters are attached to consolidated leaf pages, so that lookups
+    /// can skip the pages that don't contain the key. The rate is converted to
+    /// the number of bits per key and the number of probes of the filters.
+    /// It must be in (0, 1).
+    ///
+    /// Default: 0.01
+    pub bloom_fp_rate: f64,
+
+    /// The maximum size of the delta pages to consolidate into a leaf page,
+    /// as a percentage of the size of the page under them, to keep that page.
+    ///
+    /// A consolidation stops at a data page when at least two delta pages
+    /// above it are collected, and their total size is less than this
+    /// percentage of its size. The collected pages are merged into a new
+    /// delta page over the kept page, so that a large base page is not
+    /// rewritten for a few small changes. 0 disables partial consolidations.
+    ///
+    /// Default: 50
+    pub partial_consolidation_percent: usize,
+
+    /// The policy to choose the split key when a page is split.
+    ///
+    /// Default: [`SplitPolicy::Count`]
+    pub split_policy: SplitPolicy,
+
+    /// If true, pages are not consolidated or split automatically, and space
+    /// is not reclaimed in the background.
+    ///
+    /// Writes keep accumulating delta pages until the pages are compacted
+    /// explicitly with [`compact_range`]. Full write buffers are still flushed,
+    /// otherwise writes would stall. This is mostly useful for tests that
+    /// assert the structure of the table.
+    ///
+    /// [`compact_range`]: crate::raw::Table::compact_range
+    ///
+    /// Default: false
+    pub disable_background: bool,
+
+    /// The number of leaf pages to create when the table is created.
+    ///
+    /// The key space is split evenly by the first two bytes of keys, which
+    /// saves a lot of splits during the initial load if keys are distributed
+    /// uniformly. It is ignored if `initial_split_keys` is not empty. It must
+    /// be in [1, 65536].
+    ///
+    /// Default: 1
+    pub initial_leaf_count: usize,
+
+    /// The split keys of the leaf pages to create when the table is created.
+    ///
+    /// If it is not empty, the table is created with one more leaf page than
+    /// the number of keys. The keys must be non-empty and strictly increasing.
+    ///
+    /// Default: empty
+    pub initial_split_keys: Vec<Vec<u8>>,
+
+    /// The maximum number of page reads that a scan issues at once.
+    ///
+    /// If it is larger than 1, a scan reads the following leaf pages of the
+    /// same parent together, so that the reads can be submitted to the device
+    /// in one batch. This raises the throughput of scans over pages that are
+    /// not cached, at the cost of pinning more pages during the scan.
+    ///
+    /// Default: 1
+    pub read_io_depth: usize,
+
+    /// The number of latest versions of each key to retain when pages are
+    /// consolidated.
+    ///
+    /// Versions that may be visible to reads at or above the [`safe_lsn`] are
+    /// always retained. The latest versions are retained in addition to them,
+    /// so that recent history can be read without holding the safe LSN back.
+    ///
+    /// [`safe_lsn`]: crate::raw::Table::safe_lsn
+    ///
+    /// Default: 0
+    pub retain_versions: usize,
+
+    /// Skips deletes of keys that have no live values.
+    ///
+    /// If true, a delete first looks up the key and writes no tombstone if
+    /// the key is absent, which trades a read for a write of useless
+    /// tombstones.
+    ///
+    /// Default: false
+    pub skip_tombstone_for_absent: bool,
+
+    /// Skips puts of values identical to the live values of the keys.
+    ///
+    /// If true, a put first looks up the key and writes nothing if the live
+    /// value is byte-identical, which keeps delta chains short for writers
+    /// that re-put the same values. Unless [`dedup_keeps_versions`] is set,
+    /// no version is written at the LSN of a skipped put, so reads at any LSN
+    /// still return the same value, but [`scan_changes`] doesn't report the
+    /// put.
+    ///
+    /// [`dedup_keeps_versions`]: Options::dedup_keeps_versions
+    /// [`scan_changes`]: crate::raw::Table::scan_changes
+    ///
+    /// Default: false
+    pub dedup_identical_puts: bool,
+
+    /// Keeps a version at the LSN of each put deduplicated by
+    /// [`dedup_identical_puts`].
+    ///
+    /// If true, a deduplicated put still writes a delta that records its LSN
+    /// but not the value, which is shared with the previous version. The put
+    /// is then seen by [`scan_changes`] and by the conflict checks of
+    /// [`ReadTxn`] as if the value was written again, at the cost of a small
+    /// delta per put.
+    ///
+    /// [`dedup_identical_puts`]: Options::dedup_identical_puts
+    /// [`ReadTxn`]: crate::raw::ReadTxn
+    /// [`scan_changes`]: crate::raw::Table::scan_changes
+    ///
+    /// Default: false
+    pub dedup_keeps_versions: bool,
+
+    /// Rejects reads with LSNs below the [`safe_lsn`].
+    ///
+    /// Versions that are only visible to LSNs below the safe LSN may have been
+    /// dropped, so such reads may return whatever versions survived. If true,
+    /// these reads fail with [`Error::SnapshotTooOld`] instead. Reads at or
+    /// above the LSN of an open [`Snapshot`] are never rejected, since the
+    /// versions visible to them are retained.
+    ///
+    /// [`safe_lsn`]: crate::raw::Table::safe_lsn
+    /// [`Error::SnapshotTooOld`]: crate::Error::SnapshotTooOld
+    /// [`Snapshot`]: crate::raw::Snapshot
+    ///
+    /// Default: false
+    pub reject_reads_below_safe_lsn: bool,
+
+    /// Shares the table if it is already open in this process.
+    ///
+    /// Opening a table twice in a process would create two independent states
+    /// over the same files. If true, opening a table that is already open
+    /// returns a reference to the open table, and the given options are
+    /// ignored. Otherwise, it fails with [`Error::AlreadyOpen`].
+    ///
+    /// [`Error::AlreadyOpen`]: crate::Error::AlreadyOpen
+    ///
+    /// Default: false
+    pub share_open_tables: bool,
+
+    /// The operator to merge operands into values, see [`Table::merge`].
+    ///
+    /// Operands are merged when they are read, so the operator should be kept
+    /// once operands are written. Reads ignore the operands without it.
+    ///
+    /// [`Table::merge`]: crate::raw::Table::merge
+    ///
+    /// Default: `None`
+    pub merge_operator: Option<Arc<dyn MergeOperator>>,
 
     /// Options for the underlying page store.
     pub page_store: PageStoreOptions,
@@ -25,12 +195,78 @@ impl Default for Options {
     fn default() -> Self {
         Self {
             page_size: 8 << 10,
-            page_chain_length: 4,
+            data_chain_length: 4,
+            index_chain_length: 2,
+            bloom_fp_rate: 0.01,
+            partial_consolidation_percent: 50,
+            split_policy: SplitPolicy::Count,
+            disable_background: false,
+            initial_leaf_count: 1,
+            initial_split_keys: Vec::new(),
+            read_io_depth: 1,
+            retain_versions: 0,
+            skip_tombstone_for_absent: false,
+            dedup_identical_puts: false,
+            dedup_keeps_versions: false,
+            reject_reads_below_safe_lsn: false,
+            share_open_tables: false,
+            merge_operator: None,
             page_store: PageStoreOptions::default(),
         }
     }
 }
 
+impl Options {
+    /// Returns the split keys of the leaf pages to create when the table is
+    /// created, or `None` if the options are invalid.
+    pub(crate) fn presplit_keys(&self) -> Option<Vec<Vec<u8>>> {
+        if !self.initial_split_keys.is_empty() {
+            let keys = &self.initial_split_keys;
+            let valid = keys.iter().all(|k| !k.is_empty()) && keys.windows(2).all(|w| w[0] < w[1]);
+            return valid.then(|| keys.clone());
+        }
+        let n = self.initial_leaf_count;
+        if n == 0 || n > 1 << 16 {
+            return None;
+        }
+        let keys = (1..n)
+            .map(|i| (((i << 16) / n) as u16).to_be_bytes().to_vec())
+            .collect();
+        Some(keys)
+    }
+}
+
+/// Policies to choose the split key of a page.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SplitPolicy {
+    /// Splits the page into two halves with the same number of entries.
+    Count,
+    /// Splits the page into two halves with approximately the same size.
+    ///
+    /// This keeps the sizes of pages balanced if the sizes of entries vary a
+    /// lot.
+    Size,
+}
+
+/// An operator that merges operands into the values of keys.
+///
+/// This allows read-modify-write updates, like incrementing a counter, without
+/// reading the value first, see [`Table::merge`]. Operands are merged lazily
+/// by reads and consolidations, and the operands of a key may be merged in
+/// several calls, each merging some of them into the result of the previous
+/// one. So merging must be deterministic and give the same result however the
+/// operands are split.
+///
+/// [`Table::merge`]: crate::raw::Table::merge
+pub trait MergeOperator: fmt::Debug + Send + Sync {
+    /// Merges the operands into the existing value of the key, in the order
+    /// of their LSNs.
+    ///
+    /// `existing` is `None` if the key has no value. Returns the new value of
+    /// the key.
+    fn merge(&self, key: &[u8], existing: Option<&[u8]>, operands: &[&[u8]]) -> Vec<u8>;
+}
+
 /// Options to configure the behavior of reads.
 #[non_exhaustive]
 #[derive(Clone, Debug)]
@@ -57,7 +293,96 @@ impl Default for ReadOptions {
     }
 }
 
-/// Options to configure the behavior of writes.
+/// Options to configure the behavior of scans.
 #[non_exhaustive]
 #[derive(Clone, Debug)]
-pub struct WriteOptions {}
+pub struct ScanOptions {
+    /// The inclusive lower bound of keys to scan.
+    ///
+    /// Default: `None`, which scans from the start of the table.
+    pub start: Option<Vec<u8>>,
+
+    /// The exclusive upper bound of keys to scan.
+    ///
+    /// Default: `None`, which scans to the end of the table.
+    pub end: Option<Vec<u8>>,
+
+    /// The maximum visible LSN for this scan.
+    ///
+    /// Default: `u64::MAX`
+    pub lsn: u64,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            start: None,
+            end: None,
+            lsn: u64::MAX,
+        }
+    }
+}
+
+/// The versions of entries that an iterator over a table reads.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Isolation {
+    /// Reads the entries visible at the largest LSN written to the table when
+    /// the iterator is created.
+    ///
+    /// Writes with larger LSNs made during the scan are not observed, so the
+    /// scan reflects a consistent point-in-time view of the table.
+    #[default]
+    Snapshot,
+    /// Reads the latest entries of each page when the page is visited.
+    ///
+    /// Writes made during the scan may or may not be observed, depending on
+    /// whether their pages have been visited. This suits streaming scans that
+    /// want to see new entries as soon as possible.
+    Latest,
+}
+
+/// Options to configure the behavior of writes.
+#[non_exhaustive]
+#[derive(Clone, Debug, Default)]
+pub struct WriteOptions {
+    /// When a write returns, with respect to the persistence of the write.
+    ///
+    /// Default: [`Durability::Memory`]
+    pub durability: Durability,
+
+    /// The unix time in seconds at which a put expires.
+    ///
+    /// Reads treat an expired entry as deleted, and it is dropped when its page
+    /// is consolidated after the safe LSN passes it. Expiration follows the
+    /// system clock, so entries can become visible again if the clock is set
+    /// back before they are dropped. A later put without an expiration clears
+    /// the expiration of the key. Ignored by deletes.
+    ///
+    /// Default: `None`
+    pub expire_at: Option<u64>,
+}
+
+/// The levels of persistence a write waits for before it returns.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Durability {
+    /// Returns once the write is in the write buffer.
+    ///
+    /// The write is lost if the process crashes before the write buffer is
+    /// flushed.
+    #[default]
+    Memory,
+    /// Returns once the write buffer holding the write is flushed to a page
+    /// file.
+    ///
+    /// The write buffer is sealed early for the write, once the flush before
+    /// it is done, so each flush writes a page file and syncs it. Concurrent
+    /// writes that wait for durability share a flush, but a single writer
+    /// pays for a flush and a sync with every write, and the page files it
+    /// flushes are small.
+    Flushed,
+    /// Returns once the page file holding the write is synced to the device.
+    ///
+    /// Page files are synced before they are installed, so this costs the
+    /// same as [`Durability::Flushed`] and returns at the same point.
+    Synced,
+}
@@ -1,4 +1,9 @@
-use crate::{page::*, page_store::*};
+use std::collections::VecDeque;
+
+use parking_lot::Mutex;
+
+use super::MergeOperator;
+use crate::{page::*, page_store::*, util::timer::unix_timestamp};
 
 /// The root id is fixed to the minimal id in the page store.
 pub(super) const ROOT_ID: u64 = MIN_ID;
@@ -17,24 +22,36 @@ pub(super) struct PageView<'a> {
 
 /// An iterator over user entries in a page.
 pub struct PageIter<'a> {
-    iter: MergingPageIter<'a, Key<'a>, Value<'a>>,
+    iter: VersionIter<'a>,
+    range_deletes: RangeDeletes<'a>,
+    merger: ValueMerger<'a>,
     read_lsn: u64,
-    last_raw: Option<&'a [u8]>,
+    // The unix time in seconds to check expiring values against.
+    now: u64,
+    // The versions of the current raw.
+    versions: VecDeque<(Key<'a>, Value<'a>)>,
 }
 
 impl<'a> PageIter<'a> {
-    pub(super) fn new(iter: MergingPageIter<'a, Key<'a>, Value<'a>>, read_lsn: u64) -> Self {
+    pub(super) fn new(
+        iter: MergingPageIter<'a, Key<'a>, Value<'a>>,
+        range_deletes: RangeDeletes<'a>,
+        merger: ValueMerger<'a>,
+        read_lsn: u64,
+    ) -> Self {
         Self {
-            iter,
+            iter: VersionIter::new(iter),
+            range_deletes,
+            merger,
             read_lsn,
-            last_raw: None,
+            now: unix_timestamp(),
+            versions: VecDeque::new(),
         }
     }
 
     /// Positions the iterator at the first item that is at or after `target`.
     pub fn seek(&mut self, target: &[u8]) {
         self.iter.seek(&Key::new(target, self.read_lsn));
-        self.last_raw = None;
     }
 }
 
@@ -42,21 +59,343 @@ impl<'a> Iterator for PageIter<'a> {
     type Item = (&'a [u8], &'a [u8]);
 
     fn next(&mut self) -> Option<Self::Item> {
+        while self.iter.next_versions(&mut self.versions) {
+            let raw = self.versions[0].0.raw;
+            let read_lsn = self.read_lsn;
+            let deleted_lsn = self.range_deletes.deleted_lsn(raw, read_lsn);
+            let values = self
+                .versions
+                .iter()
+                .filter(|(k, _)| k.lsn <= read_lsn)
+                .map(|&(k, v)| match deleted_lsn {
+                    // Versions covered by a range delete are deleted.
+                    Some(lsn) if k.lsn <= lsn => Value::Delete,
+                    _ => v,
+                });
+            let value = self.merger.resolve(raw, values, true);
+            if let Some(value) = value.and_then(|v| v.live_at(self.now)) {
+                return Some((raw, value));
+            }
+        }
+        None
+    }
+}
+
+/// A kind of change made to a key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChangeKind {
+    /// The key is put with the value.
+    Put(Vec<u8>),
+    /// The key is deleted.
+    Delete,
+}
+
+impl From<Value<'_>> for ChangeKind {
+    fn from(value: Value<'_>) -> Self {
+        match value {
+            Value::Put(v) | Value::Expiring(v, _) => Self::Put(v.to_vec()),
+            // Unchanged versions and merge operands are resolved before they are
+            // reported, so an unresolved one has no value.
+            Value::Delete | Value::Unchanged | Value::RangeDelete(_) | Value::Merge(_) => {
+                Self::Delete
+            }
+        }
+    }
+}
+
+/// An iterator over changes in a page.
+///
+/// It yields every version whose LSN is in `(since_lsn, read_lsn]`, including
+/// deletes, in key order and from the latest to the oldest version of each
+/// key. Unchanged versions are yielded with the values of their previous
+/// versions, and merge operands with the values they are merged into. A range
+/// delete is yielded as a delete of each key in the page that it covers.
+pub(crate) struct ChangeIter<'a> {
+    iter: VersionIter<'a>,
+    range_deletes: RangeDeletes<'a>,
+    merger: ValueMerger<'a>,
+    since_lsn: u64,
+    read_lsn: u64,
+    // The changes of the current raw that are yet to be yielded.
+    changes: VecDeque<(Key<'a>, Value<'a>)>,
+}
+
+impl<'a> ChangeIter<'a> {
+    pub(super) fn new(
+        iter: MergingPageIter<'a, Key<'a>, Value<'a>>,
+        range_deletes: RangeDeletes<'a>,
+        merger: ValueMerger<'a>,
+        since_lsn: u64,
+        read_lsn: u64,
+    ) -> Self {
+        Self {
+            iter: VersionIter::new(iter),
+            range_deletes,
+            merger,
+            since_lsn,
+            read_lsn,
+            changes: VecDeque::new(),
+        }
+    }
+
+    /// Positions the iterator at the first version of the first key that is
+    /// at or after `target`.
+    pub(super) fn seek(&mut self, target: &[u8]) {
+        self.iter.seek(&Key::new(target, u64::MAX));
+        self.changes.clear();
+    }
+}
+
+impl<'a> Iterator for ChangeIter<'a> {
+    type Item = (Key<'a>, Value<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(change) = self.changes.pop_front() {
+                return Some(change);
+            }
+            if !self.iter.next_versions(&mut self.changes) {
+                return None;
+            }
+            self.range_deletes.apply(&mut self.changes);
+            // Resolve unchanged versions and merge operands before the previous
+            // versions are filtered out.
+            let mut prev = None;
+            for (k, v) in self.changes.iter_mut().rev() {
+                let value = match *v {
+                    Value::Unchanged => prev,
+                    Value::Merge(operand) => {
+                        let base = prev.unwrap_or(Value::Delete);
+                        Some(self.merger.merge(k.raw, base, &[operand]))
+                    }
+                    value => Some(value),
+                };
+                if let Some(value) = value {
+                    *v = value;
+                    prev = Some(value);
+                }
+            }
+            let (since_lsn, read_lsn) = (self.since_lsn, self.read_lsn);
+            self.changes
+                .retain(|(k, _)| k.lsn > since_lsn && k.lsn <= read_lsn);
+        }
+    }
+}
+
+/// An iterator over the versions of each raw in a page.
+struct VersionIter<'a> {
+    iter: MergingPageIter<'a, Key<'a>, Value<'a>>,
+    // The first version of the next raw, which has been taken from `iter`.
+    peeked: Option<(Key<'a>, Value<'a>)>,
+}
+
+impl<'a> VersionIter<'a> {
+    fn new(iter: MergingPageIter<'a, Key<'a>, Value<'a>>) -> Self {
+        Self { iter, peeked: None }
+    }
+
+    /// Replaces `versions` with the versions of the next raw, from the latest
+    /// to the oldest.
+    ///
+    /// Returns false if there are no more raws.
+    fn next_versions(&mut self, versions: &mut VecDeque<(Key<'a>, Value<'a>)>) -> bool {
+        versions.clear();
+        let Some(first) = self.peeked.take().or_else(|| self.iter.next()) else {
+            return false;
+        };
+        versions.push_back(first);
         for (k, v) in &mut self.iter {
-            if k.lsn > self.read_lsn {
+            if k.raw != first.0.raw {
+                self.peeked = Some((k, v));
+                break;
+            }
+            versions.push_back((k, v));
+        }
+        true
+    }
+
+    fn rewind(&mut self) {
+        self.iter.rewind();
+        self.peeked = None;
+    }
+
+    fn seek(&mut self, target: &Key<'_>) -> bool {
+        self.peeked = None;
+        self.iter.seek(target)
+    }
+}
+
+/// The range deletes in a leaf page.
+#[derive(Default)]
+pub(super) struct RangeDeletes<'a> {
+    // The start key and the end of each range delete.
+    ranges: Vec<(Key<'a>, &'a [u8])>,
+}
+
+impl<'a> RangeDeletes<'a> {
+    /// Adds the range deletes in the items.
+    pub(super) fn add(&mut self, iter: impl Iterator<Item = (Key<'a>, Value<'a>)>) {
+        self.ranges.extend(iter.filter_map(|(k, v)| match v {
+            Value::RangeDelete(end) => Some((k, end)),
+            _ => None,
+        }));
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Returns the LSN of the latest range delete visible to `lsn` that covers
+    /// the raw, if any.
+    pub(super) fn deleted_lsn(&self, raw: &[u8], lsn: u64) -> Option<u64> {
+        self.ranges
+            .iter()
+            .filter(|(start, end)| start.raw <= raw && raw < *end && start.lsn <= lsn)
+            .map(|(start, _)| start.lsn)
+            .max()
+    }
+
+    /// Replaces the range deletes in the versions of a raw with a version of
+    /// the raw for each range delete that covers it.
+    ///
+    /// The added versions keep the range delete values, and they are ordered
+    /// before the other versions with the same LSN, since a range delete
+    /// deletes them. Range deletes older than all the other versions are
+    /// skipped, because there is nothing for them to delete.
+    fn apply(&self, versions: &mut VecDeque<(Key<'a>, Value<'a>)>) {
+        let Some(&(Key { raw, .. }, _)) = versions.front() else {
+            return;
+        };
+        versions.retain(|(_, v)| !matches!(v, Value::RangeDelete(_)));
+        let Some(&(oldest, _)) = versions.back() else {
+            return;
+        };
+        for &(start, end) in &self.ranges {
+            if start.raw > raw || raw >= end || start.lsn < oldest.lsn {
                 continue;
             }
-            if let Some(last) = self.last_raw {
-                if k.raw == last {
-                    continue;
-                }
+            let i = versions.partition_point(|(k, _)| k.lsn > start.lsn);
+            if !matches!(versions.get(i), Some((k, Value::RangeDelete(_))) if k.lsn == start.lsn) {
+                versions.insert(i, (Key::new(raw, start.lsn), Value::RangeDelete(end)));
             }
-            self.last_raw = Some(k.raw);
-            if let Value::Put(value) = v {
-                return Some((k.raw, value));
+        }
+    }
+}
+
+/// Merges merge operands into the values of their previous versions.
+#[derive(Clone, Copy)]
+pub(super) struct ValueMerger<'a> {
+    operator: Option<&'a dyn MergeOperator>,
+    values: &'a MergedValues,
+}
+
+impl<'a> ValueMerger<'a> {
+    /// Creates a merger that merges operands with `operator`, and keeps the
+    /// merged values in `values`.
+    ///
+    /// Operands are ignored if there is no operator.
+    pub(super) fn new(operator: Option<&'a dyn MergeOperator>, values: &'a MergedValues) -> Self {
+        Self { operator, values }
+    }
+
+    /// Resolves the value of the first of the versions of a raw, which are
+    /// ordered from the latest to the oldest.
+    ///
+    /// Unchanged versions take the values of their previous versions, and
+    /// merge operands are merged into them. If `complete` is true, the
+    /// versions are all the versions of the raw, so that the value is always
+    /// resolved. Otherwise, returns `None` if the value depends on the
+    /// versions before them.
+    pub(super) fn resolve(
+        &self,
+        raw: &[u8],
+        versions: impl IntoIterator<Item = Value<'a>>,
+        complete: bool,
+    ) -> Option<Value<'a>> {
+        let mut operands = Vec::new();
+        for v in versions {
+            match v {
+                Value::Unchanged => {}
+                Value::Merge(operand) => operands.push(operand),
+                base => return Some(self.merge(raw, base, &operands)),
             }
         }
-        None
+        complete.then(|| self.merge(raw, Value::Delete, &operands))
+    }
+
+    /// Merges the operands, from the latest to the oldest, into the base
+    /// value.
+    ///
+    /// Returns a put of the merged value, which expires with the base value.
+    /// Range deletes are resolved to deletes.
+    pub(super) fn merge(&self, raw: &[u8], base: Value<'a>, operands: &[&[u8]]) -> Value<'a> {
+        let base = match base {
+            Value::RangeDelete(_) => Value::Delete,
+            base => base,
+        };
+        let Some(operator) = self.operator.filter(|_| !operands.is_empty()) else {
+            return base;
+        };
+        let (existing, expire_at) = match base {
+            Value::Put(v) => (Some(v), None),
+            Value::Expiring(v, expire_at) => (Some(v), Some(expire_at)),
+            _ => (None, None),
+        };
+        let operands: Vec<_> = operands.iter().rev().copied().collect();
+        let value = self.values.insert(operator.merge(raw, existing, &operands));
+        expire_at.map_or(Value::Put(value), |expire_at| {
+            Value::Expiring(value, expire_at)
+        })
+    }
+}
+
+/// Values merged from merge operands, which live as long as this.
+#[derive(Default)]
+pub(super) struct MergedValues(Mutex<Vec<Vec<u8>>>);
+
+impl MergedValues {
+    fn insert(&self, value: Vec<u8>) -> &[u8] {
+        let mut values = self.0.lock();
+        values.push(value);
+        let value: *const [u8] = values.last().unwrap().as_slice();
+        // SAFETY: the values are never modified or dropped until this is dropped,
+        // and the content of a vector is not moved when the vector is moved.
+        unsafe { &*value }
+    }
+}
+
+/// Values that the right page of a split may need from the left page.
+pub(super) trait SplitValue<K>: Sized {
+    /// Returns the items that the right page of a split at `split_key` needs
+    /// from the items of the left page, in order.
+    fn right_items(split_key: &K, left: impl Iterator<Item = (K, Self)>) -> Vec<(K, Self)>;
+}
+
+impl<'a> SplitValue<Key<'a>> for Value<'a> {
+    // Range deletes that cross the split key are clipped to start at it.
+    fn right_items(
+        split_key: &Key<'a>,
+        left: impl Iterator<Item = (Key<'a>, Self)>,
+    ) -> Vec<(Key<'a>, Self)> {
+        let mut items: Vec<_> = left
+            .filter_map(|(k, v)| match v {
+                Value::RangeDelete(end) if split_key.raw < end => {
+                    Some((Key::new(split_key.raw, k.lsn), v))
+                }
+                _ => None,
+            })
+            .collect();
+        items.sort_by(|a, b| a.0.cmp(&b.0));
+        items
+    }
+}
+
+impl<'a> SplitValue<&'a [u8]> for Index {
+    fn right_items(
+        _: &&'a [u8],
+        _: impl Iterator<Item = (&'a [u8], Self)>,
+    ) -> Vec<(&'a [u8], Self)> {
+        Vec::new()
     }
 }
 
@@ -132,19 +471,136 @@ where
 
 /// An iterator that merges multiple leaf delta pages for consolidation.
 pub(super) struct MergingLeafPageIter<'a> {
-    iter: MergingPageIter<'a, Key<'a>, Value<'a>>,
+    iter: VersionIter<'a>,
     safe_lsn: u64,
-    last_raw: Option<&'a [u8]>,
-    skip_same_raw: bool,
+    retain_versions: usize,
+    merger: ValueMerger<'a>,
+    // Whether the pages make up the whole chain.
+    whole_chain: bool,
+    range_deletes: RangeDeletes<'a>,
+    // The versions of the current raw that are yet to be output.
+    versions: VecDeque<(Key<'a>, Value<'a>)>,
+    // The unix time in seconds to check expiring values against.
+    now: u64,
 }
 
 impl<'a> MergingLeafPageIter<'a> {
-    pub(super) fn new(iter: MergingPageIter<'a, Key<'a>, Value<'a>>, safe_lsn: u64) -> Self {
+    /// Creates an iterator that drops versions invisible to the safe LSN,
+    /// except the latest `retain_versions` versions of each raw.
+    ///
+    /// Merge operands that are dropped are merged into the kept versions with
+    /// `merger`. If `whole_chain` is false, there are more pages after these
+    /// in the chain, so that operands whose values are in those pages are
+    /// kept as they are.
+    pub(super) fn new(
+        iter: MergingPageIter<'a, Key<'a>, Value<'a>>,
+        safe_lsn: u64,
+        retain_versions: usize,
+        merger: ValueMerger<'a>,
+        whole_chain: bool,
+    ) -> Self {
         Self {
-            iter,
+            iter: VersionIter::new(iter),
             safe_lsn,
-            last_raw: None,
-            skip_same_raw: false,
+            retain_versions,
+            merger,
+            whole_chain,
+            range_deletes: RangeDeletes::default(),
+            versions: VecDeque::new(),
+            now: unix_timestamp(),
+        }
+    }
+
+    /// Applies the range deletes in the pages to the versions they cover.
+    ///
+    /// If the pages make up the whole chain, range deletes invisible to the
+    /// safe LSN are dropped along with the versions they cover. Otherwise,
+    /// they are kept for the versions in the rest of the chain.
+    pub(super) fn with_range_deletes(mut self) -> Self {
+        self.range_deletes.add(&mut self.iter.iter);
+        self.iter.rewind();
+        self
+    }
+
+    /// Drops the versions of the current raw that are invisible to the safe
+    /// LSN, except the latest `retain_versions` versions.
+    fn drop_versions(&mut self) {
+        let versions = &mut self.versions;
+        // Keep versions that are not visible to the safe LSN yet, and the latest
+        // versions that should be retained.
+        let mut len = versions
+            .iter()
+            .enumerate()
+            .take_while(|(i, (k, _))| k.lsn > self.safe_lsn || *i < self.retain_versions)
+            .count();
+        // Older versions are not visible to the safe LSN, except the latest of them if
+        // no kept version is. If it is a delete or has expired, we can drop it along
+        // with all older versions. A version whose value is not in these pages is kept
+        // as it is.
+        let raw = match versions.front() {
+            Some((k, _)) => k.raw,
+            None => return,
+        };
+        let resolve = |i: usize| {
+            let values = versions.range(i..).map(|&(_, v)| v);
+            self.merger.resolve(raw, values, self.whole_chain)
+        };
+        let has_visible = len > 0 && versions[len - 1].0.lsn <= self.safe_lsn;
+        if !has_visible && len < versions.len() {
+            let value = resolve(len);
+            if value.map_or(true, |v| v.live_at(self.now).is_some()) {
+                len += 1;
+            }
+        }
+        // The last kept version can't refer to the dropped versions.
+        if len > 0
+            && len < versions.len()
+            && matches!(versions[len - 1].1, Value::Unchanged | Value::Merge(_))
+        {
+            match resolve(len - 1) {
+                Some(value) => versions[len - 1].1 = value,
+                // The dropped operands can't be merged without the rest of the chain.
+                None if versions
+                    .range(len..)
+                    .any(|(_, v)| matches!(v, Value::Merge(_))) =>
+                {
+                    len = versions.len();
+                }
+                None => {}
+            }
+        }
+        versions.truncate(len);
+    }
+
+    /// Drops the versions of the current raw like [`Self::drop_versions`],
+    /// with the range deletes that cover the raw as delete versions.
+    fn drop_covered_versions(&mut self) {
+        let (safe_lsn, drop) = (self.safe_lsn, self.whole_chain);
+        let is_dropped = |lsn: u64| drop && lsn <= safe_lsn;
+        // The range deletes that start at the raw are kept as they are.
+        let kept: Vec<_> = self
+            .versions
+            .iter()
+            .filter(|(k, v)| matches!(v, Value::RangeDelete(_)) && !is_dropped(k.lsn))
+            .copied()
+            .collect();
+        self.range_deletes.apply(&mut self.versions);
+        self.drop_versions();
+        // The versions of kept range deletes are not needed, since the range
+        // deletes still cover the kept versions. Others become point deletes.
+        self.versions.retain_mut(|(k, v)| match v {
+            Value::RangeDelete(_) if is_dropped(k.lsn) => {
+                *v = Value::Delete;
+                true
+            }
+            Value::RangeDelete(_) => false,
+            _ => true,
+        });
+        for version in kept {
+            let i = self
+                .versions
+                .partition_point(|(k, _)| k.lsn > version.0.lsn);
+            self.versions.insert(i, version);
         }
     }
 }
@@ -153,53 +609,32 @@ impl<'a> Iterator for MergingLeafPageIter<'a> {
     type Item = (Key<'a>, Value<'a>);
 
     fn next(&mut self) -> Option<Self::Item> {
-        for (k, v) in &mut self.iter {
-            if let Some(last) = self.last_raw {
-                if k.raw == last {
-                    // Skip versions of the same raw.
-                    if self.skip_same_raw {
-                        continue;
-                    }
-                    // Output versions that are visible to the safe LSN.
-                    if k.lsn > self.safe_lsn {
-                        return Some((k, v));
-                    }
-                    // This is the oldest version visible to the safe LSN.
-                    self.skip_same_raw = true;
-                    match v {
-                        Value::Put(_) => return Some((k, v)),
-                        Value::Delete => continue,
-                    }
-                }
+        loop {
+            if let Some(version) = self.versions.pop_front() {
+                return Some(version);
             }
-            // This is the latest version of this raw.
-            self.last_raw = Some(k.raw);
-            self.skip_same_raw = k.lsn <= self.safe_lsn;
-            match v {
-                // If the latest version is a delete and all older versions are not visible to the
-                // safe LSN, we can skip all of them.
-                Value::Delete if k.lsn <= self.safe_lsn => {
-                    continue;
-                }
-                _ => return Some((k, v)),
+            if !self.iter.next_versions(&mut self.versions) {
+                return None;
+            }
+            if self.range_deletes.is_empty() {
+                self.drop_versions();
+            } else {
+                self.drop_covered_versions();
             }
         }
-        None
     }
 }
 
 impl<'a> RewindableIterator for MergingLeafPageIter<'a> {
     fn rewind(&mut self) {
         self.iter.rewind();
-        self.last_raw = None;
-        self.skip_same_raw = false;
+        self.versions.clear();
     }
 }
 
 impl<'a> SeekableIterator<Key<'_>> for MergingLeafPageIter<'a> {
     fn seek(&mut self, target: &Key<'_>) -> bool {
-        self.last_raw = None;
-        self.skip_same_raw = false;
+        self.versions.clear();
         self.iter.seek(target)
     }
 }
@@ -284,6 +719,8 @@ mod tests {
 
     #[test]
     fn page_iter() {
+        let values = MergedValues::default();
+        let merger = ValueMerger::new(None, &values);
         let data = vec![
             (Key::new(&[1], 3), Value::Put(&[3])),
             (Key::new(&[1], 2), Value::Put(&[2])),
@@ -304,7 +741,7 @@ mod tests {
         ];
         for (lsn, expect) in lsn_expect {
             let merging_iter = build_merging_iter([owned_page.as_iter()], None);
-            let mut iter = PageIter::new(merging_iter, lsn);
+            let mut iter = PageIter::new(merging_iter, RangeDeletes::default(), merger, lsn);
             for (a, b) in (&mut iter).zip(expect) {
                 assert_eq!(a, b);
             }
@@ -312,7 +749,7 @@ mod tests {
 
         {
             let merging_iter = build_merging_iter([owned_page.as_iter()], None);
-            let mut iter = PageIter::new(merging_iter, 1);
+            let mut iter = PageIter::new(merging_iter, RangeDeletes::default(), merger, 1);
             iter.seek(&[]);
             assert_eq!(iter.next(), Some(([1].as_slice(), [1].as_slice())));
             iter.seek(&[1]);
@@ -326,6 +763,47 @@ mod tests {
         }
     }
 
+    #[test]
+    fn change_iter() {
+        let values = MergedValues::default();
+        let merger = ValueMerger::new(None, &values);
+        let data = vec![
+            (Key::new(&[1], 3), Value::Put(&[3])),
+            (Key::new(&[1], 2), Value::Put(&[2])),
+            (Key::new(&[1], 1), Value::Put(&[1])),
+            (Key::new(&[3], 3), Value::Put(&[3])),
+            (Key::new(&[3], 1), Value::Delete),
+            (Key::new(&[5], 2), Value::Delete),
+            (Key::new(&[5], 1), Value::Put(&[1])),
+        ];
+        let owned_page = OwnedSortedPage::from_slice(&data);
+
+        let lsn_expect = [
+            ((0, 1), vec![data[2], data[4], data[6]]),
+            ((1, 1), vec![]),
+            ((1, 2), vec![data[1], data[5]]),
+            ((2, 3), vec![data[0], data[3]]),
+            ((0, 4), data.clone()),
+            ((3, 4), vec![]),
+        ];
+        for ((since_lsn, lsn), expect) in lsn_expect {
+            let merging_iter = build_merging_iter([owned_page.as_iter()], None);
+            let iter = ChangeIter::new(
+                merging_iter,
+                RangeDeletes::default(),
+                merger,
+                since_lsn,
+                lsn,
+            );
+            assert_eq!(iter.collect::<Vec<_>>(), expect);
+        }
+
+        let merging_iter = build_merging_iter([owned_page.as_iter()], None);
+        let mut iter = ChangeIter::new(merging_iter, RangeDeletes::default(), merger, 0, 4);
+        iter.seek(&[2]);
+        assert_eq!(iter.collect::<Vec<_>>(), data[3..]);
+    }
+
     #[test]
     fn merging_page_iter() {
         let data = raw_slice(&[[1], [3], [5]]);
@@ -345,6 +823,8 @@ mod tests {
 
     #[test]
     fn merging_leaf_page_iter() {
+        let values = MergedValues::default();
+        let merger = ValueMerger::new(None, &values);
         let data = vec![
             (Key::new(&[1], 3), Value::Put(&[3])),
             (Key::new(&[1], 2), Value::Put(&[2])),
@@ -368,7 +848,7 @@ mod tests {
         ];
         for (lsn, expect) in lsn_expect {
             let merging_iter = build_merging_iter([owned_page.as_iter()], None);
-            let mut iter = MergingLeafPageIter::new(merging_iter, lsn);
+            let mut iter = MergingLeafPageIter::new(merging_iter, lsn, 0, merger, false);
             for (a, b) in (&mut iter).zip(expect) {
                 assert_eq!(a, b);
             }
@@ -376,7 +856,7 @@ mod tests {
 
         {
             let merging_iter = build_merging_iter([owned_page.as_iter()], None);
-            let mut iter = MergingLeafPageIter::new(merging_iter, 2);
+            let mut iter = MergingLeafPageIter::new(merging_iter, 2, 0, merger, false);
             iter.seek(&Key::new(&[], 2));
             assert_eq!(iter.next(), Some(data[0]));
             iter.seek(&Key::new(&[1], 2));
@@ -391,7 +871,7 @@ mod tests {
 
         {
             let merging_iter = build_merging_iter([owned_page.as_iter()], None);
-            let mut iter = MergingLeafPageIter::new(merging_iter, 2);
+            let mut iter = MergingLeafPageIter::new(merging_iter, 2, 0, merger, false);
             assert_eq!(iter.next(), Some(data[0]));
             assert_eq!(iter.next(), Some(data[1]));
 
@@ -399,6 +879,234 @@ mod tests {
             assert_eq!(iter.next(), Some(data[0]));
             assert_eq!(iter.next(), Some(data[1]));
         }
+
+        let retain_expect = [
+            (1, vec![data[0], data[3], data[5]]),
+            (
+                2,
+                vec![data[0], data[1], data[3], data[4], data[5], data[6]],
+            ),
+            (3, data.clone()),
+        ];
+        for (retain_versions, expect) in retain_expect {
+            let merging_iter = build_merging_iter([owned_page.as_iter()], None);
+            let iter = MergingLeafPageIter::new(merging_iter, 4, retain_versions, merger, false);
+            assert_eq!(iter.collect::<Vec<_>>(), expect);
+        }
+    }
+
+    #[test]
+    fn unchanged_versions() {
+        let values = MergedValues::default();
+        let merger = ValueMerger::new(None, &values);
+        let data = vec![
+            (Key::new(&[1], 4), Value::Unchanged),
+            (Key::new(&[1], 3), Value::Unchanged),
+            (Key::new(&[1], 2), Value::Put(&[2])),
+            (Key::new(&[1], 1), Value::Put(&[1])),
+            (Key::new(&[3], 2), Value::Unchanged),
+        ];
+        let owned_page = OwnedSortedPage::from_slice(&data);
+
+        // Unchanged versions are read with the values of their previous versions.
+        let lsn_expect = [(1, [1]), (3, [2]), (4, [2])];
+        for (lsn, expect) in lsn_expect {
+            let merging_iter = build_merging_iter([owned_page.as_iter()], None);
+            let iter = PageIter::new(merging_iter, RangeDeletes::default(), merger, lsn);
+            let expect = vec![([1].as_slice(), expect.as_slice())];
+            assert_eq!(iter.collect::<Vec<_>>(), expect);
+        }
+
+        let resolved = |lsn| (Key::new(&[1], lsn), Value::Put(&[2]));
+        let lsn_expect = [
+            (
+                (0, 4),
+                vec![resolved(4), resolved(3), data[2], data[3], data[4]],
+            ),
+            ((3, 4), vec![resolved(4)]),
+        ];
+        for ((since_lsn, lsn), expect) in lsn_expect {
+            let merging_iter = build_merging_iter([owned_page.as_iter()], None);
+            let iter = ChangeIter::new(
+                merging_iter,
+                RangeDeletes::default(),
+                merger,
+                since_lsn,
+                lsn,
+            );
+            assert_eq!(iter.collect::<Vec<_>>(), expect);
+        }
+
+        // The kept versions don't refer to the dropped versions.
+        let lsn_expect = [
+            (3, vec![data[0], resolved(3), data[4]]),
+            (4, vec![resolved(4), data[4]]),
+        ];
+        for (lsn, expect) in lsn_expect {
+            let merging_iter = build_merging_iter([owned_page.as_iter()], None);
+            let iter = MergingLeafPageIter::new(merging_iter, lsn, 0, merger, false);
+            assert_eq!(iter.collect::<Vec<_>>(), expect);
+        }
+    }
+
+    #[test]
+    fn range_deletes() {
+        let values = MergedValues::default();
+        let merger = ValueMerger::new(None, &values);
+        let deletes = [(Key::new(&[1], 3), Value::RangeDelete(&[4]))];
+        let data = [
+            (Key::new(&[1], 1), Value::Put(&[1])),
+            (Key::new(&[2], 4), Value::Put(&[4])),
+            (Key::new(&[2], 2), Value::Put(&[2])),
+            (Key::new(&[3], 1), Value::Put(&[1])),
+            (Key::new(&[4], 1), Value::Put(&[1])),
+        ];
+        let deletes_page = OwnedSortedPage::from_slice(&deletes);
+        let data_page = OwnedSortedPage::from_slice(&data);
+        let merging_iter =
+            || build_merging_iter([deletes_page.as_iter(), data_page.as_iter()], None);
+        let range_deletes = || {
+            let mut range_deletes = RangeDeletes::default();
+            range_deletes.add(deletes_page.as_iter());
+            range_deletes
+        };
+
+        // Versions covered by the range delete are deleted at its LSN.
+        let lsn_expect = [
+            (
+                2,
+                as_slice(&[([1], [1]), ([2], [2]), ([3], [1]), ([4], [1])]),
+            ),
+            (3, as_slice(&[([4], [1])])),
+            (4, as_slice(&[([2], [4]), ([4], [1])])),
+        ];
+        for (lsn, expect) in lsn_expect {
+            let iter = PageIter::new(merging_iter(), range_deletes(), merger, lsn);
+            assert_eq!(iter.collect::<Vec<_>>(), expect);
+        }
+
+        // The range delete is a delete of each key that it covers.
+        let deleted = |raw: &'static [u8]| (Key::new(raw, 3), deletes[0].1);
+        let expect = vec![
+            deleted(&[1]),
+            data[0],
+            data[1],
+            deleted(&[2]),
+            data[2],
+            deleted(&[3]),
+            data[3],
+            data[4],
+        ];
+        let iter = ChangeIter::new(merging_iter(), range_deletes(), merger, 0, 4);
+        assert_eq!(iter.collect::<Vec<_>>(), expect);
+
+        // The range delete and the versions it covers are dropped once it is
+        // invisible to the safe LSN, unless it may cover the rest of the chain.
+        let lsn_expect = [
+            (
+                (2, true),
+                vec![deletes[0], data[0], data[1], data[2], data[3], data[4]],
+            ),
+            ((3, true), vec![data[1], data[4]]),
+            ((3, false), vec![deletes[0], data[1], data[4]]),
+        ];
+        for ((lsn, drop), expect) in lsn_expect {
+            let iter =
+                MergingLeafPageIter::new(merging_iter(), lsn, 0, merger, drop).with_range_deletes();
+            assert_eq!(iter.collect::<Vec<_>>(), expect);
+        }
+
+        // The right page of a split gets the part of the range delete after the
+        // split key.
+        let split_key = Key::new([2].as_slice(), u64::MAX);
+        let left = deletes.into_iter().chain([data[0]]);
+        let expect = vec![(Key::new(&[2], 3), deletes[0].1)];
+        assert_eq!(Value::right_items(&split_key, left), expect);
+        let split_key = Key::new([4].as_slice(), u64::MAX);
+        assert_eq!(Value::right_items(&split_key, deletes.into_iter()), vec![]);
+    }
+
+    #[derive(Debug)]
+    struct AppendOperator;
+
+    impl MergeOperator for AppendOperator {
+        fn merge(&self, _: &[u8], existing: Option<&[u8]>, operands: &[&[u8]]) -> Vec<u8> {
+            let mut value = existing.unwrap_or_default().to_vec();
+            operands.iter().for_each(|v| value.extend_from_slice(v));
+            value
+        }
+    }
+
+    #[test]
+    fn merge_operands() {
+        let data = [
+            (Key::new(&[1], 4), Value::Merge(&[4])),
+            (Key::new(&[1], 3), Value::Merge(&[3])),
+            (Key::new(&[1], 2), Value::Put(&[2])),
+            (Key::new(&[1], 1), Value::Merge(&[1])),
+            (Key::new(&[3], 2), Value::Merge(&[2])),
+            (Key::new(&[3], 1), Value::Delete),
+            (Key::new(&[5], 2), Value::Merge(&[2])),
+            (Key::new(&[5], 1), Value::Merge(&[1])),
+        ];
+        let owned_page = OwnedSortedPage::from_slice(&data);
+        let merging_iter = || build_merging_iter([owned_page.as_iter()], None);
+        let values = MergedValues::default();
+        let merger = ValueMerger::new(Some(&AppendOperator), &values);
+
+        // Operands are merged into the values of their previous versions, or
+        // into nothing if there are none.
+        let lsn_expect: [(u64, &[(&[u8], &[u8])]); 2] = [
+            (1, &[(&[1], &[1]), (&[5], &[1])]),
+            (4, &[(&[1], &[2, 3, 4]), (&[3], &[2]), (&[5], &[1, 2])]),
+        ];
+        for (lsn, expect) in lsn_expect {
+            let iter = PageIter::new(merging_iter(), RangeDeletes::default(), merger, lsn);
+            assert_eq!(iter.collect::<Vec<_>>(), expect);
+        }
+
+        let put =
+            |raw: &'static [u8], lsn, value: &'static [u8]| (Key::new(raw, lsn), Value::Put(value));
+        let expect = vec![
+            put(&[1], 4, &[2, 3, 4]),
+            put(&[1], 3, &[2, 3]),
+            data[2],
+            put(&[1], 1, &[1]),
+            put(&[3], 2, &[2]),
+            data[5],
+            put(&[5], 2, &[1, 2]),
+            put(&[5], 1, &[1]),
+        ];
+        let iter = ChangeIter::new(merging_iter(), RangeDeletes::default(), merger, 0, 4);
+        assert_eq!(iter.collect::<Vec<_>>(), expect);
+
+        // Dropped operands are merged into the kept versions, unless their values
+        // may be in the rest of the chain.
+        let whole_chain_expect = [
+            (
+                false,
+                vec![
+                    data[0],
+                    put(&[1], 3, &[2, 3]),
+                    put(&[3], 2, &[2]),
+                    data[6],
+                    data[7],
+                ],
+            ),
+            (
+                true,
+                vec![
+                    data[0],
+                    put(&[1], 3, &[2, 3]),
+                    put(&[3], 2, &[2]),
+                    put(&[5], 2, &[1, 2]),
+                ],
+            ),
+        ];
+        for (whole_chain, expect) in whole_chain_expect {
+            let iter = MergingLeafPageIter::new(merging_iter(), 3, 0, merger, whole_chain);
+            assert_eq!(iter.collect::<Vec<_>>(), expect);
+        }
     }
 
     #[test]
@@ -30,15 +30,21 @@ impl Display for TreeStats {
                 split_page: {}, \
                 reconcile_page: {}, \
                 consolidate_page: {}, \
+                skip_page: {}, \
                 read_bytes: {}, \
-                write_bytes: {}",
+                write_bytes: {}, \
+                consolidate_bytes: {}, \
+                skip_write: {}",
             self.success.read,
             self.success.write,
             self.success.split_page,
             self.success.reconcile_page,
             self.success.consolidate_page,
+            self.success.skip_page,
             self.success.read_bytes,
-            self.success.write_bytes
+            self.success.write_bytes,
+            self.success.consolidate_bytes,
+            self.success.skip_write
         )?;
         writeln!(
             f,
@@ -80,8 +86,15 @@ pub struct TxnStats {
     pub reconcile_page: u64,
     pub consolidate_page: u64,
     pub rewrite_page: u64,
+    /// The number of pages skipped by lookups because of page filters.
+    pub skip_page: u64,
     pub read_bytes: u64,
     pub write_bytes: u64,
+    /// The number of bytes of the pages built by consolidations.
+    pub consolidate_bytes: u64,
+    /// The number of deletes skipped because the keys are absent, and puts
+    /// skipped because the values are unchanged.
+    pub skip_write: u64,
 }
 
 #[derive(Default)]
@@ -94,6 +107,9 @@ pub(super) struct AtomicTxnStats {
     pub(super) reconcile_page: Counter,
     pub(super) consolidate_page: Counter,
     pub(super) rewrite_page: Counter,
+    pub(super) skip_page: Counter,
+    pub(super) consolidate_bytes: Counter,
+    pub(super) skip_write: Counter,
 }
 
 impl AtomicTxnStats {
@@ -107,6 +123,9 @@ impl AtomicTxnStats {
             reconcile_page: self.reconcile_page.get(),
             consolidate_page: self.consolidate_page.get(),
             rewrite_page: self.rewrite_page.get(),
+            skip_page: self.skip_page.get(),
+            consolidate_bytes: self.consolidate_bytes.get(),
+            skip_write: self.skip_write.get(),
         }
     }
 }
@@ -122,6 +141,9 @@ impl TxnStats {
             reconcile_page: self.reconcile_page.wrapping_sub(o.reconcile_page),
             consolidate_page: self.consolidate_page.wrapping_sub(o.consolidate_page),
             rewrite_page: self.rewrite_page.wrapping_sub(o.rewrite_page),
+            skip_page: self.skip_page.wrapping_sub(o.skip_page),
+            consolidate_bytes: self.consolidate_bytes.wrapping_sub(o.consolidate_bytes),
+            skip_write: self.skip_write.wrapping_sub(o.skip_write),
         }
     }
 }
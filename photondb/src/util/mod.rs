@@ -4,3 +4,4 @@ pub(crate) mod latch;
 pub(crate) mod linked_list;
 pub(crate) mod notify;
 pub(crate) mod shutdown;
+pub(crate) mod timer;